@@ -0,0 +1,73 @@
+//! Aggregating PVSS dealings, against the previous version that copied every form
+//! before composing it. Both produce the same result; the difference is one QFI copy
+//! per dealing and recipient, plus a neutral form per missing share.
+
+use bicycl::{Mpz, RandGen, QFI};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use robust_threshold_ecdsa::cl_setup::SecurityLevel;
+use robust_threshold_ecdsa::utils::*;
+
+fn cloning_aggregate(pp: &PubParams, dealings: &[PvssDealing]) -> (QFI, Vec<QFI>) {
+    let randomness = dealings
+        .iter()
+        .map(|d| d.shares_ciphertext.randomness.clone())
+        .reduce(|acc, r| acc.compose(&pp.cl, &r))
+        .unwrap()
+        .clone();
+    let encryption = (1..=pp.n)
+        .map(|id| {
+            dealings
+                .iter()
+                .map(|d| {
+                    d.shares_ciphertext
+                        .encryption
+                        .get(&id)
+                        .unwrap_or(&pp.cl.one())
+                        .clone()
+                })
+                .reduce(|acc, e| acc.compose(&pp.cl, &e))
+                .unwrap()
+                .clone()
+        })
+        .collect();
+    (randomness, encryption)
+}
+
+fn bench_joint_pvss(c: &mut Criterion) {
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let h = G::base_point2();
+
+    let mut group = c.benchmark_group("joint_pvss");
+    for n in [8, 32] {
+        let (pp, _) = PubParams::with_level(SecurityLevel::Toy, n, n / 2, &mut rng).unwrap();
+        let dealings: Vec<PvssDealing> = (0..n)
+            .map(|_| PvssDealing::random(&pp, &mut rng, h).0)
+            .collect();
+
+        let joint = JointPvssResult::new(&pp, dealings.clone());
+        let (randomness, encryption) = cloning_aggregate(&pp, &dealings);
+        assert!(joint.shares_ciphertext.randomness == randomness);
+        assert!(joint
+            .shares_ciphertext
+            .encryption
+            .values()
+            .eq(encryption.iter()));
+
+        group.bench_with_input(BenchmarkId::new("by_reference", n), &n, |b, _| {
+            // the dealings are handed over by value, so their copy is left out of the timing
+            b.iter_batched(
+                || dealings.clone(),
+                |dealings| JointPvssResult::new(&pp, dealings),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("cloning", n), &n, |b, _| {
+            b.iter(|| cloning_aggregate(&pp, &dealings))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_joint_pvss);
+criterion_main!(benches);
@@ -0,0 +1,70 @@
+//! Proving and verifying the two MtA dealings of a presignature, with a `DualMtaNizk`
+//! against a pair of `MtaNizk`s. The dual proof evaluates the PVSS encryptions at its
+//! challenge once, where each of the pair does it on its own; the dealings themselves
+//! cost the same either way, so they are built outside of the timing.
+
+use bicycl::{Mpz, RandGen};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use robust_threshold_ecdsa::cl_setup::SecurityLevel;
+use robust_threshold_ecdsa::utils::*;
+
+fn bench_mta(c: &mut Criterion) {
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let h = G::base_point2();
+
+    let mut group = c.benchmark_group("mta");
+    for n in [8, 32] {
+        let (pp, _) = PubParams::with_level(SecurityLevel::Toy, n, n / 2, &mut rng).unwrap();
+        let dealings: Vec<PvssDealing> = (0..n)
+            .map(|_| PvssDealing::random(&pp, &mut rng, h).0)
+            .collect();
+        let k_pvss = JointPvssResult::new(&pp, dealings);
+
+        let scalars = [Zq::random(), Zq::random()];
+        let pubs = [G::generator() * &scalars[0], G::generator() * &scalars[1]];
+        let [(gamma_dealing, gamma_masks), (x_dealing, x_masks)] =
+            MtaDealing::new_dual(&pp, &k_pvss, [&scalars[0], &scalars[1]], &mut rng, h);
+        let pairs = [(&gamma_dealing, &gamma_masks), (&x_dealing, &x_masks)];
+
+        group.bench_with_input(BenchmarkId::new("separate", n), &n, |b, _| {
+            b.iter(|| {
+                for (i, (dealing, masks)) in pairs.iter().enumerate() {
+                    let proof = MtaNizk::prove_with_aux(
+                        &pp,
+                        &k_pvss,
+                        dealing,
+                        h,
+                        &mut rng,
+                        &scalars[i],
+                        masks,
+                        b"bench",
+                    )
+                    .unwrap();
+                    assert!(proof.verify_with_aux(&pp, &k_pvss, dealing, h, &pubs[i], b"bench"));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("dual", n), &n, |b, _| {
+            b.iter(|| {
+                let dealings = [&gamma_dealing, &x_dealing];
+                let proof = DualMtaNizk::prove(
+                    &pp,
+                    &k_pvss,
+                    dealings,
+                    h,
+                    &mut rng,
+                    [&scalars[0], &scalars[1]],
+                    [&gamma_masks, &x_masks],
+                    b"bench",
+                )
+                .unwrap();
+                assert!(proof.verify(&pp, &k_pvss, dealings, h, [&pubs[0], &pubs[1]], b"bench"));
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_mta);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+//! Evaluating a degree-32 polynomial over the class group at a 128-bit challenge, as the
+//! NIZKs do: by Horner's rule, against one simultaneous exponentiation by the powers of
+//! the challenge, which are computed in each iteration too.
+
+use bicycl::{Mpz, RandGen};
+use criterion::{criterion_group, criterion_main, Criterion};
+use curv::{arithmetic::Samplable, BigInt};
+use robust_threshold_ecdsa::cl_setup::SecurityLevel;
+use robust_threshold_ecdsa::utils::*;
+
+fn bench_qf_polynomial_eval(c: &mut Criterion) {
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let (pp, _) = PubParams::with_level(SecurityLevel::Toy, 2, 2, &mut rng).unwrap();
+
+    let poly = QFPolynomial {
+        coeffs: (0..=32)
+            .map(|_| {
+                pp.cl
+                    .power_of_h(&rng.random_mpz(&pp.cl.encrypt_randomness_bound()))
+            })
+            .collect(),
+    };
+    // challenges in the NIZKs are 128 bits long
+    let x = Zq::from_bigint(&BigInt::sample(128));
+
+    let mut group = c.benchmark_group("qf_polynomial_eval");
+    group.bench_function("horner", |b| b.iter(|| poly.eval(&pp.cl, &x)));
+    group.bench_function("simultaneous", |b| {
+        b.iter(|| {
+            let powers = QFPolynomial::powers(&x, poly.coeffs.len());
+            poly.eval_with_powers(&pp.cl, &powers)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_qf_polynomial_eval);
+criterion_main!(benches);
@@ -24,12 +24,39 @@ use crate::ffi::BICYCL;
 use autocxx::{c_long, c_ulong};
 use cxx::{CxxString, let_cxx_string};
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use serde::de::Error as _;
 
 use curv::{
     arithmetic::{BasicOps, Converter, Samplable},
     elliptic::curves::{Point, Scalar, Secp256k1},
     BigInt,
 };
+
+/// Process-wide counts of the class group operations done through this wrapper. They
+/// only move when the `metrics` feature is on; work BICYCL does internally, as in
+/// `CL_HSMqk::encrypt`, is not counted.
+pub mod counters {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// `QFI::exp` and `PublicKey::exponentiation`.
+    pub static EXP: AtomicU64 = AtomicU64::new(0);
+    pub static COMPOSE: AtomicU64 = AtomicU64::new(0);
+    pub static POWER_OF_H: AtomicU64 = AtomicU64::new(0);
+    pub static POWER_OF_F: AtomicU64 = AtomicU64::new(0);
+
+    #[inline]
+    pub(crate) fn bump(counter: &AtomicU64) {
+        #[cfg(feature = "metrics")]
+        counter.fetch_add(1, Ordering::Relaxed);
+        #[cfg(not(feature = "metrics"))]
+        let _ = counter;
+    }
+
+    pub fn read(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
+
 pub struct Mpz {
     mpz: Pin<Box<BICYCL::Mpz>>,
 }
@@ -192,6 +219,11 @@ impl Mpz {
         BICYCL::Mpz::new8(&*cxx_vec, cxx_vec.len() * 8).within_box()
     }
 
+    /// The sign of the integer: -1, 0 or 1.
+    pub fn sign(&self) -> i32 {
+        self.mpz.sgn().0
+    }
+
     pub fn pow(&self, exponent: u64) -> Self {
         let mut res = BICYCL::Mpz::new().within_box();
         BICYCL::Mpz::pow(res.as_mut(), &*self.mpz, c_ulong::from(exponent));
@@ -294,6 +326,7 @@ impl QFI {
     }
 
     pub fn compose(&self, c: &CL_HSMqk, qf2: &QFI) -> Self {
+        counters::bump(&counters::COMPOSE);
         let mut result = BICYCL::QFI::new().within_box();
         c.c.Cl_G().nucomp(result.as_mut(), &*self.qfi, &*qf2.qfi);
         QFI {
@@ -302,6 +335,7 @@ impl QFI {
     }
 
     pub fn exp(&self, c: &CL_HSMqk, n: &Mpz) -> Self {
+        counters::bump(&counters::EXP);
         let mut result = BICYCL::QFI::new().within_box();
         c.c.Cl_G().nupow(result.as_mut(), &*self.qfi, &*n.mpz);
         QFI {
@@ -309,6 +343,11 @@ impl QFI {
         }
     }
 
+    /// b^2 - 4ac, the discriminant of the class group the form belongs to.
+    pub fn discriminant(&self) -> Mpz {
+        self.b() * self.b() + -(Mpz::from(4i64) * self.a() * self.c())
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut a_vec = self.a().to_bytes();
         let b_vec = self.b().to_bytes();
@@ -380,6 +419,15 @@ impl<'de> Deserialize<'de> for QFI {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
         let se_qfi = SerializableQFI::deserialize(deserializer)?;
 
+        // BICYCL throws on forms that are not positive definite, which would abort
+        // the process, so reject them here: a > 0 and b^2 - 4ac < 0
+        let a = BigInt::from_bytes(&se_qfi.a);
+        let b = BigInt::from_bytes(&se_qfi.b);
+        let c = BigInt::from_bytes(&se_qfi.c);
+        if a == BigInt::from(0) || &b * &b >= BigInt::from(4) * &a * &c {
+            return Err(D::Error::custom("not a positive definite quadratic form"));
+        }
+
         let mpz_a = Mpz::bicycl_mpz_with_vec(&se_qfi.a);
         let mpz_b = Mpz::bicycl_mpz_with_vec(&se_qfi.b);
         let mpz_c = Mpz::bicycl_mpz_with_vec(&se_qfi.c);
@@ -394,6 +442,15 @@ pub struct CL_HSMqk {
     c: Pin<Box<BICYCL::CL_HSMqk>>,
 }
 
+// SAFETY: every method taking `&self` reaches the C++ object through a shared
+// reference only, so autocxx binds it to a const member function of BICYCL, and those
+// keep no mutable state or caches in the object: they read its integers and forms and
+// write their results into fresh objects. Randomness comes from a `&mut RandGen` the
+// caller owns, which is why `RandGen` stays `!Sync` and the parallel nonce dealings
+// each seed their own. GMP allows concurrent reads of the same integer.
+unsafe impl Sync for CL_HSMqk {
+}
+
 impl Clone for CL_HSMqk {
     fn clone(&self) -> Self {
         CL_HSMqk {
@@ -463,11 +520,22 @@ impl CL_HSMqk {
         }
     }
 
+    pub fn p(&self) -> Mpz {
+        Mpz {
+            mpz: BICYCL::Mpz::copy_from(self.c.p()).within_box(),
+        }
+    }
+
+    pub fn k(&self) -> usize {
+        self.c.k()
+    }
+
     pub fn one(&self) -> QFI {
         self.power_of_h(&Mpz::from(0u64))
     }
     
     pub fn power_of_h(&self, e: &Mpz) -> QFI {
+        counters::bump(&counters::POWER_OF_H);
         let mut result = BICYCL::QFI::new().within_box();
         self.c.power_of_h(result.as_mut(), &*e.mpz);
         QFI {
@@ -476,6 +544,7 @@ impl CL_HSMqk {
     }
 
     pub fn power_of_f(&self, m: &Mpz) -> QFI {
+        counters::bump(&counters::POWER_OF_F);
         QFI {
             qfi: self.c.power_of_f(&*m.mpz).within_box(),
         }
@@ -536,6 +605,11 @@ pub struct PublicKey {
     pk: Pin<Box<BICYCL::CL_HSMqk_PublicKey>>,
 }
 
+// SAFETY: as for `CL_HSMqk`, `&self` methods only call const member functions, which
+// read the key and its precomputed powers and write into fresh objects.
+unsafe impl Sync for PublicKey {
+}
+
 impl Clone for PublicKey {
     fn clone(&self) -> Self {
         PublicKey {
@@ -562,6 +636,7 @@ impl PublicKey {
     }
 
     pub fn exponentiation(&self, c: &CL_HSMqk, n: &Mpz) -> QFI {
+        counters::bump(&counters::EXP);
         let mut r = BICYCL::QFI::new().within_box();
         self.pk.exponentiation(&*c.c, r.as_mut(), &*n.mpz);
         QFI {
@@ -0,0 +1,24 @@
+#![no_main]
+
+mod fixture;
+
+use fixture::FIXTURE;
+use libfuzzer_sys::fuzz_target;
+use robust_threshold_ecdsa::codec::decode;
+use robust_threshold_ecdsa::spdz::DkgMsg;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(msg) = decode::<DkgMsg>(data) {
+        FIXTURE.with(|f| match msg {
+            DkgMsg::Pvss(msg) => {
+                let _ = msg.verify(&f.pp, f.h);
+            }
+            DkgMsg::PowOpen(msg) => {
+                msg.verify(f.h, &f.k_pvss.curve_mac(1));
+            }
+            DkgMsg::KeyConfirm(msg) => {
+                msg.verify(&[0; 32], f.h, &f.k_pvss.curve_mac(1), &f.point);
+            }
+        });
+    }
+});
@@ -0,0 +1,51 @@
+//! Public state a receiver checks incoming messages against. Building it takes a class
+//! group setup, so it is built once per fuzzing process.
+
+use bicycl::{CL_HSMqk, Mpz, RandGen};
+use robust_threshold_ecdsa::cl_setup::{ClFromLevel, SecurityLevel};
+use robust_threshold_ecdsa::utils::*;
+
+pub struct Fixture {
+    pub pp: PubParams,
+    pub h: &'static G,
+    pub k_pvss: JointPvssResult,
+    pub gamma_pvss: JointPvssResult,
+    pub point: G,
+    pub mac: G,
+    pub m: Zq,
+    pub r: Zq,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(1u64));
+        let cl = CL_HSMqk::from_level(SecurityLevel::Toy, &mut rng);
+        let (pp, _) = PubParams::with_cl(cl, 3, 2, &mut rng).unwrap();
+        let h = G::base_point2();
+
+        let mut joint = || {
+            let dealings = (0..pp.t)
+                .map(|_| PvssDealing::random(&pp, &mut rng, h).0)
+                .collect();
+            JointPvssResult::new(&pp, dealings)
+        };
+        let k_pvss = joint();
+        let gamma_pvss = joint();
+
+        Fixture {
+            pp,
+            h,
+            k_pvss,
+            gamma_pvss,
+            point: G::generator() * Zq::random(),
+            mac: h * Zq::random(),
+            m: Zq::random(),
+            r: Zq::random(),
+        }
+    }
+}
+
+thread_local! {
+    pub static FIXTURE: Fixture = Fixture::new();
+}
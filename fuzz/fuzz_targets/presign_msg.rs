@@ -0,0 +1,36 @@
+#![no_main]
+
+mod fixture;
+
+use fixture::FIXTURE;
+use libfuzzer_sys::fuzz_target;
+use robust_threshold_ecdsa::codec::decode;
+use robust_threshold_ecdsa::presign::*;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(msg) = decode::<PresignMsg>(data) {
+        FIXTURE.with(|f| {
+            let _ = match msg {
+                PresignMsg::NonceGen(msg) => msg.verify(&NonceGenContext {
+                    pp: &f.pp,
+                    h: f.h,
+                    session: &PresignSession::default(),
+                }),
+                PresignMsg::MtAwc(msg) => msg.verify(&MtAwcContext {
+                    pp: &f.pp,
+                    h: f.h,
+                    session: &PresignSession::default(),
+                    k_pvss: &f.k_pvss,
+                    gamma_pvss: &f.gamma_pvss,
+                    sender: 1,
+                    x_pub: &f.point,
+                }),
+                PresignMsg::PreSignFinal(msg) => msg.verify(&PreSignFinalContext {
+                    h: f.h,
+                    delta_mac: &f.mac,
+                    Gamma: &f.point,
+                }),
+            };
+        });
+    }
+});
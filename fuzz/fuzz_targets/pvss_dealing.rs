@@ -0,0 +1,16 @@
+#![no_main]
+
+mod fixture;
+
+use fixture::FIXTURE;
+use libfuzzer_sys::fuzz_target;
+use robust_threshold_ecdsa::codec::decode;
+use robust_threshold_ecdsa::utils::{PvssDealing, PvssNizk};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((dealing, proof)) = decode::<(PvssDealing, PvssNizk)>(data) {
+        FIXTURE.with(|f| {
+            let _ = proof.verify_detailed_with_aux(&dealing, &f.pp, f.h, &[]);
+        });
+    }
+});
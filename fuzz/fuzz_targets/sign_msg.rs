@@ -0,0 +1,23 @@
+#![no_main]
+
+mod fixture;
+
+use fixture::FIXTURE;
+use libfuzzer_sys::fuzz_target;
+use robust_threshold_ecdsa::codec::decode;
+use robust_threshold_ecdsa::presign::{OnlineSignContext, OnlineSignMsg};
+use robust_threshold_ecdsa::spdz::SignMsg;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(SignMsg::OnlineSign(msg)) = decode::<SignMsg>(data) {
+        FIXTURE.with(|f| {
+            let _ = msg.verify(&OnlineSignContext {
+                h: f.h,
+                k_mac: &f.mac,
+                chi_mac: &f.mac,
+                m: &f.m,
+                r: &f.r,
+            });
+        });
+    }
+});
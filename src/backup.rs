@@ -0,0 +1,190 @@
+//! Password-protected backups of a party's long-term secrets: its CL secret key and the
+//! output of a DKG. The key is stretched with Argon2id and the backup sealed with
+//! ChaCha20-Poly1305, so a wrong password or a corrupted file fails authentication
+//! instead of yielding wrong secrets.
+
+use argon2::Argon2;
+use bicycl::{CL_HSMqk, Mpz, SecretKey};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::codec::{decode, encode};
+use crate::spdz::DkgOutput;
+use crate::utils::Id;
+
+/// Leading bytes of a backup, which are authenticated along with the contents.
+const BACKUP_MAGIC: &[u8; 4] = b"RTEB";
+const BACKUP_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = BACKUP_MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("not a key backup, or one of an unknown version")]
+    Malformed,
+    #[error("the password is wrong, or the backup was altered")]
+    Authentication,
+}
+
+/// What a backup holds once opened.
+#[derive(Clone, Serialize, Deserialize)]
+struct BackupContents {
+    my_id: Id,
+    t: Id,
+    n: Id,
+    cl_sk: Vec<u8>,
+    key: DkgOutput,
+}
+
+/// A backup opened by `DkgOutput::import_encrypted`.
+pub struct RestoredBackup {
+    pub key: DkgOutput,
+    pub my_id: Id,
+    pub t: Id,
+    pub n: Id,
+    pub cl_sk: SecretKey,
+}
+
+fn derive_key(password: &[u8], salt: &[u8]) -> Zeroizing<Key> {
+    let mut key = Zeroizing::new(Key::default());
+    Argon2::default()
+        .hash_password_into(password, salt, &mut key)
+        .expect("the salt and key lengths suit Argon2");
+    key
+}
+
+impl DkgOutput {
+    /// Seals this output together with the party's id, the committee's threshold and
+    /// size, and its CL secret key under `password`.
+    pub fn export_encrypted(
+        &self,
+        my_id: Id,
+        t: Id,
+        n: Id,
+        cl_sk: &SecretKey,
+        password: &[u8],
+    ) -> Vec<u8> {
+        let mut header = [0u8; HEADER_LEN];
+        header[..4].copy_from_slice(BACKUP_MAGIC);
+        header[4] = BACKUP_VERSION;
+        getrandom::getrandom(&mut header[5..]).expect("the platform provides no randomness");
+        let (salt, nonce) = header[5..].split_at(SALT_LEN);
+
+        let contents = BackupContents {
+            my_id,
+            t,
+            n,
+            cl_sk: cl_sk.mpz().to_bytes(),
+            key: self.clone(),
+        };
+        let plaintext = Zeroizing::new(encode(&contents));
+        let sealed = ChaCha20Poly1305::new(&derive_key(password, salt))
+            .encrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: &header,
+                },
+            )
+            .expect("the backup fits into a single message");
+
+        [&header[..], &sealed].concat()
+    }
+
+    /// Opens a backup made by `export_encrypted`, restoring the CL secret key in `cl`.
+    pub fn import_encrypted(
+        backup: &[u8],
+        password: &[u8],
+        cl: &CL_HSMqk,
+    ) -> Result<RestoredBackup, BackupError> {
+        if backup.len() < HEADER_LEN || &backup[..4] != BACKUP_MAGIC || backup[4] != BACKUP_VERSION
+        {
+            return Err(BackupError::Malformed);
+        }
+        let (header, sealed) = backup.split_at(HEADER_LEN);
+        let (salt, nonce) = header[5..].split_at(SALT_LEN);
+
+        let plaintext = ChaCha20Poly1305::new(&derive_key(password, salt))
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: header,
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| BackupError::Authentication)?;
+        let contents: BackupContents = decode(&plaintext).map_err(|_| BackupError::Malformed)?;
+
+        let cl_sk = SecretKey::from_mpz(cl, &Mpz::from_bytes(&contents.cl_sk));
+        Ok(RestoredBackup {
+            key: contents.key,
+            my_id: contents.my_id,
+            t: contents.t,
+            n: contents.n,
+            cl_sk,
+        })
+    }
+}
+
+#[test]
+pub fn test_backup_round_trip() {
+    use crate::spdz::simulate_pp;
+    use crate::test_utils::simulation::dkg_locally;
+    use crate::utils::PartyId;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let keys = dkg_locally(&pp, &secret_keys);
+
+    let key = &keys[&PartyId::from_share_index(2).to_wire_index()];
+    let backup = key.export_encrypted(2, pp.t, pp.n, &secret_keys[&2], b"correct horse");
+    let restored = DkgOutput::import_encrypted(&backup, b"correct horse", &pp.cl).unwrap();
+    assert_eq!(&restored.key, key);
+    assert_eq!((restored.my_id, restored.t, restored.n), (2, pp.t, pp.n));
+    assert!(restored.cl_sk.mpz() == secret_keys[&2].mpz());
+
+    // a fresh salt and nonce each time
+    assert_ne!(
+        key.export_encrypted(2, pp.t, pp.n, &secret_keys[&2], b"correct horse"),
+        backup
+    );
+}
+
+#[test]
+pub fn test_backup_wrong_password() {
+    use crate::spdz::{simulate_pp, ThresholdPubKey};
+    use crate::utils::{Zq, G};
+    use std::collections::BTreeMap;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let key = DkgOutput {
+        share: Zq::random(),
+        tpk: ThresholdPubKey {
+            pk: G::generator().to_point(),
+            pub_shares: BTreeMap::new(),
+        },
+        qualified: vec![1, 2],
+        transcript: None,
+    };
+    let backup = key.export_encrypted(1, pp.t, pp.n, &secret_keys[&1], b"correct horse");
+
+    assert!(matches!(
+        DkgOutput::import_encrypted(&backup, b"battery staple", &pp.cl),
+        Err(BackupError::Authentication)
+    ));
+
+    let mut tampered = backup.clone();
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        DkgOutput::import_encrypted(&tampered, b"correct horse", &pp.cl),
+        Err(BackupError::Authentication)
+    ));
+    assert!(matches!(
+        DkgOutput::import_encrypted(&backup[..HEADER_LEN - 1], b"correct horse", &pp.cl),
+        Err(BackupError::Malformed)
+    ));
+}
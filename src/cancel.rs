@@ -0,0 +1,322 @@
+//! Cancelling a session that is under way. A party that gives up tells its peers with a
+//! best-effort abort message, so that they fail promptly instead of waiting for a round
+//! that will never complete. Since any peer can send one, a party heeds aborts only
+//! once a quorum of its peers sent them; its own controller stops it at once.
+//!
+//! Dropping the future of a run is safe as well: the state machine is owned by it, and
+//! the secrets a state holds are scalars that wipe their memory when dropped. Only the
+//! peers are left waiting then, which is what the abort message is for.
+
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+
+use futures::future::poll_fn;
+use round_based::{Outgoing, PartyIndex, ProtocolMessage};
+use serde::{Deserialize, Serialize};
+
+use crate::spdz::{Error, StateError, StateMachine};
+use crate::transport::{drive, Hooks, Transport};
+
+/// Shared between the code running a session and whoever may want to stop it.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<Mutex<TokenState>>);
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: bool,
+    wakers: Vec<Waker>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.cancelled = true;
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.lock().unwrap().cancelled
+    }
+
+    /// Completes once the token is cancelled.
+    pub fn cancelled(&self) -> impl Future<Output = ()> + '_ {
+        poll_fn(move |cx| {
+            let mut state = self.0.lock().unwrap();
+            if state.cancelled {
+                return Poll::Ready(());
+            }
+            if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                state.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AbortableMsg<M> {
+    Payload(M),
+    /// The sender gave up on `session_id` while in `round`.
+    Abort {
+        session_id: Vec<u8>,
+        round: u16,
+    },
+}
+
+impl<M: ProtocolMessage> ProtocolMessage for AbortableMsg<M> {
+    fn round(&self) -> u16 {
+        match self {
+            AbortableMsg::Payload(msg) => msg.round(),
+            // delivered whatever round the receiver is in
+            AbortableMsg::Abort { .. } => 0,
+        }
+    }
+}
+
+/// Runs a protocol whose peers may abort it. Once `quorum` distinct peers sent an abort
+/// for this session, the run fails with `PeerAborted`; aborts for another session are
+/// ignored. The senders are those the transport vouches for, so a quorum of one suits
+/// only a transport that authenticates them. For a robust protocol, n - t + 1 aborts
+/// leave fewer than t parties to go on with, and fewer should not stop it.
+pub struct Abortable<S: StateMachine> {
+    inner: S,
+    session_id: Vec<u8>,
+    quorum: usize,
+    aborted_by: BTreeSet<PartyIndex>,
+    round: u16, // the last round we sent a message in
+    outbox: Vec<Outgoing<AbortableMsg<S::Msg>>>,
+}
+
+impl<S: StateMachine> Abortable<S> {
+    pub fn new(inner: S, session_id: &[u8], quorum: usize) -> Self {
+        Abortable {
+            inner,
+            session_id: session_id.to_vec(),
+            quorum,
+            aborted_by: BTreeSet::new(),
+            round: 0,
+            outbox: vec![],
+        }
+    }
+
+    /// The message telling the peers that we give up.
+    pub fn abort_msg(&self) -> AbortableMsg<S::Msg> {
+        AbortableMsg::Abort {
+            session_id: self.session_id.clone(),
+            round: self.round,
+        }
+    }
+
+    /// Gives up on the session: the abort message is the next one to send.
+    pub fn cancel(&mut self) {
+        let msg = self.abort_msg();
+        self.outbox.push(Outgoing::broadcast(msg));
+    }
+}
+
+impl<S> StateMachine for Abortable<S>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage,
+{
+    type Msg = AbortableMsg<S::Msg>;
+    type Output = S::Output;
+
+    fn handle_message(&mut self, from: PartyIndex, msg: Self::Msg) -> Result<(), StateError> {
+        match msg {
+            AbortableMsg::Payload(msg) => self.inner.handle_message(from, msg),
+            AbortableMsg::Abort { session_id, .. } if session_id == self.session_id => {
+                self.aborted_by.insert(from);
+                if self.aborted_by.len() < self.quorum {
+                    return Ok(());
+                }
+                Err(StateError::PeerAborted { party: from })
+            }
+            AbortableMsg::Abort { .. } => Ok(()),
+        }
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<Self::Msg>> {
+        let outgoing = self.inner.wants_to_send();
+        for Outgoing { msg, .. } in &outgoing {
+            self.round = self.round.max(msg.round());
+        }
+        let payloads = outgoing
+            .into_iter()
+            .map(|Outgoing { recipient, msg }| Outgoing {
+                recipient,
+                msg: AbortableMsg::Payload(msg),
+            });
+        payloads.chain(self.outbox.drain(..)).collect()
+    }
+
+    fn try_finish(self) -> Result<S::Output, Self> {
+        let Abortable {
+            inner,
+            session_id,
+            quorum,
+            aborted_by,
+            round,
+            outbox,
+        } = self;
+        inner.try_finish().map_err(|inner| Abortable {
+            inner,
+            session_id,
+            quorum,
+            aborted_by,
+            round,
+            outbox,
+        })
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        self.inner.on_timeout()
+    }
+
+    fn on_version(&mut self, version: u16) {
+        self.inner.on_version(version)
+    }
+}
+
+/// Like `run_over`, but gives up once `token` is cancelled, the next time it waits for
+/// messages: the peers are sent an abort for `session_id`, and the run fails with
+/// `Cancelled`. Aborts from `quorum` peers fail the run with `PeerAborted`, see
+/// `Abortable`.
+pub async fn run_cancellable<S, T>(
+    transport: &mut T,
+    state: S,
+    session_id: &[u8],
+    quorum: usize,
+    token: &CancellationToken,
+) -> Result<S::Output, Error<T::ReceiveError, T::SendError>>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage,
+    T: Transport<AbortableMsg<S::Msg>>,
+{
+    let state = Abortable::new(state, session_id, quorum);
+    drive(transport, state, &mut Cancellation(token)).await
+}
+
+/// The hooks of `run_cancellable`, whose timer is the token being cancelled. The abort
+/// goes out on a best-effort basis: if it does not get through, the peers are no worse
+/// off than without it.
+struct Cancellation<'a>(&'a CancellationToken);
+
+impl<'a, S: StateMachine> Hooks<Abortable<S>> for Cancellation<'a> {
+    type Timer = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+    fn start_timer(&mut self) -> Option<Self::Timer> {
+        Some(Box::pin(self.0.cancelled()))
+    }
+
+    fn on_timer(&mut self, state: &mut Abortable<S>) -> Result<bool, StateError> {
+        state.cancel();
+        Err(StateError::Cancelled)
+    }
+}
+
+/// Stands in for a party losing its connection in the middle of `round`: its messages
+/// of that round never leave, and the session is cancelled.
+#[cfg(test)]
+struct CutOff<'a, T> {
+    inner: T,
+    round: u16,
+    token: &'a CancellationToken,
+}
+
+#[cfg(test)]
+impl<'a, M, T: Transport<M>> Transport<M> for CutOff<'a, T> {
+    type SendError = T::SendError;
+    type ReceiveError = T::ReceiveError;
+
+    async fn broadcast(&mut self, round: u16, msg: M) -> Result<(), Self::SendError> {
+        if self.token.is_cancelled() || round < self.round {
+            return self.inner.broadcast(round, msg).await;
+        }
+        self.token.cancel();
+        Ok(())
+    }
+
+    async fn send_to(
+        &mut self,
+        round: u16,
+        recipient: PartyIndex,
+        msg: M,
+    ) -> Result<(), Self::SendError> {
+        if self.token.is_cancelled() || round < self.round {
+            return self.inner.send_to(round, recipient, msg).await;
+        }
+        self.token.cancel();
+        Ok(())
+    }
+
+    async fn receive(&mut self, round: u16) -> Result<Vec<(usize, M)>, Self::ReceiveError> {
+        self.inner.receive(round).await
+    }
+}
+
+#[test]
+pub fn test_peer_abort() {
+    use crate::spdz::{simulate_pp, DkgState};
+    use crate::transport::MemoryTransport;
+    use crate::utils::G;
+    use futures::executor::block_on;
+    use futures::future::join_all;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let (pp, secret_keys) = (&pp, &secret_keys);
+    let h = G::base_point2();
+    let session_id = b"cancelled in round 2";
+    let tokens: Vec<CancellationToken> = (0..pp.n).map(|_| CancellationToken::new()).collect();
+
+    // party 3 is cut off while sending its opening, the second DKG round
+    let outcomes = block_on(join_all(
+        MemoryTransport::committee(pp.n.into())
+            .into_iter()
+            .zip(1..=pp.n)
+            .zip(&tokens)
+            .map(|((transport, i), token)| async move {
+                let state = DkgState::new(i, pp, h, &secret_keys[&i], false);
+                let mut transport = CutOff {
+                    inner: transport,
+                    round: if i == 3 { 1 } else { u16::MAX },
+                    token,
+                };
+                run_cancellable(&mut transport, state, session_id, 1, token).await
+            }),
+    ));
+
+    assert!(matches!(outcomes[2], Err(Error::Cancelled)));
+    for outcome in &outcomes[..2] {
+        assert!(matches!(outcome, Err(Error::PeerAborted { party: 2 })));
+    }
+    assert!(tokens[2].is_cancelled() && !tokens[0].is_cancelled());
+
+    // an abort for another session goes unheeded, and one peer short of the quorum
+    // cannot stop the session, however often it aborts
+    let dkg = DkgState::new(1, pp, h, &secret_keys[&1], false);
+    let mut state = Abortable::new(dkg, session_id, 2);
+    let other = AbortableMsg::Abort {
+        session_id: b"another session".to_vec(),
+        round: 1,
+    };
+    assert!(state.handle_message(1, other).is_ok());
+    let abort = state.abort_msg();
+    for _ in 0..2 {
+        assert!(state.handle_message(1, abort.clone()).is_ok());
+    }
+    assert!(matches!(
+        state.handle_message(2, abort),
+        Err(StateError::PeerAborted { party: 2 })
+    ));
+}
@@ -0,0 +1,120 @@
+//! The class group operations this crate relies on, gathered in one trait. BICYCL
+//! provides them today; another backend, such as one without a native GMP dependency,
+//! only has to implement `ClBackend`.
+
+use bicycl::{CL_HSMqk, Mpz, QFI};
+
+pub trait ClBackend {
+    /// A class group element.
+    type Form: Clone;
+
+    /// The neutral element.
+    fn one(&self) -> Self::Form;
+    /// h^e, for the generator h of the subgroup of unknown order.
+    fn power_of_h(&self, e: &Mpz) -> Self::Form;
+    /// f^m, for the generator f of the subgroup where discrete logs are easy.
+    fn power_of_f(&self, m: &Mpz) -> Self::Form;
+    fn compose(&self, a: &Self::Form, b: &Self::Form) -> Self::Form;
+    fn exp(&self, a: &Self::Form, e: &Mpz) -> Self::Form;
+    fn encrypt_randomness_bound(&self) -> Mpz;
+    fn discriminant(&self) -> Mpz;
+}
+
+impl ClBackend for CL_HSMqk {
+    type Form = QFI;
+
+    fn one(&self) -> QFI {
+        CL_HSMqk::one(self)
+    }
+
+    fn power_of_h(&self, e: &Mpz) -> QFI {
+        CL_HSMqk::power_of_h(self, e)
+    }
+
+    fn power_of_f(&self, m: &Mpz) -> QFI {
+        CL_HSMqk::power_of_f(self, m)
+    }
+
+    fn compose(&self, a: &QFI, b: &QFI) -> QFI {
+        a.compose(self, b)
+    }
+
+    fn exp(&self, a: &QFI, e: &Mpz) -> QFI {
+        a.exp(self, e)
+    }
+
+    fn encrypt_randomness_bound(&self) -> Mpz {
+        CL_HSMqk::encrypt_randomness_bound(self)
+    }
+
+    fn discriminant(&self) -> Mpz {
+        CL_HSMqk::discriminant(self)
+    }
+}
+
+/// The additive group of scalars posing as a class group, with h = 1 and f = 2.
+#[cfg(test)]
+pub struct MockBackend;
+
+#[cfg(test)]
+impl MockBackend {
+    fn scalar(e: &Mpz) -> crate::utils::Zq {
+        crate::utils::mpz_to_scalar(e)
+    }
+}
+
+#[cfg(test)]
+impl ClBackend for MockBackend {
+    type Form = crate::utils::Zq;
+
+    fn one(&self) -> Self::Form {
+        Self::Form::zero()
+    }
+
+    fn power_of_h(&self, e: &Mpz) -> Self::Form {
+        Self::scalar(e)
+    }
+
+    fn power_of_f(&self, m: &Mpz) -> Self::Form {
+        Self::scalar(m) * Self::Form::from(2)
+    }
+
+    fn compose(&self, a: &Self::Form, b: &Self::Form) -> Self::Form {
+        a + b
+    }
+
+    fn exp(&self, a: &Self::Form, e: &Mpz) -> Self::Form {
+        a * Self::scalar(e)
+    }
+
+    fn encrypt_randomness_bound(&self) -> Mpz {
+        Mpz::from(1u64 << 40)
+    }
+
+    fn discriminant(&self) -> Mpz {
+        Mpz::from(-23i64)
+    }
+}
+
+#[test]
+pub fn test_qf_polynomial_eval_mock_backend() {
+    use crate::utils::{QFPolynomial, Zq};
+
+    for degree in [0, 1, 2, 5] {
+        let coeffs: Vec<Zq> = (0..=degree).map(|_| Zq::random()).collect();
+        let poly = QFPolynomial {
+            coeffs: coeffs.clone(),
+        };
+        let x = Zq::random();
+
+        // in the mock group, evaluating the polynomial is plain scalar arithmetic
+        let expected = coeffs
+            .iter()
+            .rev()
+            .fold(Zq::zero(), |acc, coeff| acc * &x + coeff);
+        assert_eq!(poly.eval(&MockBackend, &x), expected);
+
+        let powers = QFPolynomial::powers(&x, poly.coeffs.len());
+        assert_eq!(poly.eval_with_powers(&MockBackend, &powers), expected);
+    }
+}
@@ -0,0 +1,297 @@
+use std::{fs, io, path::Path};
+
+use bicycl::{CL_HSMqk, Mpz, RandGen};
+use curv::{
+    arithmetic::{BitManipulation, Converter, Modulo, Primes},
+    BigInt,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::utils::{fresh_rng, Zq};
+
+/// Bump when the file layout changes; older files are then rejected rather than misread.
+const FORMAT_VERSION: u32 = 1;
+
+/// Security level of the class group, fixing the bit size of the fundamental discriminant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SecurityLevel {
+    /// 1348-bit discriminant, on par with 2048-bit RSA
+    Bits112,
+    /// 1827-bit discriminant, on par with 3072-bit RSA and with the curve itself
+    Bits128,
+    /// 600-bit discriminant, for tests only: it offers no meaningful security
+    #[cfg(any(test, feature = "danger-toy-params"))]
+    Toy,
+}
+
+impl SecurityLevel {
+    /// Discriminant sizes from the BICYCL parameter table.
+    pub fn discriminant_bits(self) -> usize {
+        match self {
+            SecurityLevel::Bits112 => 1348,
+            SecurityLevel::Bits128 => 1827,
+            #[cfg(any(test, feature = "danger-toy-params"))]
+            SecurityLevel::Toy => 600,
+        }
+    }
+}
+
+/// Builds the class group over the scalar field of the curve at a given security level.
+pub trait ClFromLevel {
+    fn from_level(level: SecurityLevel, rng: &mut RandGen) -> Self;
+}
+
+impl ClFromLevel for CL_HSMqk {
+    fn from_level(level: SecurityLevel, rng: &mut RandGen) -> Self {
+        CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&Zq::group_order().to_bytes()),
+            1,
+            level.discriminant_bits(),
+            rng,
+            &Mpz::from_bytes(&fud_factor().to_bytes()),
+            false,
+        )
+    }
+}
+
+/// Slack on the class number bound for the encryption randomness.
+fn fud_factor() -> BigInt {
+    BigInt::from(1) << 40
+}
+
+#[derive(Debug, Error)]
+pub enum SetupError {
+    #[error("could not access the setup file: {0}")]
+    Io(#[from] io::Error),
+    #[error("the setup file is not well-formed: {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("the setup file has version {got}, expected {FORMAT_VERSION}")]
+    Version { got: u32 },
+    #[error("invalid class group parameters: {0}")]
+    Invalid(&'static str),
+}
+
+/// Public parameters of a CL_HSMqk class group over the scalar field of the curve: the
+/// fundamental discriminant is -q*p, with the conductor q^k. Integers are stored as
+/// big-endian hex so the file does not depend on how `Mpz` is serialized.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClSetup {
+    pub version: u32,
+    pub security: SecurityLevel,
+    pub q: String,
+    pub p: String,
+    pub k: usize,
+    pub fud_factor: String,
+}
+
+impl ClSetup {
+    /// Samples a fresh discriminant, which takes a while at the higher levels.
+    pub fn generate(security: SecurityLevel) -> ClSetup {
+        let mut rng = fresh_rng();
+        let cl = CL_HSMqk::from_level(security, &mut rng);
+
+        ClSetup {
+            version: FORMAT_VERSION,
+            security,
+            q: hex::encode(cl.q().to_bytes()),
+            p: hex::encode(cl.p().to_bytes()),
+            k: cl.k(),
+            fud_factor: hex::encode(fud_factor().to_bytes()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SetupError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Reads a setup file and rejects it unless `verify` passes.
+    pub fn load(path: impl AsRef<Path>) -> Result<ClSetup, SetupError> {
+        let setup: ClSetup = serde_json::from_slice(&fs::read(path)?)?;
+        setup.verify()?;
+        Ok(setup)
+    }
+
+    /// Re-checks what the class group constructor relies on, so that a setup from
+    /// elsewhere is not trusted blindly: q is the group order of the curve, p is a prime
+    /// with -p*q = 1 mod 4 and (q/p) = -1, and the discriminant is as large as the
+    /// security level requires.
+    pub fn verify(&self) -> Result<(), SetupError> {
+        if self.version != FORMAT_VERSION {
+            return Err(SetupError::Version { got: self.version });
+        }
+        let q = Self::parse(&self.q)?;
+        let p = Self::parse(&self.p)?;
+        Self::parse(&self.fud_factor)?;
+
+        if &q != Zq::group_order() {
+            return Err(SetupError::Invalid("q is not the group order of the curve"));
+        }
+        if self.k == 0 {
+            return Err(SetupError::Invalid("k must be positive"));
+        }
+        if !p.is_probable_prime(64) {
+            return Err(SetupError::Invalid("p is not prime"));
+        }
+        let four = BigInt::from(4);
+        if (&p * &q) % &four != BigInt::from(3) {
+            return Err(SetupError::Invalid("-p*q is not 1 mod 4"));
+        }
+        // Euler's criterion, as p is an odd prime
+        let legendre = BigInt::mod_pow(&q, &((&p - BigInt::from(1)) >> 1), &p);
+        if legendre != &p - BigInt::from(1) {
+            return Err(SetupError::Invalid("q is a square mod p"));
+        }
+        if (&p * &q).bit_length() < self.security.discriminant_bits() {
+            return Err(SetupError::Invalid(
+                "the discriminant is too small for the security level",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The class group described by the setup, which must have passed `verify`.
+    pub fn cl(&self) -> CL_HSMqk {
+        let mpz = |field: &str| Mpz::from_bytes(&hex::decode(field).unwrap());
+        CL_HSMqk::new(
+            &mpz(&self.q),
+            self.k,
+            &mpz(&self.p),
+            &mpz(&self.fud_factor),
+            false,
+        )
+    }
+
+    /// Loads the setup at `path`, or generates one and saves it there if there is none.
+    pub fn load_or_generate(
+        path: impl AsRef<Path>,
+        security: SecurityLevel,
+    ) -> Result<ClSetup, SetupError> {
+        match Self::load(&path) {
+            Err(SetupError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                let setup = Self::generate(security);
+                setup.save(&path)?;
+                Ok(setup)
+            }
+            result => result,
+        }
+    }
+
+    fn parse(field: &str) -> Result<BigInt, SetupError> {
+        hex::decode(field)
+            .map(|bytes| BigInt::from_bytes(&bytes))
+            .map_err(|_| SetupError::Invalid("integers must be hex encoded"))
+    }
+}
+
+#[cfg(test)]
+fn temp_setup_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("cl_setup_{}_{}.json", name, std::process::id()))
+}
+
+#[test]
+pub fn test_setup_round_trip() {
+    let setup = ClSetup::generate(SecurityLevel::Toy);
+    setup.verify().unwrap();
+
+    let path = temp_setup_path("round_trip");
+    setup.save(&path).unwrap();
+    let loaded = ClSetup::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded, setup);
+    assert_eq!(loaded.cl().discriminant(), setup.cl().discriminant());
+}
+
+#[test]
+pub fn test_setup_corrupted_file_rejected() {
+    let setup = ClSetup::generate(SecurityLevel::Toy);
+    let path = temp_setup_path("corrupted");
+
+    // p replaced by a multiple of 3
+    let mut p = BigInt::from_bytes(&hex::decode(&setup.p).unwrap());
+    p = &p - (&p % BigInt::from(3));
+    ClSetup {
+        p: hex::encode(p.to_bytes()),
+        ..setup.clone()
+    }
+    .save(&path)
+    .unwrap();
+    assert!(matches!(
+        ClSetup::load(&path),
+        Err(SetupError::Invalid("p is not prime"))
+    ));
+
+    // weaker than it claims
+    ClSetup {
+        security: SecurityLevel::Bits128,
+        ..setup.clone()
+    }
+    .save(&path)
+    .unwrap();
+    assert!(matches!(ClSetup::load(&path), Err(SetupError::Invalid(_))));
+
+    fs::write(&path, b"{\"version\": 1, \"q\": ").unwrap();
+    assert!(matches!(ClSetup::load(&path), Err(SetupError::Format(_))));
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+pub fn test_setup_drives_signing() {
+    use crate::spdz::*;
+    use crate::test_utils::simulation::{dkg_locally, presign_locally};
+    use crate::utils::*;
+
+    let setup = ClSetup::generate(SecurityLevel::Toy);
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let (pp, secret_keys) = PubParams::with_cl(setup.cl(), 3, 2, &mut rng).unwrap();
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let presignatures = presign_locally(&pp, &secret_keys, &keys);
+
+    let msg = b"signed over a stored setup";
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let signatures = run_locally(
+        presignatures
+            .into_iter()
+            .map(|(i, presignature)| {
+                let my_id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                (
+                    i,
+                    SignState::new(my_id, &pp, h, &context, presignature, msg),
+                )
+            })
+            .collect(),
+    );
+
+    assert_eq!(signatures.len(), pp.n as usize);
+    assert!(signatures
+        .values()
+        .all(|outcome| outcome.signature.verify(&context.pk, msg)));
+}
+
+#[test]
+#[ignore = "generating a 112-bit class group takes a while"]
+pub fn test_bits112_pvss_dealing() {
+    use crate::utils::*;
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let (pp, secret_keys) = PubParams::with_level(SecurityLevel::Bits112, 3, 2, &mut rng).unwrap();
+    assert!(pp.cl.discriminant().to_bytes().len() * 8 >= 1348);
+
+    let h = G::base_point2();
+    let (dealing, r, _, shares) = PvssDealing::random(&pp, &mut rng, h);
+    let proof = PvssNizk::prove_with_aux(&pp, &dealing, &r, &shares, &mut rng, h, &[]);
+    assert!(proof.verify_with_aux(&dealing, &pp, h, &[]));
+    assert_eq!(
+        dealing
+            .shares_ciphertext
+            .decrypt(&pp.cl, 2, &secret_keys[&2]),
+        shares[&2]
+    );
+}
@@ -0,0 +1,1015 @@
+//! Wire encoding of protocol messages. Decoding is bounded: a peer claiming a huge
+//! vector length gets an error instead of making the receiver allocate for it.
+
+use std::fmt;
+
+use bincode::Options;
+use curv::elliptic::curves::{Curve, Point};
+use round_based::{MessageDestination, Outgoing, PartyIndex};
+use serde::{
+    de::{self, DeserializeOwned},
+    ser::SerializeTuple,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use thiserror::Error;
+
+use crate::spdz::{DkgMsg, PresignMsg, SignMsg, StateError, StateMachine};
+use crate::transcript::Phase;
+use crate::utils::{Id, ProtocolLimits, Zq, G};
+
+/// Upper bound on an encoded message. The largest honest message, an `MtAwcMsg`, carries
+/// two MtA dealings of n class group elements each and stays far below this for any
+/// committee the protocol is practical for.
+pub const MAX_MESSAGE_LEN: u64 = 1 << 24;
+
+/// A curve point on the wire: its compressed encoding as a fixed-length tuple, so that
+/// no length prefix is needed, and all zeros for the point at infinity. Decoding
+/// rejects anything that is not on the curve, and any encoding of a point other than
+/// the one `to_bytes(true)` gives, such as an x-coordinate at or above the field prime.
+struct CompressedRef<'a, E: Curve>(&'a Point<E>);
+
+struct Compressed<E: Curve>(Point<E>);
+
+fn compressed_len<E: Curve>() -> usize {
+    Point::<E>::generator().to_point().to_bytes(true).len()
+}
+
+impl<'a, E: Curve> Serialize for CompressedRef<'a, E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes = if self.0.is_zero() {
+            vec![0; compressed_len::<E>()]
+        } else {
+            self.0.to_bytes(true).to_vec()
+        };
+        let mut tuple = serializer.serialize_tuple(bytes.len())?;
+        for byte in &bytes {
+            tuple.serialize_element(byte)?;
+        }
+        tuple.end()
+    }
+}
+
+impl<'de, E: Curve> Deserialize<'de> for Compressed<E> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BytesVisitor(usize);
+
+        impl<'de> de::Visitor<'de> for BytesVisitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "the {} bytes of a compressed curve point", self.0)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+                (0..self.0)
+                    .map(|i| {
+                        seq.next_element()?
+                            .ok_or_else(|| de::Error::invalid_length(i, &self))
+                    })
+                    .collect()
+            }
+        }
+
+        let len = compressed_len::<E>();
+        let bytes = deserializer.deserialize_tuple(len, BytesVisitor(len))?;
+        if bytes.iter().all(|&byte| byte == 0) {
+            return Ok(Compressed(Point::zero()));
+        }
+        let point = Point::<E>::from_bytes(&bytes)
+            .map_err(|_| de::Error::custom("not a point on the curve"))?;
+        if point.to_bytes(true)[..] != bytes[..] {
+            return Err(de::Error::custom("not the canonical encoding of a point"));
+        }
+        Ok(Compressed(point))
+    }
+}
+
+/// `#[serde(with)]` support for a compressed point.
+pub(crate) mod compressed_point {
+    use super::*;
+
+    pub fn serialize<E: Curve, S: Serializer>(
+        point: &Point<E>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        CompressedRef(point).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, E: Curve, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Point<E>, D::Error> {
+        Compressed::deserialize(deserializer).map(|point| point.0)
+    }
+}
+
+/// `#[serde(with)]` support for a vector of compressed points.
+pub(crate) mod compressed_points {
+    use super::*;
+
+    pub fn serialize<E: Curve, S: Serializer>(
+        points: &[Point<E>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(points.iter().map(CompressedRef))
+    }
+
+    pub fn deserialize<'de, E: Curve, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Point<E>>, D::Error> {
+        let points: Vec<Compressed<E>> = Deserialize::deserialize(deserializer)?;
+        Ok(points.into_iter().map(|point| point.0).collect())
+    }
+}
+
+/// `#[serde(with)]` support for compressed points keyed by party.
+pub(crate) mod compressed_point_map {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    pub fn serialize<E: Curve, S: Serializer>(
+        points: &BTreeMap<Id, Point<E>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(points.iter().map(|(id, point)| (id, CompressedRef(point))))
+    }
+
+    pub fn deserialize<'de, E: Curve, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<Id, Point<E>>, D::Error> {
+        let points: BTreeMap<Id, Compressed<E>> = Deserialize::deserialize(deserializer)?;
+        Ok(points
+            .into_iter()
+            .map(|(id, point)| (id, point.0))
+            .collect())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("the message exceeds the size limit")]
+    TooLong,
+    #[error("the message is malformed: {0}")]
+    Malformed(bincode::Error),
+}
+
+fn options(limit: u64) -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(limit)
+}
+
+pub fn encode<M: Serialize>(msg: &M) -> Vec<u8> {
+    options(MAX_MESSAGE_LEN)
+        .serialize(msg)
+        .expect("protocol messages serialize within the size limit")
+}
+
+/// The length of `encode(msg)`, without encoding it.
+pub fn encoded_len<M: Serialize>(msg: &M) -> u64 {
+    options(u64::MAX)
+        .serialized_size(msg)
+        .expect("protocol messages serialize")
+}
+
+/// Decodes a message from an untrusted peer. The result still has to pass the
+/// message's own `verify` before it is used.
+pub fn decode<M: DeserializeOwned>(bytes: &[u8]) -> Result<M, DecodeError> {
+    decode_within(bytes, MAX_MESSAGE_LEN)
+}
+
+/// Like `decode`, for a message of at most `max_bytes`.
+pub fn decode_within<M: DeserializeOwned>(bytes: &[u8], max_bytes: u64) -> Result<M, DecodeError> {
+    if bytes.len() as u64 > max_bytes {
+        return Err(DecodeError::TooLong);
+    }
+    options(max_bytes).deserialize(bytes).map_err(|e| match *e {
+        bincode::ErrorKind::SizeLimit => DecodeError::TooLong,
+        _ => DecodeError::Malformed(e),
+    })
+}
+
+/// The encoding of `encode` and `decode`, on the message enums of the protocols
+/// themselves.
+pub trait WireMessage: Serialize + DeserializeOwned {
+    fn to_bincode(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    /// Decodes a message from an untrusted peer, see `decode`.
+    fn from_bincode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        decode(bytes)
+    }
+}
+
+impl WireMessage for DkgMsg {}
+impl WireMessage for PresignMsg {}
+impl WireMessage for SignMsg {}
+
+/// One round of the protocol and the estimated size of the message each party
+/// broadcasts in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoundInfo {
+    pub phase: Phase,
+    pub round: u16,
+    pub message_bytes: usize,
+}
+
+/// The rounds of DKG (without key confirmation), presigning and signing, for capacity
+/// planning. Every message is a broadcast, so a party sends each of its messages to
+/// n - 1 others and receives as many.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolInfo {
+    pub rounds: Vec<RoundInfo>,
+}
+
+/// Estimates the encoded message sizes for t-of-n over the class group `cl`. They follow
+/// the field layout of the messages. Curve points and scalars take the size of an actual
+/// encoding. Class group elements and the proofs' responses vary in length, and count at
+/// the size they typically have for the discriminant and the randomness bound of `cl`.
+pub fn protocol_info(t: Id, n: Id, cl: &bicycl::CL_HSMqk) -> ProtocolInfo {
+    // the fixed-width framing of bincode
+    const TAG: usize = 4;
+    const LEN: usize = 8;
+    const ID: usize = 2;
+    // the digest of the previous round that later presigning and signing messages
+    // start with, and of each dealing in the MtA round
+    const DIGEST: usize = 32;
+    let (t, n) = (t as usize, n as usize);
+
+    let point = encode(&CompressedRef(&G::generator().to_point())).len();
+    let scalar = encode(&-Zq::from(1u64)).len();
+    // the coefficients of a reduced form are about the square root of the discriminant
+    let coeff = (cl.discriminant().to_bytes().len() + 1) / 2;
+    let form = 3 * (LEN + coeff);
+    // z1 = u1 + e * r, with u1 and r below the randomness bound and e below q
+    let response = LEN + cl.encrypt_randomness_bound().to_bytes().len() + 32;
+
+    let ciphertext = form + LEN + n * (ID + form);
+    let pvss = (LEN + t * point + ciphertext) + (2 * scalar + response);
+    let open_power = point + 2 * scalar;
+    let mta = ciphertext + LEN + n * (ID + point);
+    // one proof covers both dealings of a presignature: e, then z1 and z2 for each
+    let mta_proof = scalar + 2 * response + 2 * scalar;
+    // the digests of the t nonce dealings an MtA message responds to, by dealer
+    let dealing_digests = LEN + t * (ID + DIGEST);
+
+    let round = |phase, round, message_bytes| RoundInfo {
+        phase,
+        round,
+        message_bytes: TAG + message_bytes,
+    };
+    ProtocolInfo {
+        rounds: vec![
+            round(Phase::Dkg, 0, pvss),
+            round(Phase::Dkg, 1, open_power),
+            round(Phase::Presign, 0, DIGEST + 2 * pvss),
+            round(
+                Phase::Presign,
+                1,
+                dealing_digests + open_power + 2 * mta + mta_proof,
+            ),
+            round(Phase::Presign, 2, DIGEST + scalar + point),
+            round(Phase::Sign, 0, DIGEST + scalar),
+        ],
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EncodedError {
+    #[error("party {from} sent an undecodable message: {source}")]
+    Decode {
+        from: PartyIndex,
+        source: DecodeError,
+    },
+    #[error(transparent)]
+    State(#[from] StateError),
+}
+
+/// A state machine that takes and hands out encoded messages, for hosts that only
+/// move opaque bytes around, such as a JavaScript transport.
+pub struct Encoded<S> {
+    inner: S,
+    max_bytes: u64,
+}
+
+impl<S> Encoded<S>
+where
+    S: StateMachine,
+    S::Msg: Serialize + DeserializeOwned,
+{
+    pub fn new(inner: S) -> Self {
+        Encoded {
+            inner,
+            max_bytes: MAX_MESSAGE_LEN,
+        }
+    }
+
+    /// Turns away messages longer than the committee's `max_message_bytes` before
+    /// decoding them.
+    pub fn with_limits(mut self, limits: &ProtocolLimits) -> Self {
+        self.max_bytes = limits.max_message_bytes;
+        self
+    }
+
+    pub fn handle_bytes(&mut self, from: PartyIndex, bytes: &[u8]) -> Result<(), EncodedError> {
+        let msg = decode_within(bytes, self.max_bytes)
+            .map_err(|source| EncodedError::Decode { from, source })?;
+        Ok(self.inner.handle_message(from, msg)?)
+    }
+
+    /// Drains the encoded messages produced so far, each with its recipient, or `None`
+    /// for a broadcast.
+    pub fn wants_to_send(&mut self) -> Vec<(Option<PartyIndex>, Vec<u8>)> {
+        self.inner
+            .wants_to_send()
+            .into_iter()
+            .map(|Outgoing { recipient, msg }| {
+                let recipient = match recipient {
+                    MessageDestination::AllParties => None,
+                    MessageDestination::OneParty(j) => Some(j),
+                };
+                (recipient, encode(&msg))
+            })
+            .collect()
+    }
+
+    pub fn try_finish(self) -> Result<S::Output, Self> {
+        let max_bytes = self.max_bytes;
+        self.inner
+            .try_finish()
+            .map_err(|inner| Encoded { inner, max_bytes })
+    }
+
+    /// See `StateMachine::on_timeout`.
+    pub fn on_timeout(&mut self) -> Result<(), EncodedError> {
+        Ok(self.inner.on_timeout()?)
+    }
+}
+
+#[test]
+pub fn test_decode_rejects_fuzz_regressions() {
+    use crate::spdz::{DkgMsg, PresignMsg, SignMsg};
+    use crate::utils::{PvssDealing, PvssNizk};
+
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/regressions");
+    let read = |path: &str| std::fs::read(dir.join(path)).unwrap();
+
+    assert!(decode::<(PvssDealing, PvssNizk)>(&read("pvss_dealing/huge_degree")).is_err());
+    assert!(decode::<(PvssDealing, PvssNizk)>(&read("pvss_dealing/zero_form")).is_err());
+    assert!(decode::<DkgMsg>(&read("dkg_msg/huge_degree")).is_err());
+    assert!(decode::<PresignMsg>(&read("presign_msg/huge_degree")).is_err());
+    assert!(decode::<SignMsg>(&read("sign_msg/bad_variant")).is_err());
+}
+
+#[test]
+pub fn test_verifiers_reject_garbage() {
+    use crate::spdz::simulate_pp;
+    use crate::utils::*;
+    use crate::{verify_dleq, verify_mta, verify_pvss};
+    use sha2::{Digest, Sha256};
+
+    let (pp, _) = simulate_pp(3, 2);
+    let h = &pp.curve_generator;
+    let mut rng = fresh_rng();
+
+    // genuine inputs to each verifier
+    let (dealing, r, _, shares) = PvssDealing::random(&pp, &mut rng, h);
+    let pvss_proof = PvssNizk::prove_with_aux(&pp, &dealing, &r, &shares, &mut rng, h, &[]);
+    let pvss = (dealing.clone(), pvss_proof);
+    assert!(verify_pvss(&pp, pvss.0.clone(), pvss.1.clone(), &[]));
+
+    let pvss_result = JointPvssResult::new(&pp, vec![dealing]);
+    let scalar = Zq::random();
+    let (mta_dealing, masks) = MtaDealing::new(&pp, &pvss_result, &scalar, &mut rng, h);
+    let mta_proof = MtaNizk::prove_with_aux(
+        &pp,
+        &pvss_result,
+        &mta_dealing,
+        h,
+        &mut rng,
+        &scalar,
+        &masks,
+        &[],
+    )
+    .unwrap();
+    let mta = (
+        pvss_result,
+        mta_dealing,
+        G::generator() * &scalar,
+        mta_proof,
+    );
+    let check_mta = |(result, dealing, scalar_pub, proof): (_, _, _, _)| {
+        verify_mta(&pp, result, dealing, scalar_pub, proof, &[])
+    };
+    assert!(check_mta(mta.clone()));
+
+    let x = Zq::random();
+    let (gen1, gen2) = (G::generator().to_point(), h.clone());
+    let (pow1, pow2) = (&gen1 * &x, &gen2 * &x);
+    let dleq_proof = DleqNizk::prove(&gen1, &pow1, &gen2, &pow2, &x, &mut rng);
+    let dleq = (gen1, pow1, gen2, pow2, dleq_proof);
+    let check_dleq = |(gen1, pow1, gen2, pow2, proof): (_, _, _, _, _)| {
+        verify_dleq(gen1, pow1, gen2, pow2, proof)
+    };
+    assert!(check_dleq(dleq.clone()));
+
+    // corrupted encodings of those, and bytes of the same length made up out of nothing,
+    // either fail to decode or fail to verify
+    fn noise(i: usize) -> [u8; 32] {
+        Sha256::digest((i as u64).to_le_bytes()).into()
+    }
+    fn garbage(genuine: &[u8], i: usize) -> [Vec<u8>; 2] {
+        let mut flipped = genuine.to_vec();
+        let bits = noise(i);
+        let at = u64::from_le_bytes(bits[..8].try_into().unwrap()) as usize % flipped.len();
+        flipped[at] ^= bits[8] | 1;
+        let random: Vec<u8> = (0..genuine.len())
+            .flat_map(|j| noise(i << 32 | j))
+            .take(genuine.len())
+            .collect();
+        [flipped, random]
+    }
+    fn fuzz<M: Serialize + DeserializeOwned>(genuine: &M, verify: impl Fn(M) -> bool) {
+        let bytes = encode(genuine);
+        for i in 0..64 {
+            for bytes in garbage(&bytes, i) {
+                if let Ok(msg) = decode::<M>(&bytes) {
+                    assert!(!verify(msg), "case {i}");
+                }
+            }
+        }
+    }
+    fuzz(&pvss, |(dealing, proof)| {
+        verify_pvss(&pp, dealing, proof, &[])
+    });
+    fuzz(&mta, check_mta);
+    fuzz(&dleq, check_dleq);
+}
+
+#[test]
+pub fn test_decoded_forms_are_checked() {
+    use crate::spdz::simulate_pp;
+    use crate::utils::*;
+    use bicycl::{Mpz, RandGen, QFI};
+
+    // a = 0, and b^2 >= 4ac
+    assert!(decode::<QFI>(&encode(&(vec![0u8], vec![1u8], vec![1u8]))).is_err());
+    assert!(decode::<QFI>(&encode(&(vec![1u8], vec![3u8], vec![2u8]))).is_err());
+
+    let (pp, _) = simulate_pp(3, 2);
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let (dealing, _, _, _) = PvssDealing::random(&pp, &mut rng, G::base_point2());
+
+    let decoded: PvssDealing = decode(&encode(&dealing)).unwrap();
+    assert_eq!(decoded.shares_ciphertext.check_discriminant(&pp.cl), Ok(()));
+
+    let (other_pp, _) = simulate_pp(3, 2);
+    assert_eq!(
+        decoded.shares_ciphertext.check_discriminant(&other_pp.cl),
+        Err(NizkError::DiscriminantMismatch)
+    );
+}
+
+#[test]
+pub fn test_encoded_dkg() {
+    use crate::spdz::{simulate_pp, DkgState};
+    use crate::test_utils::simulation::dkg_states;
+    use crate::utils::G;
+    use std::collections::BTreeMap;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut parties: BTreeMap<PartyIndex, Encoded<DkgState>> = dkg_states(&pp, &secret_keys)
+        .into_iter()
+        .map(|(i, state)| (i, Encoded::new(state)))
+        .collect();
+
+    // the parties only ever see bytes, as they would behind a JavaScript transport
+    loop {
+        let mut sent = vec![];
+        for (&i, party) in parties.iter_mut() {
+            sent.extend(party.wants_to_send().into_iter().map(|out| (i, out)));
+        }
+        if sent.is_empty() {
+            break;
+        }
+        for (sender, (recipient, bytes)) in sent {
+            for (&j, party) in parties.iter_mut() {
+                if j != sender && recipient.map_or(true, |r| r == j) {
+                    party.handle_bytes(sender, &bytes).unwrap();
+                }
+            }
+        }
+    }
+
+    let keys: Vec<_> = parties
+        .into_values()
+        .map(|party| party.try_finish().ok().unwrap())
+        .collect();
+    assert!(keys.iter().all(|key| key.tpk == keys[0].tpk));
+
+    let mut party = Encoded::new(DkgState::new(1, &pp, h, &secret_keys[&1], false));
+    assert!(matches!(
+        party.handle_bytes(1, &[0xff; 8]),
+        Err(EncodedError::Decode { from: 1, .. })
+    ));
+
+    // longer than the committee allows, so never decoded
+    let limits = crate::utils::ProtocolLimits {
+        max_message_bytes: 8,
+        ..Default::default()
+    };
+    let mut party =
+        Encoded::new(DkgState::new(1, &pp, h, &secret_keys[&1], false)).with_limits(&limits);
+    assert!(matches!(
+        party.handle_bytes(1, &[0xff; 9]),
+        Err(EncodedError::Decode {
+            from: 1,
+            source: DecodeError::TooLong
+        })
+    ));
+}
+
+#[test]
+pub fn test_decode_within() {
+    let bytes = encode(&(7u64, vec![1u8; 16]));
+    let len = bytes.len() as u64;
+    assert_eq!(
+        decode_within::<(u64, Vec<u8>)>(&bytes, len).unwrap(),
+        (7, vec![1; 16])
+    );
+    assert!(matches!(
+        decode_within::<(u64, Vec<u8>)>(&bytes, len - 1),
+        Err(DecodeError::TooLong)
+    ));
+}
+
+#[test]
+pub fn test_protocol_info() {
+    use crate::spdz::{
+        run_locally, simulate_pp, DkgState, PresignSession, PresignState, SignState, SigningContext,
+    };
+    use crate::transcript::{Entry, Recorded, Transcript};
+    use crate::utils::PartyId;
+
+    let (pp, secret_keys) = simulate_pp(4, 3);
+    let h = G::base_point2();
+    let id = |i: PartyIndex| PartyId::from_wire_index(i).unwrap().to_share_index();
+
+    // a recorded ceremony holds every message exactly as it was encoded
+    let keys = run_locally(
+        (0..pp.n)
+            .map(|i| {
+                let state = DkgState::new(id(i), &pp, h, &secret_keys[&id(i)], false);
+                (i, Recorded::new(i, state, Transcript::new(b"", &pp)))
+            })
+            .collect(),
+    );
+    let presignatures = run_locally(
+        keys.iter()
+            .map(|(&i, (key, transcript))| {
+                let state = PresignState::new(
+                    id(i),
+                    &pp,
+                    h,
+                    &secret_keys[&id(i)],
+                    key,
+                    PresignSession::default(),
+                );
+                (i, Recorded::new(i, state, transcript.clone()))
+            })
+            .collect(),
+    );
+    let context = SigningContext::from_dkg(&pp, &keys[&0].0);
+    let outcomes = run_locally(
+        presignatures
+            .into_iter()
+            .map(|(i, (presignature, transcript))| {
+                let state = SignState::new(id(i), &pp, h, &context, presignature, b"sized");
+                (i, Recorded::new(i, state, transcript))
+            })
+            .collect(),
+    );
+
+    let info = protocol_info(pp.t, pp.n, &pp.cl);
+    assert_eq!(info.rounds.len(), 6);
+    let (_, transcript) = &outcomes[&0];
+    for expected in &info.rounds {
+        let sizes: Vec<usize> = transcript
+            .links
+            .iter()
+            .filter_map(|link| match &link.entry {
+                Entry::Message {
+                    phase,
+                    round,
+                    bytes,
+                    ..
+                } if *phase == expected.phase && *round == expected.round => Some(bytes.len()),
+                _ => None,
+            })
+            .collect();
+        assert!(!sizes.is_empty(), "no messages for {:?}", expected);
+        for size in sizes {
+            // within a tenth of the estimate either way
+            assert!(
+                10 * size.abs_diff(expected.message_bytes) <= expected.message_bytes,
+                "{:?}: {} bytes encoded",
+                expected,
+                size
+            );
+        }
+    }
+}
+
+#[test]
+pub fn test_presignature_size() {
+    use crate::spdz::simulate_pp;
+    use crate::test_utils::simulation::{dkg_locally, presign_locally};
+
+    let (pp, secret_keys) = simulate_pp(8, 3);
+    let keys = dkg_locally(&pp, &secret_keys);
+    let presignatures = presign_locally(&pp, &secret_keys, &keys);
+
+    // R, Γ and three scalars, then a MAC of k and of chi for each party: nothing of the
+    // messages it was combined from. A scalar may encode shorter than -1 does.
+    let point = encode(&G::generator()).len();
+    let scalar = encode(&-Zq::from(1u64)).len();
+    let macs = 8 + pp.n as usize * (2 + point);
+    for presignature in presignatures.values() {
+        assert!(presignature.disqualified.is_empty());
+        assert_eq!(presignature.k_macs.len(), pp.n as usize);
+        assert!(encode(presignature).len() <= 2 * point + 3 * scalar + 2 * macs + 8);
+    }
+}
+
+#[test]
+pub fn test_compressed_points() {
+    use crate::spdz::{simulate_pp, MtAwcMsg, OpenPowerMsg, PresignSession};
+    use crate::utils::{JointPvssResult, PvssDealing};
+    use bicycl::{Mpz, RandGen};
+    use std::collections::BTreeMap;
+
+    let (pp, _) = simulate_pp(16, 9);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let mut joint_dealing = || {
+        let dealings = (0..pp.t)
+            .map(|_| PvssDealing::random(&pp, &mut rng, h).0)
+            .collect();
+        JointPvssResult::new(&pp, dealings)
+    };
+    let (k_pvss, gamma_pvss) = (joint_dealing(), joint_dealing());
+    let msg = MtAwcMsg::new(
+        &pp,
+        h,
+        &mut rng,
+        &k_pvss,
+        &gamma_pvss,
+        1,
+        &Zq::random(),
+        &Zq::random(),
+        &PresignSession::default(),
+        BTreeMap::new(),
+    );
+    let bytes = encode(&msg);
+    assert_eq!(decode::<MtAwcMsg>(&bytes).unwrap(), msg);
+
+    // the opening and the MACs take 33 bytes each instead of curv's encoding, which a
+    // MAC map under curv's encoding would spend on every recipient
+    let curv_point = encode(&G::generator().to_point()).len();
+    assert_eq!(encode(&CompressedRef(&msg.gamma_pub.point)).len(), 33);
+    assert!(curv_point > 33);
+    for dealing in [&msg.k_gamma_dealing, &msg.k_x_dealing] {
+        let compressed_macs = encode(dealing).len() - encode(&dealing.shares_ciphertext).len();
+        let curv_macs: BTreeMap<Id, _> = dealing
+            .curve_macs
+            .iter()
+            .map(|(&j, mac)| (j, mac.to_point()))
+            .collect();
+        assert_eq!(
+            encode(&curv_macs).len() - compressed_macs,
+            pp.n as usize * (curv_point - 33)
+        );
+    }
+
+    // the point leads an opening, and 0x05 is no prefix
+    let opening = encode(&msg.gamma_pub);
+    assert_eq!(decode::<OpenPowerMsg>(&opening).unwrap(), msg.gamma_pub);
+    let mut bad_prefix = opening;
+    bad_prefix[0] = 0x05;
+    assert!(decode::<OpenPowerMsg>(&bad_prefix).is_err());
+}
+
+#[test]
+pub fn test_non_canonical_points() {
+    use curv::elliptic::curves::Secp256k1;
+
+    let decodes = |bytes: &[u8; 33]| decode::<Compressed<Secp256k1>>(bytes).is_ok();
+    let with_x = |x: [u8; 32]| {
+        let mut bytes = [0x02; 33];
+        bytes[1..].copy_from_slice(&x);
+        bytes
+    };
+
+    // x = 0 is not on secp256k1, and all zeros is the point at infinity
+    assert!(!decodes(&with_x([0; 32])));
+    assert!(decodes(&[0; 33]));
+
+    // a point with a small x, sent with x + p in its place for the field prime p: both
+    // fit in 32 bytes, and only the first is the encoding of the point
+    let prime_low = 0xFFFF_FC2Fu32;
+    let small_x = |x: u32| {
+        let mut bytes = [0; 32];
+        bytes[28..].copy_from_slice(&x.to_be_bytes());
+        bytes
+    };
+    let x = (1u32..).find(|&x| decodes(&with_x(small_x(x)))).unwrap();
+    let mut aliased = [0xFF; 32];
+    aliased[24..28].copy_from_slice(&0xFFFF_FFFEu32.to_be_bytes());
+    aliased[28..].copy_from_slice(&(prime_low + x).to_be_bytes());
+    assert!(!decodes(&with_x(aliased)));
+}
+
+#[test]
+pub fn test_pre_dual_mta_layout_rejected() {
+    use crate::spdz::{simulate_pp, MtAwcMsg, OpenPowerMsg, PresignSession};
+    use crate::utils::{JointPvssResult, MtaDealing, MtaNizk, PvssDealing};
+    use crate::version::{Versioned, VersionedBody, VersionedMsg, PROTOCOL_VERSION};
+    use bicycl::{Mpz, RandGen};
+    use std::collections::BTreeMap;
+
+    // `MtAwcMsg` up to version 5, with a separate proof for each MtA dealing
+    #[derive(Serialize)]
+    struct PreDualMtAwcMsg {
+        gamma_pub: OpenPowerMsg,
+        k_gamma_dealing: MtaDealing,
+        k_gamma_proof: MtaNizk,
+        k_x_dealing: MtaDealing,
+        k_x_proof: MtaNizk,
+    }
+    #[derive(Serialize)]
+    #[allow(dead_code)]
+    enum PreDualPresignMsg {
+        NonceGen(()),
+        MtAwc(PreDualMtAwcMsg),
+    }
+
+    // rejects on the version stamp, before the payload reaches the protocol
+    struct Unreachable;
+    impl StateMachine for Unreachable {
+        type Msg = PresignMsg;
+        type Output = ();
+        fn handle_message(&mut self, _: PartyIndex, _: PresignMsg) -> Result<(), StateError> {
+            unreachable!("a stale payload was passed on")
+        }
+        fn wants_to_send(&mut self) -> Vec<Outgoing<PresignMsg>> {
+            vec![]
+        }
+        fn try_finish(self) -> Result<(), Self> {
+            Err(self)
+        }
+    }
+
+    let (pp, _) = simulate_pp(4, 3);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let mut joint_dealing = || {
+        let dealings = (0..pp.t)
+            .map(|_| PvssDealing::random(&pp, &mut rng, h).0)
+            .collect();
+        JointPvssResult::new(&pp, dealings)
+    };
+    let (k_pvss, gamma_pvss) = (joint_dealing(), joint_dealing());
+    let msg = MtAwcMsg::new(
+        &pp,
+        h,
+        &mut rng,
+        &k_pvss,
+        &gamma_pvss,
+        1,
+        &Zq::random(),
+        &Zq::random(),
+        &PresignSession::default(),
+        BTreeMap::new(),
+    );
+    let proof = || MtaNizk {
+        e: Zq::random(),
+        z1: Mpz::from(1u64),
+        z2: Zq::random(),
+    };
+    let stale = VersionedMsg::payload(
+        5,
+        PreDualPresignMsg::MtAwc(PreDualMtAwcMsg {
+            gamma_pub: msg.gamma_pub,
+            k_gamma_dealing: msg.k_gamma_dealing,
+            k_gamma_proof: proof(),
+            k_x_dealing: msg.k_x_dealing,
+            k_x_proof: proof(),
+        }),
+    );
+
+    // the old layout rarely parses as the new one, and never under the current stamp
+    match decode::<VersionedMsg<PresignMsg>>(&encode(&stale)) {
+        Err(_) => {}
+        Ok(decoded) => {
+            assert!(matches!(
+                decoded.body,
+                VersionedBody::Payload { version: 5, .. }
+            ));
+            let mut party = Versioned::fixed(Unreachable, PROTOCOL_VERSION);
+            assert!(matches!(
+                party.handle_message(0, decoded),
+                Err(StateError::VersionMismatch { peer: 0, .. })
+            ));
+        }
+    }
+}
+
+/// Runs `states` locally, keeping a copy of every message sent.
+#[cfg(test)]
+fn run_tapped<S>(
+    states: std::collections::BTreeMap<PartyIndex, S>,
+) -> (
+    std::collections::BTreeMap<PartyIndex, S::Output>,
+    Vec<S::Msg>,
+)
+where
+    S: StateMachine,
+    S::Msg: Clone,
+{
+    use std::{cell::RefCell, rc::Rc};
+
+    struct Tapped<S: StateMachine> {
+        inner: S,
+        sent: Rc<RefCell<Vec<S::Msg>>>,
+    }
+
+    impl<S: StateMachine> StateMachine for Tapped<S>
+    where
+        S::Msg: Clone,
+    {
+        type Msg = S::Msg;
+        type Output = S::Output;
+
+        fn handle_message(&mut self, from: PartyIndex, msg: S::Msg) -> Result<(), StateError> {
+            self.inner.handle_message(from, msg)
+        }
+
+        fn wants_to_send(&mut self) -> Vec<Outgoing<S::Msg>> {
+            let outgoing = self.inner.wants_to_send();
+            let mut sent = self.sent.borrow_mut();
+            sent.extend(outgoing.iter().map(|out| out.msg.clone()));
+            outgoing
+        }
+
+        fn try_finish(self) -> Result<S::Output, Self> {
+            let sent = self.sent;
+            self.inner
+                .try_finish()
+                .map_err(|inner| Tapped { inner, sent })
+        }
+    }
+
+    let sent = Rc::new(RefCell::new(vec![]));
+    let outputs = crate::spdz::run_locally(
+        states
+            .into_iter()
+            .map(|(i, inner)| {
+                let sent = sent.clone();
+                (i, Tapped { inner, sent })
+            })
+            .collect(),
+    );
+    let sent = sent.take();
+    (outputs, sent)
+}
+
+#[test]
+pub fn test_messages_round_trip() {
+    use crate::spdz::{
+        simulate_pp, DkgState, PresignSession, PresignState, SignState, SigningContext,
+    };
+    use crate::utils::PartyId;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    fn dkg_variant(msg: &DkgMsg) -> &'static str {
+        match msg {
+            DkgMsg::Pvss(_) => "DkgMsg::Pvss",
+            DkgMsg::PowOpen(_) => "DkgMsg::PowOpen",
+            DkgMsg::KeyConfirm(_) => "DkgMsg::KeyConfirm",
+            DkgMsg::PvssHeader(_) => "DkgMsg::PvssHeader",
+            DkgMsg::PvssLeaf(_) => "DkgMsg::PvssLeaf",
+            DkgMsg::MaskedPvss(_) => "DkgMsg::MaskedPvss",
+        }
+    }
+    fn presign_variant(msg: &PresignMsg) -> &'static str {
+        match msg {
+            PresignMsg::NonceGen(_) => "PresignMsg::NonceGen",
+            PresignMsg::MtAwc(_) => "PresignMsg::MtAwc",
+            PresignMsg::PreSignFinal(_) => "PresignMsg::PreSignFinal",
+        }
+    }
+    fn sign_variant(msg: &SignMsg) -> &'static str {
+        match msg {
+            SignMsg::OnlineSign(_) => "SignMsg::OnlineSign",
+        }
+    }
+
+    // bit for bit: the decoded message is equal, and encodes to the same bytes
+    let mut sizes = BTreeMap::new();
+    fn check<M>(sizes: &mut BTreeMap<&'static str, usize>, variant: &'static str, msg: &M)
+    where
+        M: WireMessage + PartialEq + fmt::Debug,
+    {
+        let bytes = msg.to_bincode();
+        let decoded = M::from_bincode(&bytes).unwrap();
+        assert_eq!(&decoded, msg, "{variant}");
+        assert_eq!(decoded.to_bincode(), bytes, "{variant}");
+        sizes.insert(variant, bytes.len());
+    }
+
+    let (pp, secret_keys) = simulate_pp(5, 3);
+    let h = G::base_point2();
+    let wire = |i: Id| PartyId::from_share_index(i).to_wire_index();
+
+    let (keys, sent) = run_tapped(
+        (1..=pp.n)
+            .map(|i| {
+                let state = DkgState::new(i, &pp, h, &secret_keys[&i], false)
+                    .with_key_confirmation(b"round trip");
+                (wire(i), state)
+            })
+            .collect(),
+    );
+    assert_eq!(keys.len(), pp.n as usize);
+    let light = DkgState::new(1, &pp, h, &secret_keys[&1], false)
+        .with_light_dealing(b"round trip")
+        .unwrap()
+        .wants_to_send();
+    for msg in sent.iter().chain(light.iter().map(|out| &out.msg)) {
+        check(&mut sizes, dkg_variant(msg), msg);
+    }
+
+    let (presignatures, sent) = run_tapped(
+        (1..=pp.n)
+            .map(|i| {
+                let state = PresignState::new(
+                    i,
+                    &pp,
+                    h,
+                    &secret_keys[&i],
+                    &keys[&wire(i)],
+                    PresignSession::default(),
+                );
+                (wire(i), state)
+            })
+            .collect(),
+    );
+    for msg in &sent {
+        check(&mut sizes, presign_variant(msg), msg);
+    }
+
+    let context = SigningContext::from_dkg(&pp, &keys[&wire(1)]);
+    let (signatures, sent) = run_tapped(
+        presignatures
+            .into_iter()
+            .map(|(i, presignature)| {
+                let my_id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                let state = SignState::new(my_id, &pp, h, &context, presignature, b"round trip");
+                (i, state)
+            })
+            .collect(),
+    );
+    assert_eq!(signatures.len(), pp.n as usize);
+    for msg in &sent {
+        check(&mut sizes, sign_variant(msg), msg);
+    }
+
+    let pp = pp.with_compact_pvss();
+    let compact = DkgState::new(1, &pp, h, &secret_keys[&1], false)
+        .with_compact_dealing(b"round trip")
+        .unwrap()
+        .wants_to_send();
+    for Outgoing { msg, .. } in &compact {
+        check(&mut sizes, dkg_variant(msg), msg);
+    }
+
+    let expected: BTreeSet<&str> = [
+        "DkgMsg::Pvss",
+        "DkgMsg::PowOpen",
+        "DkgMsg::KeyConfirm",
+        "DkgMsg::PvssHeader",
+        "DkgMsg::PvssLeaf",
+        "DkgMsg::MaskedPvss",
+        "PresignMsg::NonceGen",
+        "PresignMsg::MtAwc",
+        "PresignMsg::PreSignFinal",
+        "SignMsg::OnlineSign",
+    ]
+    .into();
+    assert_eq!(sizes.keys().copied().collect::<BTreeSet<_>>(), expected);
+}
@@ -0,0 +1,147 @@
+//! Lagrange interpolation over Zq. Parties come in as the nonzero points their shares
+//! are evaluated at (see `PartyId::eval_point`), or as 1-based ids mapped through
+//! `party_eval_point`. The 0-based wire indices of the drivers have no place here:
+//! convert them with `PartyId::from_wire_index` first.
+
+use std::collections::BTreeMap;
+
+use crate::utils::{party_eval_point, Id, Zq};
+
+/// The Lagrange coefficient of `xj` among `points`, for evaluating at `x`: the value at
+/// `x` of the polynomial of degree below `points.len()` that is 1 at `xj` and 0 at the
+/// other points. `None` unless the points are distinct and nonzero, and `xj` is one of
+/// them.
+pub fn lagrange_coeff_at(points: &[Zq], xj: &Zq, x: &Zq) -> Option<Zq> {
+    let distinct = points
+        .iter()
+        .enumerate()
+        .all(|(i, p)| !p.is_zero() && !points[..i].contains(p));
+    if !distinct || !points.contains(xj) {
+        return None;
+    }
+    Some(coeff(points, xj, x))
+}
+
+/// `lagrange_coeff_at` for interpolating at zero, where the secret sits.
+pub fn lagrange_coeff_at_zero(points: &[Zq], xj: &Zq) -> Option<Zq> {
+    lagrange_coeff_at(points, xj, &Zq::zero())
+}
+
+/// The Lagrange coefficients of all of `parties` for evaluating at `x`. The ids must be
+/// distinct.
+pub fn lagrange_coeffs_at(parties: &[Id], x: &Zq) -> BTreeMap<Id, Zq> {
+    let points: Vec<Zq> = parties.iter().map(|&id| party_eval_point(id)).collect();
+    let coeffs: BTreeMap<Id, Zq> = parties
+        .iter()
+        .zip(&points)
+        .map(|(&id, xj)| (id, coeff(&points, xj, x)))
+        .collect();
+    assert_eq!(coeffs.len(), parties.len(), "party ids must be distinct");
+    coeffs
+}
+
+/// `lagrange_coeffs_at` for interpolating at zero.
+pub fn lagrange_coeffs_at_zero(parties: &[Id]) -> BTreeMap<Id, Zq> {
+    lagrange_coeffs_at(parties, &Zq::zero())
+}
+
+/// Interpolates the shares at zero.
+pub fn interpolate_at_zero(shares: &BTreeMap<Id, Zq>) -> Zq {
+    let parties: Vec<Id> = shares.keys().copied().collect();
+    lagrange_coeffs_at_zero(&parties)
+        .iter()
+        .map(|(id, coeff)| coeff * &shares[id])
+        .sum()
+}
+
+fn coeff(points: &[Zq], xj: &Zq, x: &Zq) -> Zq {
+    let (num, den) = points
+        .iter()
+        .filter(|&p| p != xj)
+        .fold((Zq::from(1u64), Zq::from(1u64)), |(num, den), p| {
+            (num * (x - p), den * (xj - p))
+        });
+    num * den.invert().expect("the points are distinct")
+}
+
+#[cfg(test)]
+fn points(ids: &[Id]) -> Vec<Zq> {
+    ids.iter().map(|&id| party_eval_point(id)).collect()
+}
+
+#[test]
+pub fn test_lagrange_coeffs_of_small_subsets() {
+    let minus = |x: u64| -Zq::from(x);
+
+    // {1, 2}: 2 and -1, as f(0) = 2 f(1) - f(2) for a line
+    let subset = points(&[1, 2]);
+    assert_eq!(
+        lagrange_coeff_at_zero(&subset, &subset[0]),
+        Some(Zq::from(2u64))
+    );
+    assert_eq!(lagrange_coeff_at_zero(&subset, &subset[1]), Some(minus(1)));
+    assert_eq!(
+        lagrange_coeffs_at_zero(&[1, 2]),
+        BTreeMap::from([(1, Zq::from(2u64)), (2, minus(1))])
+    );
+
+    // {2, 3, 5}: 15/3, 10/-2 and 6/6
+    assert_eq!(
+        lagrange_coeffs_at_zero(&[2, 3, 5]),
+        BTreeMap::from([(2, Zq::from(5u64)), (3, minus(5)), (5, Zq::from(1u64))])
+    );
+
+    // at one of the points, its own coefficient is 1 and the others 0
+    let subset = points(&[2, 3, 5]);
+    for xj in &subset {
+        for xi in &subset {
+            let expected = if xi == xj { 1u64 } else { 0 };
+            assert_eq!(lagrange_coeff_at(&subset, xj, xi), Some(Zq::from(expected)));
+        }
+    }
+
+    // the coefficients sum to 1 wherever they are evaluated
+    let x = Zq::random();
+    let sum: Zq = lagrange_coeffs_at(&[2, 3, 5], &x).into_values().sum();
+    assert_eq!(sum, Zq::from(1u64));
+}
+
+#[test]
+pub fn test_lagrange_coeff_rejects_bad_points() {
+    let subset = points(&[1, 3]);
+    assert_eq!(lagrange_coeff_at_zero(&subset, &party_eval_point(2)), None);
+    assert_eq!(
+        lagrange_coeff_at_zero(&points(&[1, 3, 3]), &subset[0]),
+        None
+    );
+    let with_zero = [Zq::zero(), party_eval_point(1)];
+    assert_eq!(lagrange_coeff_at_zero(&with_zero, &with_zero[1]), None);
+}
+
+#[test]
+pub fn test_interpolate_random_polynomials() {
+    use crate::utils::Polynomial;
+
+    for (t, subset) in [
+        (1, vec![4]),
+        (2, vec![1, 3]),
+        (3, vec![2, 3, 5]),
+        (4, vec![1, 7, 8, 20]),
+    ] {
+        let poly = Polynomial {
+            coeffs: (0..t).map(|_| Zq::random()).collect(),
+        };
+        let shares: BTreeMap<Id, Zq> = subset
+            .iter()
+            .map(|&id| (id, poly.eval(&party_eval_point(id))))
+            .collect();
+        assert_eq!(interpolate_at_zero(&shares), poly.coeffs[0]);
+
+        let x = Zq::random();
+        let at_x: Zq = lagrange_coeffs_at(&subset, &x)
+            .iter()
+            .map(|(id, coeff)| coeff * &shares[id])
+            .sum();
+        assert_eq!(at_x, poly.eval(&x));
+    }
+}
@@ -9,7 +9,8 @@ use curv::{
     BigInt,
 };
 use futures::SinkExt;
-use utils::PvssDealing;
+use utils::{DleqNizk, JointPvssResult, MtaDealing, MtaNizk, PubParams, PvssDealing, PvssNizk, G};
+use validate::Validate;
 
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
@@ -19,7 +20,72 @@ pub mod utils;
 // pub mod tests;
 
 pub mod spdz;
+pub mod presign;
+pub mod backup;
+pub mod cancel;
 pub mod cdn;
+pub mod cl_backend;
+pub mod cl_setup;
+pub mod codec;
+pub mod interpolation;
+pub mod metrics;
+pub mod par;
+pub mod participant;
+pub mod rekey;
+pub mod replay;
+pub mod resend;
+pub mod schnorr;
+pub mod transcript;
+pub mod transport;
+pub mod validate;
+pub mod version;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(test)]
+mod robustness_tests;
+#[cfg(test)]
+mod test_utils;
+
+// The NIZK verifiers as free functions of owned values, fresh from `codec::decode`, so
+// that a fuzz harness reaches them without running the protocol up to where they are
+// checked. Each checks the shape of its inputs first, and returns false rather than
+// panicking on anything that does not verify.
+
+/// Checks a PVSS dealing to the whole committee of `pp` and its proof, with `aux` bound
+/// into the challenge and the curve polynomial in base `pp.curve_generator`.
+pub fn verify_pvss(pp: &PubParams, dealing: PvssDealing, proof: PvssNizk, aux: &[u8]) -> bool {
+    dealing.validate(pp).is_ok() && proof.verify_with_aux(&dealing, pp, &pp.curve_generator, aux)
+}
+
+/// Checks that `mta_dealing` multiplies the shares of `pvss_result` by the discrete log
+/// of `scalar_pub`. Both are addressed to the whole committee of `pp`, as in an
+/// `MtAwcMsg`.
+pub fn verify_mta(
+    pp: &PubParams,
+    pvss_result: JointPvssResult,
+    mta_dealing: MtaDealing,
+    scalar_pub: G,
+    proof: MtaNizk,
+    aux: &[u8],
+) -> bool {
+    pvss_result.validate(pp).is_ok()
+        && mta_dealing.validate(pp).is_ok()
+        && proof.verify_with_aux(
+            pp,
+            &pvss_result,
+            &mta_dealing,
+            &pp.curve_generator,
+            &scalar_pub,
+            aux,
+        )
+}
+
+/// Checks that `pow1` and `pow2` have the same discrete log to the bases `gen1` and
+/// `gen2`.
+pub fn verify_dleq(gen1: G, pow1: G, gen2: G, pow2: G, proof: DleqNizk) -> bool {
+    proof.verify(&gen1, &pow1, &gen2, &pow2)
+}
 
 // // Pre-signing phase consists of 3 rounds: NonceGen, MtAwc, & PreSignFinal;
 // // Besides, the online signing phase has another non-interactive round.
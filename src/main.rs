@@ -1,306 +1,249 @@
-use std::{
-    collections::BTreeMap, io,
+use std::{collections::BTreeMap, path::PathBuf, process, time::Instant};
+
+use bicycl::{Mpz, RandGen};
+use clap::{ArgGroup, Parser, ValueEnum};
+use serde::Serialize;
+
+use robust_threshold_ecdsa::{
+    cl_setup::{ClSetup, SecurityLevel},
+    metrics::{self, run_locally_metered, PhaseMetrics},
+    spdz::{
+        simulate_pp, simulate_pp_seeded, DkgState, PreSignatureContext, PresignSession,
+        PresignState, SignState, SigningContext,
+    },
+    utils::{Id, PartyId, ProtocolLimits, PubParams, Zq, G},
 };
-use bicycl::{CL_HSMqk, Mpz, PublicKey, RandGen, SecretKey};
-use curv::elliptic::curves::{Secp256k1, Point};
-use futures::SinkExt;
-// use round_optimal_robust_threshold_ecdsa::{
-//     *,
-//     ni_dkg::{PvssDealing, NiDkgOutput},
-//     tests::{Msg, Error},
-// };
-use round_based::{
-    rounds_router::simple_store::RoundInput, rounds_router::RoundsRouter, simulation::Simulation,
-    Delivery, Mpc, MpcParty, Outgoing, PartyIndex,
-};
-
-use robust_threshold_ecdsa::spdz;
-
-// mod tests;
 
-#[tokio::main]
-async fn main() {
-    println!("Hello, world!");
+/// Runs DKG, presigning and signing for a simulated committee and prints the signature.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+#[command(group(ArgGroup::new("msg").required(true).args(["message", "message_hex"])))]
+struct Args {
+    /// Number of parties
+    #[arg(long, default_value_t = 3)]
+    parties: Id,
+
+    /// Number of parties needed to sign, below the number of parties
+    #[arg(long, default_value_t = 2)]
+    threshold: Id,
+
+    /// Message to sign, as UTF-8 text
+    #[arg(long)]
+    message: Option<String>,
+
+    /// Message to sign, hex encoded
+    #[arg(long)]
+    message_hex: Option<String>,
+
+    /// Seed for the class group and the CL keys; the protocol nonces stay random.
+    /// With --setup-file, only the keys are derived from it
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Class group setup to reuse, generated and saved there on first use
+    #[arg(long)]
+    setup_file: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = Output::Text)]
+    output: Output,
+
+    /// Report operation counts and bytes sent per phase; needs the `metrics` feature
+    #[arg(long)]
+    metrics: bool,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Output {
+    Json,
+    Text,
+}
 
-// #[tokio::main]
-// async fn main() {
-//     let n: u16 = 3;
-//     let t: usize = 2;
-
-//     let mut simulation = Simulation::<Msg>::new();
-//     let mut party_output = vec![];
-
-//     let seed = Mpz::from(chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default());
-//     let mut rand_gen = RandGen::new();
-//     rand_gen.set_seed(&seed);
-
-//     let q = Mpz::from("115792089237316195423570985008687907852837564279074904382605163141518161494337");
-//     let clgroup = CL_HSMqk::with_rand_gen(&q, 1, 150, &mut rand_gen, &Mpz::from(0i64), false);
-
-//     let mut clsk = BTreeMap::<usize, SecretKey>::new();
-//     let mut clpk = BTreeMap::<usize, PublicKey>::new();
-
-//     println!("Please enter a message to be signed:");
-//     let mut message = String::new();
-//     io::stdin().read_line(&mut message)
-//         .expect("Failed to read line");
-//     message = message.trim().to_string();
-
-//     for i in 0..n {
-//         let sk_i = clgroup.secret_key_gen(&mut rand_gen);
-//         let pk_i = clgroup.public_key_gen(&sk_i);
-//         // clgroup.gq
-//         clsk.insert(i.into(), sk_i);
-//         clpk.insert(i.into(), pk_i);
-//     }
-
-//     for i in 0..n {
-//         let party = simulation.add_party();
-//         let mysk = clsk[&(i as usize)].clone();
-
-//         let mut rand = RandGen::new();
-//         rand.set_seed(&rand_gen.random_mpz(&clgroup.encrypt_randomness_bound()));
-
-//         let output =
-//             protocol_dkg_presign_sign(message.clone(), party, i, t, n.into(), clgroup.clone(), rand, clpk.clone(), mysk);
-//         party_output.push(output);
-//     }
-
-//     let _output = futures::future::try_join_all(party_output).await.unwrap();
-//     for (sig, pk) in &_output {
-//         println!("-----------------------------------");
-//         println!("SignatureECDSA = {}", sig);
-//         if let (Some(x), Some(y)) = (pk.x_coord(), pk.y_coord()) {
-//             println!("Public key = {{\n\tx = {},\n\ty = {}\n}}", x, y);
-//         }
-//     }
-//     println!("-----------------------------------");
-//     let failed_count = _output.iter().filter(|(sig, pk)| !sig.verify(pk, &message)).count();
-//     if failed_count == 0 {
-//         println!("All signatures verified passed!");
-//     } else {
-//         println!("Partial signature verification failed!!");
-//     }
-// }
-
-// pub async fn protocol_dkg_presign_sign<M>(
-//     msg: String,
-//     party: M,
-//     myid: PartyIndex,
-//     t: usize,
-//     n: usize,
-//     clgroup: CL_HSMqk,
-//     mut rand_gen: RandGen,
-//     clpk: BTreeMap<usize, PublicKey>,
-//     mysk: SecretKey,
-// ) -> Result<(SignatureECDSA, Point<Secp256k1>), Error<M::SendError, M::ReceiveError>>
-//     where
-//         M: Mpc<ProtocolMessage=Msg>,
-// {
-//     let parties: Vec<usize> = (0..n).collect();
-//     let parties_excl_myself: Vec<usize> = (0..n).filter(|j| *j != (myid as usize)).collect();
-
-//     let n_u16 = u16::try_from(n).unwrap();
-//     let MpcParty { delivery, .. } = party.into_party();
-//     let (incoming, mut outgoing) = delivery.split();
-//     let mut rounds = RoundsRouter::<Msg>::builder();
-//     let round0 = rounds.add_round(RoundInput::<PvssDealing>::broadcast(myid, n_u16));
-//     let round1 = rounds.add_round(RoundInput::<NonceGenMsg>::broadcast(myid, n_u16));
-//     let round2 = rounds.add_round(RoundInput::<MtAwcMsg>::broadcast(myid, n_u16));
-//     let round3 = rounds.add_round(RoundInput::<PreSignFinalMsg>::broadcast(myid, n_u16));
-//     let round4 = rounds.add_round(RoundInput::<OnlineSignMsg>::broadcast(myid, n_u16));
-//     let mut rounds = rounds.listen(incoming);
-
-//     // Step 0: DKG of x
-//     let my_ni_dkg_msg = PvssDealing::new(t, parties.clone(), &clgroup, &mut rand_gen, &clpk);
-
-//     outgoing
-//         .send(Outgoing::broadcast(Msg::NiDkgMsg(my_ni_dkg_msg.clone())))
-//         .await
-//         .unwrap();
-
-//     let x_dkg_messages = rounds
-//         .complete(round0)
-//         .await
-//         .unwrap()
-//         .into_vec_including_me(my_ni_dkg_msg);
-
-//     let x_dkg_output = NiDkgOutput::from_combining(
-//         parties.clone(),
-//         &x_dkg_messages,
-//         myid.into(),
-//         clgroup.clone(),
-//         &mut rand_gen,
-//         false,
-//         clpk.clone(),
-//         &mysk,
-//     );
-
-//     // Step 1: Generation of nonces k and gamma
-//     let my_nonce_gen_msg = NonceGenMsg {
-//         k_dkg_msg: PvssDealing::new(t, parties.clone(), &clgroup, &mut rand_gen, &clpk),
-//         gamma_dkg_msg: PvssDealing::new(t, parties.clone(), &clgroup, &mut rand_gen, &clpk),
-//     };
-
-//     outgoing
-//         .send(Outgoing::broadcast(Msg::NonceGenMsg(
-//             my_nonce_gen_msg.clone(),
-//         )))
-//         .await
-//         .unwrap();
-
-//     let nonce_gen_messages = rounds
-//         .complete(round1)
-//         .await
-//         .unwrap()
-//         .into_vec_including_me(my_nonce_gen_msg);
-
-//     // Step 1->2 transition: prepare input from output
-//     let (k_dkg_messages, gamma_dkg_messages): (Vec<_>, Vec<_>) = nonce_gen_messages
-//         .into_iter()
-//         .map(|msg| (msg.k_dkg_msg, msg.gamma_dkg_msg))
-//         .unzip();
-
-//     let k_dkg_output = NiDkgOutput::from_combining(
-//         x_dkg_output.parties.clone(),
-//         &k_dkg_messages,
-//         myid.into(),
-//         clgroup.clone(),
-//         &mut rand_gen,
-//         true,
-//         clpk.clone(),
-//         &mysk,
-//     );
-
-//     let gamma_dkg_output = NiDkgOutput::from_combining(
-//         x_dkg_output.parties.clone(),
-//         &gamma_dkg_messages,
-//         myid.into(),
-//         clgroup.clone(),
-//         &mut rand_gen,
-//         false,
-//         clpk.clone(),
-//         &mysk,
-//     );
-
-//     // Step 2: Nonce conversion, or MtAwc
-//     let (my_mta_msg, betas, nus) = MtAwcMsg::new(
-//         parties_excl_myself.clone(),
-//         myid.into(),
-//         clgroup.clone(),
-//         &mut rand_gen,
-//         &clpk,
-//         k_dkg_output.clone(),
-//         gamma_dkg_output.clone().share,
-//         x_dkg_output.clone().share,
-//     );
-
-//     outgoing
-//         .send(Outgoing::broadcast(Msg::MtAwcMsg(my_mta_msg.clone())))
-//         .await
-//         .unwrap();
-
-//     // we want MtA messages, excluding myself's, to be arranged into a BTreeMap for Step 3
-//     let mut mta_messages: BTreeMap<usize, MtAwcMsg> = rounds
-//         .complete(round2)
-//         .await
-//         .unwrap()
-//         .into_iter_indexed()
-//         .map(|(j, _, msg)| (j.into(), msg))
-//         .collect();
-//     mta_messages.insert(myid.into(), my_mta_msg);
-
-
-//     // Step 3: PreSign final round aka Share Revelation
-//     let (my_presign_final_msg, mus_to_me, nus) = PreSignFinalMsg::new(
-//         parties_excl_myself.clone(),
-//         t,
-//         myid.into(),
-//         mta_messages.clone(),
-//         clgroup.clone(),
-//         &mut rand_gen,
-//         mysk,
-//         betas,
-//         nus,
-//         gamma_dkg_output.clone(),
-//         x_dkg_output.clone(),
-//         k_dkg_output.clone().share,
-//     );
-
-//     outgoing
-//         .send(Outgoing::broadcast(Msg::PreSignFinalMsg(
-//             my_presign_final_msg.clone(),
-//         )))
-//         .await
-//         .unwrap();
-
-//     let mut presign_final_messages: BTreeMap<usize, PreSignFinalMsg> = rounds
-//         .complete(round3)
-//         .await
-//         .unwrap()
-//         .into_iter_indexed()
-//         .map(|(j, _, msg)| (j.into(), msg))
-//         .collect();
-//     presign_final_messages.insert(myid.into(), my_presign_final_msg);
-
-//     // and finally you may follow me; farewell he said
-//     let presignature = PreSignature::from(
-//         parties.clone(),
-//         myid.into(),
-//         mta_messages,
-//         presign_final_messages,
-//         mus_to_me,
-//         nus,
-//         gamma_dkg_output.pk,
-//         k_dkg_output.clone(),
-//     );
-
-//     // Step 4: Online Signing
-//     let (my_online_sign_msg, r, m) = OnlineSignMsg::new(
-//         msg,
-//         parties_excl_myself,
-//         t,
-//         myid.into(),
-//         x_dkg_output.clone(),
-//         presignature.clone(),
-//         k_dkg_output.share.clone(),
-//     );
-
-//     outgoing
-//         .send(Outgoing::broadcast(Msg::OnlineSignMsg(
-//             my_online_sign_msg.clone(),
-//         )))
-//         .await
-//         .unwrap();
-
-//     let mut online_sign_messages: BTreeMap<usize, OnlineSignMsg> = rounds
-//         .complete(round4)
-//         .await
-//         .unwrap()
-//         .into_iter_indexed()
-//         .map(|(j, _, msg)| (j.into(), msg))
-//         .collect();
-//     online_sign_messages.insert(myid.into(), my_online_sign_msg);
-
-//     let pk = x_dkg_output.pk.clone();
-
-//     let signature = SignatureECDSA::from(
-//         parties,
-//         myid.into(),
-//         online_sign_messages,
-//         r,
-//         m,
-//         presignature,
-//         x_dkg_output.clone(),
-//     );
+#[derive(Serialize)]
+struct Report {
+    r: String,
+    s: String,
+    public_key: String,
+    /// Wall-clock milliseconds per phase
+    timings: BTreeMap<&'static str, f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    metrics: Vec<PhaseMetrics>,
+}
 
-//     // let tt = x_dkg_output.pk.x_coord();
-//     // if let Some(xx) = x_dkg_output.pk.x_coord() {
-//     //     println!("ddd = {}", xx);
-//     // }
+/// One phase per row.
+fn print_metrics(metrics: &[PhaseMetrics]) {
+    println!(
+        "{:<10} {:>12} {:>10} {:>10} {:>10} {:>12}",
+        "phase", "wall (ms)", "qfi exp", "qfi comp", "ec mult", "bytes sent"
+    );
+    for m in metrics {
+        println!(
+            "{:<10} {:>12.2} {:>10} {:>10} {:>10} {:>12}",
+            m.phase,
+            m.wall_time.as_secs_f64() * 1e3,
+            m.qfi_exps,
+            m.qfi_compositions,
+            m.ec_mults,
+            m.bytes_sent
+        );
+    }
+}
 
+fn fail(msg: &str) -> ! {
+    eprintln!("error: {msg}");
+    process::exit(2)
+}
 
-//     Ok((signature, pk))
-// }
+fn main() {
+    let args = Args::parse();
+
+    if args.threshold == 0 || args.threshold >= args.parties {
+        fail(&format!(
+            "--threshold must be between 1 and {} for {} parties, got {}",
+            args.parties.saturating_sub(1),
+            args.parties,
+            args.threshold
+        ));
+    }
+    if let Err(e) = ProtocolLimits::default().check(args.parties, args.threshold) {
+        fail(&e.to_string());
+    }
+    if args.metrics && !metrics::ENABLED {
+        fail("--metrics needs a build with the `metrics` feature");
+    }
+    let message = match (&args.message, &args.message_hex) {
+        (Some(text), _) => text.as_bytes().to_vec(),
+        (None, Some(hex)) => hex::decode(hex.trim_start_matches("0x"))
+            .unwrap_or_else(|e| fail(&format!("--message-hex is not valid hex: {e}"))),
+        (None, None) => unreachable!("clap requires one of the message flags"),
+    };
+
+    let mut timings = BTreeMap::new();
+    let mut phase_metrics = Vec::new();
+    let h = G::base_point2();
+
+    let now = Instant::now();
+    let (pp, secret_keys) = match (&args.setup_file, args.seed) {
+        (Some(path), seed) => {
+            let setup = ClSetup::load_or_generate(path, SecurityLevel::Bits128)
+                .unwrap_or_else(|e| fail(&format!("{}: {e}", path.display())));
+            let mut rng = RandGen::new();
+            rng.set_seed(&match seed {
+                Some(seed) => Mpz::from(seed),
+                None => Mpz::from(&Zq::random()),
+            });
+            PubParams::with_cl(setup.cl(), args.parties, args.threshold, &mut rng)
+                .unwrap_or_else(|e| fail(&e.to_string()))
+        }
+        (None, Some(seed)) => simulate_pp_seeded(args.parties, args.threshold, &Mpz::from(seed)),
+        (None, None) => simulate_pp(args.parties, args.threshold),
+    };
+    timings.insert("setup", now.elapsed().as_secs_f64() * 1e3);
+
+    let wire = |i: Id| PartyId::from_share_index(i).to_wire_index();
+
+    let now = Instant::now();
+    let keys = run_locally_metered(
+        "dkg",
+        || {
+            (1..=pp.n)
+                .map(|i| (wire(i), DkgState::new(i, &pp, h, &secret_keys[&i], false)))
+                .collect()
+        },
+        &mut phase_metrics,
+    );
+    timings.insert("dkg", now.elapsed().as_secs_f64() * 1e3);
+
+    // presigning and signing go ahead without the parties the DKG disqualified
+    let key = keys
+        .values()
+        .next()
+        .unwrap_or_else(|| fail("no party finished the DKG"));
+    let honest: Vec<Id> = key
+        .honest_parties()
+        .into_iter()
+        .filter(|&i| keys.contains_key(&wire(i)))
+        .collect();
+
+    let now = Instant::now();
+    let presignatures = run_locally_metered(
+        "presign",
+        || {
+            honest
+                .iter()
+                .map(|&i| {
+                    let state = PresignState::new(
+                        i,
+                        &pp,
+                        h,
+                        &secret_keys[&i],
+                        &keys[&wire(i)],
+                        PresignSession::default(),
+                    );
+                    (wire(i), state)
+                })
+                .collect()
+        },
+        &mut phase_metrics,
+    );
+    timings.insert("presign", now.elapsed().as_secs_f64() * 1e3);
+
+    // a presignature may have been stored for a while before it is used
+    for (i, presignature) in &presignatures {
+        let my_id = PartyId::from_wire_index(*i).unwrap().to_share_index();
+        let context = PreSignatureContext { h, my_id };
+        if let Err(e) = presignature.validate(&context, &pp, &keys[i]) {
+            fail(&format!("party {my_id} holds an invalid presignature: {e}"));
+        }
+    }
+
+    let now = Instant::now();
+    let context = SigningContext::from_dkg(&pp, key);
+    let signatures = run_locally_metered(
+        "sign",
+        || {
+            presignatures
+                .into_iter()
+                .map(|(i, presignature)| {
+                    let my_id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                    let state = SignState::new(my_id, &pp, h, &context, presignature, &message);
+                    (i, state)
+                })
+                .collect()
+        },
+        &mut phase_metrics,
+    );
+    timings.insert("sign", now.elapsed().as_secs_f64() * 1e3);
+
+    let signature = &signatures
+        .values()
+        .next()
+        .unwrap_or_else(|| fail("no party finished signing"))
+        .signature;
+    if !signature.verify(&context.pk, &message) {
+        fail("the signature does not verify");
+    }
+
+    let report = Report {
+        r: hex::encode(&*signature.r.to_bytes()),
+        s: hex::encode(&*signature.s.to_bytes()),
+        public_key: hex::encode(&*context.pk.to_bytes(true)),
+        timings,
+        metrics: if args.metrics { phase_metrics } else { vec![] },
+    };
+
+    match args.output {
+        Output::Json => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+        Output::Text => {
+            println!("r          = {}", report.r);
+            println!("s          = {}", report.s);
+            println!("public key = {}", report.public_key);
+            for (phase, ms) in &report.timings {
+                println!("{phase:<10} : {ms:.2} ms");
+            }
+            if !report.metrics.is_empty() {
+                println!();
+                print_metrics(&report.metrics);
+            }
+        }
+    }
+}
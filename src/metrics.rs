@@ -0,0 +1,280 @@
+//! Per-phase timing and operation counts, for capacity planning. The class group
+//! operations are counted by BICYCL's wrapper and the curve multiplications by
+//! `CurvePolynomial`, both only with the `metrics` feature; without it, a phase reports
+//! its wall time and the bytes it sent, and zero operations.
+//!
+//! The counters are process-wide: phases measured while other protocol runs go on in
+//! the same process count their operations too.
+
+use std::collections::BTreeMap;
+use std::ops::{Add, Mul};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use bicycl::counters;
+use round_based::{Outgoing, PartyIndex};
+use serde::Serialize;
+
+use crate::codec::encode;
+use crate::spdz::{run_locally, StateError, StateMachine};
+use crate::utils::Id;
+
+/// Whether this build counts operations.
+pub const ENABLED: bool = cfg!(feature = "metrics");
+
+static EC_MULTS: AtomicU64 = AtomicU64::new(0);
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn count_ec_mults(count: usize) {
+    #[cfg(feature = "metrics")]
+    EC_MULTS.fetch_add(count as u64, Ordering::Relaxed);
+    #[cfg(not(feature = "metrics"))]
+    let _ = count;
+}
+
+/// Class group operations, by the primitive of BICYCL's wrapper they went through.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ClOpCounts {
+    /// Exponentiations of arbitrary forms, including public keys.
+    pub exp: u64,
+    pub compose: u64,
+    pub power_of_h: u64,
+    pub power_of_f: u64,
+}
+
+impl ClOpCounts {
+    fn read() -> Self {
+        ClOpCounts {
+            exp: counters::read(&counters::EXP),
+            compose: counters::read(&counters::COMPOSE),
+            power_of_h: counters::read(&counters::POWER_OF_H),
+            power_of_f: counters::read(&counters::POWER_OF_F),
+        }
+    }
+
+    fn since(self, before: Self) -> Self {
+        ClOpCounts {
+            exp: self.exp - before.exp,
+            compose: self.compose - before.compose,
+            power_of_h: self.power_of_h - before.power_of_h,
+            power_of_f: self.power_of_f - before.power_of_f,
+        }
+    }
+}
+
+impl Add for ClOpCounts {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        ClOpCounts {
+            exp: self.exp + other.exp,
+            compose: self.compose + other.compose,
+            power_of_h: self.power_of_h + other.power_of_h,
+            power_of_f: self.power_of_f + other.power_of_f,
+        }
+    }
+}
+
+impl Mul<u64> for ClOpCounts {
+    type Output = Self;
+
+    fn mul(self, times: u64) -> Self {
+        ClOpCounts {
+            exp: self.exp * times,
+            compose: self.compose * times,
+            power_of_h: self.power_of_h * times,
+            power_of_f: self.power_of_f * times,
+        }
+    }
+}
+
+/// What one phase of a run cost, summed over all parties.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PhaseMetrics {
+    pub phase: &'static str,
+    pub wall_time: Duration,
+    /// Class group exponentiations, including powers of h.
+    pub qfi_exps: u64,
+    pub qfi_compositions: u64,
+    /// Curve scalar multiplications in polynomial evaluations.
+    pub ec_mults: u64,
+    /// Encoded size of the messages sent, counting a broadcast once.
+    pub bytes_sent: u64,
+    /// The class group operations in detail.
+    pub cl_ops: ClOpCounts,
+}
+
+#[derive(Clone, Copy)]
+struct Snapshot {
+    cl_ops: ClOpCounts,
+    ec_mults: u64,
+    bytes_sent: u64,
+}
+
+impl Snapshot {
+    fn take() -> Self {
+        Snapshot {
+            cl_ops: ClOpCounts::read(),
+            ec_mults: EC_MULTS.load(Ordering::Relaxed),
+            bytes_sent: BYTES_SENT.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Runs `f` and reports what it cost as `phase`.
+pub fn measure<T>(phase: &'static str, f: impl FnOnce() -> T) -> (T, PhaseMetrics) {
+    let before = Snapshot::take();
+    let now = Instant::now();
+    let output = f();
+    let wall_time = now.elapsed();
+    let after = Snapshot::take();
+    let cl_ops = after.cl_ops.since(before.cl_ops);
+    let metrics = PhaseMetrics {
+        phase,
+        wall_time,
+        qfi_exps: cl_ops.exp + cl_ops.power_of_h,
+        qfi_compositions: cl_ops.compose,
+        ec_mults: after.ec_mults - before.ec_mults,
+        bytes_sent: after.bytes_sent - before.bytes_sent,
+        cl_ops,
+    };
+    (output, metrics)
+}
+
+/// `run_locally` with each party `Metered`, appending the cost of the run to `metrics`.
+/// The states are made by `states` inside the measurement, since their constructors
+/// already deal the first round.
+pub fn run_locally_metered<S>(
+    phase: &'static str,
+    states: impl FnOnce() -> BTreeMap<PartyIndex, S>,
+    metrics: &mut Vec<PhaseMetrics>,
+) -> BTreeMap<PartyIndex, S::Output>
+where
+    S: StateMachine,
+    S::Msg: Clone + Serialize,
+{
+    let (outputs, phase_metrics) = measure(phase, || {
+        run_locally(
+            states()
+                .into_iter()
+                .map(|(i, state)| (i, Metered(state)))
+                .collect(),
+        )
+    });
+    metrics.push(phase_metrics);
+    outputs
+}
+
+/// Counts the encoded size of the messages a party sends.
+pub struct Metered<S>(pub S);
+
+impl<S> StateMachine for Metered<S>
+where
+    S: StateMachine,
+    S::Msg: Serialize,
+{
+    type Msg = S::Msg;
+    type Output = S::Output;
+
+    fn handle_message(&mut self, from: PartyIndex, msg: S::Msg) -> Result<(), StateError> {
+        self.0.handle_message(from, msg)
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<S::Msg>> {
+        let outgoing = self.0.wants_to_send();
+        let bytes: usize = outgoing
+            .iter()
+            .map(|Outgoing { msg, .. }| encode(msg).len())
+            .sum();
+        BYTES_SENT.fetch_add(bytes as u64, Ordering::Relaxed);
+        outgoing
+    }
+
+    fn try_finish(self) -> Result<Self::Output, Self> {
+        self.0.try_finish().map_err(Metered)
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        self.0.on_timeout()
+    }
+
+    fn on_version(&mut self, version: u16) {
+        self.0.on_version(version)
+    }
+}
+
+// The cost of the building blocks in a committee of n, following their code. Every
+// polynomial over the class group has a coefficient for each of the n recipients and a
+// neutral constant term, so evaluating it takes n + 1 exponentiations and compositions.
+
+const fn ops(exp: u64, compose: u64, power_of_h: u64, power_of_f: u64) -> ClOpCounts {
+    ClOpCounts {
+        exp,
+        compose,
+        power_of_h,
+        power_of_f,
+    }
+}
+
+/// `PvssDealing::random`: h^r, then f^share * pk^r for each recipient.
+fn pvss_deal(n: u64) -> ClOpCounts {
+    ops(n, n, 1, n)
+}
+
+/// `PvssNizk::prove_with_aux`
+fn pvss_prove(n: u64) -> ClOpCounts {
+    ops(n + 2, n + 2, 1, 1)
+}
+
+/// `PvssNizk::verify_detailed_with_aux`: two polynomial evaluations, of the keys and
+/// of the ciphertexts.
+fn pvss_verify(n: u64) -> ClOpCounts {
+    ops(2 * n + 5, 2 * n + 5, 1, 1)
+}
+
+/// `JointPvssAccumulator` on `dealings` dealings: each but the first composes its
+/// randomness and n ciphertexts into the sum.
+fn pvss_fold(n: u64, dealings: u64) -> ClOpCounts {
+    ops(0, (dealings - 1) * (n + 1), 0, 0)
+}
+
+/// `MtaDealing::with_masks`
+fn mta_deal(n: u64) -> ClOpCounts {
+    ops(n + 1, n, 0, n)
+}
+
+/// `DualMtaNizk::prove`
+fn dual_mta_prove(n: u64) -> ClOpCounts {
+    ops(n + 5, n + 3, 0, 2)
+}
+
+/// `DualMtaNizk::verify_detailed`
+fn dual_mta_verify(n: u64) -> ClOpCounts {
+    ops(3 * n + 11, 3 * n + 9, 0, 2)
+}
+
+/// The class group operations of DKG, presigning and signing among t-of-n honest
+/// parties, summed over all of them, without running anything. It is exact for the
+/// default configuration of each phase, as `DkgState::new` and `PresignState::new`
+/// set it up, and grows with key confirmation, membership proofs, or parties that
+/// misbehave. Decryptions are left out, as BICYCL does them internally.
+///
+/// Each party verifies the dealings and messages of all n - 1 others, also past the
+/// first t valid ones it qualifies, so each kind is verified n(n - 1) times in all.
+/// Signing does no class group operations, and neither does a committee with no
+/// parties or a threshold of 0, which no phase accepts.
+pub fn estimate_cl_ops(t: Id, n: Id) -> ClOpCounts {
+    if t == 0 || n == 0 {
+        return ClOpCounts::default();
+    }
+    let (t, n) = (t as u64, n as u64);
+
+    let dkg = (pvss_deal(n) + pvss_prove(n) + pvss_fold(n, t)) * n + pvss_verify(n) * (n * (n - 1));
+
+    let presign_dealing =
+        (pvss_deal(n) + pvss_prove(n) + pvss_fold(n, t) + mta_deal(n)) * 2 + dual_mta_prove(n);
+    let presign_verification = pvss_verify(n) * 2 + dual_mta_verify(n);
+    let presign = presign_dealing * n + presign_verification * (n * (n - 1));
+
+    dkg + presign
+}
@@ -0,0 +1,43 @@
+//! Parallel iteration where threads are available. wasm32 has none to spawn, so
+//! there the same call sites fall back to sequential iterators.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use rayon::iter::ParallelIterator;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeIntoParIter: rayon::iter::IntoParallelIterator + Sized {
+    fn maybe_into_par_iter(self) -> Self::Iter {
+        self.into_par_iter()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: rayon::iter::IntoParallelIterator> MaybeIntoParIter for T {}
+
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeIntoParIter: IntoIterator + Sized {
+    fn maybe_into_par_iter(self) -> Self::IntoIter {
+        self.into_iter()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl<T: IntoIterator> MaybeIntoParIter for T {}
+
+/// Runs both closures, in parallel if possible.
+pub fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        rayon::join(a, b)
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        (a(), b())
+    }
+}
@@ -0,0 +1,373 @@
+//! A party's place in a committee, checked once when it is built: its index, the
+//! t-of-n committee and its CL keys, the party's own CL secret key, and the session it
+//! takes part in. The entry points here take a `Participant` instead of the long
+//! positional lists of `spdz::dkg`, `spdz::presign` and `spdz::sign`, where swapping
+//! t and n still compiles.
+
+use bicycl::{CL_HSMqk, SecretKey};
+use round_based::{Mpc, PartyIndex};
+use thiserror::Error;
+
+use crate::cl_setup::ClSetup;
+use crate::presign::PreSignature;
+use crate::spdz::{
+    run_within, DkgMsg, DkgOutput, DkgState, Error, PresignMsg, PresignSession, PresignState,
+    SignMsg, SignState, SigningContext, SigningOutcome,
+};
+use crate::utils::{
+    CLKeyRing, Id, LimitError, PartyId, ProtocolLimits, PubParams, Qualification, G,
+};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ParticipantError {
+    #[error("no {field} was given")]
+    Missing { field: &'static str },
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    #[error("index {index} is outside a committee of {n}")]
+    IndexOutOfRange { index: PartyIndex, n: Id },
+    #[error("the keyring has no CL public key for parties {ids:?}")]
+    IncompleteKeyring { ids: Vec<Id> },
+    #[error("the CL secret key does not match the keyring's public key for party {id}")]
+    SecretKeyMismatch { id: Id },
+}
+
+/// One party of a t-of-n committee; see `Participant::builder`.
+pub struct Participant {
+    id: Id, // in the range 1..=n
+    pp: PubParams,
+    cl_secret: SecretKey,
+    session: Option<Vec<u8>>,
+}
+
+impl Participant {
+    pub fn builder() -> ParticipantBuilder {
+        ParticipantBuilder::default()
+    }
+
+    /// In the range 1..=n, as the shares are indexed.
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// In the range 0..n, as the messages are addressed.
+    pub fn index(&self) -> PartyIndex {
+        PartyId::from_share_index(self.id).to_wire_index()
+    }
+
+    pub fn pp(&self) -> &PubParams {
+        &self.pp
+    }
+
+    pub fn cl_secret(&self) -> &SecretKey {
+        &self.cl_secret
+    }
+
+    pub fn session(&self) -> Option<&[u8]> {
+        self.session.as_deref()
+    }
+}
+
+/// Collects the parts of a `Participant`, all of them required but the session and the
+/// settings that have defaults.
+#[derive(Default)]
+pub struct ParticipantBuilder {
+    index: Option<PartyIndex>,
+    threshold: Option<Id>,
+    parties: Option<Id>,
+    cl: Option<CL_HSMqk>,
+    cl_keyring: Option<CLKeyRing>,
+    cl_secret: Option<SecretKey>,
+    session: Option<Vec<u8>>,
+    curve_generator: Option<G>,
+    compact_pvss: bool,
+    limits: Option<ProtocolLimits>,
+    qualification: Qualification,
+}
+
+impl ParticipantBuilder {
+    /// Our wire index, in the range 0..n.
+    pub fn index(mut self, index: PartyIndex) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// How many parties it takes to sign.
+    pub fn threshold(mut self, t: Id) -> Self {
+        self.threshold = Some(t);
+        self
+    }
+
+    /// The size n of the committee.
+    pub fn parties(mut self, n: Id) -> Self {
+        self.parties = Some(n);
+        self
+    }
+
+    /// The class group of a setup file.
+    pub fn cl_setup(self, setup: &ClSetup) -> Self {
+        self.cl_group(setup.cl())
+    }
+
+    /// A class group already at hand.
+    pub fn cl_group(mut self, cl: CL_HSMqk) -> Self {
+        self.cl = Some(cl);
+        self
+    }
+
+    /// The CL public keys of parties 1..=n.
+    pub fn cl_keyring(mut self, keyring: CLKeyRing) -> Self {
+        self.cl_keyring = Some(keyring);
+        self
+    }
+
+    /// Our CL secret key, matching our key in the keyring.
+    pub fn cl_secret(mut self, sk: SecretKey) -> Self {
+        self.cl_secret = Some(sk);
+        self
+    }
+
+    /// Binds the DKG to `session` with a key confirmation round, and the presigning
+    /// runs to it, and labels the signing outcomes with it.
+    pub fn session(mut self, session: &[u8]) -> Self {
+        self.session = Some(session.to_vec());
+        self
+    }
+
+    /// The second curve generator h, in place of `G::base_point2`. Nobody may know its
+    /// discrete log to base g, and the whole committee must use the same one.
+    pub fn curve_generator(mut self, h: G) -> Self {
+        self.curve_generator = Some(h);
+        self
+    }
+
+    /// Lets the DKG deal in the compact PVSS mode; see `PubParams::with_compact_pvss`.
+    pub fn compact_pvss(mut self) -> Self {
+        self.compact_pvss = true;
+        self
+    }
+
+    /// The limits to check the committee and messages against, in place of the defaults.
+    pub fn limits(mut self, limits: ProtocolLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// How many dealings must verify, in place of `Qualification::HonestMajority`.
+    pub fn qualification(mut self, qualification: Qualification) -> Self {
+        self.qualification = qualification;
+        self
+    }
+
+    /// Checks the committee against the limits and the qualification, our index against
+    /// n, and that the keyring covers the committee with our secret key behind our entry.
+    pub fn build(self) -> Result<Participant, ParticipantError> {
+        let missing = |field| ParticipantError::Missing { field };
+        let index = self.index.ok_or(missing("index"))?;
+        let t = self.threshold.ok_or(missing("threshold"))?;
+        let n = self.parties.ok_or(missing("number of parties"))?;
+        let cl = self.cl.ok_or(missing("CL setup"))?;
+        let cl_keyring = self.cl_keyring.ok_or(missing("CL keyring"))?;
+        let cl_secret = self.cl_secret.ok_or(missing("CL secret key"))?;
+
+        let limits = self.limits.unwrap_or_default();
+        limits.check(n, t)?;
+        if index >= n {
+            return Err(ParticipantError::IndexOutOfRange { index, n });
+        }
+        let ids = cl_keyring.missing(n);
+        if !ids.is_empty() {
+            return Err(ParticipantError::IncompleteKeyring { ids });
+        }
+        let id = PartyId::from_wire_index(index).unwrap().to_share_index();
+        if cl.public_key_gen(&cl_secret).elt() != cl_keyring[&id].elt() {
+            return Err(ParticipantError::SecretKeyMismatch { id });
+        }
+
+        let pp = PubParams {
+            cl,
+            t,
+            n,
+            cl_keyring,
+            curve_generator: self
+                .curve_generator
+                .unwrap_or_else(|| G::base_point2().clone()),
+            limits,
+            compact_pvss: self.compact_pvss,
+            qualification: Qualification::default(),
+        }
+        .with_qualification(self.qualification)?;
+
+        Ok(Participant {
+            id,
+            pp,
+            cl_secret,
+            session: self.session,
+        })
+    }
+}
+
+/// Runs the DKG, with key confirmation if `me` has a session.
+pub async fn dkg<M>(
+    party: M,
+    me: &Participant,
+    lazy_verification: bool,
+) -> Result<DkgOutput, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = DkgMsg>,
+{
+    let pp = &me.pp;
+    let state = DkgState::new(
+        me.id,
+        pp,
+        &pp.curve_generator,
+        &me.cl_secret,
+        lazy_verification,
+    );
+    match &me.session {
+        Some(session) => run_within(party, state.with_key_confirmation(session), &pp.limits).await,
+        None => run_within(party, state, &pp.limits).await,
+    }
+}
+
+/// Presigns the presignature numbered `counter` in the session of `me`, which every
+/// party of the run must pass alike and no two runs on the key may share. Without a
+/// session the proofs are bound to the counter alone.
+pub async fn presign<M>(
+    party: M,
+    me: &Participant,
+    key: &DkgOutput,
+    counter: u64,
+) -> Result<PreSignature, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = PresignMsg>,
+{
+    let pp = &me.pp;
+    let state = PresignState::new(
+        me.id,
+        pp,
+        &pp.curve_generator,
+        &me.cl_secret,
+        key,
+        PresignSession::new(me.session().unwrap_or_default(), counter),
+    );
+    run_within(party, state, &pp.limits).await
+}
+
+/// Signs `msg`, labelling the outcome with the session of `me` if it has one.
+pub async fn sign<M>(
+    party: M,
+    me: &Participant,
+    context: &SigningContext,
+    presignature: PreSignature,
+    msg: &[u8],
+) -> Result<SigningOutcome, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = SignMsg>,
+{
+    let pp = &me.pp;
+    let state = SignState::new(me.id, pp, &pp.curve_generator, context, presignature, msg);
+    match &me.session {
+        Some(session) => run_within(party, state.with_session_id(session), &pp.limits).await,
+        None => run_within(party, state, &pp.limits).await,
+    }
+}
+
+#[test]
+pub fn test_participant_builder() {
+    use crate::spdz::simulate_pp;
+    use crate::utils::Zq;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let builder = |index: PartyIndex, t: Id, n: Id| {
+        Participant::builder()
+            .index(index)
+            .threshold(t)
+            .parties(n)
+            .cl_group(pp.cl.clone())
+            .cl_keyring(pp.cl_keyring.clone())
+            .cl_secret(secret_keys[&1].clone())
+    };
+
+    let me = builder(0, 2, 3).session(b"session").build().unwrap();
+    assert_eq!(
+        (me.id(), me.index(), me.session()),
+        (1, 0, Some(&b"session"[..]))
+    );
+    assert_eq!(me.pp().fingerprint(), pp.fingerprint());
+    assert!(!me.pp().compact_pvss);
+
+    // the settings with defaults
+    let h = G::generator() * Zq::from(7u64);
+    let limits = ProtocolLimits {
+        max_n: 3,
+        ..ProtocolLimits::default()
+    };
+    let me = builder(0, 2, 3)
+        .curve_generator(h.clone())
+        .compact_pvss()
+        .limits(limits)
+        .build()
+        .unwrap();
+    assert_eq!(me.pp().curve_generator, h);
+    assert!(me.pp().compact_pvss);
+    assert_eq!(me.pp().limits, limits);
+    let robust = builder(0, 2, 3).qualification(Qualification::Robust);
+    assert_eq!(robust.build().unwrap().pp().min_qualified(), 3);
+
+    let err = |builder: ParticipantBuilder| builder.build().err().unwrap();
+    assert_eq!(
+        err(Participant::builder().index(0)),
+        ParticipantError::Missing { field: "threshold" }
+    );
+    // t and n swapped
+    assert_eq!(
+        err(builder(0, 3, 2)),
+        ParticipantError::Limit(LimitError::InvalidThreshold { t: 3, n: 2 })
+    );
+    assert_eq!(
+        err(builder(0, 2, 4).limits(limits)),
+        ParticipantError::Limit(LimitError::TooManyParties { n: 4, max: 3 })
+    );
+    assert_eq!(
+        err(builder(0, 3, 3).qualification(Qualification::Robust)),
+        ParticipantError::Limit(LimitError::UnreachableQualification { need: 5, n: 3 })
+    );
+    assert_eq!(
+        err(builder(3, 2, 3)),
+        ParticipantError::IndexOutOfRange { index: 3, n: 3 }
+    );
+    assert_eq!(
+        err(builder(0, 2, 4)),
+        ParticipantError::IncompleteKeyring { ids: vec![4] }
+    );
+    // party 1's key under index 1, which is party 2
+    assert_eq!(
+        err(builder(1, 2, 3)),
+        ParticipantError::SecretKeyMismatch { id: 2 }
+    );
+}
+
+#[test]
+pub fn test_participants_match_the_simulation() {
+    use crate::spdz::simulate_pp;
+    use crate::test_utils::simulation::{participants, run_full_protocol, run_with_participants};
+
+    let msg = b"built, not listed";
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let committee = participants(&pp, &secret_keys);
+    assert!(committee
+        .iter()
+        .all(|me| me.pp().fingerprint() == pp.fingerprint()));
+
+    // the same outcomes as the positional entry points give
+    for outcomes in [
+        run_full_protocol(2, 3, msg),
+        run_with_participants(&committee, msg),
+    ] {
+        assert_eq!(outcomes.len(), 3);
+        assert!(outcomes
+            .iter()
+            .all(|(signature, pk)| *pk == outcomes[0].1 && signature.verify(pk, msg)));
+    }
+}
@@ -0,0 +1,714 @@
+//! Messages of the presigning phase and of the online signing round that consumes
+//! its output. Each message comes with the checks a receiver runs before accepting
+//! it, given what the receiver knows at that point of the protocol.
+
+use bicycl::RandGen;
+use curv::arithmetic::Converter;
+use round_based::{PartyIndex, ProtocolMessage, RoundMessage};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::interpolation::lagrange_coeffs_at;
+use crate::replay::ReplayCache;
+use crate::spdz::{DkgOutput, DkgTranscript, OpenPowerMsg, PvssMsg};
+use crate::utils::*;
+use crate::validate::{Validate, ValidationError};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PresignError {
+    #[error("the message is malformed: {0}")]
+    Malformed(#[from] ValidationError),
+    #[error("the dealing of {nonce} does not verify: {source}")]
+    Dealing {
+        nonce: &'static str,
+        source: NizkError,
+    },
+    #[error("the opening of the gamma commitment does not match the sender's MAC")]
+    GammaOpening,
+    #[error("the MtA dealings of k * gamma and k * x do not verify: {source}")]
+    Mta { source: NizkError },
+    #[error("the share does not match its MAC")]
+    MacMismatch,
+    #[error("the share refers to a different nonce commitment")]
+    NonceMismatch,
+    #[error("the nonce has no usable x coordinate")]
+    DegenerateNonce,
+    #[error("the own share of {share} does not match its MAC")]
+    OwnMacMismatch { share: &'static str },
+    #[error("the nonce does not match Γ and delta")]
+    NonceCommitmentMismatch,
+    #[error("the MACs of {share} do not lie on a polynomial of degree below t")]
+    InconsistentMacs { share: &'static str },
+    #[error("no MACs for parties {parties:?}, who hold key shares")]
+    MissingParties { parties: Vec<Id> },
+    #[error("the message responds to other messages of the previous round than ours")]
+    ViewMismatch,
+    #[error("the dealing of {nonce} was dealt before")]
+    ReplayedDealing { nonce: &'static str },
+}
+
+/// Why a party's contribution was left out of a signature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisqualReason {
+    /// Its nonce dealings did not verify.
+    NonceDealing,
+    /// Its Γ opening or MtA dealings did not verify.
+    Mta,
+    /// Its share of delta did not match its MAC.
+    DeltaShare,
+    /// Its signature share did not match its MACs.
+    SignatureShare,
+    /// Its message failed `Validate`, e.g. for a point at infinity or a missing party.
+    Malformed,
+    /// Its message responds to other messages of the previous round than ours.
+    ViewMismatch,
+    /// It repeated a nonce dealing of an earlier session, see `ReplayCache`.
+    ReplayedDealing,
+    /// Its share of delta matched its MAC, but came with another Γ than ours.
+    NonceMismatch,
+    /// The DKG left it without a verified public key share.
+    NotQualifiedInDkg,
+    /// Parties reported different first round messages from it, see
+    /// `equivocating_dealers`.
+    Equivocation,
+}
+
+/// Which presignature the proofs of a presigning run belong to: `counter` tells apart the
+/// presignatures of the session `session_id`. The MtA proofs are bound to it, so that none
+/// can be replayed in another run among the same parties, and the nonce dealings to the
+/// session id. The session id is what makes a run fresh, so it should not be one any
+/// single party picked; see `from_contributions`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PresignSession {
+    pub session_id: Vec<u8>,
+    pub counter: u64,
+}
+
+impl PresignSession {
+    pub fn new(session_id: &[u8], counter: u64) -> Self {
+        PresignSession {
+            session_id: session_id.to_vec(),
+            counter,
+        }
+    }
+
+    /// The session whose id hashes the random `contributions` of all parties, e.g. sent
+    /// along before presigning. No dealer can then steer its dealings into a session it
+    /// dealt in before, as long as one contribution was drawn afresh.
+    pub fn from_contributions(contributions: &BTreeMap<Id, [u8; 32]>, counter: u64) -> Self {
+        let mut hasher = TranscriptHasher::new("presign/session");
+        for (id, contribution) in contributions {
+            hasher
+                .append("id", &id.to_be_bytes())
+                .append("contribution", contribution);
+        }
+        PresignSession {
+            session_id: hasher.finalize().to_vec(),
+            counter,
+        }
+    }
+
+    /// The context of the proof for `instance` in `round`, passed to the NIZKs as `aux`.
+    pub fn aux(&self, round: u16, instance: &str) -> Vec<u8> {
+        let mut hasher = TranscriptHasher::new("presign/aux");
+        hasher
+            .append("session_id", &self.session_id)
+            .append("counter", &self.counter.to_be_bytes())
+            .append("round", &round.to_be_bytes())
+            .append("instance", instance.as_bytes());
+        hasher.finalize().to_vec()
+    }
+
+    /// The context of the PVSS proof of the dealing of `nonce` in a first round
+    /// message. It binds the session id but not the counter, which the MtA proofs bind.
+    pub fn dealing_aux(&self, nonce: &str) -> Vec<u8> {
+        let mut hasher = TranscriptHasher::new("presign/dealing-aux");
+        hasher
+            .append("session_id", &self.session_id)
+            .append("nonce", nonce.as_bytes());
+        hasher.finalize().to_vec()
+    }
+}
+
+/// The round of `PresignMsg::MtAwc`, as its aux values record it.
+pub(crate) const MTA_ROUND: u16 = <PresignMsg as RoundMessage<MtAwcMsg>>::ROUND;
+
+/// A digest of the messages a party accepted in presigning round `round`, by sender.
+/// Each message of the next round carries the sender's, and receivers compare it with
+/// their own: a message relayed into a run whose previous round went differently for
+/// the receiver is told apart before it is combined with messages it does not respond
+/// to. A sender that showed different parties different messages is caught this way
+/// one round late. The first round is covered by `dealing_digests` instead.
+pub fn round_digest<M: Serialize>(round: u16, accepted: &BTreeMap<Id, M>) -> [u8; 32] {
+    let mut hasher = TranscriptHasher::new("presign/round-digest");
+    hasher.append("round", &round.to_be_bytes());
+    for (id, msg) in accepted {
+        hasher
+            .append("id", &id.to_be_bytes())
+            .append("msg", &crate::codec::encode(msg));
+    }
+    hasher.finalize()
+}
+
+/// The digest of each first round message in `accepted`, by dealer. An MtA message
+/// carries those of the dealings its sender combined the nonces from, so that receivers
+/// can tell which dealer it saw differently from them rather than only that it did.
+pub fn dealing_digests<M: Serialize>(accepted: &BTreeMap<Id, M>) -> BTreeMap<Id, [u8; 32]> {
+    accepted
+        .iter()
+        .map(|(id, msg)| {
+            let mut hasher = TranscriptHasher::new("presign/dealing-digest");
+            hasher
+                .append("id", &id.to_be_bytes())
+                .append("msg", &crate::codec::encode(msg));
+            (*id, hasher.finalize())
+        })
+        .collect()
+}
+
+/// The dealers for which `reports`, the `dealing_digests` of different MtA messages,
+/// name different first round messages. Honest parties pass on what they received, so
+/// such a dealer showed different parties different dealings. A party that lies about
+/// a digest gets the dealer dropped the same way; the two cannot be told apart, and
+/// dropping the dealer keeps both the dealer's victims and everyone else in the run.
+pub fn equivocating_dealers<'m>(
+    reports: impl IntoIterator<Item = &'m BTreeMap<Id, [u8; 32]>>,
+) -> BTreeSet<Id> {
+    let mut seen: BTreeMap<Id, &[u8; 32]> = BTreeMap::new();
+    let mut equivocating = BTreeSet::new();
+    for (id, digest) in reports.into_iter().flatten() {
+        if *seen.entry(*id).or_insert(digest) != digest {
+            equivocating.insert(*id);
+        }
+    }
+    equivocating
+}
+
+/// What a receiver checks a first round message against.
+#[derive(Clone, Copy)]
+pub struct NonceGenContext<'a> {
+    pub pp: &'a PubParams,
+    pub h: &'a G,
+    pub session: &'a PresignSession,
+}
+
+/// What a receiver checks a second round message against: the joint nonce dealings
+/// of the first round and the `dealing_digests` of the messages they were combined
+/// from, and the sender's id and public key share.
+#[derive(Clone, Copy)]
+pub struct MtAwcContext<'a> {
+    pub pp: &'a PubParams,
+    pub h: &'a G,
+    pub session: &'a PresignSession,
+    pub k_pvss: &'a JointPvssResult,
+    pub gamma_pvss: &'a JointPvssResult,
+    pub nonce_digests: &'a BTreeMap<Id, [u8; 32]>,
+    pub sender: Id,
+    pub x_pub: &'a G,
+}
+
+/// What a receiver checks a third round message against: the sender's MAC of delta,
+/// the receiver's own Γ, and the digest of the MtA messages it was combined from.
+#[derive(Clone, Copy)]
+pub struct PreSignFinalContext<'a> {
+    pub h: &'a G,
+    pub delta_mac: &'a G,
+    pub Gamma: &'a G,
+    pub prev_round_digest: &'a [u8; 32],
+}
+
+/// What a party checks its own presignature against.
+#[derive(Clone, Copy)]
+pub struct PreSignatureContext<'a> {
+    pub h: &'a G,
+    pub my_id: Id,
+}
+
+/// What a receiver checks a signature share against: the sender's MACs from the
+/// presignature, the message hash m and r, and the presignature's `view_digest`.
+#[derive(Clone, Copy)]
+pub struct OnlineSignContext<'a> {
+    pub h: &'a G,
+    pub k_mac: &'a G,
+    pub chi_mac: &'a G,
+    pub m: &'a Zq,
+    pub r: &'a Zq,
+    pub prev_round_digest: &'a [u8; 32],
+}
+
+/// Dealings of the two nonces k and gamma, sent in the first presigning round. No
+/// presigning round comes before it, so it carries the `PubParams::fingerprint` of the
+/// sender's committee in place of a round digest. The PVSS proofs are bound to the
+/// session id, see `PresignSession::dealing_aux`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NonceGenMsg {
+    pub(crate) prev_round_digest: [u8; 32],
+    pub(crate) k: PvssMsg,
+    pub(crate) gamma: PvssMsg,
+}
+
+impl NonceGenMsg {
+    /// Deals in the default session, that of `PresignState::new`.
+    pub fn random(pp: &PubParams, rng: &mut RandGen, curve_generator: &G) -> Self {
+        Self::random_in_session(pp, rng, curve_generator, &PresignSession::default())
+    }
+
+    pub fn random_in_session(
+        pp: &PubParams,
+        rng: &mut RandGen,
+        curve_generator: &G,
+        session: &PresignSession,
+    ) -> Self {
+        let k_aux = session.dealing_aux("k");
+        let gamma_aux = session.dealing_aux("gamma");
+
+        // the two dealings share no state, so each gets its own generator and thread
+        let k_seed = rng.random_mpz(&pp.cl.encrypt_randomness_bound());
+        let gamma_seed = rng.random_mpz(&pp.cl.encrypt_randomness_bound());
+
+        let (k, gamma) = crate::par::join(
+            move || PvssMsg::random_with_aux(pp, &mut seeded_rng(&k_seed), curve_generator, &k_aux),
+            move || {
+                let mut rng = seeded_rng(&gamma_seed);
+                PvssMsg::random_with_aux(pp, &mut rng, curve_generator, &gamma_aux)
+            },
+        );
+
+        NonceGenMsg {
+            prev_round_digest: pp.fingerprint(),
+            k,
+            gamma,
+        }
+    }
+
+    /// Checks that the sender is of the same committee, and the PVSS proofs of both
+    /// dealings in the receiver's session.
+    pub fn verify(&self, context: &NonceGenContext) -> Result<(), PresignError> {
+        let NonceGenContext { pp, h, session } = *context;
+        self.validate(pp)?;
+        if self.prev_round_digest != pp.fingerprint() {
+            return Err(PresignError::ViewMismatch);
+        }
+        for (nonce, msg) in [("k", &self.k), ("gamma", &self.gamma)] {
+            msg.verify_with_aux(pp, h, &session.dealing_aux(nonce))
+                .map_err(|source| PresignError::Dealing { nonce, source })?;
+        }
+        Ok(())
+    }
+
+    /// Checks with `cache` that neither dealing was dealt before by `dealer`, and
+    /// records both.
+    pub fn check_fresh(&self, dealer: Id, cache: &mut dyn ReplayCache) -> Result<(), PresignError> {
+        // both are recorded, so that a dealing repeated as the other nonce is caught too
+        let k_fresh = cache.insert(dealer, DkgTranscript::dealing_hash(&self.k.dealing));
+        let gamma_fresh = cache.insert(dealer, DkgTranscript::dealing_hash(&self.gamma.dealing));
+        match (k_fresh, gamma_fresh) {
+            (false, _) => Err(PresignError::ReplayedDealing { nonce: "k" }),
+            (_, false) => Err(PresignError::ReplayedDealing { nonce: "gamma" }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Second presigning round: open Γ_i = g^gamma_i and multiply the joint k
+/// by gamma_i and by the key share x_i under encryption, both proven at once.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MtAwcMsg {
+    pub(crate) nonce_digests: BTreeMap<Id, [u8; 32]>,
+    pub(crate) gamma_pub: OpenPowerMsg,
+    pub(crate) k_gamma_dealing: MtaDealing,
+    pub(crate) k_x_dealing: MtaDealing,
+    pub(crate) mta_proof: DualMtaNizk,
+}
+
+impl MtAwcMsg {
+    /// Responds to the first round messages with `dealing_digests` `nonce_digests`,
+    /// which `k_pvss` and `gamma_pvss` were combined from.
+    pub fn new(
+        pp: &PubParams,
+        h: &G,
+        rng: &mut RandGen,
+        k_pvss: &JointPvssResult,
+        gamma_pvss: &JointPvssResult,
+        my_id: Id,
+        gamma_share: &Zq,
+        x_share: &Zq,
+        session: &PresignSession,
+        nonce_digests: BTreeMap<Id, [u8; 32]>,
+    ) -> Self {
+        let point = G::generator() * gamma_share;
+        let proof = DleqNizk::prove(
+            h,
+            &gamma_pvss.curve_mac(my_id),
+            &G::generator(),
+            &point,
+            gamma_share,
+            rng,
+        );
+
+        let [(k_gamma_dealing, k_gamma_masks), (k_x_dealing, k_x_masks)] =
+            MtaDealing::new_dual(pp, k_pvss, [gamma_share, x_share], rng, h);
+        let mta_proof = DualMtaNizk::prove(
+            pp,
+            k_pvss,
+            [&k_gamma_dealing, &k_x_dealing],
+            h,
+            rng,
+            [gamma_share, x_share],
+            [&k_gamma_masks, &k_x_masks],
+            &session.aux(MTA_ROUND, "k*gamma,k*x"),
+        )
+        .expect("the dealings are built from the same PVSS result");
+
+        MtAwcMsg {
+            nonce_digests,
+            gamma_pub: OpenPowerMsg { point, proof },
+            k_gamma_dealing,
+            k_x_dealing,
+            mta_proof,
+        }
+    }
+
+    /// Checks that the message responds to the same first round messages as ours, the
+    /// opening of Γ_sender against the sender's gamma MAC, and both MtA dealings against
+    /// Γ_sender and the sender's public key share respectively.
+    pub fn verify(&self, context: &MtAwcContext) -> Result<(), PresignError> {
+        let MtAwcContext {
+            pp,
+            h,
+            session,
+            k_pvss,
+            gamma_pvss,
+            nonce_digests,
+            sender,
+            x_pub,
+        } = *context;
+
+        self.validate(pp)?;
+        if self.nonce_digests != *nonce_digests {
+            return Err(PresignError::ViewMismatch);
+        }
+        if !self.gamma_pub.verify(h, &gamma_pvss.curve_mac(sender)) {
+            return Err(PresignError::GammaOpening);
+        }
+        self.mta_proof
+            .verify_detailed(
+                pp,
+                k_pvss,
+                [&self.k_gamma_dealing, &self.k_x_dealing],
+                h,
+                [&self.gamma_pub.point, x_pub],
+                &session.aux(MTA_ROUND, "k*gamma,k*x"),
+            )
+            .map_err(|source| PresignError::Mta { source })
+    }
+}
+
+/// Third presigning round: reveal the share of delta = k * gamma, along with the
+/// aggregate Γ = g^gamma the sender combined from the round 2 openings.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PreSignFinalMsg {
+    pub(crate) prev_round_digest: [u8; 32],
+    pub(crate) delta_share: Zq,
+    #[serde(with = "crate::codec::compressed_point")]
+    pub(crate) Gamma: G,
+}
+
+impl PreSignFinalMsg {
+    /// The nonce commitment the sender's share of delta refers to; R = Γ^(1/delta).
+    pub fn committed_nonce(&self) -> &G {
+        &self.Gamma
+    }
+
+    /// Checks that the message responds to the same MtA messages as ours, the share of
+    /// delta against the sender's MAC, then that it refers to the receiver's own Γ.
+    pub fn verify(&self, context: &PreSignFinalContext) -> Result<(), PresignError> {
+        if self.prev_round_digest != *context.prev_round_digest {
+            return Err(PresignError::ViewMismatch);
+        }
+        if context.h * &self.delta_share != *context.delta_mac {
+            return Err(PresignError::MacMismatch);
+        }
+        if self.Gamma != *context.Gamma {
+            return Err(PresignError::NonceMismatch);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum PresignMsg {
+    NonceGen(NonceGenMsg),
+    MtAwc(MtAwcMsg),
+    PreSignFinal(PreSignFinalMsg),
+}
+
+/// A party's share of a presignature, together with the MACs that let it check
+/// the other parties' signature shares.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PreSignature {
+    pub R: G,
+    /// The aggregate Γ and delta = k * gamma that R = Γ^(1/delta) was derived from.
+    pub Gamma: G,
+    pub delta: Zq,
+    pub k_share: Zq,
+    pub chi_share: Zq,
+    pub k_macs: BTreeMap<Id, G>,
+    pub chi_macs: BTreeMap<Id, G>,
+    /// Parties whose messages were rejected while presigning.
+    pub disqualified: BTreeMap<PartyIndex, DisqualReason>,
+}
+
+/// The public part of a presignature, for an independent service, such as a custody
+/// policy engine, to check the nonce before any signature share is released.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PresignatureAnnouncement {
+    pub R: G,
+    pub r: Zq,
+    pub k_commitments: BTreeMap<Id, G>,
+    pub chi_commitments: BTreeMap<Id, G>,
+}
+
+/// A party's shares of one pair of nonces k and gamma, from a nonce generation run held
+/// ahead of presigning. It is spent by `PresignState::from_nonces`, which takes it by
+/// value, as a nonce used in two presignatures leaks the key. For the same reason it
+/// cannot be serialized, so no copy of it outlives the process, and its shares are
+/// wiped when it is dropped.
+#[derive(Debug)]
+pub struct NoncePair {
+    /// The `PubParams::fingerprint` of the committee that generated the nonces.
+    pub(crate) fingerprint: [u8; 32],
+    pub(crate) k_pvss: JointPvssResult,
+    pub(crate) gamma_pvss: JointPvssResult,
+    pub(crate) k_share: Zq,
+    pub(crate) gamma_share: Zq,
+    /// The `dealing_digests` of the nonce dealings the pair was combined from.
+    pub(crate) nonce_digests: BTreeMap<Id, [u8; 32]>,
+    /// Parties whose nonce dealings were rejected.
+    pub(crate) disqualified: BTreeMap<PartyIndex, DisqualReason>,
+}
+
+impl NoncePair {
+    /// The `PubParams::fingerprint` of the committee that generated the nonces.
+    pub fn fingerprint(&self) -> &[u8; 32] {
+        &self.fingerprint
+    }
+}
+
+/// Overwrites the shares, in the same way as `Polynomial::zeroize`.
+impl Zeroize for NoncePair {
+    fn zeroize(&mut self) {
+        self.k_share = Zq::zero();
+        self.gamma_share = Zq::zero();
+    }
+}
+
+impl Drop for NoncePair {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for NoncePair {}
+
+impl PreSignature {
+    /// Assembles a presignature with the nonce R = Γ^(1/delta). A nonce whose x coordinate
+    /// reduces to zero would sign with r = 0, which no verifier accepts, so it is refused
+    /// and the caller presigns again.
+    pub fn new(
+        Gamma: G,
+        delta: Zq,
+        k_share: Zq,
+        chi_share: Zq,
+        k_macs: BTreeMap<Id, G>,
+        chi_macs: BTreeMap<Id, G>,
+        disqualified: BTreeMap<PartyIndex, DisqualReason>,
+    ) -> Result<Self, PresignError> {
+        let R = &Gamma * delta.invert().ok_or(PresignError::DegenerateNonce)?;
+        match R.x_coord() {
+            Some(x) if !Zq::from_bigint(&x).is_zero() => Ok(PreSignature {
+                R,
+                Gamma,
+                delta,
+                k_share,
+                chi_share,
+                k_macs,
+                chi_macs,
+                disqualified,
+            }),
+            _ => Err(PresignError::DegenerateNonce),
+        }
+    }
+
+    /// Re-randomizes the nonce by a public, nonzero `tweak` that every signer applies
+    /// alike: R becomes R^tweak, and the shares of k and chi are divided by it along with
+    /// their MACs, so the signature shares still combine to a valid signature under the
+    /// new R. Whoever saw only the announced R cannot link it to signatures made after
+    /// re-randomizing with a tweak unknown to them.
+    ///
+    /// The presignature still signs only once: two signatures from one presignature
+    /// reveal the key, whether or not it was re-randomized in between. Fails with
+    /// `DegenerateNonce` for a zero tweak or a new R without a usable x coordinate,
+    /// leaving the presignature as it was.
+    pub fn rerandomize(&mut self, tweak: &Zq) -> Result<(), PresignError> {
+        let tweak_inv = tweak.invert().ok_or(PresignError::DegenerateNonce)?;
+        let R = &self.R * tweak;
+        match R.x_coord() {
+            Some(x) if !Zq::from_bigint(&x).is_zero() => {}
+            _ => return Err(PresignError::DegenerateNonce),
+        }
+
+        // R = Γ^(1/delta) still holds with delta / tweak
+        self.R = R;
+        self.delta = &self.delta * &tweak_inv;
+        self.k_share = &self.k_share * &tweak_inv;
+        self.chi_share = &self.chi_share * &tweak_inv;
+        for mac in self.k_macs.values_mut().chain(self.chi_macs.values_mut()) {
+            *mac = &*mac * &tweak_inv;
+        }
+        Ok(())
+    }
+
+    /// The nonce point R.
+    pub fn r_point(&self) -> G {
+        self.R.clone()
+    }
+
+    /// The r of the signatures made with this presignature: the x coordinate of R mod q.
+    pub fn r_scalar(&self) -> Zq {
+        Zq::from_bigint(&self.R.x_coord().unwrap())
+    }
+
+    /// The commitments h^k_j to the parties' shares of the nonce.
+    pub fn commitments(&self) -> &BTreeMap<Id, G> {
+        &self.k_macs
+    }
+
+    /// A digest of the public part of the presignature, which every party that presigned
+    /// along holds alike. Signature shares carry it in place of a round digest, as the
+    /// parties may have finished presigning on different shares of delta.
+    pub fn view_digest(&self) -> [u8; 32] {
+        let mut hasher = TranscriptHasher::new("presign/view");
+        hasher.append("announcement", &crate::codec::encode(&self.announcement()));
+        hasher.finalize()
+    }
+
+    /// Everything about this presignature an outside party may see, without the shares.
+    pub fn announcement(&self) -> PresignatureAnnouncement {
+        PresignatureAnnouncement {
+            R: self.r_point(),
+            r: self.r_scalar(),
+            k_commitments: self.k_macs.clone(),
+            chi_commitments: self.chi_macs.clone(),
+        }
+    }
+
+    /// Checks the party's own shares against its MACs, and that the nonce is usable.
+    pub fn verify(&self, context: &PreSignatureContext) -> Result<(), PresignError> {
+        let macs = self
+            .k_macs
+            .get(&context.my_id)
+            .zip(self.chi_macs.get(&context.my_id));
+        match macs {
+            Some((k_mac, chi_mac))
+                if context.h * &self.k_share == *k_mac
+                    && context.h * &self.chi_share == *chi_mac => {}
+            _ => return Err(PresignError::MacMismatch),
+        }
+        match self.R.x_coord() {
+            Some(x) if !Zq::from_bigint(&x).is_zero() => Ok(()),
+            _ => Err(PresignError::DegenerateNonce),
+        }
+    }
+
+    /// Re-checks a presignature before it is used, e.g. after it sat in storage, as far
+    /// as public data and the holder's own shares allow: the holder's MACs, R against Γ
+    /// and delta, that every party holding a share of `key` and not disqualified while
+    /// presigning has MACs, and that the MACs of k and of chi each lie on a polynomial
+    /// of degree below t.
+    pub fn validate(
+        &self,
+        context: &PreSignatureContext,
+        pp: &PubParams,
+        key: &DkgOutput,
+    ) -> Result<(), PresignError> {
+        let PreSignatureContext { h, my_id } = *context;
+        for (share, own, macs) in [
+            ("k", &self.k_share, &self.k_macs),
+            ("chi", &self.chi_share, &self.chi_macs),
+        ] {
+            if macs.get(&my_id) != Some(&(h * own)) {
+                return Err(PresignError::OwnMacMismatch { share });
+            }
+        }
+
+        if &self.R * &self.delta != self.Gamma {
+            return Err(PresignError::NonceCommitmentMismatch);
+        }
+        self.verify(context)?;
+
+        let missing: Vec<Id> = key
+            .tpk
+            .pub_shares
+            .keys()
+            .copied()
+            .filter(|&id| {
+                let from = PartyId::from_share_index(id).to_wire_index();
+                !self.disqualified.contains_key(&from)
+                    && !(self.k_macs.contains_key(&id) && self.chi_macs.contains_key(&id))
+            })
+            .collect();
+        if !missing.is_empty() {
+            return Err(PresignError::MissingParties { parties: missing });
+        }
+
+        for (share, macs) in [("k", &self.k_macs), ("chi", &self.chi_macs)] {
+            if !on_low_degree_polynomial(pp, macs) {
+                return Err(PresignError::InconsistentMacs { share });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the points lie on one polynomial of degree below t, judged by interpolating
+/// the first t of them at the evaluation points of the others.
+fn on_low_degree_polynomial(pp: &PubParams, points: &BTreeMap<Id, G>) -> bool {
+    let basis: Vec<Id> = points.keys().copied().take(pp.t as usize).collect();
+    points.iter().skip(basis.len()).all(|(&j, point)| {
+        let coeffs = lagrange_coeffs_at(&basis, &party_eval_point(j));
+        coeffs.iter().map(|(i, c)| c * &points[i]).sum::<G>() == *point
+    })
+}
+
+/// The single online signing round: a share of s = k * m + r * chi.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OnlineSignMsg {
+    pub(crate) prev_round_digest: [u8; 32],
+    pub(crate) sig_share: Zq,
+}
+
+impl OnlineSignMsg {
+    /// Checks that the share is made with the same presignature as ours, and against
+    /// the sender's MACs of k and chi.
+    pub fn verify(&self, context: &OnlineSignContext) -> Result<(), PresignError> {
+        let OnlineSignContext {
+            h,
+            k_mac,
+            chi_mac,
+            m,
+            r,
+            prev_round_digest,
+        } = *context;
+        if self.prev_round_digest != *prev_round_digest {
+            return Err(PresignError::ViewMismatch);
+        }
+        if h * &self.sig_share == k_mac * m + chi_mac * r {
+            Ok(())
+        } else {
+            Err(PresignError::MacMismatch)
+        }
+    }
+}
@@ -0,0 +1,489 @@
+//! Rotation of a party's long-term CL key without a new DKG. The party encrypts its key
+//! share under the new key and proves, with one Fiat-Shamir challenge, that it holds
+//! the old key and that the ciphertext encrypts the discrete log of its public share.
+//! Every party checks the proof against its keyring and the key's public shares, and
+//! registers the new key. The key share, and so the joint key, stay as they are.
+//!
+//! A committee may also presign and sign under keys other than those of the DKG, e.g.
+//! ephemeral ones on the machines that sign. Each party then broadcasts a
+//! `SigningKeyMsg` for the session, and `PubParams::with_signing_keys` puts the keys in
+//! a keyring of their own, leaving the DKG keyring as it is.
+
+use std::collections::BTreeMap;
+
+use bicycl::{ClearText, Mpz, PublicKey, SecretKey, QFI};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::spdz::{DkgOutput, ThresholdPubKey};
+use crate::utils::*;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RekeyError {
+    #[error("party {id} is not in the committee")]
+    NotInCommittee { id: Id },
+    #[error("party {id} has no public share of the key")]
+    NoPubShare { id: Id },
+    #[error("the rotation proof does not verify")]
+    Proof(#[from] NizkError),
+    #[error("party {id} announced more than one signing key")]
+    DuplicateKey { id: Id },
+    #[error("no signing key for parties {ids:?}")]
+    MissingKeys { ids: Vec<Id> },
+}
+
+/// Announces that party `id` replaces its CL public key by `new_pk`. It carries the
+/// party's key share encrypted under `new_pk`, and a `RekeyNizk` binding the two.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RekeyMsg {
+    pub id: Id,
+    pub new_pk: QFI,
+    pub randomness: QFI,
+    pub encryption: QFI,
+    pub proof: RekeyNizk,
+}
+
+/// Proof of knowledge of the old CL secret key sk, and of r and x such that
+/// (h^r, f^x * new_pk^r) is the encrypted share and g^x the public share.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RekeyNizk {
+    pub e: Zq,
+    pub z_sk: Mpz,
+    pub z_r: Mpz,
+    pub z_x: Zq,
+}
+
+impl DkgOutput {
+    /// Party `my_id`'s announcement that it now uses `new_pk`, made with its current key
+    /// `old_sk`. The other parties pass it to `RekeyMsg::apply`; the party itself applies
+    /// it too, and decrypts with the new secret key from then on.
+    pub fn rekey(
+        &self,
+        my_id: Id,
+        pp: &PubParams,
+        old_sk: &SecretKey,
+        new_pk: &PublicKey,
+        rng: &mut impl ClRandomness,
+    ) -> RekeyMsg {
+        let r = rng.sample_encrypt_randomness(&pp.cl);
+        let x = Mpz::from(&self.share);
+        let ciphertext = pp
+            .cl
+            .encrypt_with_r(new_pk, &ClearText::with_mpz(&pp.cl, &x), &r);
+
+        let (u_sk, u_r, v) = (
+            rng.sample_response_mask(&pp.cl),
+            rng.sample_response_mask(&pp.cl),
+            rng.sample_scalar(),
+        );
+        let U0 = pp.cl.power_of_h(&u_sk);
+        let U1 = pp.cl.power_of_h(&u_r);
+        let U2 = pp
+            .cl
+            .power_of_f(&Mpz::from(&v))
+            .compose(&pp.cl, &new_pk.exponentiation(&pp.cl, &u_r));
+        let U3 = G::generator() * &v;
+
+        let (new_pk, randomness, encryption) = (new_pk.elt(), ciphertext.c1(), ciphertext.c2());
+        let statement = statement(&new_pk, &randomness, &encryption);
+        let pub_share = &self.tpk.pub_shares[&my_id];
+        let e = RekeyNizk::challenge(pp, my_id, &statement, pub_share, [&U0, &U1, &U2], &U3);
+
+        RekeyMsg {
+            id: my_id,
+            new_pk,
+            randomness,
+            encryption,
+            proof: RekeyNizk {
+                z_sk: u_sk + Mpz::from(&e) * &old_sk.mpz(),
+                z_r: u_r + Mpz::from(&e) * &r,
+                z_x: &v + &e * &self.share,
+                e,
+            },
+        }
+    }
+}
+
+impl RekeyMsg {
+    /// Checks the proof against the keyring of `pp` and the public shares of `tpk`.
+    pub fn verify(&self, pp: &PubParams, tpk: &ThresholdPubKey) -> Result<(), RekeyError> {
+        let id = self.id;
+        if !(1..=pp.n).contains(&id) {
+            return Err(RekeyError::NotInCommittee { id });
+        }
+        let old_pk = pp
+            .cl_keyring
+            .get(&id)
+            .ok_or(NizkError::NotInKeyring { id })?;
+        let pub_share = tpk
+            .pub_shares
+            .get(&id)
+            .ok_or(RekeyError::NoPubShare { id })?;
+        let discriminant = pp.cl.discriminant();
+        if [&self.new_pk, &self.randomness, &self.encryption]
+            .iter()
+            .any(|form| form.discriminant() != discriminant)
+        {
+            return Err(NizkError::DiscriminantMismatch.into());
+        }
+        let RekeyNizk { e, z_sk, z_r, z_x } = &self.proof;
+        if z_sk.to_bytes().len() > masked_response_len(pp)
+            || z_r.to_bytes().len() > masked_response_len(pp)
+        {
+            return Err(NizkError::DeserializationError.into());
+        }
+
+        let minus_e = -Mpz::from(e);
+        let U0 = pp
+            .cl
+            .power_of_h(z_sk)
+            .compose(&pp.cl, &old_pk.elt().exp(&pp.cl, &minus_e));
+        let U1 = pp
+            .cl
+            .power_of_h(z_r)
+            .compose(&pp.cl, &self.randomness.exp(&pp.cl, &minus_e));
+        let U2 = pp
+            .cl
+            .power_of_f(&Mpz::from(z_x))
+            .compose(&pp.cl, &self.new_pk.exp(&pp.cl, z_r))
+            .compose(&pp.cl, &self.encryption.exp(&pp.cl, &minus_e));
+        let U3 = G::generator() * z_x - pub_share * e;
+
+        let statement = statement(&self.new_pk, &self.randomness, &self.encryption);
+        let expected = RekeyNizk::challenge(pp, id, &statement, pub_share, [&U0, &U1, &U2], &U3);
+        if scalar_ct_eq(&expected, e) {
+            Ok(())
+        } else {
+            Err(NizkError::ChallengeMismatch.into())
+        }
+    }
+
+    /// Verifies the message and registers the new key in the keyring of `pp`. This
+    /// changes `PubParams::fingerprint`, so all parties apply it before the next run.
+    pub fn apply(&self, pp: &mut PubParams, tpk: &ThresholdPubKey) -> Result<(), RekeyError> {
+        self.verify(pp, tpk)?;
+        let new_pk = PublicKey::from_qfi(&pp.cl, &self.new_pk);
+        pp.cl_keyring.insert(self.id, new_pk);
+        Ok(())
+    }
+}
+
+/// The new key and the encrypted share, as the challenge hashes them.
+fn statement(new_pk: &QFI, randomness: &QFI, encryption: &QFI) -> Vec<u8> {
+    crate::codec::encode(&(new_pk, randomness, encryption))
+}
+
+impl RekeyNizk {
+    fn challenge(
+        pp: &PubParams,
+        id: Id,
+        statement: &[u8],
+        pub_share: &G,
+        U: [&QFI; 3],
+        U3: &G,
+    ) -> Zq {
+        let mut hasher = TranscriptHasher::new("rekey");
+        hasher
+            .append(
+                "instance",
+                &instance_digest(&pp.cl.discriminant(), pp.t, pp.n),
+            )
+            .append("id", &id.to_be_bytes())
+            .append("old_pk", &pp.cl_keyring[&id].to_bytes())
+            .append("statement", statement)
+            .append("pub_share", &pub_share.to_bytes(false));
+        for (label, form) in ["U0", "U1", "U2"].into_iter().zip(U) {
+            hasher.append(label, &form.to_bytes());
+        }
+        hasher.append("U3", &U3.to_bytes(false));
+        hasher.challenge()
+    }
+}
+
+/// A CL public key for presigning and signing, with a proof that party `id` holds its
+/// secret key. The proof is bound to the session the key is announced for, so that an
+/// announcement cannot be replayed into a later session.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SigningKeyMsg {
+    pub id: Id,
+    pub pk: QFI,
+    pub proof: PossessionProof,
+}
+
+/// A Schnorr proof of knowledge of the discrete log to base h of a CL public key,
+/// bound to the party announcing it, to the parameters of the DKG and to the session.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PossessionProof {
+    pub e: Zq,
+    pub z: Mpz,
+}
+
+impl SigningKeyMsg {
+    /// Announces the key of `sk` as party `id`'s signing key in session `session_id`,
+    /// under the DKG parameters `pp`.
+    pub fn new(
+        pp: &PubParams,
+        id: Id,
+        sk: &SecretKey,
+        session_id: &[u8],
+        rng: &mut impl ClRandomness,
+    ) -> Self {
+        let pk = pp.cl.public_key_gen(sk).elt();
+        let u = rng.sample_response_mask(&pp.cl);
+        let U = pp.cl.power_of_h(&u);
+        let e = PossessionProof::challenge(pp, id, session_id, &pk, &U);
+        let z = u + Mpz::from(&e) * &sk.mpz();
+
+        SigningKeyMsg {
+            id,
+            pk,
+            proof: PossessionProof { e, z },
+        }
+    }
+
+    pub fn verify(&self, pp: &PubParams, session_id: &[u8]) -> Result<(), RekeyError> {
+        let id = self.id;
+        if !(1..=pp.n).contains(&id) {
+            return Err(RekeyError::NotInCommittee { id });
+        }
+        if self.pk.discriminant() != pp.cl.discriminant() {
+            return Err(NizkError::DiscriminantMismatch.into());
+        }
+        let PossessionProof { e, z } = &self.proof;
+        if z.to_bytes().len() > masked_response_len(pp) {
+            return Err(NizkError::DeserializationError.into());
+        }
+
+        let U = pp
+            .cl
+            .power_of_h(z)
+            .compose(&pp.cl, &self.pk.exp(&pp.cl, &-Mpz::from(e)));
+        let expected = PossessionProof::challenge(pp, id, session_id, &self.pk, &U);
+        if scalar_ct_eq(&expected, e) {
+            Ok(())
+        } else {
+            Err(NizkError::ChallengeMismatch.into())
+        }
+    }
+}
+
+impl PossessionProof {
+    fn challenge(pp: &PubParams, id: Id, session_id: &[u8], pk: &QFI, U: &QFI) -> Zq {
+        let mut hasher = TranscriptHasher::new("signing key");
+        hasher
+            .append("fingerprint", &pp.fingerprint())
+            .append("session_id", session_id)
+            .append("id", &id.to_be_bytes())
+            .append("pk", &pk.to_bytes())
+            .append("U", &U.to_bytes());
+        hasher.challenge()
+    }
+}
+
+impl PubParams {
+    /// The parameters to presign and sign with in session `session_id` under the keys
+    /// of `msgs`, after checking their proofs. Each party of `qualified`, the dealers
+    /// the DKG qualified, must announce a key; any other party may, and keeps its DKG
+    /// key if it does not. All else is as in `self`, the parameters of the DKG, so the
+    /// key shares and public shares of the DKG work with them unchanged: the MtA
+    /// dealings are encrypted to the new keys, and the nonce dealings too. `self` keeps
+    /// the DKG keyring, for a later DKG or rotation.
+    pub fn with_signing_keys(
+        &self,
+        session_id: &[u8],
+        qualified: &[Id],
+        msgs: &[SigningKeyMsg],
+    ) -> Result<PubParams, RekeyError> {
+        let mut keys = BTreeMap::new();
+        for msg in msgs {
+            msg.verify(self, session_id)?;
+            let pk = PublicKey::from_qfi(&self.cl, &msg.pk);
+            if keys.insert(msg.id, pk).is_some() {
+                return Err(RekeyError::DuplicateKey { id: msg.id });
+            }
+        }
+        let missing: Vec<Id> = qualified
+            .iter()
+            .copied()
+            .filter(|id| !keys.contains_key(id))
+            .collect();
+        if !missing.is_empty() {
+            return Err(RekeyError::MissingKeys { ids: missing });
+        }
+        let mut cl_keyring = self.cl_keyring.clone();
+        for (id, pk) in keys {
+            cl_keyring.insert(id, pk);
+        }
+
+        Ok(PubParams {
+            cl: self.cl.clone(),
+            t: self.t,
+            n: self.n,
+            cl_keyring,
+            curve_generator: self.curve_generator.clone(),
+            limits: self.limits,
+            compact_pvss: self.compact_pvss,
+            qualification: self.qualification,
+        })
+    }
+}
+
+#[test]
+pub fn test_rekey_then_sign() {
+    use crate::spdz::{
+        run_locally, simulate_pp, PresignSession, PresignState, SignState, SigningContext,
+    };
+    use crate::test_utils::simulation::dkg_among;
+
+    let (mut pp, mut secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let keys = dkg_among(&pp, &secret_keys);
+    let tpk = keys[0].tpk.clone();
+    let mut rng = fresh_rng();
+
+    // party 2 rotates its key
+    let new_sk = pp.cl.secret_key_gen(&mut rng);
+    let new_pk = pp.cl.public_key_gen(&new_sk);
+    let msg = keys[1].rekey(2, &pp, &secret_keys[&2], &new_pk, &mut rng);
+    assert_eq!(msg.verify(&pp, &tpk), Ok(()));
+
+    // only the holder of the old key can rotate it, and only for its own share
+    let forged = keys[1].rekey(2, &pp, &secret_keys[&1], &new_pk, &mut rng);
+    assert_eq!(
+        forged.verify(&pp, &tpk),
+        Err(RekeyError::Proof(NizkError::ChallengeMismatch))
+    );
+    let other_share = keys[0].rekey(2, &pp, &secret_keys[&2], &new_pk, &mut rng);
+    assert!(other_share.verify(&pp, &tpk).is_err());
+
+    let fingerprint = pp.fingerprint();
+    msg.apply(&mut pp, &tpk).unwrap();
+    assert_ne!(pp.fingerprint(), fingerprint);
+    assert_eq!(pp.cl_keyring[&2].to_bytes(), new_pk.to_bytes());
+    // proven against the old key, so it does not verify a second time
+    assert!(msg.verify(&pp, &tpk).is_err());
+    secret_keys.insert(2, new_sk);
+
+    // the new key decrypts the share it was sent
+    let ciphertext = bicycl::CipherText::new(&msg.randomness, &msg.encryption);
+    let decrypted = pp.cl.decrypt(&secret_keys[&2], &ciphertext).mpz();
+    assert_eq!(mpz_to_scalar_ct(&decrypted, 32), keys[1].share);
+
+    // the same key shares sign under the new keyring
+    let presignatures = run_locally(
+        (1..=pp.n)
+            .map(|i| {
+                let key = &keys[i as usize - 1];
+                let state =
+                    PresignState::new(i, &pp, h, &secret_keys[&i], key, PresignSession::default());
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+    let context = SigningContext::from_dkg(&pp, &keys[0]);
+    let message = b"signed after a key rotation";
+    let outcomes = run_locally(
+        presignatures
+            .into_iter()
+            .map(|(i, presignature)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                let state = SignState::new(id, &pp, h, &context, presignature, message);
+                (i, state)
+            })
+            .collect(),
+    );
+    assert_eq!(outcomes.len(), pp.n as usize);
+    for outcome in outcomes.values() {
+        assert!(outcome.signature.verify(&tpk.pk, message));
+    }
+}
+
+#[test]
+pub fn test_sign_with_signing_keys() {
+    use crate::spdz::{
+        run_locally, simulate_pp, PresignSession, PresignState, SignState, SigningContext,
+    };
+    use crate::test_utils::simulation::dkg_among;
+
+    // DKG under keyring A
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let keys = dkg_among(&pp, &secret_keys);
+    let mut rng = fresh_rng();
+
+    // keyring B, on the machines that sign
+    let signing_keys: BTreeMap<Id, SecretKey> = (1..=pp.n)
+        .map(|i| (i, pp.cl.secret_key_gen(&mut rng)))
+        .collect();
+    let session_id = b"signing session 1";
+    let msgs: Vec<SigningKeyMsg> = signing_keys
+        .iter()
+        .map(|(&i, sk)| SigningKeyMsg::new(&pp, i, sk, session_id, &mut rng))
+        .collect();
+    let qualified = &keys[0].qualified;
+
+    // a key announced as another party's, without its secret key
+    let mut stolen = msgs.clone();
+    stolen[0].id = 2;
+    assert_eq!(
+        pp.with_signing_keys(session_id, qualified, &stolen).err(),
+        Some(RekeyError::Proof(NizkError::ChallengeMismatch))
+    );
+    assert_eq!(
+        pp.with_signing_keys(session_id, qualified, &msgs[..2])
+            .err(),
+        Some(RekeyError::MissingKeys { ids: vec![3] })
+    );
+    // an announcement of an earlier session does not carry over
+    assert_eq!(
+        pp.with_signing_keys(b"signing session 2", qualified, &msgs)
+            .err(),
+        Some(RekeyError::Proof(NizkError::ChallengeMismatch))
+    );
+    // a party the DKG did not qualify keeps its DKG key
+    let partial = pp
+        .with_signing_keys(session_id, &[1, 2], &msgs[..2])
+        .unwrap();
+    assert_eq!(
+        partial.cl_keyring[&3].to_bytes(),
+        pp.cl_keyring[&3].to_bytes()
+    );
+
+    let signing_pp = pp.with_signing_keys(session_id, qualified, &msgs).unwrap();
+    assert_ne!(signing_pp.fingerprint(), pp.fingerprint());
+
+    let presignatures = run_locally(
+        (1..=pp.n)
+            .map(|i| {
+                let key = &keys[i as usize - 1];
+                let state = PresignState::new(
+                    i,
+                    &signing_pp,
+                    h,
+                    &signing_keys[&i],
+                    key,
+                    PresignSession::default(),
+                );
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+    assert_eq!(presignatures.len(), pp.n as usize);
+
+    let context = SigningContext::from_dkg(&signing_pp, &keys[0]);
+    let message = b"signed under ephemeral keys";
+    let outcomes = run_locally(
+        presignatures
+            .into_iter()
+            .map(|(i, presignature)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                let state = SignState::new(id, &signing_pp, h, &context, presignature, message);
+                (i, state)
+            })
+            .collect(),
+    );
+    assert_eq!(outcomes.len(), pp.n as usize);
+    for outcome in outcomes.values() {
+        assert!(outcome.signature.verify(&keys[0].tpk.pk, message));
+    }
+}
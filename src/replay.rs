@@ -0,0 +1,88 @@
+//! Memory of the nonce dealings seen in earlier presigning sessions. A dealer that sends
+//! a dealing it already sent, e.g. one recorded from an earlier session on the same key,
+//! knows nothing new about its nonce, but may have learned how the earlier presignature
+//! was spent. `PresignState::with_replay_cache` disqualifies such dealers.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::utils::Id;
+
+/// A record of the dealings seen, by dealer and `DkgTranscript::dealing_hash`.
+/// Implementations may forget old entries, or keep them in a database shared by the
+/// sessions of a key.
+pub trait ReplayCache {
+    /// Records that `dealer` dealt `dealing_hash`. Like `HashSet::insert`, returns
+    /// whether it was not recorded before.
+    fn insert(&mut self, dealer: Id, dealing_hash: [u8; 32]) -> bool;
+}
+
+/// A cache shared by the sessions of a key, possibly on other threads.
+pub type SharedReplayCache = Arc<Mutex<dyn ReplayCache + Send>>;
+
+/// Remembers the `capacity` dealings seen last.
+pub struct LruReplayCache {
+    capacity: usize,
+    seen: HashSet<(Id, [u8; 32])>,
+    order: VecDeque<(Id, [u8; 32])>, // least recently seen first
+}
+
+impl LruReplayCache {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "a replay cache must hold a dealing");
+        LruReplayCache {
+            capacity,
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// A `SharedReplayCache` of `capacity` dealings.
+    pub fn shared(capacity: usize) -> SharedReplayCache {
+        Arc::new(Mutex::new(Self::new(capacity)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+impl ReplayCache for LruReplayCache {
+    fn insert(&mut self, dealer: Id, dealing_hash: [u8; 32]) -> bool {
+        let entry = (dealer, dealing_hash);
+        if !self.seen.insert(entry) {
+            // seen again, so it is the most recent now
+            let at = self.order.iter().position(|e| *e == entry).unwrap();
+            self.order.remove(at);
+            self.order.push_back(entry);
+            return false;
+        }
+        self.order.push_back(entry);
+        if self.order.len() > self.capacity {
+            let oldest = self.order.pop_front().unwrap();
+            self.seen.remove(&oldest);
+        }
+        true
+    }
+}
+
+#[test]
+pub fn test_lru_replay_cache() {
+    let mut cache = LruReplayCache::new(2);
+    assert!(cache.insert(1, [1; 32]));
+    assert!(!cache.insert(1, [1; 32]));
+    // the same dealing from another dealer is another entry
+    assert!(cache.insert(2, [1; 32]));
+    assert_eq!(cache.len(), 2);
+
+    // refreshed above, so (1, [1; 32]) outlives (2, [1; 32])
+    assert!(!cache.insert(1, [1; 32]));
+    assert!(cache.insert(3, [3; 32]));
+    assert_eq!(cache.len(), 2);
+    assert!(!cache.insert(1, [1; 32]));
+    assert!(cache.insert(2, [1; 32]));
+}
@@ -0,0 +1,491 @@
+//! Retransmission of lost messages. A round that waits for a message lost on the way
+//! would otherwise wait until its deadline and go ahead without it, or fail with fewer
+//! than t. Here a party that is still missing messages when the grace period of a
+//! round is over asks the senders to send them again, and the senders answer from the
+//! messages they keep, by round.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+
+use futures::future::{select, Either};
+use round_based::{MessageDestination, Outgoing, PartyIndex, ProtocolMessage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::codec::encode;
+use crate::spdz::{Error, StateError, StateMachine};
+use crate::transport::{deliver, drive, Hooks, Transport};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ResendableMsg<M> {
+    Payload(M),
+    /// Asks the parties `missing` to send their messages of `round` for the sender again.
+    Resend {
+        round: u16,
+        missing: Vec<PartyIndex>,
+    },
+}
+
+impl<M: ProtocolMessage> ProtocolMessage for ResendableMsg<M> {
+    fn round(&self) -> u16 {
+        match self {
+            ResendableMsg::Payload(msg) => msg.round(),
+            // delivered whatever round the receiver is in, also once it is done
+            ResendableMsg::Resend { .. } => 0,
+        }
+    }
+}
+
+/// Who asks for what in `run_with_resend`.
+#[derive(Clone, Copy, Debug)]
+pub struct ResendConfig {
+    /// Our wire index, and the size of the committee.
+    pub me: PartyIndex,
+    pub n: u16,
+    /// How many times a round asks for its missing messages before it goes ahead with
+    /// those it has, as at a deadline of `run_with_deadlines`. Zero turns retransmission
+    /// off.
+    pub max_retries: u32,
+}
+
+/// Like `run_with_deadlines`, with `grace` as the deadline of each round, but a round
+/// whose grace period is over first asks for its missing messages, up to
+/// `max_retries` times, each followed by another grace period. Only then does the state
+/// go ahead with the messages it has, or fail if they are fewer than t.
+///
+/// A message that arrives again from the same sender is dropped, so a retransmission
+/// never counts as a second message. A request is answered with the messages of its
+/// round that were for the party asking, and a party is answered at most `max_retries`
+/// times per round, as often as an honest one asks. Once the state is done, the run
+/// answers requests for `max_retries + 1` more grace periods, for peers that are still
+/// waiting on us.
+pub async fn run_with_resend<S, T, D, F>(
+    transport: &mut T,
+    state: S,
+    config: ResendConfig,
+    grace: D,
+) -> Result<S::Output, Error<T::ReceiveError, T::SendError>>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage + Clone + Serialize,
+    T: Transport<ResendableMsg<S::Msg>>,
+    D: FnMut() -> F,
+    F: Future<Output = ()>,
+{
+    let mut grace = Grace(grace);
+    let (output, mut resender) =
+        drive(transport, Resending::new(state, config), &mut grace).await?;
+
+    if config.max_retries > 0 {
+        for _ in 0..=config.max_retries {
+            let mut timer = Box::pin((grace.0)());
+            loop {
+                let received =
+                    match select(Box::pin(transport.receive(resender.round)), timer.as_mut()).await
+                    {
+                        Either::Left((received, _)) => received.map_err(Error::Recv)?,
+                        Either::Right(_) => break,
+                    };
+                if received.is_empty() {
+                    return Ok(output);
+                }
+                for (from, msg) in received {
+                    let from = PartyIndex::try_from(from)
+                        .map_err(|_| Error::SenderOutOfRange { index: from })?;
+                    if let ResendableMsg::Resend { round, missing } = msg {
+                        for outgoing in resender.answer(from, round, &missing) {
+                            deliver(transport, outgoing).await.map_err(Error::Send)?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Our messages by round, kept to answer resend requests, and how many requests of
+/// each peer and round were answered.
+struct Resender<M> {
+    me: PartyIndex,
+    max_retries: u32,
+    round: u16, // the last round we sent a message in
+    sent: BTreeMap<u16, Vec<(MessageDestination, M)>>,
+    answered: BTreeMap<(PartyIndex, u16), u32>,
+}
+
+impl<M: Clone> Resender<M> {
+    /// Our messages of `round` for `from`, if we are among its `missing` and it did not
+    /// ask more often than an honest party does.
+    fn answer(
+        &mut self,
+        from: PartyIndex,
+        round: u16,
+        missing: &[PartyIndex],
+    ) -> Vec<Outgoing<ResendableMsg<M>>> {
+        if !missing.contains(&self.me) {
+            return vec![];
+        }
+        let answered = self.answered.entry((from, round)).or_default();
+        if *answered >= self.max_retries {
+            return vec![];
+        }
+        *answered += 1;
+        self.sent
+            .get(&round)
+            .into_iter()
+            .flatten()
+            .filter(|(recipient, _)| match recipient {
+                MessageDestination::AllParties => true,
+                MessageDestination::OneParty(j) => *j == from,
+            })
+            .map(|(_, msg)| Outgoing::p2p(from, ResendableMsg::Payload(msg.clone())))
+            .collect()
+    }
+}
+
+/// The state of `run_with_resend`: drops payloads it has already handled, answers
+/// resend requests, and asks for missing messages when `Grace` says so.
+struct Resending<S: StateMachine> {
+    inner: S,
+    n: u16,
+    resender: Resender<S::Msg>,
+    heard: BTreeSet<(PartyIndex, [u8; 32])>, // the digests of the payloads handled
+    heard_in: BTreeSet<(u16, PartyIndex)>,
+    retries: u32, // the requests made in the current round
+    outbox: Vec<Outgoing<ResendableMsg<S::Msg>>>,
+}
+
+impl<S: StateMachine> Resending<S>
+where
+    S::Msg: ProtocolMessage + Clone,
+{
+    fn new(inner: S, config: ResendConfig) -> Self {
+        Resending {
+            inner,
+            n: config.n,
+            resender: Resender {
+                me: config.me,
+                max_retries: config.max_retries,
+                round: 0,
+                sent: BTreeMap::new(),
+                answered: BTreeMap::new(),
+            },
+            heard: BTreeSet::new(),
+            heard_in: BTreeSet::new(),
+            retries: 0,
+            outbox: vec![],
+        }
+    }
+
+    /// Asks for the messages of the current round we have not heard, unless we asked
+    /// `max_retries` times already.
+    fn request_missing(&mut self) -> bool {
+        if self.retries >= self.resender.max_retries {
+            return false;
+        }
+        self.retries += 1;
+        let (me, round) = (self.resender.me, self.resender.round);
+        let missing = (0..self.n)
+            .filter(|&i| i != me && !self.heard_in.contains(&(round, i)))
+            .collect();
+        self.outbox.push(Outgoing::broadcast(ResendableMsg::Resend {
+            round,
+            missing,
+        }));
+        true
+    }
+}
+
+impl<S> StateMachine for Resending<S>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage + Clone + Serialize,
+{
+    type Msg = ResendableMsg<S::Msg>;
+    type Output = (S::Output, Resender<S::Msg>);
+
+    fn handle_message(&mut self, from: PartyIndex, msg: Self::Msg) -> Result<(), StateError> {
+        match msg {
+            ResendableMsg::Payload(msg) => {
+                let digest = Sha256::digest(encode(&msg)).into();
+                if !self.heard.insert((from, digest)) {
+                    return Ok(());
+                }
+                self.heard_in.insert((msg.round(), from));
+                self.inner.handle_message(from, msg)
+            }
+            ResendableMsg::Resend { round, missing } => {
+                let answers = self.resender.answer(from, round, &missing);
+                self.outbox.extend(answers);
+                Ok(())
+            }
+        }
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<Self::Msg>> {
+        let mut outgoing = std::mem::take(&mut self.outbox);
+        for Outgoing { recipient, msg } in self.inner.wants_to_send() {
+            if msg.round() > self.resender.round {
+                self.resender.round = msg.round();
+                self.retries = 0;
+            }
+            let sent = self.resender.sent.entry(msg.round()).or_default();
+            sent.push((recipient, msg.clone()));
+            outgoing.push(Outgoing {
+                recipient,
+                msg: ResendableMsg::Payload(msg),
+            });
+        }
+        outgoing
+    }
+
+    fn try_finish(self) -> Result<Self::Output, Self> {
+        if !self.outbox.is_empty() {
+            return Err(self);
+        }
+        let Resending {
+            inner,
+            n,
+            resender,
+            heard,
+            heard_in,
+            retries,
+            outbox,
+        } = self;
+        match inner.try_finish() {
+            Ok(output) => Ok((output, resender)),
+            Err(inner) => Err(Resending {
+                inner,
+                n,
+                resender,
+                heard,
+                heard_in,
+                retries,
+                outbox,
+            }),
+        }
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        self.inner.on_timeout()
+    }
+
+    fn on_version(&mut self, version: u16) {
+        self.inner.on_version(version)
+    }
+}
+
+/// The hooks of `run_with_resend`: a round whose grace period is over asks for its
+/// missing messages while it has retries left, and times out after that.
+struct Grace<D>(D);
+
+impl<S, D, F> Hooks<Resending<S>> for Grace<D>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage + Clone + Serialize,
+    D: FnMut() -> F,
+    F: Future<Output = ()>,
+{
+    type Timer = F;
+
+    fn start_timer(&mut self) -> Option<F> {
+        Some((self.0)())
+    }
+
+    fn on_timer(&mut self, state: &mut Resending<S>) -> Result<bool, StateError> {
+        if state.request_missing() {
+            return Ok(true);
+        }
+        state.on_timeout().map(|()| false)
+    }
+}
+
+/// Loses every fifth message delivered to any party of a committee sharing `delivered`,
+/// 20% of them all, in an order that does not change from one run to the next.
+#[cfg(test)]
+struct Lossy<T> {
+    inner: T,
+    delivered: std::rc::Rc<std::cell::Cell<usize>>,
+}
+
+#[cfg(test)]
+impl<M, T: Transport<M>> Transport<M> for Lossy<T> {
+    type SendError = T::SendError;
+    type ReceiveError = T::ReceiveError;
+
+    async fn broadcast(&mut self, round: u16, msg: M) -> Result<(), Self::SendError> {
+        self.inner.broadcast(round, msg).await
+    }
+
+    async fn send_to(
+        &mut self,
+        round: u16,
+        recipient: PartyIndex,
+        msg: M,
+    ) -> Result<(), Self::SendError> {
+        self.inner.send_to(round, recipient, msg).await
+    }
+
+    async fn receive(&mut self, round: u16) -> Result<Vec<(usize, M)>, Self::ReceiveError> {
+        loop {
+            let received = self.inner.receive(round).await?;
+            if received.is_empty() {
+                return Ok(received);
+            }
+            let kept: Vec<_> = received
+                .into_iter()
+                .filter(|_| {
+                    self.delivered.set(self.delivered.get() + 1);
+                    self.delivered.get() % 5 != 0
+                })
+                .collect();
+            if !kept.is_empty() {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+#[test]
+pub fn test_resend_over_lossy_transport() {
+    use crate::spdz::{
+        run_locally, simulate_pp, PresignSession, PresignState, SignState, SigningContext,
+        StateError,
+    };
+    use crate::test_utils::simulation::dkg_among;
+    use crate::transport::MemoryTransport;
+    use crate::utils::{PartyId, G};
+    use futures::executor::block_on;
+    use futures::future::{join_all, poll_fn};
+    use std::task::Poll;
+
+    // counted in polls, as in the deadline tests of `transport`
+    let grace = || {
+        let mut polls = 0;
+        poll_fn(move |cx| {
+            polls += 1;
+            if polls > 100 {
+                return Poll::Ready(());
+            }
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+    };
+
+    // with t = n, signing needs every share, so any loss strands someone
+    let (pp, secret_keys) = simulate_pp(3, 3);
+    let (pp, secret_keys) = (&pp, &secret_keys);
+    let h = G::base_point2();
+    let keys = dkg_among(pp, secret_keys);
+    let presignatures = run_locally(
+        (1..=pp.n)
+            .map(|i| {
+                let key = &keys[i as usize - 1];
+                let state =
+                    PresignState::new(i, pp, h, &secret_keys[&i], key, PresignSession::default());
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+    let msg = b"signed over a lossy network";
+    let context = SigningContext::from_dkg(pp, &keys[0]);
+
+    let sign = |max_retries: u32| {
+        let delivered = std::rc::Rc::new(std::cell::Cell::new(0));
+        block_on(join_all(
+            MemoryTransport::committee(pp.n.into())
+                .into_iter()
+                .zip(presignatures.clone())
+                .map(|(transport, (i, presignature))| {
+                    let (context, delivered) = (&context, delivered.clone());
+                    async move {
+                        let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                        let state = SignState::new(id, pp, h, context, presignature, msg);
+                        let mut transport = Lossy {
+                            inner: transport,
+                            delivered,
+                        };
+                        let config = ResendConfig {
+                            me: i,
+                            n: pp.n,
+                            max_retries,
+                        };
+                        run_with_resend(&mut transport, state, config, grace).await
+                    }
+                }),
+        ))
+    };
+
+    let outcomes = sign(5);
+    for outcome in &outcomes {
+        let outcome = outcome.as_ref().unwrap();
+        assert!(outcome.signature.verify(&context.pk, msg));
+    }
+
+    // without retransmission, whoever lost a share gives up at its deadline
+    let outcomes = sign(0);
+    assert!(outcomes.iter().any(|outcome| outcome.is_err()));
+    for outcome in outcomes {
+        match outcome {
+            Ok(outcome) => assert!(outcome.signature.verify(&context.pk, msg)),
+            Err(err) => assert!(matches!(
+                err,
+                Error::State(StateError::TooFewQualified {
+                    got: 2,
+                    need: 3,
+                    ..
+                })
+            )),
+        }
+    }
+}
+
+#[test]
+pub fn test_resend_answers() {
+    use crate::spdz::{simulate_pp, DkgState};
+    use crate::utils::G;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let config = ResendConfig {
+        me: 0,
+        n: pp.n,
+        max_retries: 2,
+    };
+
+    // a request is answered with the header and the leaf for the party asking, and
+    // only as often as an honest party asks
+    let mut state = Resending::new(
+        DkgState::new(1, &pp, h, &secret_keys[&1], false)
+            .with_light_dealing(b"resend test")
+            .unwrap(),
+        config,
+    );
+    assert_eq!(state.wants_to_send().len(), 3);
+    let request = || ResendableMsg::Resend {
+        round: 0,
+        missing: vec![0],
+    };
+    for _ in 0..config.max_retries {
+        state.handle_message(1, request()).unwrap();
+        let answers = state.wants_to_send();
+        assert_eq!(answers.len(), 2);
+        assert!(answers
+            .iter()
+            .all(|answer| answer.recipient == MessageDestination::OneParty(1)));
+    }
+    state.handle_message(1, request()).unwrap();
+    assert!(state.wants_to_send().is_empty());
+
+    // a payload that arrives twice is handled once, rather than being a second message
+    let mut state = Resending::new(DkgState::new(1, &pp, h, &secret_keys[&1], false), config);
+    let msg = DkgState::new(2, &pp, h, &secret_keys[&2], false)
+        .wants_to_send()
+        .remove(0)
+        .msg;
+    for _ in 0..2 {
+        let payload = ResendableMsg::Payload(msg.clone());
+        assert!(state.handle_message(1, payload).is_ok());
+    }
+}
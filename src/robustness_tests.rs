@@ -0,0 +1,783 @@
+//! End-to-end runs with a misbehaving committee member.
+
+use crate::spdz::*;
+use crate::test_utils::adversary::{AdversarialParty, Corruption};
+use crate::test_utils::simulation::{dkg_locally, presign_locally};
+use crate::utils::*;
+use bicycl::{Mpz, RandGen, SecretKey};
+use round_based::{PartyIndex, ProtocolMessage};
+use std::collections::BTreeMap;
+
+#[test]
+pub fn test_malicious_dealer() {
+    let (pp, secret_keys) = simulate_pp(4, 3);
+    let h = G::base_point2();
+    let cheater: Id = 1;
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let malformed = PvssMsg::malformed(&pp, &mut rng, h);
+
+    let states = (1..=pp.n)
+        .map(|i| {
+            let state = if i == cheater {
+                DkgState::with_pvss_msg(i, &pp, h, &secret_keys[&i], false, malformed.clone())
+            } else {
+                DkgState::new(i, &pp, h, &secret_keys[&i], false)
+            };
+            (PartyId::from_share_index(i).to_wire_index(), state)
+        })
+        .collect();
+
+    // the cheater counts its own dealing and so cannot agree with anyone on the key
+    let keys = run_locally(states);
+    assert!(!keys.contains_key(&(PartyId::from_share_index(cheater).to_wire_index())));
+    assert_eq!(keys.len(), pp.n as usize - 1);
+
+    let honest: Vec<Id> = (1..=pp.n).filter(|&i| i != cheater).collect();
+    for key in keys.values() {
+        assert_eq!(key.qualified, honest);
+        assert_eq!(key.tpk, keys[&1].tpk);
+        assert_eq!(
+            key.tpk.pub_shares.keys().copied().collect::<Vec<_>>(),
+            honest
+        );
+    }
+
+    // the cheater takes part in presigning with a made-up share, which the others reject
+    let mut all_keys: BTreeMap<PartyIndex, DkgOutput> = keys.clone();
+    all_keys.insert(
+        PartyId::from_share_index(cheater).to_wire_index(),
+        DkgOutput {
+            share: Zq::random(),
+            ..keys[&1].clone()
+        },
+    );
+
+    let presignatures = presign_locally(&pp, &secret_keys, &all_keys);
+
+    let msg = b"signed despite a cheating dealer";
+    let context = SigningContext::from_dkg(&pp, &keys[&1]);
+    let signatures = run_locally(
+        presignatures
+            .into_iter()
+            .filter(|(i, _)| keys.contains_key(i))
+            .map(|(i, presignature)| {
+                let state = SignState::new(
+                    PartyId::from_wire_index(i).unwrap().to_share_index(),
+                    &pp,
+                    h,
+                    &context,
+                    presignature,
+                    msg,
+                );
+                (i, state)
+            })
+            .collect(),
+    );
+
+    assert_eq!(signatures.len(), honest.len());
+    for outcome in signatures.values() {
+        assert!(outcome.signature.verify(&keys[&1].tpk.pk, msg));
+    }
+}
+
+#[test]
+pub fn test_presign_among_honest_parties() {
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let h = G::base_point2();
+    let dropped: Id = 3;
+
+    // the dropped party opens a power of a made-up share, then leaves
+    let keys = run_locally(
+        (1..=pp.n)
+            .map(|i| {
+                let state = DkgState::new(i, &pp, h, &secret_keys[&i], false);
+                let tamper: fn(DkgMsg) -> Option<DkgMsg> = if i == dropped {
+                    |msg| match msg {
+                        DkgMsg::PowOpen(mut msg) => {
+                            msg.point = G::generator() * Zq::random();
+                            Some(DkgMsg::PowOpen(msg))
+                        }
+                        msg => Some(msg),
+                    }
+                } else {
+                    Some
+                };
+                let state = Tampered {
+                    inner: state,
+                    tamper,
+                };
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+    let honest: Vec<Id> = (1..=pp.n).filter(|&i| i != dropped).collect();
+    let keys: BTreeMap<Id, DkgOutput> = honest
+        .iter()
+        .map(|&i| {
+            (
+                i,
+                keys[&PartyId::from_share_index(i).to_wire_index()].clone(),
+            )
+        })
+        .collect();
+    for key in keys.values() {
+        assert_eq!(key.honest_parties(), honest);
+    }
+
+    // without the dropped party, which the first two rounds no longer wait for
+    let presignatures = run_locally(
+        keys.iter()
+            .map(|(&i, key)| {
+                let state =
+                    PresignState::new(i, &pp, h, &secret_keys[&i], key, PresignSession::default());
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+    assert_eq!(presignatures.len(), honest.len());
+
+    // had it come back, the others would have left it out all the same
+    let mut with_dropped = keys.clone();
+    with_dropped.insert(
+        dropped,
+        DkgOutput {
+            share: Zq::random(),
+            ..keys[&1].clone()
+        },
+    );
+    let rejoined = run_locally(
+        with_dropped
+            .iter()
+            .map(|(&i, key)| {
+                let state =
+                    PresignState::new(i, &pp, h, &secret_keys[&i], key, PresignSession::default());
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+    let dropped_index = PartyId::from_share_index(dropped).to_wire_index();
+    for &i in &honest {
+        assert_eq!(
+            rejoined[&PartyId::from_share_index(i).to_wire_index()].disqualified,
+            BTreeMap::from([(dropped_index, DisqualReason::NotQualifiedInDkg)])
+        );
+    }
+
+    let msg = b"signed without a dropped party";
+    let context = SigningContext::from_dkg(&pp, &keys[&1]);
+    let signatures = run_locally(
+        presignatures
+            .into_iter()
+            .map(|(i, presignature)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                (i, SignState::new(id, &pp, h, &context, presignature, msg))
+            })
+            .collect(),
+    );
+    assert_eq!(signatures.len(), honest.len());
+    for outcome in signatures.values() {
+        assert!(outcome.signature.verify(&context.pk, msg));
+    }
+}
+
+/// The party that misbehaves in the adversary scenarios below.
+const ADVERSARY: Id = 2;
+
+struct Outcome {
+    signatures: BTreeMap<PartyIndex, SignatureECDSA>,
+    aborted: BTreeMap<PartyIndex, StateError>,
+}
+
+/// Presigns and signs with every party, `ADVERSARY` corrupting the given rounds.
+fn run_adversarial(
+    pp: &PubParams,
+    secret_keys: &BTreeMap<Id, SecretKey>,
+    keys: &BTreeMap<PartyIndex, DkgOutput>,
+    context: &SigningContext,
+    msg: &[u8],
+    presign_corruption: Option<(u16, Corruption<PresignMsg>)>,
+    sign_corruption: Option<(u16, Corruption<SignMsg>)>,
+) -> Outcome {
+    let h = G::base_point2();
+    let adversary = PartyId::from_share_index(ADVERSARY).to_wire_index();
+
+    let presigning = run_locally_detailed(
+        keys.iter()
+            .map(|(&i, key)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                let mut party = AdversarialParty::new(
+                    i,
+                    pp.n,
+                    PresignState::new(id, pp, h, &secret_keys[&id], key, PresignSession::default()),
+                );
+                if let (true, Some((round, corruption))) = (i == adversary, &presign_corruption) {
+                    party = party.corrupt_round(*round, corruption.clone());
+                }
+                (i, party)
+            })
+            .collect(),
+    );
+
+    let signing = run_locally_detailed(
+        presigning
+            .outputs
+            .into_iter()
+            .map(|(i, presignature)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                let mut party = AdversarialParty::new(
+                    i,
+                    pp.n,
+                    SignState::new(id, pp, h, context, presignature, msg),
+                );
+                if let (true, Some((round, corruption))) = (i == adversary, &sign_corruption) {
+                    party = party.corrupt_round(*round, corruption.clone());
+                }
+                (i, party)
+            })
+            .collect(),
+    );
+
+    let mut aborted = presigning.aborted;
+    aborted.extend(signing.aborted);
+    Outcome {
+        signatures: signing
+            .outputs
+            .into_iter()
+            .map(|(i, outcome)| (i, SignatureECDSA::from(outcome)))
+            .collect(),
+        aborted,
+    }
+}
+
+/// Every honest party either signs validly or aborts, blaming the adversary. Honest
+/// parties that end up with the same nonce sign alike, even when others stall; without
+/// `may_stall`, none of them may be left waiting, and they all sign alike.
+fn assert_robust(outcome: &Outcome, n: Id, pk: &G, msg: &[u8], may_stall: bool) {
+    let adversary = PartyId::from_share_index(ADVERSARY).to_wire_index();
+    for (&i, signature) in &outcome.signatures {
+        assert!(signature.verify(pk, msg), "party {} signed invalidly", i);
+    }
+    let honest_signatures: Vec<(&PartyIndex, &SignatureECDSA)> = outcome
+        .signatures
+        .iter()
+        .filter(|(&i, _)| i != adversary)
+        .collect();
+    for (i, signature) in &honest_signatures {
+        for (j, other) in &honest_signatures {
+            assert!(
+                signature.r != other.r || signature == other,
+                "parties {} and {} signed differently with the same nonce",
+                i,
+                j
+            );
+        }
+    }
+    for (&i, err) in &outcome.aborted {
+        if i == adversary {
+            continue;
+        }
+        match err {
+            StateError::KeyDisagreement { differing } => {
+                assert_eq!(
+                    differing,
+                    &vec![adversary],
+                    "party {} blamed the wrong parties",
+                    i
+                )
+            }
+            StateError::TooFewQualified { blamed, .. } => assert!(
+                blamed.contains(&adversary),
+                "party {} gave up without blaming the adversary",
+                i
+            ),
+            err => panic!("party {} aborted with {}", i, err),
+        }
+    }
+    if !may_stall {
+        for i in (0..n).filter(|&i| i != adversary) {
+            assert!(
+                outcome.signatures.contains_key(&i) || outcome.aborted.contains_key(&i),
+                "party {} neither finished nor aborted",
+                i
+            );
+        }
+        if let Some((_, first)) = honest_signatures.first() {
+            assert!(
+                honest_signatures.iter().all(|(_, other)| other == first),
+                "honest parties signed differently"
+            );
+        }
+    }
+}
+
+#[test]
+pub fn test_adversarial_party() {
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let h = G::base_point2();
+    let msg = b"signed next to an adversary";
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let pk = &context.pk;
+
+    // round numbers and stale messages, taken from a session the adversary ran before
+    let adversary_key = &keys[&PartyId::from_share_index(ADVERSARY).to_wire_index()];
+    let stale_nonce_gen = PresignState::new(
+        ADVERSARY,
+        &pp,
+        h,
+        &secret_keys[&ADVERSARY],
+        adversary_key,
+        PresignSession::default(),
+    )
+    .wants_to_send()
+    .remove(0)
+    .msg;
+    let first_presign_round = stale_nonce_gen.round();
+    let stale_presignature = presign_locally(&pp, &secret_keys, &keys)
+        .remove(&PartyId::from_share_index(ADVERSARY).to_wire_index())
+        .unwrap();
+    let stale_sign = SignState::new(ADVERSARY, &pp, h, &context, stale_presignature, b"earlier")
+        .wants_to_send()
+        .remove(0)
+        .msg;
+    let sign_round = stale_sign.round();
+
+    let honest = run_adversarial(&pp, &secret_keys, &keys, &context, msg, None, None);
+    assert_robust(&honest, pp.n, pk, msg, false);
+    assert_eq!(honest.signatures.len(), pp.n as usize);
+
+    for round in first_presign_round..first_presign_round + 3 {
+        for corruption in [
+            Corruption::Drop,
+            Corruption::BitFlip,
+            Corruption::Equivocate,
+        ] {
+            // the first two rounds wait for all n messages, so withholding one stalls
+            // everyone, and equivocating can leave honest parties with split views
+            let may_stall = matches!(corruption, Corruption::Drop | Corruption::Equivocate);
+            let outcome = run_adversarial(
+                &pp,
+                &secret_keys,
+                &keys,
+                &context,
+                msg,
+                Some((round, corruption)),
+                None,
+            );
+            assert_robust(&outcome, pp.n, pk, msg, may_stall);
+        }
+    }
+    let outcome = run_adversarial(
+        &pp,
+        &secret_keys,
+        &keys,
+        &context,
+        msg,
+        Some((first_presign_round, Corruption::Replay(stale_nonce_gen))),
+        None,
+    );
+    assert_robust(&outcome, pp.n, pk, msg, false);
+
+    // t = 2 of the three honest parties suffice to sign, whatever the adversary sends
+    for corruption in [
+        Corruption::Drop,
+        Corruption::BitFlip,
+        Corruption::Equivocate,
+        Corruption::Replay(stale_sign),
+    ] {
+        let outcome = run_adversarial(
+            &pp,
+            &secret_keys,
+            &keys,
+            &context,
+            msg,
+            None,
+            Some((sign_round, corruption)),
+        );
+        assert_robust(&outcome, pp.n, pk, msg, false);
+        assert!(outcome.signatures.len() >= pp.n as usize - 1);
+    }
+}
+
+#[test]
+pub fn test_sign_share_blame() {
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let h = G::base_point2();
+    let msg = b"signed with one bad share";
+    let adversary = PartyId::from_share_index(ADVERSARY).to_wire_index();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let presignatures = presign_locally(&pp, &secret_keys, &keys);
+
+    let sign = |i: PartyIndex| {
+        let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+        SignState::new(id, &pp, h, &context, presignatures[&i].clone(), msg)
+    };
+    let mut shares: BTreeMap<PartyIndex, OnlineSignMsg> = presignatures
+        .keys()
+        .map(|&i| {
+            let SignMsg::OnlineSign(share) = sign(i).wants_to_send().remove(0).msg;
+            (i, share)
+        })
+        .collect();
+    let sign_round = SignMsg::OnlineSign(shares[&0].clone()).round();
+
+    // the adversary flips a bit of its signature share; the others sign without it
+    let signing = run_locally_detailed(
+        presignatures
+            .keys()
+            .map(|&i| {
+                let mut party = AdversarialParty::new(i, pp.n, sign(i));
+                if i == adversary {
+                    party = party.corrupt_round(sign_round, Corruption::BitFlip);
+                }
+                (i, party)
+            })
+            .collect(),
+    );
+    assert!(signing.aborted.is_empty());
+    for (i, outcome) in signing.outputs.iter().filter(|(&i, _)| i != adversary) {
+        assert!(
+            outcome.signature.verify(&context.pk, msg),
+            "party {} signed invalidly",
+            i
+        );
+        assert!(!outcome.participants.contains(&adversary));
+        assert_eq!(
+            outcome.disqualified,
+            BTreeMap::from([(adversary, DisqualReason::SignatureShare)])
+        );
+    }
+
+    // a combiner given all n shares names the adversary, and signs once it is dropped
+    let tampered = &shares[&adversary].sig_share + Zq::from(1u64);
+    shares.insert(
+        adversary,
+        OnlineSignMsg {
+            prev_round_digest: shares[&adversary].prev_round_digest,
+            sig_share: tampered,
+        },
+    );
+
+    let combine = |shares: &BTreeMap<PartyIndex, OnlineSignMsg>| {
+        SignatureECDSA::try_from_with_blame(&pp, h, &context, &presignatures[&0], msg, shares)
+    };
+    assert_eq!(
+        combine(&shares),
+        Err(SignError::InvalidPartial {
+            parties: vec![adversary]
+        })
+    );
+    shares.remove(&adversary);
+    assert!(combine(&shares).unwrap().verify(&context.pk, msg));
+
+    shares.retain(|&i, _| i == 0);
+    assert_eq!(
+        combine(&shares),
+        Err(SignError::TooFewShares { got: 1, need: 2 })
+    );
+}
+
+#[test]
+pub fn test_robust_qualification() {
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let pp = pp.with_qualification(Qualification::Robust).unwrap();
+    assert_eq!(pp.min_qualified(), 3);
+    let h = G::base_point2();
+    let wire = |i: Id| PartyId::from_share_index(i).to_wire_index();
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let malformed = PvssMsg::malformed(&pp, &mut rng, h);
+    let dkg = |cheaters: &[Id]| {
+        run_locally_detailed(
+            (1..=pp.n)
+                .map(|i| {
+                    let state = if cheaters.contains(&i) {
+                        let dealing = malformed.clone();
+                        DkgState::with_pvss_msg(i, &pp, h, &secret_keys[&i], false, dealing)
+                    } else {
+                        DkgState::new(i, &pp, h, &secret_keys[&i], false)
+                    };
+                    (wire(i), state)
+                })
+                .collect(),
+        )
+    };
+
+    // three bad dealings leave a single good one, which would control the key
+    let run = dkg(&[2, 3, 4]);
+    assert!(run.outputs.is_empty());
+    assert!(matches!(
+        run.aborted[&wire(1)],
+        StateError::InsufficientQualifiedDealings { got: 1, need: 3 }
+    ));
+
+    // one bad dealing leaves three, enough to go ahead and presign in the same mode
+    let keys = dkg(&[4]).outputs;
+    assert_eq!(keys.len(), pp.n as usize);
+    assert!(keys.values().all(|key| key.tpk == keys[&0].tpk));
+    let presignatures = presign_locally(&pp, &secret_keys, &keys);
+    assert_eq!(presignatures.len(), pp.n as usize);
+}
+
+#[test]
+pub fn test_bad_dealer_past_the_first_t() {
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let h = G::base_point2();
+    let cheater: Id = 4;
+
+    // the first t dealings are good already, the last one is still checked and rejected
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let mut dealings: BTreeMap<Id, PvssMsg> = (1..=pp.n)
+        .map(|i| {
+            let msg = PvssMsg::random_with_aux(&pp, &mut rng, h, &[]);
+            (
+                i,
+                msg.with_membership_proof(&pp, i, &secret_keys[&i], &mut rng),
+            )
+        })
+        .collect();
+    let malformed = PvssMsg::malformed(&pp, &mut rng, h);
+    dealings.insert(
+        cheater,
+        malformed.with_membership_proof(&pp, cheater, &secret_keys[&cheater], &mut rng),
+    );
+    let combined = combine_dealings(&pp, h, &dealings).unwrap();
+    assert_eq!(combined.qualified, vec![1, 2]);
+    assert_eq!(
+        combined
+            .rejected
+            .iter()
+            .map(|(j, _)| *j)
+            .collect::<Vec<_>>(),
+        vec![cheater]
+    );
+
+    // likewise a nonce dealing bound to another session than the receivers'
+    let keys = dkg_locally(&pp, &secret_keys);
+    let presignatures = run_locally(
+        keys.iter()
+            .map(|(&i, key)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                let session = if id == cheater {
+                    PresignSession::new(b"another session", 0)
+                } else {
+                    PresignSession::default()
+                };
+                let state = PresignState::new(id, &pp, h, &secret_keys[&id], key, session);
+                (i, state)
+            })
+            .collect(),
+    );
+    let cheater_index = PartyId::from_share_index(cheater).to_wire_index();
+    for (i, presignature) in presignatures.iter().filter(|(&i, _)| i != cheater_index) {
+        assert_eq!(
+            presignature.disqualified.get(&cheater_index),
+            Some(&DisqualReason::NonceDealing),
+            "party {} did not disqualify the cheater",
+            i
+        );
+    }
+}
+
+#[cfg(feature = "signing-transcript")]
+#[test]
+pub fn test_find_bad_contributor() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let msg = b"signed with one bad share";
+    let adversary = PartyId::from_share_index(ADVERSARY).to_wire_index();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let presignatures = presign_locally(&pp, &secret_keys, &keys);
+    let sign = |i: PartyIndex| {
+        let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+        SignState::new(id, &pp, h, &context, presignatures[&i].clone(), msg)
+    };
+
+    // signed as usual, every share checks out and nobody is to blame
+    let outcomes = run_locally(presignatures.keys().map(|&i| (i, sign(i))).collect());
+    for outcome in outcomes.values() {
+        assert!(outcome.transcript.contributions.len() >= pp.t as usize);
+        assert_eq!(outcome.transcript.find_bad_contributor(&context.pk), None);
+    }
+
+    // combined unchecked, a bad partial spoils the signature and is traced to its sender
+    let mut shares: BTreeMap<PartyIndex, OnlineSignMsg> = presignatures
+        .keys()
+        .map(|&i| {
+            let SignMsg::OnlineSign(share) = sign(i).wants_to_send().remove(0).msg;
+            (i, share)
+        })
+        .collect();
+    let tampered = &shares[&adversary].sig_share + Zq::from(1u64);
+    shares.get_mut(&adversary).unwrap().sig_share = tampered;
+
+    let (signature, transcript) = SignatureECDSA::try_from_with_transcript(
+        &pp,
+        h,
+        &context,
+        &presignatures[&0],
+        msg,
+        &shares,
+    );
+    assert_eq!(
+        signature,
+        Err(SignError::InvalidPartial {
+            parties: vec![adversary]
+        })
+    );
+    assert!(!transcript.signature().verify(&context.pk, msg));
+    assert_eq!(
+        transcript.find_bad_contributor(&context.pk),
+        Some(ADVERSARY)
+    );
+}
+
+#[test]
+pub fn test_wrong_delta_share() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let adversary = PartyId::from_share_index(ADVERSARY).to_wire_index();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+
+    // the adversary reveals a share of delta that does not match its MAC
+    let tamper: fn(PresignMsg) -> Option<PresignMsg> = |msg| match msg {
+        PresignMsg::PreSignFinal(mut msg) => {
+            msg.delta_share = &msg.delta_share + Zq::from(1u64);
+            Some(PresignMsg::PreSignFinal(msg))
+        }
+        msg => Some(msg),
+    };
+    let presignatures = run_locally(
+        keys.iter()
+            .map(|(&i, key)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                let state = PresignState::new(
+                    id,
+                    &pp,
+                    h,
+                    &secret_keys[&id],
+                    key,
+                    PresignSession::default(),
+                );
+                let tamper = if i == adversary { tamper } else { Some };
+                (
+                    i,
+                    Tampered {
+                        inner: state,
+                        tamper,
+                    },
+                )
+            })
+            .collect(),
+    );
+    let honest: BTreeMap<PartyIndex, PreSignature> = presignatures
+        .into_iter()
+        .filter(|(i, _)| *i != adversary)
+        .collect();
+    assert_eq!(honest.len(), pp.n as usize - 1);
+    for presignature in honest.values() {
+        assert_eq!(
+            presignature.disqualified,
+            BTreeMap::from([(adversary, DisqualReason::DeltaShare)])
+        );
+        assert_eq!(presignature.R, honest[&0].R);
+    }
+
+    // the t honest parties sign on their own
+    let msg = b"signed without the adversary";
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let signatures = run_locally(
+        honest
+            .into_iter()
+            .map(|(i, presignature)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                (i, SignState::new(id, &pp, h, &context, presignature, msg))
+            })
+            .collect(),
+    );
+    assert_eq!(signatures.len(), pp.n as usize - 1);
+    for outcome in signatures.values() {
+        assert!(outcome.signature.verify(&context.pk, msg));
+    }
+}
+
+#[test]
+pub fn test_spliced_nonce_gen() {
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let h = G::base_point2();
+    let adversary = PartyId::from_share_index(ADVERSARY).to_wire_index();
+    let victim: PartyIndex = 0;
+
+    let keys = dkg_locally(&pp, &secret_keys);
+
+    // the adversary deals a second pair of nonces, valid on its own, to the victim only
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let spliced = PresignMsg::NonceGen(NonceGenMsg::random(&pp, &mut rng, h));
+    let corruption = Corruption::Splice {
+        to: vec![victim],
+        msg: spliced.clone(),
+    };
+    let presigning = run_locally_detailed(
+        keys.iter()
+            .map(|(&i, key)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                let mut party = AdversarialParty::new(
+                    i,
+                    pp.n,
+                    PresignState::new(
+                        id,
+                        &pp,
+                        h,
+                        &secret_keys[&id],
+                        key,
+                        PresignSession::default(),
+                    ),
+                );
+                if i == adversary {
+                    party = party.corrupt_round(spliced.round(), corruption.clone());
+                }
+                (i, party)
+            })
+            .collect(),
+    );
+
+    // every dealing verifies, but the MtA messages name both of the adversary's, so
+    // every party drops it from the nonces and deals the MtA round again
+    assert!(presigning.aborted.is_empty());
+    assert_eq!(presigning.outputs.len(), pp.n as usize);
+    for presignature in presigning.outputs.values() {
+        assert_eq!(
+            presignature.disqualified,
+            BTreeMap::from([(adversary, DisqualReason::Equivocation)])
+        );
+        assert_eq!(presignature.R, presigning.outputs[&victim].R);
+    }
+
+    let msg = b"signed past an equivocating dealer";
+    let context = SigningContext::from_dkg(&pp, &keys[&victim]);
+    let signatures = run_locally(
+        presigning
+            .outputs
+            .into_iter()
+            .map(|(i, presignature)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                (i, SignState::new(id, &pp, h, &context, presignature, msg))
+            })
+            .collect(),
+    );
+    assert_eq!(signatures.len(), pp.n as usize);
+    for outcome in signatures.values() {
+        assert!(outcome.signature.verify(&context.pk, msg));
+    }
+}
@@ -0,0 +1,371 @@
+//! Threshold BIP-340 Schnorr signatures from the same key shares. A Schnorr signature
+//! is linear in the nonce and the key, so a second DKG run yields a shared nonce and
+//! one round of signature shares finishes the job, with no MtA in between.
+
+use std::collections::BTreeMap;
+
+use curv::{arithmetic::Converter, BigInt};
+use round_based::{Mpc, Outgoing, PartyIndex, ProtocolMessage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::spdz::{
+    insert_once, run_within, sender_id, too_few_by_deadline, DkgOutput, Error, SignError,
+    StateError, StateMachine,
+};
+use crate::utils::*;
+
+/// A BIP-340 signature: the x coordinate of the even-y nonce point, and s.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SchnorrSignature {
+    pub r: [u8; 32],
+    pub s: Zq,
+}
+
+impl SchnorrSignature {
+    /// The 64-byte encoding of BIP-340.
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&self.r);
+        bytes[32..].copy_from_slice(&self.s.to_bytes());
+        bytes
+    }
+
+    /// BIP-340 verification under the x-only form of `pk`.
+    pub fn verify(&self, pk: &G, msg: &[u8]) -> bool {
+        if pk.is_zero() {
+            return false;
+        }
+        let (pk, _) = with_even_y(pk);
+        let e = challenge(&self.r, &x_only(&pk), msg);
+        let R = G::generator() * &self.s - pk * e;
+
+        !R.is_zero() && !has_odd_y(&R) && x_only(&R) == self.r
+    }
+
+    /// Combines signature shares collected outside `SchnorrSignState`, keyed by the
+    /// sender's wire index, as `SignatureECDSA::try_from_with_blame` does for ECDSA:
+    /// each share is checked against the public shares of `key` and `nonce` first, and
+    /// a single mismatch fails the combination with every offender named.
+    pub fn try_from_with_blame(
+        pp: &PubParams,
+        key: &DkgOutput,
+        nonce: &DkgOutput,
+        msg: &[u8],
+        shares: &BTreeMap<PartyIndex, PartialSchnorrMsg>,
+    ) -> Result<Self, SignError> {
+        let challenge = Challenge::new(key, nonce, msg);
+
+        let mut s_shares = BTreeMap::new();
+        let mut parties = vec![];
+        for (&from, share) in shares {
+            match PartyId::from_wire_index(from).map(PartyId::to_share_index) {
+                Some(id) if challenge.verify_share(id, &share.s_share) => {
+                    s_shares.insert(id, share.s_share.clone());
+                }
+                _ => parties.push(from),
+            }
+        }
+
+        if !parties.is_empty() {
+            return Err(SignError::InvalidPartial { parties });
+        }
+        match pp.interpolate(&s_shares) {
+            Some(s) => Ok(SchnorrSignature { r: challenge.r, s }),
+            None => Err(SignError::TooFewShares {
+                got: s_shares.len(),
+                need: pp.t as usize,
+            }),
+        }
+    }
+}
+
+fn x_only(point: &G) -> [u8; 32] {
+    point.to_bytes(true)[1..].try_into().unwrap()
+}
+
+fn has_odd_y(point: &G) -> bool {
+    point.to_bytes(true)[0] == 0x03
+}
+
+/// The point with the same x coordinate and an even y, and the sign that takes the
+/// point (and its discrete log) there.
+fn with_even_y(point: &G) -> (G, Zq) {
+    if has_odd_y(point) {
+        (-point, -Zq::from(1u64))
+    } else {
+        (point.clone(), Zq::from(1u64))
+    }
+}
+
+fn tagged_hash(tag: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag);
+    let mut hasher = Sha256::new().chain_update(tag_hash).chain_update(tag_hash);
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// e = H_BIP0340/challenge(R.x || P.x || m) mod q
+fn challenge(r: &[u8; 32], pk_x: &[u8; 32], msg: &[u8]) -> Zq {
+    let hash = tagged_hash(b"BIP0340/challenge", &[&r[..], &pk_x[..], msg]);
+    Zq::from_bigint(&BigInt::from_bytes(&hash))
+}
+
+/// A share of s = k + e * x, evaluated at the sender's point.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PartialSchnorrMsg {
+    pub(crate) s_share: Zq,
+}
+
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum SchnorrMsg {
+    PartialSig(PartialSchnorrMsg),
+}
+
+/// What the shares of a signature on one message are checked against. Both the nonce
+/// and the key are flipped to their even-y forms, by negating every share along with
+/// the joint point.
+struct Challenge {
+    r: [u8; 32],
+    e: Zq,
+    nonce_sign: Zq,
+    key_sign: Zq,
+    // the public shares of the signed-for k and x, after the flips
+    nonce_shares: BTreeMap<Id, G>,
+    key_shares: BTreeMap<Id, G>,
+}
+
+impl Challenge {
+    fn new(key: &DkgOutput, nonce: &DkgOutput, msg: &[u8]) -> Self {
+        let (R, nonce_sign) = with_even_y(&nonce.tpk.pk);
+        let (pk, key_sign) = with_even_y(&key.tpk.pk);
+        let r = x_only(&R);
+        let e = challenge(&r, &x_only(&pk), msg);
+
+        let flip = |shares: &BTreeMap<Id, G>, sign: &Zq| -> BTreeMap<Id, G> {
+            shares.iter().map(|(&j, X)| (j, X * sign)).collect()
+        };
+        Challenge {
+            r,
+            nonce_shares: flip(&nonce.tpk.pub_shares, &nonce_sign),
+            key_shares: flip(&key.tpk.pub_shares, &key_sign),
+            e,
+            nonce_sign,
+            key_sign,
+        }
+    }
+
+    /// Our share of s = k + e * x.
+    fn s_share(&self, key: &DkgOutput, nonce: &DkgOutput) -> Zq {
+        &self.nonce_sign * &nonce.share + &self.e * &self.key_sign * &key.share
+    }
+
+    fn verify_share(&self, id: Id, s_share: &Zq) -> bool {
+        match (self.nonce_shares.get(&id), self.key_shares.get(&id)) {
+            (Some(K), Some(X)) => G::generator() * s_share == K + X * &self.e,
+            _ => false,
+        }
+    }
+}
+
+/// One round of Schnorr signing. `nonce` is the output of a DKG run used for this
+/// signature only; signing two messages with it reveals the key.
+///
+/// Each party sends its share of s = k + e * x, which the others check against the
+/// public shares of both DKG runs and interpolate once t of them are in.
+pub struct SchnorrSignState<'a> {
+    my_id: Id, // in the range 1..=n
+    pp: &'a PubParams,
+    challenge: Challenge,
+    messages: BTreeMap<Id, PartialSchnorrMsg>,
+    s_shares: BTreeMap<Id, Zq>, // the verified ones
+    outbox: Vec<Outgoing<SchnorrMsg>>,
+}
+
+impl<'a> SchnorrSignState<'a> {
+    pub fn new(
+        my_id: Id,
+        pp: &'a PubParams,
+        key: &DkgOutput,
+        nonce: DkgOutput,
+        msg: &[u8],
+    ) -> Self {
+        let challenge = Challenge::new(key, &nonce, msg);
+        let s_share = challenge.s_share(key, &nonce);
+        let msg = PartialSchnorrMsg {
+            s_share: s_share.clone(),
+        };
+
+        SchnorrSignState {
+            my_id,
+            pp,
+            challenge,
+            messages: BTreeMap::from([(my_id, msg.clone())]),
+            s_shares: BTreeMap::from([(my_id, s_share)]),
+            outbox: vec![Outgoing::broadcast(SchnorrMsg::PartialSig(msg))],
+        }
+    }
+
+    fn check_share(&mut self, id: Id) {
+        let s_share = &self.messages[&id].s_share;
+        if self.challenge.verify_share(id, s_share) {
+            self.s_shares.insert(id, s_share.clone());
+        }
+    }
+}
+
+impl<'a> StateMachine for SchnorrSignState<'a> {
+    type Msg = SchnorrMsg;
+    type Output = SchnorrSignature;
+
+    fn handle_message(&mut self, from: PartyIndex, msg: SchnorrMsg) -> Result<(), StateError> {
+        let id = sender_id(self.pp, self.my_id, from)?;
+
+        match msg {
+            SchnorrMsg::PartialSig(msg) => {
+                insert_once(&mut self.messages, id, msg, from)?;
+            }
+        }
+        self.check_share(id);
+        Ok(())
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<SchnorrMsg>> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    fn try_finish(self) -> Result<SchnorrSignature, Self> {
+        match self.pp.interpolate(&self.s_shares) {
+            Some(s) if self.outbox.is_empty() => Ok(SchnorrSignature {
+                r: self.challenge.r,
+                s,
+            }),
+            _ => Err(self),
+        }
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        too_few_by_deadline(self.pp, self.s_shares.keys().copied().collect())
+    }
+}
+
+pub async fn sign<M>(
+    party: M,
+    my_id: Id, // in the range 1..=n, subtract one before use
+    pp: &PubParams,
+    key: &DkgOutput,
+    nonce: DkgOutput,
+    msg: &[u8],
+) -> Result<SchnorrSignature, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = SchnorrMsg>,
+{
+    run_within(
+        party,
+        SchnorrSignState::new(my_id, pp, key, nonce, msg),
+        &pp.limits,
+    )
+    .await
+}
+
+#[test]
+#[cfg(not(feature = "curve-p256"))]
+pub fn test_schnorr_matches_k256() {
+    use crate::spdz::{run_locally, simulate_pp};
+    use crate::test_utils::simulation::dkg_locally;
+    use k256::ecdsa::signature::hazmat::PrehashVerifier;
+    use k256::schnorr::{Signature, VerifyingKey};
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    // reruns the DKG until the joint point has the wanted parity
+    let dkg_with_odd_y = |odd: bool| loop {
+        let outputs = dkg_locally(&pp, &secret_keys);
+        if has_odd_y(&outputs[&0].tpk.pk) == odd {
+            return outputs;
+        }
+    };
+
+    let msg = [0x42u8; 32];
+    for key_odd in [false, true] {
+        let keys = dkg_with_odd_y(key_odd);
+        let pk = &keys[&0].tpk.pk;
+        let verifying_key = VerifyingKey::from_bytes(&x_only(pk)).unwrap();
+
+        for nonce_odd in [false, true] {
+            let nonces = dkg_with_odd_y(nonce_odd);
+            let signatures = run_locally(
+                nonces
+                    .into_iter()
+                    .map(|(i, nonce)| {
+                        let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                        (i, SchnorrSignState::new(id, &pp, &keys[&i], nonce, &msg))
+                    })
+                    .collect(),
+            );
+
+            assert_eq!(signatures.len(), pp.n as usize);
+            for signature in signatures.values() {
+                assert!(signature.verify(pk, &msg));
+                let bytes = signature.to_bytes();
+                let k256_signature = Signature::try_from(&bytes[..]).unwrap();
+                assert!(
+                    verifying_key.verify_prehash(&msg, &k256_signature).is_ok(),
+                    "key odd: {key_odd}, nonce odd: {nonce_odd}"
+                );
+                assert!(!signature.verify(pk, &[0x43u8; 32]));
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg(not(feature = "curve-p256"))]
+pub fn test_schnorr_combine_with_blame() {
+    use crate::spdz::simulate_pp;
+    use crate::test_utils::simulation::dkg_locally;
+    use k256::ecdsa::signature::hazmat::PrehashVerifier;
+    use k256::schnorr::{Signature, VerifyingKey};
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let keys = dkg_locally(&pp, &secret_keys);
+    let nonces = dkg_locally(&pp, &secret_keys);
+    let pk = &keys[&0].tpk.pk;
+    let msg = [0x17u8; 32];
+
+    let mut shares: BTreeMap<PartyIndex, PartialSchnorrMsg> = nonces
+        .iter()
+        .map(|(&i, nonce)| {
+            let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+            let mut state = SchnorrSignState::new(id, &pp, &keys[&i], nonce.clone(), &msg);
+            let SchnorrMsg::PartialSig(share) = state.wants_to_send().remove(0).msg;
+            (i, share)
+        })
+        .collect();
+    let combine = |shares: &BTreeMap<PartyIndex, PartialSchnorrMsg>| {
+        SchnorrSignature::try_from_with_blame(&pp, &keys[&0], &nonces[&0], &msg, shares)
+    };
+
+    let signature = combine(&shares).unwrap();
+    assert!(signature.verify(pk, &msg));
+    let verifying_key = VerifyingKey::from_bytes(&x_only(pk)).unwrap();
+    let k256_signature = Signature::try_from(&signature.to_bytes()[..]).unwrap();
+    assert!(verifying_key.verify_prehash(&msg, &k256_signature).is_ok());
+
+    // a bad share is named, and the rest still sign
+    let tampered = &shares[&1].s_share + Zq::from(1u64);
+    shares.get_mut(&1).unwrap().s_share = tampered;
+    assert_eq!(
+        combine(&shares),
+        Err(SignError::InvalidPartial { parties: vec![1] })
+    );
+    shares.remove(&1);
+    assert_eq!(combine(&shares), Ok(signature));
+
+    shares.retain(|&i, _| i == 0);
+    assert_eq!(
+        combine(&shares),
+        Err(SignError::TooFewShares { got: 1, need: 2 })
+    );
+}
@@ -1,11 +1,23 @@
 use bicycl::{CL_HSMqk, CipherText, ClearText, Mpz, PublicKey, RandGen, SecretKey, QFI};
-use curv::{arithmetic::Converter, BigInt};
-use futures::SinkExt;
+use curv::{
+    arithmetic::{Converter, Samplable},
+    BigInt,
+};
+use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
-use std::time::Instant;
+use sha2::{Digest, Sha256};
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
 
+use crate::cl_setup::SecurityLevel;
+use crate::interpolation::lagrange_coeffs_at;
+#[cfg(feature = "signing-transcript")]
+use crate::interpolation::lagrange_coeffs_at_zero;
+pub use crate::presign::*;
+use crate::replay::SharedReplayCache;
+use crate::transport::{run_over, run_over_within, RoundBasedTransport};
 use crate::utils::*;
+use crate::validate::{Validate, ValidationError};
+use crate::version::{VersionRange, COMPACT_PVSS_VERSION};
 
 use round_based::{
     rounds_router::{
@@ -14,231 +26,6382 @@ use round_based::{
     },
     simulation::Simulation,
 };
-use round_based::{Delivery, Mpc, MpcParty, MsgId, Outgoing, PartyIndex, ProtocolMessage};
+use round_based::{
+    Delivery, MessageDestination, Mpc, MpcParty, MsgId, Outgoing, PartyIndex, ProtocolMessage,
+};
 use thiserror::Error;
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PvssMsg {
-    dealing: PvssDealing,
-    proof: PvssNizk,
+    pub(crate) dealing: PvssDealing,
+    pub(crate) proof: PvssNizk,
+    pub(crate) membership: Option<MembershipProof>, // signs the dealing, if the dealer chose to
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct OpenPowerMsg {
-    point: G,
-    proof: DleqNizk,
+    #[serde(with = "crate::codec::compressed_point")]
+    pub(crate) point: G,
+    pub(crate) proof: DleqNizk,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ThresholdPubKey {
-    pk: G,
-    pub_shares: BTreeMap<Id, G>,
+    pub pk: G,
+    pub pub_shares: BTreeMap<Id, G>,
+}
+
+/// What a party keeps after the DKG: its share of the secret key and the public key material
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DkgOutput {
+    pub share: Zq,
+    pub tpk: ThresholdPubKey,
+    pub qualified: Vec<Id>, // dealers whose sharings make up the key
+    pub transcript: Option<DkgTranscript>,
+}
+
+/// A record of how a key came about, for audit: the qualified dealers with hashes of their
+/// dealings, and the accepted openings of the public shares. Anyone holding the dealings
+/// can recompute the joint sharing, check the openings against it and arrive at `pk`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DkgTranscript {
+    pub qualified: Vec<Id>,
+    pub dealing_hashes: Vec<[u8; 32]>, // in the order of `qualified`
+    pub openings: BTreeMap<Id, OpenPowerMsg>,
+    pub pk: G,
+}
+
+impl DkgTranscript {
+    pub fn dealing_hash(dealing: &PvssDealing) -> [u8; 32] {
+        Sha256::digest(crate::codec::encode(dealing)).into()
+    }
+
+    /// Checks the transcript against the dealings of the qualified parties.
+    pub fn verify(&self, dealings: &BTreeMap<Id, PvssDealing>, pp: &PubParams, h: &G) -> bool {
+        if self.qualified.len() != self.dealing_hashes.len() {
+            return false;
+        }
+        let qualified_dealings: Option<Vec<PvssDealing>> = self
+            .qualified
+            .iter()
+            .zip(&self.dealing_hashes)
+            .map(|(j, hash)| {
+                dealings
+                    .get(j)
+                    .filter(|dealing| Self::dealing_hash(dealing) == *hash)
+                    .cloned()
+            })
+            .collect();
+        let pvss_result = match qualified_dealings {
+            Some(dealings) => JointPvssResult::new(pp, dealings),
+            None => return false,
+        };
+
+        if !self
+            .openings
+            .iter()
+            .all(|(&id, opening)| id >= 1 && opening.verify(h, &pvss_result.curve_mac(id)))
+        {
+            return false;
+        }
+        match pp.lagrange_coeffs(self.openings.keys().copied().collect()) {
+            Some(lagrange_coeffs) => {
+                let pk: G = self
+                    .openings
+                    .iter()
+                    .map(|(i, opening)| &lagrange_coeffs[i] * &opening.point)
+                    .sum();
+                pk == self.pk
+            }
+            None => false,
+        }
+    }
+}
+
+impl DkgOutput {
+    /// The parties whose public share was opened and verified in the DKG, in order.
+    /// Presigning and signing go ahead among these: a party whose opening was rejected,
+    /// or that fell silent, is not expected to contribute later.
+    pub fn honest_parties(&self) -> Vec<Id> {
+        self.tpk.pub_shares.keys().copied().collect()
+    }
+
+    /// Checks a claimed key share of party `id` against its public share g^share.
+    pub fn verify_share(&self, id: Id, share: &Zq) -> bool {
+        self.public_share_of(id)
+            .map_or(false, |pub_share| G::generator() * share == pub_share)
+    }
+
+    /// The public share of party `id`. Parties whose opening was rejected in the DKG have
+    /// none on record, so theirs is interpolated from the others, of which the DKG
+    /// guarantees at least t.
+    pub fn public_share_of(&self, id: Id) -> Option<G> {
+        let pub_shares = &self.tpk.pub_shares;
+        match pub_shares.get(&id) {
+            Some(pub_share) => Some(pub_share.clone()),
+            None if !pub_shares.is_empty() && id >= 1 => {
+                let parties: Vec<Id> = pub_shares.keys().copied().collect();
+                Some(recover_public_share(&parties, pub_shares, id))
+            }
+            None => None,
+        }
+    }
+}
+
+/// Interpolates the public share of `target` on the curve from those of `parties`, which
+/// must all be in `cmts` and number at least t.
+pub fn recover_public_share(parties: &[Id], cmts: &BTreeMap<Id, G>, target: Id) -> G {
+    if parties.contains(&target) {
+        return cmts[&target].clone();
+    }
+    let coeffs = lagrange_coeffs_at(parties, &party_eval_point(target));
+    parties
+        .iter()
+        .map(|i| &cmts[i] * &coeffs[i])
+        .fold(G::zero(), |acc, term| acc + term)
 }
 
 impl PvssMsg {
+    /// Packs a dealing with its proof, for dealers that make both themselves with
+    /// `PvssDealing::random` and `PvssNizk::prove`.
+    pub fn new(dealing: PvssDealing, proof: PvssNizk) -> Self {
+        PvssMsg {
+            dealing,
+            proof,
+            membership: None,
+        }
+    }
+
+    /// Signs the dealing as party `my_id` of the keyring, with a proof of knowledge of
+    /// its CL secret key.
+    pub fn with_membership_proof(
+        mut self,
+        pp: &PubParams,
+        my_id: Id,
+        my_cl_sk: &SecretKey,
+        rng: &mut impl ClRandomness,
+    ) -> Self {
+        self.membership = Some(MembershipProof::prove(
+            pp,
+            my_id,
+            &self.dealing,
+            my_cl_sk,
+            rng,
+        ));
+        self
+    }
+
+    /// Checks that the dealing was signed by the keyring's party `dealer`.
+    pub fn verify_membership(&self, pp: &PubParams, dealer: Id) -> Result<(), NizkError> {
+        self.membership
+            .as_ref()
+            .ok_or(NizkError::MissingMembershipProof)?
+            .verify(pp, dealer, &self.dealing)
+    }
+
+    pub fn dealing(&self) -> &PvssDealing {
+        &self.dealing
+    }
+
+    /// Splits the message for light dealing into a header for everyone and a leaf for
+    /// each recipient.
+    pub fn split(&self) -> (PvssHeader, BTreeMap<Id, PvssLeaf>) {
+        let ciphertext = &self.dealing.shares_ciphertext;
+        let header = PvssHeader {
+            root: ciphertext.merkle_root(),
+            randomness: ciphertext.randomness.clone(),
+            curve_polynomial: self.dealing.curve_polynomial.clone(),
+            proof: self.proof.clone(),
+        };
+        let leaves = ciphertext
+            .encryption
+            .iter()
+            .map(|(&id, enc)| {
+                let leaf = PvssLeaf {
+                    recipient: id,
+                    ciphertext: enc.clone(),
+                    path: ciphertext.membership_proof(id).unwrap(),
+                };
+                (id, leaf)
+            })
+            .collect();
+
+        (header, leaves)
+    }
+
     pub fn random(pp: &PubParams, rng: &mut RandGen, curve_generator: &G) -> Self {
-        let (dealing, r, _, shares) = PvssDealing::random(pp, rng, curve_generator);
-        let proof = PvssNizk::prove(pp, &dealing, &r, &shares, rng, curve_generator);
+        Self::random_with_aux(pp, rng, curve_generator, &[])
+    }
+
+    /// Like `random`, with the proof bound to the context `aux`.
+    pub fn random_with_aux(
+        pp: &PubParams,
+        rng: &mut RandGen,
+        curve_generator: &G,
+        aux: &[u8],
+    ) -> Self {
+        let (dealing, r, _, mut shares) = PvssDealing::random(pp, rng, curve_generator);
+        let proof = PvssNizk::prove_with_aux(pp, &dealing, &r, &shares, rng, curve_generator, aux);
+        zeroize_shares(&mut shares);
+
+        PvssMsg::new(dealing, proof)
+    }
+
+    pub fn verify(&self, pp: &PubParams, curve_generator: &G) -> Result<(), NizkError> {
+        self.verify_with_aux(pp, curve_generator, &[])
+    }
+
+    pub fn verify_with_aux(
+        &self,
+        pp: &PubParams,
+        curve_generator: &G,
+        aux: &[u8],
+    ) -> Result<(), NizkError> {
+        self.proof
+            .verify_detailed_with_aux(&self.dealing, pp, curve_generator, aux)
+    }
+
+    /// A dealing whose curve polynomial does not match the encrypted shares.
+    #[cfg(test)]
+    pub fn malformed(pp: &PubParams, rng: &mut RandGen, curve_generator: &G) -> Self {
+        let (dealing, r, _, shares) = PvssDealing::new_malformed(pp, rng, curve_generator);
+        let proof = PvssNizk::prove_with_aux(pp, &dealing, &r, &shares, rng, curve_generator, &[]);
+
+        PvssMsg::new(dealing, proof)
+    }
+}
+
+/// A dealing without its encrypted shares, which `root` commits to instead.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PvssHeader {
+    pub(crate) root: [u8; 32],
+    pub(crate) randomness: QFI,
+    pub(crate) curve_polynomial: CurvePolynomial,
+    pub(crate) proof: PvssNizk,
+}
+
+/// The encrypted share of one recipient, with its path to the root of the dealing.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PvssLeaf {
+    pub(crate) recipient: Id,
+    pub(crate) ciphertext: QFI,
+    pub(crate) path: MerklePath,
+}
+
+impl PvssHeader {
+    /// Checks the recipient's leaf against the header and decrypts its share. Without
+    /// the other leaves the PVSS proof cannot be checked, so the share is checked against
+    /// its MAC on the curve polynomial instead.
+    pub fn open(
+        &self,
+        pp: &PubParams,
+        curve_generator: &G,
+        leaf: &PvssLeaf,
+        cl_sk: &SecretKey,
+    ) -> Result<Zq, NizkError> {
+        if self.curve_polynomial.coeffs.len() != pp.t as usize {
+            return Err(NizkError::DegreeMismatch {
+                got: self.curve_polynomial.coeffs.len(),
+                expected: pp.t as usize,
+            });
+        }
+        if !CLMultiRecvCiphertext::verify_membership(
+            &self.root,
+            leaf.recipient,
+            &leaf.ciphertext,
+            &leaf.path,
+        ) {
+            return Err(NizkError::MembershipMismatch);
+        }
 
-        PvssMsg { dealing, proof }
+        let ciphertext = self.dealing_for(leaf).shares_ciphertext;
+        ciphertext.check_discriminant(&pp.cl)?;
+        let share = ciphertext.decrypt(&pp.cl, leaf.recipient, cl_sk);
+        if curve_generator * &share
+            != self
+                .curve_polynomial
+                .eval(&party_eval_point(leaf.recipient))
+        {
+            return Err(NizkError::ShareMismatch);
+        }
+        Ok(share)
+    }
+
+    /// The dealing as the recipient of `leaf` sees it, with no shares for anyone else.
+    fn dealing_for(&self, leaf: &PvssLeaf) -> PvssDealing {
+        PvssDealing {
+            curve_polynomial: self.curve_polynomial.clone(),
+            shares_ciphertext: CLMultiRecvCiphertext {
+                randomness: self.randomness.clone(),
+                encryption: BTreeMap::from([(leaf.recipient, leaf.ciphertext.clone())]),
+            },
+        }
     }
 }
 
 impl OpenPowerMsg {
-    pub fn new(secret: &Zq, gen1: &G, gen2: &G, pow1: &G) -> Self {
+    pub fn new(secret: &Zq, gen1: &G, gen2: &G, pow1: &G, rng: &mut impl ClRandomness) -> Self {
         let point = gen2 * secret;
-        let proof = DleqNizk::prove(gen1, gen2, pow1, &point, secret);
+        let proof = DleqNizk::prove(gen1, gen2, pow1, &point, secret, rng);
 
         OpenPowerMsg { point, proof }
     }
+
+    /// Checks that the opened point g^x matches `mac` = h^x.
+    pub fn verify(&self, h: &G, mac: &G) -> bool {
+        self.proof.verify(h, mac, &G::generator(), &self.point)
+    }
 }
 
-#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
-pub enum DkgMsg {
-    Pvss(PvssMsg),
-    PowOpen(OpenPowerMsg),
+/// The dealers that make up a key and their joint sharing, as settled after the first
+/// DKG round.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CombinedDealings {
+    pub qualified: Vec<Id>,
+    pub pvss_result: JointPvssResult,
+    /// Every dealing that failed its checks, and why.
+    pub rejected: Vec<(Id, DealerDisqualReason)>,
 }
 
-#[derive(Debug, Error)]
-pub enum Error<RecvErr, SendErr> {
-    #[error("sending, round 1")]
-    Round1Send(#[source] SendErr),
-    #[error("receiving, round 1")]
-    Round1Recv(#[source] CompleteRoundError<RoundInputError, RecvErr>),
-    #[error("sending, round 2")]
-    Round2Send(#[source] SendErr),
-    #[error("receiving, round 2")]
-    Round2Recv(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+/// Why a dealer's DKG dealing did not qualify. A buggy peer tends to fail on the shape
+/// of its dealing, while a proof that does not verify points to a cheating dealer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DealerDisqualReason {
+    /// The dealer is not in the committee.
+    UnknownDealer,
+    /// Its curve polynomial has `got` coefficients instead of t.
+    WrongDegree { got: usize, expected: usize },
+    /// A coefficient of its curve polynomial is the point at infinity.
+    IdentityCommitment,
+    /// Its encrypted shares leave out a party, name one outside the committee, or are
+    /// not in the class group.
+    MalformedCiphertexts,
+    /// Its PVSS proof is for another set of parties than the committee.
+    WrongParties,
+    /// The challenge of its PVSS proof does not match.
+    ChallengeMismatch,
+    /// Its PVSS proof does not decode: the response is larger than any an honest
+    /// dealer produces.
+    UndecodableProof,
+    /// Its dealing holds no share for us, or one that is not part of the dealing.
+    MissingShare,
+    /// The share it dealt us does not match its curve polynomial.
+    ShareMismatch,
+    /// Its membership proof is missing or does not verify under its key.
+    Membership,
 }
 
-pub async fn dkg<M>(
-    party: M,
-    my_id: Id, // in the range 1..=n, subtract one before use
-    pp: &PubParams,
-    h: &G,
-    my_cl_sk: &SecretKey,
-    lazy_verification: bool,
-) -> Result<ThresholdPubKey, Error<M::ReceiveError, M::SendError>>
-where
-    M: Mpc<ProtocolMessage = DkgMsg>,
-{
-    let mut rng = RandGen::new();
-    rng.set_seed(&Mpz::from(&Zq::random()));
+impl DealerDisqualReason {
+    fn from_validation(err: ValidationError) -> Self {
+        match err {
+            ValidationError::WrongLength { got, expected, .. } => {
+                DealerDisqualReason::WrongDegree { got, expected }
+            }
+            ValidationError::IdentityPoint { .. } => DealerDisqualReason::IdentityCommitment,
+            _ => DealerDisqualReason::MalformedCiphertexts,
+        }
+    }
 
-    // boilerplate
-    let MpcParty { delivery, .. } = party.into_party();
-    let (incoming, mut outgoing) = delivery.split();
+    fn from_proof(err: NizkError) -> Self {
+        match err {
+            NizkError::DegreeMismatch { got, expected } => {
+                DealerDisqualReason::WrongDegree { got, expected }
+            }
+            NizkError::PartySetMismatch => DealerDisqualReason::WrongParties,
+            NizkError::DiscriminantMismatch => DealerDisqualReason::MalformedCiphertexts,
+            NizkError::ChallengeMismatch => DealerDisqualReason::ChallengeMismatch,
+            NizkError::DeserializationError => DealerDisqualReason::UndecodableProof,
+            NizkError::MembershipMismatch => DealerDisqualReason::MissingShare,
+            NizkError::ShareMismatch => DealerDisqualReason::ShareMismatch,
+            NizkError::NotInKeyring { .. } => DealerDisqualReason::UnknownDealer,
+            NizkError::MissingMembershipProof => DealerDisqualReason::Membership,
+        }
+    }
+}
 
-    let i = (my_id - 1) as u16;
-    let n = pp.n as u16;
+/// Checks the dealing of dealer `j`: its shape always, even when its proof is not
+/// checked, since the dealings are combined; then its membership proof with
+/// `check_membership`, and its PVSS proof with `check_proof`.
+fn check_dealing(
+    pp: &PubParams,
+    h: &G,
+    j: Id,
+    msg: &PvssMsg,
+    check_membership: bool,
+    check_proof: bool,
+) -> Result<(), DealerDisqualReason> {
+    if !(1..=pp.n).contains(&j) {
+        return Err(DealerDisqualReason::UnknownDealer);
+    }
+    msg.validate(pp)
+        .map_err(DealerDisqualReason::from_validation)?;
+    if check_membership && msg.verify_membership(pp, j).is_err() {
+        return Err(DealerDisqualReason::Membership);
+    }
+    if check_proof {
+        msg.verify(pp, h).map_err(DealerDisqualReason::from_proof)?;
+    }
+    Ok(())
+}
 
-    let mut rounds = RoundsRouter::<DkgMsg>::builder();
-    let round1 = rounds.add_round(RoundInput::<PvssMsg>::broadcast(i, n));
-    let round2 = rounds.add_round(RoundInput::<OpenPowerMsg>::broadcast(i, n));
-    let mut rounds = rounds.listen(incoming);
+/// Logs a rejected dealing, when built with the `tracing` feature.
+fn log_rejected_dealing(dealer: Id, reason: &DealerDisqualReason) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(dealer, ?reason, "dealing rejected");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (dealer, reason);
+}
 
-    // Round 1 interaction
-    let pvss_msg = PvssMsg::random(pp, &mut rng, h);
-    outgoing
-        .send(Outgoing::broadcast(DkgMsg::Pvss(pvss_msg.clone())))
-        .await
-        .map_err(Error::Round1Send)?;
+/// Combines DKG dealings without taking part in the DKG, so no secret key is needed: the
+/// first t dealings with a valid proof are summed up, once `PubParams::min_qualified`
+/// of them verified. An aggregator outside the committee can do this and hand the joint
+/// ciphertexts to the share holders. It settles on the same dealers as every party of
+/// the committee whose own dealing is valid. Every dealing must carry a valid membership
+/// proof, since the combiner has no other way to tell a member's dealing from an
+/// outsider's. Every dealing is checked, past the first t valid ones too, and each
+/// rejected one is reported with the reason.
+pub fn combine_dealings(
+    pp: &PubParams,
+    h: &G,
+    dealings: &BTreeMap<Id, PvssMsg>,
+) -> Result<CombinedDealings, StateError> {
+    let mut rejected = vec![];
+    let qualified: Vec<Id> = dealings
+        .iter()
+        .filter(|(&j, msg)| match check_dealing(pp, h, j, msg, true, true) {
+            Ok(()) => true,
+            Err(reason) => {
+                log_rejected_dealing(j, &reason);
+                rejected.push((j, reason));
+                false
+            }
+        })
+        .map(|(&j, _)| j)
+        .collect();
+    let qualified = qualify(pp, qualified)?;
 
-    let pvss_messages = rounds.complete(round1).await.map_err(Error::Round1Recv)?;
+    let pvss_result = fold_dealings(pp, &qualified, dealings);
+    Ok(CombinedDealings {
+        qualified,
+        pvss_result,
+        rejected,
+    })
+}
 
-    // Round 1 processing
-    let start = Instant::now();
-    let mut pvss_dealings = BTreeMap::new();
-    pvss_dealings.insert(my_id, pvss_msg.dealing);
+/// The joint sharing of the `qualified` dealers, which must be distinct and at least one.
+fn fold_dealings(
+    pp: &PubParams,
+    qualified: &[Id],
+    messages: &BTreeMap<Id, PvssMsg>,
+) -> JointPvssResult {
+    let mut acc = JointPvssAccumulator::new(pp);
+    for j in qualified {
+        acc.add_dealing(*j, &messages[j].dealing)
+            .expect("the qualified dealers are distinct");
+    }
+    acc.finish().expect("at least one dealer qualified")
+}
 
-    pvss_messages
-        .into_iter_indexed()
-        .map(|(inner_id, _, msg)| ((inner_id + 1) as Id, msg))
-        .filter(|(_, msg)| lazy_verification || msg.proof.verify(&msg.dealing, pp, h))
-        .take(pp.t as usize)
-        .for_each(|(j, msg)| {
-            pvss_dealings.insert(j, msg.dealing);
+/// The first t of the dealings that verified, in order, provided at least
+/// `PubParams::min_qualified` of them did.
+fn qualify<T>(pp: &PubParams, verified: Vec<T>) -> Result<Vec<T>, StateError> {
+    let need = pp.min_qualified();
+    if verified.len() < need {
+        return Err(StateError::InsufficientQualifiedDealings {
+            got: verified.len(),
+            need,
         });
+    }
+    Ok(verified.into_iter().take(pp.t as usize).collect())
+}
 
-    let pvss_result = JointPvssResult::new(
-        pp,
-        pvss_dealings
-            .values()
-            .take(pp.t as usize)
-            .cloned()
-            .collect(),
-    );
-
-    let my_ciphertext = CipherText::new(
-        &pvss_result.shares_ciphertext.randomness,
-        &pvss_result.shares_ciphertext.encryption[&my_id],
-    );
-
-    let my_share = Zq::from(BigInt::from_bytes(
-        &pp.cl.decrypt(my_cl_sk, &my_ciphertext).mpz().to_bytes(),
-    ));
-
+/// The share holder's part between the two combinations: decrypts its share of the joint
+/// sharing and opens g^share, with a proof that it matches the share's MAC.
+pub fn open_share(
+    pp: &PubParams,
+    h: &G,
+    my_id: Id,
+    my_cl_sk: &SecretKey,
+    pvss_result: &JointPvssResult,
+    rng: &mut impl ClRandomness,
+) -> (Zq, OpenPowerMsg) {
+    let my_share = pvss_result
+        .shares_ciphertext
+        .decrypt(&pp.cl, my_id, my_cl_sk);
     let my_pub_share = G::generator() * &my_share;
-
-    let dleq_proof = DleqNizk::prove(
-        &h,
-        &pvss_result.curve_macs[&my_id],
+    let proof = DleqNizk::prove(
+        h,
+        &pvss_result.curve_mac(my_id),
         &G::generator(),
         &my_pub_share,
         &my_share,
+        rng,
     );
 
     let open_power_msg = OpenPowerMsg {
         point: my_pub_share,
-        proof: dleq_proof,
+        proof,
     };
+    (my_share, open_power_msg)
+}
 
-    // Round 2 interaction
-    outgoing
-        .send(Outgoing::broadcast(DkgMsg::PowOpen(open_power_msg.clone())))
-        .await
-        .map_err(Error::Round2Send)?;
-
-    let open_power_messages = rounds.complete(round2).await.map_err(Error::Round2Recv)?;
-
-    // Round 2 processing
-    let mut pub_shares = BTreeMap::new();
-    pub_shares.insert(my_id, open_power_msg.point);
-
-    open_power_messages
-        .into_iter_indexed()
-        .map(|(inner_id, _, msg)| ((inner_id + 1) as Id, msg))
-        .filter(|(id, msg)| {
-            msg.proof
-                .verify(&h, &pvss_result.curve_macs[id], &G::generator(), &msg.point)
+/// Combines the openings of the public shares into the public key, keeping those that
+/// match their MACs. Like `combine_dealings`, this needs no secret key.
+pub fn combine_openings(
+    pp: &PubParams,
+    h: &G,
+    pvss_result: &JointPvssResult,
+    openings: &BTreeMap<Id, OpenPowerMsg>,
+) -> Result<ThresholdPubKey, StateError> {
+    let pub_shares: BTreeMap<Id, G> = openings
+        .iter()
+        .filter(|(&id, msg)| {
+            (1..=pp.n).contains(&id)
+                && msg.validate(pp).is_ok()
+                && msg.verify(h, &pvss_result.curve_mac(id))
         })
-        .for_each(|(j, msg)| {
-            pub_shares.insert(j, msg.point);
-        });
+        .map(|(&id, msg)| (id, msg.point.clone()))
+        .collect();
 
     let lagrange_coeffs = pp
         .lagrange_coeffs(pub_shares.keys().copied().collect())
-        .unwrap();
+        .ok_or_else(|| StateError::TooFewQualified {
+            got: pub_shares.len(),
+            need: pp.t as usize,
+            blamed: unqualified(pp, pub_shares.keys()),
+        })?;
     let pk = pub_shares
         .iter()
         .map(|(i, share)| &lagrange_coeffs[i] * share)
         .sum();
 
-    // todo: interpolate the missing public shares.
-
     Ok(ThresholdPubKey { pk, pub_shares })
 }
 
-pub fn simulate_pp(n: Id, t: Id) -> (PubParams, BTreeMap<Id, SecretKey>) {
-    let mut rng = RandGen::new();
-    rng.set_seed(&Mpz::from(&Zq::random()));
+/// Optional last DKG round: a digest of the key as the sender sees it, with a proof
+/// that the sender knows the share behind its MAC.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyConfirmMsg {
+    pub(crate) digest: Vec<u8>,
+    pub(crate) proof: DleqNizk,
+}
 
-    let cl = CL_HSMqk::with_rand_gen(
-        &Mpz::from_bytes(&Zq::group_order().to_bytes()),
-        1,
-        1827,
-        &mut rng,
-        &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
-        false,
-    );
+impl KeyConfirmMsg {
+    pub fn digest(pk: &G, qualified: &[Id], session_id: &[u8]) -> Vec<u8> {
+        Sha256::new()
+            .chain_update(pk.to_bytes(true))
+            .chain_update(
+                qualified
+                    .iter()
+                    .flat_map(|id| id.to_be_bytes())
+                    .collect::<Vec<u8>>(),
+            )
+            .chain_update(session_id)
+            .finalize()
+            .to_vec()
+    }
+
+    /// Checks the digest against the receiver's own, and the proof against the sender's
+    /// MAC and public key share.
+    pub fn verify(&self, digest: &[u8], h: &G, mac: &G, pub_share: &G) -> bool {
+        self.digest == digest && self.proof.verify(h, mac, &G::generator(), pub_share)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum DkgMsg {
+    Pvss(PvssMsg),
+    PowOpen(OpenPowerMsg),
+    KeyConfirm(KeyConfirmMsg),
+    PvssHeader(PvssHeader),
+    PvssLeaf(PvssLeaf),
+    MaskedPvss(MaskedPvssDealing),
+}
 
-    let mut secret_keys = BTreeMap::new();
-    let mut cl_keyring = BTreeMap::new();
+#[derive(Debug, Error)]
+pub enum Error<RecvErr, SendErr> {
+    #[error("sending message")]
+    Send(#[source] SendErr),
+    #[error("receiving message")]
+    Recv(#[source] RecvErr),
+    #[error("incoming messages ended before the protocol completed")]
+    UnexpectedEof,
+    #[error("message from wire index {index}, which is beyond any party index")]
+    SenderOutOfRange { index: usize },
+    #[error("the session was cancelled")]
+    Cancelled,
+    #[error("party {party} aborted the session")]
+    PeerAborted { party: PartyIndex },
+    #[error("the signing policy rejected the presignature")]
+    PolicyRejected,
+    #[error("the keyring has no CL public key for parties {ids:?}")]
+    MissingClKey { ids: Vec<Id> },
+    #[error("handling incoming message")]
+    State(#[source] StateError),
+    #[error("parties {differing:?} ended up with a different key")]
+    KeyDisagreement { differing: Vec<PartyIndex> },
+    #[error("party {peer} speaks wire format versions {theirs}, but we speak {ours}")]
+    VersionMismatch {
+        peer: PartyIndex,
+        theirs: VersionRange,
+        ours: VersionRange,
+    },
+}
 
-    for i in 1..=n {
-        let sk = cl.secret_key_gen(&mut rng);
-        cl_keyring.insert(i, cl.public_key_gen(&sk));
-        secret_keys.insert(i, sk);
+impl<RecvErr, SendErr> From<StateError> for Error<RecvErr, SendErr> {
+    fn from(err: StateError) -> Self {
+        match err {
+            StateError::KeyDisagreement { differing } => Error::KeyDisagreement { differing },
+            StateError::PeerAborted { party } => Error::PeerAborted { party },
+            StateError::Cancelled => Error::Cancelled,
+            StateError::VersionMismatch { peer, theirs, ours } => {
+                Error::VersionMismatch { peer, theirs, ours }
+            }
+            err => Error::State(err),
+        }
     }
+}
 
-    (
-        PubParams {
-            n,
-            t,
-            cl,
-            cl_keyring,
-        },
-        secret_keys,
-    )
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("message from unknown party {0}")]
+    UnknownSender(PartyIndex),
+    #[error("party {0} sent a second message for the same round")]
+    DuplicateSender(PartyIndex),
+    #[error("only {got} parties qualified, but {need} are required; {blamed:?} did not")]
+    TooFewQualified {
+        got: usize,
+        need: usize,
+        blamed: Vec<PartyIndex>,
+    },
+    #[error("a deadline passed in a DKG without key confirmation")]
+    KeyConfirmationRequired,
+    #[error("party {from} sent a message longer than the limit of {max} bytes")]
+    MessageTooLong { from: PartyIndex, max: u64 },
+    #[error("only {got} dealings verified, but {need} are required")]
+    InsufficientQualifiedDealings { got: usize, need: usize },
+    #[error("batch of {got} messages, but {expected} instances are running")]
+    BatchSizeMismatch { got: usize, expected: usize },
+    #[error("parties {differing:?} ended up with a different key")]
+    KeyDisagreement { differing: Vec<PartyIndex> },
+    #[error("the nonce has no usable x coordinate; discard it and presign again")]
+    DegenerateNonce,
+    #[error("the nonces were generated by another committee or under another keyring")]
+    ForeignNonces,
+    #[error("party {party} aborted the session")]
+    PeerAborted { party: PartyIndex },
+    #[error("the session was cancelled")]
+    Cancelled,
+    #[error("party {peer} speaks wire format versions {theirs}, but we speak {ours}")]
+    VersionMismatch {
+        peer: PartyIndex,
+        theirs: VersionRange,
+        ours: VersionRange,
+    },
 }
 
-#[tokio::test]
-pub async fn test_dkg() {
-    let (pp, secret_keys) = simulate_pp(3, 2);
-    let h = G::base_point2();
+/// A DKG mode asked of a `DkgState` that the committee or the state's other modes rule out.
+#[derive(Debug, Error, PartialEq)]
+pub enum DkgModeError {
+    #[error("the committee has not enabled compact dealing")]
+    CompactDisabled,
+    #[error("{mode} does not combine with {other}")]
+    Incompatible {
+        mode: &'static str,
+        other: &'static str,
+    },
+}
 
-    let mut simulation = Simulation::<DkgMsg>::new();
-    let mut party_output = vec![];
+/// The share index of the party at wire index `from`, if that is someone else in the committee.
+pub(crate) fn sender_id(pp: &PubParams, my_id: Id, from: PartyIndex) -> Result<Id, StateError> {
+    match PartyId::from_wire_index(from).map(PartyId::to_share_index) {
+        Some(id) if id <= pp.n && id != my_id => Ok(id),
+        _ => Err(StateError::UnknownSender(from)),
+    }
+}
+
+/// Stores the message `id` sent for a round. A second one is an error rather than a
+/// replacement: which of the two counts would otherwise depend on delivery order.
+pub(crate) fn insert_once<M>(
+    messages: &mut BTreeMap<Id, M>,
+    id: Id,
+    msg: M,
+    from: PartyIndex,
+) -> Result<(), StateError> {
+    match messages.entry(id) {
+        Entry::Vacant(entry) => {
+            entry.insert(msg);
+            Ok(())
+        }
+        Entry::Occupied(_) => Err(StateError::DuplicateSender(from)),
+    }
+}
 
-    let now = Instant::now();
+/// Sans-IO interface of a protocol phase: the caller feeds in received messages,
+/// forwards whatever the state wants to send, and polls for the output.
+pub trait StateMachine: Sized {
+    type Msg;
+    type Output;
 
-    for i in 1..=pp.n {
-        let party = simulation.add_party();
-        let result = dkg(party, i, &pp, &h, &secret_keys[&i], false);
-        party_output.push(result);
+    fn handle_message(&mut self, from: PartyIndex, msg: Self::Msg) -> Result<(), StateError>;
+
+    /// Drains the messages produced so far.
+    fn wants_to_send(&mut self) -> Vec<Outgoing<Self::Msg>>;
+
+    /// Returns the output once all rounds are done and every message has been handed out.
+    fn try_finish(self) -> Result<Self::Output, Self>;
+
+    /// Called when the deadline of the current round has passed: a robust round then
+    /// goes ahead with the messages it has, provided they are enough to qualify t
+    /// parties, rather than waiting for all n. Fails if fewer than t have arrived.
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        Ok(())
+    }
+
+    /// Called by `Versioned` with the wire format version the committee settled on,
+    /// before any of the messages produced so far are handed out.
+    fn on_version(&mut self, _version: u16) {}
+}
+
+/// Whether a robust round has heard from enough parties to go ahead: all n of them,
+/// or, once its deadline has passed, t.
+fn heard_enough(pp: &PubParams, received: usize, deadline_passed: bool) -> bool {
+    received >= pp.n as usize || (deadline_passed && received >= pp.t as usize)
+}
+
+/// The error for a round whose deadline passed before t parties were heard from,
+/// blaming those who were not.
+pub(crate) fn too_few_by_deadline(pp: &PubParams, heard: Vec<Id>) -> Result<(), StateError> {
+    if heard.len() < pp.t as usize {
+        return Err(StateError::TooFewQualified {
+            got: heard.len(),
+            need: pp.t as usize,
+            blamed: unqualified(pp, &heard),
+        });
+    }
+    Ok(())
+}
+
+/// The parties of the committee outside `qualified`, by wire index.
+pub(crate) fn unqualified<'a>(
+    pp: &PubParams,
+    qualified: impl IntoIterator<Item = &'a Id>,
+) -> Vec<PartyIndex> {
+    let qualified: BTreeSet<&Id> = qualified.into_iter().collect();
+    (1..=pp.n)
+        .filter(|id| !qualified.contains(id))
+        .map(|id| PartyId::from_share_index(id).to_wire_index())
+        .collect()
+}
+
+/// Checks the keyring against the committee before anything is dealt, so that a
+/// deployment that left out someone's key fails up front, naming who is missing. Keys of
+/// parties outside the committee are ignored.
+fn check_keyring<RecvErr, SendErr>(pp: &PubParams) -> Result<(), Error<RecvErr, SendErr>> {
+    let missing = pp.cl_keyring.missing(pp.n);
+    if !missing.is_empty() {
+        return Err(Error::MissingClKey { ids: missing });
+    }
+    let extra = pp.cl_keyring.extra(pp.n);
+    #[cfg(feature = "tracing")]
+    if !extra.is_empty() {
+        tracing::warn!(?extra, "ignoring CL keys of parties outside the committee");
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = extra;
+    Ok(())
+}
+
+/// Drives a state machine over a `round_based` party.
+pub async fn run<S, M>(
+    party: M,
+    state: S,
+) -> Result<S::Output, Error<M::ReceiveError, M::SendError>>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage,
+    M: Mpc<ProtocolMessage = S::Msg>,
+{
+    run_over(&mut RoundBasedTransport::new(party), state).await
+}
+
+/// Like `run`, but holds incoming messages to `limits`, see `run_over_within`.
+pub async fn run_within<S, M>(
+    party: M,
+    state: S,
+    limits: &ProtocolLimits,
+) -> Result<S::Output, Error<M::ReceiveError, M::SendError>>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage + Serialize,
+    M: Mpc<ProtocolMessage = S::Msg>,
+{
+    run_over_within(&mut RoundBasedTransport::new(party), state, limits).await
+}
+
+/// One message per instance of a batched protocol, all sent in the same round trip.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchMsg<M>(pub Vec<M>);
+
+impl<M: ProtocolMessage> ProtocolMessage for BatchMsg<M> {
+    fn round(&self) -> u16 {
+        self.0.first().map_or(0, |msg| msg.round())
+    }
+}
+
+/// Runs independent instances of a protocol side by side. The instances move through
+/// their rounds in lockstep, so their messages are bundled and the number of messages
+/// does not grow with the batch size.
+pub struct Batched<S: StateMachine> {
+    instances: Vec<Option<S>>,
+    outputs: Vec<Option<S::Output>>,
+}
+
+impl<S: StateMachine> Batched<S> {
+    pub fn new(instances: Vec<S>) -> Self {
+        Batched {
+            outputs: instances.iter().map(|_| None).collect(),
+            instances: instances.into_iter().map(Some).collect(),
+        }
+    }
+}
+
+impl<S: StateMachine> StateMachine for Batched<S> {
+    type Msg = BatchMsg<S::Msg>;
+    type Output = Vec<S::Output>;
+
+    fn handle_message(&mut self, from: PartyIndex, msg: Self::Msg) -> Result<(), StateError> {
+        if msg.0.len() != self.instances.len() {
+            return Err(StateError::BatchSizeMismatch {
+                got: msg.0.len(),
+                expected: self.instances.len(),
+            });
+        }
+
+        for (instance, msg) in self.instances.iter_mut().zip(msg.0) {
+            if let Some(instance) = instance {
+                instance.handle_message(from, msg)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<Self::Msg>> {
+        let mut bundled: Vec<Outgoing<Self::Msg>> = vec![];
+        for instance in self.instances.iter_mut().flatten() {
+            for (i, Outgoing { recipient, msg }) in instance.wants_to_send().into_iter().enumerate()
+            {
+                match bundled.get_mut(i) {
+                    Some(outgoing) => outgoing.msg.0.push(msg),
+                    None => bundled.push(Outgoing {
+                        recipient,
+                        msg: BatchMsg(vec![msg]),
+                    }),
+                }
+            }
+        }
+        bundled
+    }
+
+    fn try_finish(mut self) -> Result<Self::Output, Self> {
+        for (slot, output) in self.instances.iter_mut().zip(self.outputs.iter_mut()) {
+            if let Some(instance) = slot.take() {
+                match instance.try_finish() {
+                    Ok(result) => *output = Some(result),
+                    Err(instance) => *slot = Some(instance),
+                }
+            }
+        }
+
+        if self.outputs.iter().all(Option::is_some) {
+            Ok(self.outputs.into_iter().flatten().collect())
+        } else {
+            Err(self)
+        }
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        for instance in self.instances.iter_mut().flatten() {
+            instance.on_timeout()?;
+        }
+        Ok(())
+    }
+
+    fn on_version(&mut self, version: u16) {
+        for instance in self.instances.iter_mut().flatten() {
+            instance.on_version(version);
+        }
+    }
+}
+
+/// Runs state machines by delivering their messages directly, without any networking
+/// or async runtime, until no party can make progress. Parties that could not finish
+/// (e.g. because they were cheated against or ran into an error) are missing from the output.
+pub fn run_locally<S>(states: BTreeMap<PartyIndex, S>) -> BTreeMap<PartyIndex, S::Output>
+where
+    S: StateMachine,
+    S::Msg: Clone,
+{
+    run_locally_detailed(states).outputs
+}
+
+/// What became of each party in a local run: it finished, aborted with an error,
+/// or neither, when it was left waiting for messages that never came.
+pub struct LocalRun<O> {
+    pub outputs: BTreeMap<PartyIndex, O>,
+    pub aborted: BTreeMap<PartyIndex, StateError>,
+}
+
+/// Like `run_locally`, also reporting why parties aborted.
+pub fn run_locally_detailed<S>(states: BTreeMap<PartyIndex, S>) -> LocalRun<S::Output>
+where
+    S: StateMachine,
+    S::Msg: Clone,
+{
+    let mut aborted = BTreeMap::new();
+    let mut states: BTreeMap<PartyIndex, Option<S>> = states
+        .into_iter()
+        .map(|(i, state)| (i, Some(state)))
+        .collect();
+    let mut outputs = BTreeMap::new();
+
+    loop {
+        let mut progressed = false;
+
+        let senders: Vec<PartyIndex> = states.keys().copied().collect();
+        for sender in senders {
+            let outgoing = match states.get_mut(&sender) {
+                Some(Some(state)) => state.wants_to_send(),
+                _ => vec![],
+            };
+
+            for Outgoing { recipient, msg } in outgoing {
+                progressed = true;
+                for (&receiver, state) in states.iter_mut() {
+                    let addressed = match recipient {
+                        MessageDestination::AllParties => receiver != sender,
+                        MessageDestination::OneParty(j) => receiver == j,
+                    };
+                    if let (true, Some(party)) = (addressed, state.as_mut()) {
+                        if let Err(e) = party.handle_message(sender, msg.clone()) {
+                            aborted.insert(receiver, e);
+                            *state = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        for (&i, slot) in states.iter_mut() {
+            if let Some(state) = slot.take() {
+                match state.try_finish() {
+                    Ok(output) => {
+                        outputs.insert(i, output);
+                        progressed = true;
+                    }
+                    Err(state) => *slot = Some(state),
+                }
+            }
+        }
+
+        if !progressed {
+            return LocalRun { outputs, aborted };
+        }
+    }
+}
+
+enum DkgRound {
+    Pvss,
+    PowOpen {
+        pvss_result: JointPvssResult,
+        my_share: Zq,
+        qualified: Vec<Id>,
+    },
+    KeyConfirm {
+        pvss_result: JointPvssResult,
+        digest: Vec<u8>,
+        output: DkgOutput,
+    },
+}
+
+pub struct DkgState<'a> {
+    my_id: Id, // in the range 1..=n
+    pp: &'a PubParams,
+    h: &'a G,
+    my_cl_sk: &'a SecretKey,
+    lazy_verification: bool,
+    session_id: Option<Vec<u8>>, // set if the key is to be confirmed in an extra round
+    record_transcript: bool,
+    light_dealing: bool,
+    masked_dealing: bool,
+    require_membership: bool,
+    deadline_passed: bool, // for the current round
+    round: DkgRound,
+    pvss_messages: BTreeMap<Id, PvssMsg>,
+    pvss_headers: BTreeMap<Id, PvssHeader>, // in light dealing mode
+    pvss_leaves: BTreeMap<Id, PvssLeaf>,    // the ones addressed to us, by dealer
+    masked_dealings: BTreeMap<Id, MaskedPvssDealing>, // in compact mode
+    open_power_messages: BTreeMap<Id, OpenPowerMsg>,
+    key_confirm_messages: BTreeMap<Id, KeyConfirmMsg>,
+    rng: ForkableRng,
+    outbox: Vec<Outgoing<DkgMsg>>,
+    output: Option<DkgOutput>,
+}
+
+impl<'a> DkgState<'a> {
+    pub fn new(
+        my_id: Id,
+        pp: &'a PubParams,
+        h: &'a G,
+        my_cl_sk: &'a SecretKey,
+        lazy_verification: bool,
+    ) -> Self {
+        Self::seeded(
+            my_id,
+            pp,
+            h,
+            my_cl_sk,
+            lazy_verification,
+            ForkableRng::fresh(),
+        )
     }
 
-    let output = futures::future::try_join_all(party_output).await.unwrap();
-    let elapsed = now.elapsed();
-    println!("Elapsed: {:.2?}", elapsed);
+    /// Like `new`, with all of the party's randomness drawn from `rng`, so that a party
+    /// seeded alike and handed the same messages sends the same bytes. Only tests and
+    /// recorded test vectors should pass a fixed seed.
+    pub fn seeded(
+        my_id: Id,
+        pp: &'a PubParams,
+        h: &'a G,
+        my_cl_sk: &'a SecretKey,
+        lazy_verification: bool,
+        mut rng: ForkableRng,
+    ) -> Self {
+        let pvss_msg = PvssMsg::random(pp, &mut rng.rand_gen(), h);
+        let mut state = Self::with_pvss_msg(my_id, pp, h, my_cl_sk, lazy_verification, pvss_msg);
+        state.rng = rng;
+        state
+    }
+
+    /// Starts the DKG with a given dealing, e.g. to simulate a cheating dealer.
+    pub fn with_pvss_msg(
+        my_id: Id,
+        pp: &'a PubParams,
+        h: &'a G,
+        my_cl_sk: &'a SecretKey,
+        lazy_verification: bool,
+        pvss_msg: PvssMsg,
+    ) -> Self {
+        debug_assert_eq!(
+            *h, pp.curve_generator,
+            "h must be the curve generator of pp"
+        );
+
+        // Round 1 interaction
+        DkgState {
+            my_id,
+            pp,
+            h,
+            my_cl_sk,
+            lazy_verification,
+            session_id: None,
+            record_transcript: false,
+            light_dealing: false,
+            masked_dealing: false,
+            require_membership: false,
+            deadline_passed: false,
+            round: DkgRound::Pvss,
+            pvss_messages: BTreeMap::from([(my_id, pvss_msg.clone())]),
+            pvss_headers: BTreeMap::new(),
+            pvss_leaves: BTreeMap::new(),
+            masked_dealings: BTreeMap::new(),
+            open_power_messages: BTreeMap::new(),
+            key_confirm_messages: BTreeMap::new(),
+            rng: ForkableRng::fresh(),
+            outbox: vec![Outgoing::broadcast(DkgMsg::Pvss(pvss_msg))],
+            output: None,
+        }
+    }
+
+    /// Adds a final round in which the parties check that they all derived the same key
+    /// from the same dealers, failing with `KeyDisagreement` otherwise. A DKG needs it to
+    /// go ahead at a deadline, see `run_with_deadlines`.
+    pub fn with_key_confirmation(mut self, session_id: &[u8]) -> Self {
+        self.session_id = Some(session_id.to_vec());
+        self
+    }
+
+    /// Attaches a `DkgTranscript` to the output.
+    pub fn with_transcript(mut self) -> Self {
+        self.record_transcript = true;
+        self
+    }
+
+    /// Signs our dealing with a `MembershipProof`, and qualifies only dealers whose
+    /// dealing carries a valid one under their key in the keyring. Where the transport
+    /// does not authenticate senders, this keeps out dealings injected by anyone outside
+    /// the committee. Light and compact dealings carry no such proofs, so neither mode
+    /// combines with them.
+    pub fn with_membership_proofs(mut self) -> Result<Self, DkgModeError> {
+        self.check_mode("membership proofs")?;
+        let my_id = self.my_id;
+        let pvss_msg = self
+            .pvss_messages
+            .remove(&my_id)
+            .unwrap()
+            .with_membership_proof(self.pp, my_id, self.my_cl_sk, &mut self.rng);
+
+        // Round 1 interaction, with the signed dealing
+        self.outbox = vec![Outgoing::broadcast(DkgMsg::Pvss(pvss_msg.clone()))];
+        self.pvss_messages.insert(my_id, pvss_msg);
+        self.require_membership = true;
+        Ok(self)
+    }
+
+    /// Deals in light mode: each encrypted share goes point-to-point to its recipient,
+    /// with a Merkle path to the header that is broadcast. A party then handles one
+    /// ciphertext per dealer instead of n, but cannot check the PVSS proofs. It qualifies
+    /// a dealer whose ciphertext is in the dealing and decrypts to a share matching its
+    /// MAC, so a dealer that cheats only some parties splits the qualified sets; the
+    /// mode therefore confirms the key in a round bound to `session_id`. All parties of
+    /// a run must deal in the same mode. Since a party does not see whole dealings, it
+    /// attaches no transcript.
+    pub fn with_light_dealing(mut self, session_id: &[u8]) -> Result<Self, DkgModeError> {
+        self.check_mode("light dealing")?;
+        let my_id = self.my_id;
+        let (header, leaves) = self.pvss_messages[&my_id].split();
+
+        // Round 1 interaction, in place of the full dealing
+        self.outbox = vec![Outgoing::broadcast(DkgMsg::PvssHeader(header.clone()))];
+        for (j, leaf) in leaves {
+            if j == my_id {
+                self.pvss_leaves.insert(my_id, leaf);
+            } else {
+                self.outbox.push(Outgoing {
+                    recipient: MessageDestination::OneParty(
+                        PartyId::from_share_index(j).to_wire_index(),
+                    ),
+                    msg: DkgMsg::PvssLeaf(leaf),
+                });
+            }
+        }
+        self.pvss_headers.insert(my_id, header);
+        self.light_dealing = true;
+        Ok(self.with_key_confirmation(session_id))
+    }
+
+    /// Deals in the compact PVSS mode of `PubParams::compact_pvss`, without waiting for a
+    /// hello round to settle on it. Each share goes to everyone masked as a scalar, see
+    /// `MaskedPvssDealing`, so a dealing is about n forms shorter. As in light mode, a
+    /// party qualifies a dealer whose share for it matches its MAC, so a dealer that
+    /// cheats only some parties splits the qualified sets. The key confirmation round
+    /// bound to `session_id` is therefore part of the mode, and turns such a split into
+    /// `KeyDisagreement` instead of keys that differ. No transcript is attached. All
+    /// parties of a run must deal in the same mode.
+    pub fn with_compact_dealing(mut self, session_id: &[u8]) -> Result<Self, DkgModeError> {
+        if !self.pp.compact_pvss {
+            return Err(DkgModeError::CompactDisabled);
+        }
+        self.check_mode("compact dealing")?;
+        self.deal_masked();
+        Ok(self.with_key_confirmation(session_id))
+    }
+
+    /// Fails if a dealing mode other than plain dealing is already on, as none of them
+    /// combine.
+    fn check_mode(&self, mode: &'static str) -> Result<(), DkgModeError> {
+        let other = if self.require_membership {
+            "membership proofs"
+        } else if self.light_dealing {
+            "light dealing"
+        } else if self.masked_dealing {
+            "compact dealing"
+        } else {
+            return Ok(());
+        };
+        Err(DkgModeError::Incompatible { mode, other })
+    }
+
+    fn deal_masked(&mut self) {
+        let (dealing, mut shares) = MaskedPvssDealing::random(self.pp, &mut self.rng, self.h);
+        zeroize_shares(&mut shares);
+
+        // Round 1 interaction, in place of the full dealing
+        self.outbox = vec![Outgoing::broadcast(DkgMsg::MaskedPvss(dealing.clone()))];
+        self.masked_dealings.insert(self.my_id, dealing);
+        self.masked_dealing = true;
+    }
+
+    /// The compact counterpart of the first round: qualifies the first t dealers whose
+    /// masked share for us opens to one matching its MAC, once every dealing is in.
+    fn combine_masked_dealings(&self) -> Result<Option<(Vec<Id>, JointPvssResult)>, StateError> {
+        let (pp, h, my_id) = (self.pp, self.h, self.my_id);
+        if !heard_enough(pp, self.masked_dealings.len(), self.deadline_passed) {
+            return Ok(None);
+        }
+
+        let opened: Vec<(Id, PvssDealing)> = self
+            .masked_dealings
+            .iter()
+            .filter_map(|(&j, dealing)| {
+                let opened = dealing
+                    .validate(pp)
+                    .map_err(DealerDisqualReason::from_validation)
+                    .and_then(|()| {
+                        dealing
+                            .open(pp, h, my_id, self.my_cl_sk)
+                            .map_err(DealerDisqualReason::from_proof)
+                    });
+                if let Err(reason) = &opened {
+                    log_rejected_dealing(j, reason);
+                }
+                opened.ok().map(|dealing| (j, dealing))
+            })
+            .collect();
+        let opened = qualify(pp, opened)?;
+        let qualified: Vec<Id> = opened.iter().map(|(j, _)| *j).collect();
+        let pvss_result =
+            JointPvssResult::new(pp, opened.into_iter().map(|(_, dealing)| dealing).collect())
+                .restrict_to(&BTreeSet::from([my_id]));
+        Ok(Some((qualified, pvss_result)))
+    }
+
+    /// Dealers in light mode whose header and leaf have both arrived.
+    fn light_dealers(&self) -> Vec<Id> {
+        self.pvss_headers
+            .keys()
+            .filter(|j| self.pvss_leaves.contains_key(j))
+            .copied()
+            .collect()
+    }
+
+    /// The light counterpart of the first round: qualifies the first t dealers whose
+    /// leaf opens to a share matching the header, once every header and leaf is in.
+    fn combine_light_dealings(&self) -> Result<Option<(Vec<Id>, JointPvssResult)>, StateError> {
+        let (pp, h, my_id) = (self.pp, self.h, self.my_id);
+        if !heard_enough(pp, self.light_dealers().len(), self.deadline_passed) {
+            return Ok(None);
+        }
+
+        let qualified: Vec<Id> = self
+            .pvss_headers
+            .iter()
+            .filter(|(&j, header)| {
+                j == my_id
+                    || self.pvss_leaves.get(&j).map_or(false, |leaf| {
+                        header.validate(pp).is_ok()
+                            && leaf.validate(pp).is_ok()
+                            && header.open(pp, h, leaf, self.my_cl_sk).is_ok()
+                    })
+            })
+            .map(|(&j, _)| j)
+            .take(pp.min_qualified())
+            .collect();
+        let qualified = qualify(pp, qualified)?;
+        let pvss_result = JointPvssResult::new(
+            pp,
+            qualified
+                .iter()
+                .map(|j| self.pvss_headers[j].dealing_for(&self.pvss_leaves[j]))
+                .collect(),
+        )
+        .restrict_to(&BTreeSet::from([my_id]));
+        Ok(Some((qualified, pvss_result)))
+    }
+
+    fn proceed(&mut self) -> Result<(), StateError> {
+        let (pp, h, my_id) = (self.pp, self.h, self.my_id);
+
+        if let DkgRound::Pvss = self.round {
+            // Round 1 processing
+            let (qualified, pvss_result) = if self.light_dealing {
+                match self.combine_light_dealings()? {
+                    Some(combined) => combined,
+                    None => return Ok(()),
+                }
+            } else if self.masked_dealing {
+                match self.combine_masked_dealings()? {
+                    Some(combined) => combined,
+                    None => return Ok(()),
+                }
+            } else {
+                if !heard_enough(pp, self.pvss_messages.len(), self.deadline_passed) {
+                    return Ok(());
+                }
+
+                let mut verified: BTreeSet<Id> = self
+                    .pvss_messages
+                    .iter()
+                    .filter(|(&j, _)| j != my_id)
+                    .filter(|(&j, msg)| {
+                        let checked = check_dealing(
+                            pp,
+                            h,
+                            j,
+                            msg,
+                            self.require_membership,
+                            !self.lazy_verification,
+                        );
+                        if let Err(reason) = &checked {
+                            log_rejected_dealing(j, reason);
+                        }
+                        checked.is_ok()
+                    })
+                    .map(|(&j, _)| j)
+                    .collect();
+                verified.insert(my_id);
+
+                let qualified = qualify(pp, verified.into_iter().collect())?;
+                let pvss_result = fold_dealings(pp, &qualified, &self.pvss_messages);
+                (qualified, pvss_result)
+            };
+
+            let (my_share, open_power_msg) =
+                open_share(pp, h, my_id, self.my_cl_sk, &pvss_result, &mut self.rng);
+
+            // Round 2 interaction
+            self.outbox
+                .push(Outgoing::broadcast(DkgMsg::PowOpen(open_power_msg.clone())));
+            self.open_power_messages.insert(my_id, open_power_msg);
+            self.deadline_passed = false;
+            self.round = DkgRound::PowOpen {
+                pvss_result,
+                my_share,
+                qualified,
+            };
+        }
+
+        if let DkgRound::PowOpen {
+            pvss_result,
+            my_share,
+            qualified,
+        } = &self.round
+        {
+            if self.output.is_some()
+                || !heard_enough(pp, self.open_power_messages.len(), self.deadline_passed)
+            {
+                return Ok(());
+            }
+
+            // Round 2 processing
+            let tpk = combine_openings(pp, h, pvss_result, &self.open_power_messages)?;
+
+            // todo: interpolate the missing public shares.
+
+            let transcript = (self.record_transcript
+                && !self.light_dealing
+                && !self.masked_dealing)
+                .then(|| DkgTranscript {
+                    qualified: qualified.clone(),
+                    dealing_hashes: qualified
+                        .iter()
+                        .map(|j| DkgTranscript::dealing_hash(&self.pvss_messages[j].dealing))
+                        .collect(),
+                    openings: tpk
+                        .pub_shares
+                        .keys()
+                        .map(|id| (*id, self.open_power_messages[id].clone()))
+                        .collect(),
+                    pk: tpk.pk.clone(),
+                });
+
+            let output = DkgOutput {
+                share: my_share.clone(),
+                tpk,
+                qualified: qualified.clone(),
+                transcript,
+            };
+
+            match &self.session_id {
+                None => self.output = Some(output),
+                Some(session_id) => {
+                    let key_confirm_msg = KeyConfirmMsg {
+                        digest: KeyConfirmMsg::digest(&output.tpk.pk, qualified, session_id),
+                        proof: DleqNizk::prove(
+                            h,
+                            &pvss_result.curve_mac(my_id),
+                            &G::generator(),
+                            &output.tpk.pub_shares[&my_id],
+                            my_share,
+                            &mut self.rng,
+                        ),
+                    };
+
+                    // Round 3 interaction
+                    self.outbox.push(Outgoing::broadcast(DkgMsg::KeyConfirm(
+                        key_confirm_msg.clone(),
+                    )));
+                    self.key_confirm_messages
+                        .insert(my_id, key_confirm_msg.clone());
+                    self.deadline_passed = false;
+                    self.round = DkgRound::KeyConfirm {
+                        pvss_result: pvss_result.clone(),
+                        digest: key_confirm_msg.digest,
+                        output,
+                    };
+                }
+            }
+        }
+
+        if let DkgRound::KeyConfirm {
+            pvss_result,
+            digest,
+            output,
+        } = &self.round
+        {
+            if self.output.is_some()
+                || !heard_enough(pp, self.key_confirm_messages.len(), self.deadline_passed)
+            {
+                return Ok(());
+            }
+
+            // Round 3 processing
+            let differing: Vec<PartyIndex> = self
+                .key_confirm_messages
+                .iter()
+                .filter(|(&j, msg)| {
+                    j != my_id
+                        && !output.tpk.pub_shares.get(&j).map_or(false, |pub_share| {
+                            msg.validate(pp).is_ok()
+                                && msg.verify(digest, h, &pvss_result.curve_mac(j), pub_share)
+                        })
+                })
+                .map(|(&j, _)| PartyId::from_share_index(j).to_wire_index())
+                .collect();
+
+            if !differing.is_empty() {
+                return Err(StateError::KeyDisagreement { differing });
+            }
+
+            self.output = Some(output.clone());
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> StateMachine for DkgState<'a> {
+    type Msg = DkgMsg;
+    type Output = DkgOutput;
+
+    fn handle_message(&mut self, from: PartyIndex, msg: DkgMsg) -> Result<(), StateError> {
+        let id = sender_id(self.pp, self.my_id, from)?;
+
+        match msg {
+            DkgMsg::Pvss(msg) => {
+                insert_once(&mut self.pvss_messages, id, msg, from)?;
+            }
+            DkgMsg::PowOpen(msg) => {
+                insert_once(&mut self.open_power_messages, id, msg, from)?;
+            }
+            DkgMsg::KeyConfirm(msg) => {
+                insert_once(&mut self.key_confirm_messages, id, msg, from)?;
+            }
+            DkgMsg::PvssHeader(msg) => {
+                insert_once(&mut self.pvss_headers, id, msg, from)?;
+            }
+            DkgMsg::PvssLeaf(msg) => {
+                // a transport without point-to-point delivery hands us everyone's leaves
+                if msg.recipient != self.my_id {
+                    return Ok(());
+                }
+                insert_once(&mut self.pvss_leaves, id, msg, from)?;
+            }
+            DkgMsg::MaskedPvss(msg) => {
+                insert_once(&mut self.masked_dealings, id, msg, from)?;
+            }
+        }
+        self.proceed()
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<DkgMsg>> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    fn try_finish(mut self) -> Result<DkgOutput, Self> {
+        match self.output.take() {
+            Some(output) if self.outbox.is_empty() => Ok(output),
+            output => {
+                self.output = output;
+                Err(self)
+            }
+        }
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        if self.output.is_some() {
+            return Ok(());
+        }
+        let heard = match self.round {
+            DkgRound::Pvss if self.light_dealing => self.light_dealers(),
+            DkgRound::Pvss if self.masked_dealing => self.masked_dealings.keys().copied().collect(),
+            DkgRound::Pvss => self.pvss_messages.keys().copied().collect(),
+            DkgRound::PowOpen { .. } => self.open_power_messages.keys().copied().collect(),
+            DkgRound::KeyConfirm { .. } => self.key_confirm_messages.keys().copied().collect(),
+        };
+        // parties whose deadlines pass at different times may qualify different dealers,
+        // which only the key confirmation round catches
+        if self.session_id.is_none() {
+            return Err(StateError::KeyConfirmationRequired);
+        }
+        too_few_by_deadline(self.pp, heard)?;
+        self.deadline_passed = true;
+        self.proceed()
+    }
+
+    /// Switches to compact dealing if the committee enabled it and the version has it,
+    /// provided the key is confirmed, which compact dealing needs. A DKG with membership
+    /// proofs or in light mode keeps to its mode.
+    fn on_version(&mut self, version: u16) {
+        if self.pp.compact_pvss
+            && version >= COMPACT_PVSS_VERSION
+            && self.session_id.is_some()
+            && self.check_mode("compact dealing").is_ok()
+        {
+            self.deal_masked();
+        }
+    }
+}
+
+#[deprecated(note = "build a `Participant` and use `participant::dkg`")]
+pub async fn dkg<M>(
+    party: M,
+    my_id: Id, // in the range 1..=n, subtract one before use
+    pp: &PubParams,
+    h: &G,
+    my_cl_sk: &SecretKey,
+    lazy_verification: bool,
+) -> Result<DkgOutput, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = DkgMsg>,
+{
+    check_keyring(pp)?;
+    run_within(
+        party,
+        DkgState::new(my_id, pp, h, my_cl_sk, lazy_verification),
+        &pp.limits,
+    )
+    .await
+}
+
+/// Runs the DKG followed by a key confirmation round bound to `session_id`.
+pub async fn dkg_confirmed<M>(
+    party: M,
+    my_id: Id, // in the range 1..=n, subtract one before use
+    pp: &PubParams,
+    h: &G,
+    my_cl_sk: &SecretKey,
+    lazy_verification: bool,
+    session_id: &[u8],
+) -> Result<DkgOutput, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = DkgMsg>,
+{
+    check_keyring(pp)?;
+    let state =
+        DkgState::new(my_id, pp, h, my_cl_sk, lazy_verification).with_key_confirmation(session_id);
+    run_within(party, state, &pp.limits).await
+}
+
+enum PresignRound {
+    NonceGen,
+    MtAwc {
+        k_pvss: JointPvssResult,
+        gamma_pvss: JointPvssResult,
+        k_share: Zq,
+        nonce_digests: BTreeMap<Id, [u8; 32]>,
+    },
+    PreSignFinal {
+        k_share: Zq,
+        k_macs: BTreeMap<Id, G>,
+        mta_digest: [u8; 32],
+        Gamma: G,
+        chi_share: Zq,
+        delta_macs: BTreeMap<Id, G>,
+        chi_macs: BTreeMap<Id, G>,
+    },
+}
+
+/// Records newly rejected parties, keeping the first reason found for each.
+fn disqualify(
+    disqualified: &mut BTreeMap<PartyIndex, DisqualReason>,
+    rejected: BTreeMap<PartyIndex, DisqualReason>,
+) {
+    for (party, reason) in rejected {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(party, ?reason, "party disqualified");
+        disqualified.entry(party).or_insert(reason);
+    }
+}
+
+/// The reason to disqualify the sender of a message that failed with `err`: `Malformed`
+/// if it did not validate, `ViewMismatch` if it answered other messages than ours, else
+/// `otherwise`.
+fn blame(err: &PresignError, otherwise: DisqualReason) -> DisqualReason {
+    match err {
+        PresignError::Malformed(_) => DisqualReason::Malformed,
+        PresignError::ViewMismatch => DisqualReason::ViewMismatch,
+        PresignError::ReplayedDealing { .. } => DisqualReason::ReplayedDealing,
+        _ => otherwise,
+    }
+}
+
+/// The first presigning round's processing: combines the nonce dealings of the first t
+/// parties whose dealings of both nonces verify in `session`, once
+/// `PubParams::min_qualified` of them did, and decrypts our shares of them. Every
+/// dealing is verified, so a bad dealer is disqualified wherever its index falls. With
+/// a `replay_cache`, dealings it has seen before are disqualified too.
+fn combine_nonce_dealings(
+    context: &NonceGenContext,
+    my_id: Id,
+    my_cl_sk: &SecretKey,
+    messages: &BTreeMap<Id, NonceGenMsg>,
+    replay_cache: Option<&SharedReplayCache>,
+) -> Result<NoncePair, StateError> {
+    let (verified, disqualified) = verify_nonce_dealings(context, my_id, messages, replay_cache);
+    combine_nonces(context.pp, my_id, my_cl_sk, verified, disqualified)
+}
+
+/// The dealings of `messages` that verify, and the parties whose dealings do not.
+fn verify_nonce_dealings<'m>(
+    context: &NonceGenContext,
+    my_id: Id,
+    messages: &'m BTreeMap<Id, NonceGenMsg>,
+    replay_cache: Option<&SharedReplayCache>,
+) -> (
+    Vec<(Id, &'m NonceGenMsg)>,
+    BTreeMap<PartyIndex, DisqualReason>,
+) {
+    let mut disqualified = BTreeMap::new();
+    let verified = messages
+        .iter()
+        .filter(|(&j, msg)| {
+            let result = if j == my_id {
+                Ok(())
+            } else {
+                msg.verify(context).and_then(|()| match replay_cache {
+                    Some(cache) => {
+                        let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+                        msg.check_fresh(j, &mut *cache)
+                    }
+                    None => Ok(()),
+                })
+            };
+            if let Err(err) = &result {
+                let from = PartyId::from_share_index(j).to_wire_index();
+                disqualified.insert(from, blame(err, DisqualReason::NonceDealing));
+            }
+            result.is_ok()
+        })
+        .map(|(&j, msg)| (j, msg))
+        .collect();
+    (verified, disqualified)
+}
+
+/// Combines the first t of the `verified` nonce dealings, see `combine_nonce_dealings`.
+fn combine_nonces(
+    pp: &PubParams,
+    my_id: Id,
+    my_cl_sk: &SecretKey,
+    verified: Vec<(Id, &NonceGenMsg)>,
+    disqualified: BTreeMap<PartyIndex, DisqualReason>,
+) -> Result<NoncePair, StateError> {
+    let qualified: BTreeMap<Id, &NonceGenMsg> = qualify(pp, verified)?.into_iter().collect();
+
+    let k_pvss = JointPvssResult::new(
+        pp,
+        qualified
+            .values()
+            .map(|msg| msg.k.dealing.clone())
+            .collect(),
+    );
+    let gamma_pvss = JointPvssResult::new(
+        pp,
+        qualified
+            .values()
+            .map(|msg| msg.gamma.dealing.clone())
+            .collect(),
+    );
+
+    let k_share = k_pvss.shares_ciphertext.decrypt(&pp.cl, my_id, my_cl_sk);
+    let gamma_share = gamma_pvss
+        .shares_ciphertext
+        .decrypt(&pp.cl, my_id, my_cl_sk);
+
+    Ok(NoncePair {
+        fingerprint: pp.fingerprint(),
+        k_pvss,
+        gamma_pvss,
+        k_share,
+        gamma_share,
+        nonce_digests: dealing_digests(&qualified),
+        disqualified,
+    })
+}
+
+/// Presigning among the committee. The first two rounds wait for a message from
+/// every party in `DkgOutput::honest_parties`, as all honest parties must agree on the
+/// qualified dealers; invalid messages, and those of parties the DKG disqualified, are
+/// dropped. Past a deadline (see `run_with_deadlines`) they go ahead with t messages
+/// instead. The last round finishes as soon as t valid shares of delta have arrived,
+/// so parties that fall silent there are tolerated. A dealer that showed parties
+/// different first round messages is dropped once the MtA messages reveal it, and the
+/// MtA round is dealt again on the others' nonces.
+pub struct PresignState<'a> {
+    my_id: Id, // in the range 1..=n
+    pp: &'a PubParams,
+    h: &'a G,
+    my_cl_sk: &'a SecretKey,
+    key: &'a DkgOutput,
+    session: PresignSession,
+    replay_cache: Option<SharedReplayCache>,
+    deadline_passed: bool, // for the current round
+    round: PresignRound,
+    nonce_gen_messages: BTreeMap<Id, NonceGenMsg>,
+    nonce_dealers: BTreeSet<Id>, // whose dealings verified, to combine again without a liar
+    mta_messages: BTreeMap<Id, Vec<MtAwcMsg>>, // one per view of the first round
+    presign_final_messages: BTreeMap<Id, PreSignFinalMsg>,
+    disqualified: BTreeMap<PartyIndex, DisqualReason>,
+    rng: ForkableRng,
+    outbox: Vec<Outgoing<PresignMsg>>,
+    output: Option<PreSignature>,
+}
+
+impl<'a> PresignState<'a> {
+    /// Presigns in `session`: the MtA proofs are bound to all of it, and the nonce
+    /// dealings to its session id. All parties of a run must pass the same session.
+    pub fn new(
+        my_id: Id,
+        pp: &'a PubParams,
+        h: &'a G,
+        my_cl_sk: &'a SecretKey,
+        key: &'a DkgOutput,
+        session: PresignSession,
+    ) -> Self {
+        Self::seeded(my_id, pp, h, my_cl_sk, key, session, ForkableRng::fresh())
+    }
+
+    /// Like `new`, with all of the party's randomness drawn from `rng`; see
+    /// `DkgState::seeded`.
+    pub fn seeded(
+        my_id: Id,
+        pp: &'a PubParams,
+        h: &'a G,
+        my_cl_sk: &'a SecretKey,
+        key: &'a DkgOutput,
+        session: PresignSession,
+        mut rng: ForkableRng,
+    ) -> Self {
+        debug_assert_eq!(
+            *h, pp.curve_generator,
+            "h must be the curve generator of pp"
+        );
+
+        // Round 1 interaction
+        let nonce_gen_msg = NonceGenMsg::random_in_session(pp, &mut rng.rand_gen(), h, &session);
+
+        PresignState {
+            my_id,
+            pp,
+            h,
+            my_cl_sk,
+            key,
+            session,
+            replay_cache: None,
+            deadline_passed: false,
+            round: PresignRound::NonceGen,
+            nonce_gen_messages: BTreeMap::from([(my_id, nonce_gen_msg.clone())]),
+            nonce_dealers: BTreeSet::new(),
+            mta_messages: BTreeMap::new(),
+            presign_final_messages: BTreeMap::new(),
+            disqualified: BTreeMap::new(),
+            rng,
+            outbox: vec![Outgoing::broadcast(PresignMsg::NonceGen(nonce_gen_msg))],
+            output: None,
+        }
+    }
+
+    /// Disqualifies the parties whose nonce dealings `cache` has seen before, e.g. in
+    /// earlier sessions on the same key, and records the dealings that pass. Each party
+    /// needs a cache of its own, kept across all its sessions on the key: parties that
+    /// share one record each other's receipts, and then reject an honest dealing the
+    /// first of them already recorded as a replay.
+    pub fn with_replay_cache(mut self, cache: SharedReplayCache) -> Self {
+        self.replay_cache = Some(cache);
+        self
+    }
+
+    /// Presigns with nonces generated ahead of time, starting right at the MtA round,
+    /// whose proofs are bound to `session`. All parties must pass the same pair of a
+    /// nonce generation run, and the same session. The dealings are gone by now, so a dealer
+    /// found to have equivocated is disqualified but cannot be combined out of the nonces.
+    pub fn from_nonces(
+        my_id: Id,
+        pp: &'a PubParams,
+        h: &'a G,
+        my_cl_sk: &'a SecretKey,
+        key: &'a DkgOutput,
+        session: PresignSession,
+        nonces: NoncePair,
+    ) -> Result<Self, StateError> {
+        if nonces.fingerprint != pp.fingerprint() {
+            return Err(StateError::ForeignNonces);
+        }
+
+        let mut state = PresignState {
+            my_id,
+            pp,
+            h,
+            my_cl_sk,
+            key,
+            session,
+            replay_cache: None,
+            deadline_passed: false,
+            round: PresignRound::NonceGen,
+            nonce_gen_messages: BTreeMap::new(),
+            nonce_dealers: BTreeSet::new(),
+            mta_messages: BTreeMap::new(),
+            presign_final_messages: BTreeMap::new(),
+            disqualified: BTreeMap::new(),
+            rng: ForkableRng::fresh(),
+            outbox: vec![],
+            output: None,
+        };
+        state.start_mta(nonces);
+        Ok(state)
+    }
+
+    /// Round 2 interaction, on the nonces of the first round.
+    fn start_mta(&mut self, mut nonces: NoncePair) {
+        // the pair wipes its shares when dropped, so what outlives it is taken or copied
+        disqualify(
+            &mut self.disqualified,
+            std::mem::take(&mut nonces.disqualified),
+        );
+        let nonce_digests = std::mem::take(&mut nonces.nonce_digests);
+
+        let mta_msg = MtAwcMsg::new(
+            self.pp,
+            self.h,
+            &mut self.rng.rand_gen(),
+            &nonces.k_pvss,
+            &nonces.gamma_pvss,
+            self.my_id,
+            &nonces.gamma_share,
+            &self.key.share,
+            &self.session,
+            nonce_digests.clone(),
+        );
+
+        self.outbox
+            .push(Outgoing::broadcast(PresignMsg::MtAwc(mta_msg.clone())));
+        self.mta_messages
+            .entry(self.my_id)
+            .or_default()
+            .push(mta_msg);
+        self.deadline_passed = false;
+        self.round = PresignRound::MtAwc {
+            k_pvss: nonces.k_pvss.clone(),
+            gamma_pvss: nonces.gamma_pvss.clone(),
+            k_share: nonces.k_share.clone(),
+            nonce_digests,
+        };
+    }
+
+    /// Disqualifies the dealers the MtA messages so far name different dealings of, and
+    /// deals the MtA round again on the nonces of the others if ours were combined from
+    /// one of them. Everyone who received both versions of a dealing does the same, so
+    /// the dealer's victims go on with the rest instead of being cut off by them.
+    fn drop_equivocators(&mut self) -> Result<(), StateError> {
+        let equivocating = equivocating_dealers(
+            self.mta_messages
+                .values()
+                .flatten()
+                .map(|msg| &msg.nonce_digests),
+        );
+        let combined_from_liar = match &self.round {
+            PresignRound::MtAwc { nonce_digests, .. } => {
+                nonce_digests.keys().any(|j| equivocating.contains(j))
+            }
+            _ => return Ok(()),
+        };
+        disqualify(
+            &mut self.disqualified,
+            equivocating
+                .iter()
+                .map(|&j| {
+                    let from = PartyId::from_share_index(j).to_wire_index();
+                    (from, DisqualReason::Equivocation)
+                })
+                .collect(),
+        );
+        if !combined_from_liar || self.nonce_dealers.is_empty() {
+            return Ok(());
+        }
+
+        let verified = self
+            .nonce_gen_messages
+            .iter()
+            .filter(|(j, _)| self.nonce_dealers.contains(j) && !equivocating.contains(j))
+            .map(|(&j, msg)| (j, msg))
+            .collect();
+        let nonces = combine_nonces(
+            self.pp,
+            self.my_id,
+            self.my_cl_sk,
+            verified,
+            BTreeMap::new(),
+        )?;
+        self.start_mta(nonces);
+        Ok(())
+    }
+
+    /// Whether a round can go ahead on `received`: once every honest party of the DKG
+    /// has sent its message, or, past the deadline, t parties.
+    fn heard_from_honest<M>(&self, received: &BTreeMap<Id, M>) -> bool {
+        let honest = &self.key.tpk.pub_shares;
+        honest.keys().all(|j| received.contains_key(j))
+            || heard_enough(self.pp, received.len(), self.deadline_passed)
+    }
+
+    fn proceed(&mut self) -> Result<(), StateError> {
+        let (pp, h, my_id) = (self.pp, self.h, self.my_id);
+        let t = pp.t as usize;
+
+        if let PresignRound::NonceGen = self.round {
+            if !self.heard_from_honest(&self.nonce_gen_messages) {
+                return Ok(());
+            }
+
+            // Round 1 processing: the first t parties with valid dealings of both nonces
+            let context = NonceGenContext {
+                pp,
+                h,
+                session: &self.session,
+            };
+            let (verified, disqualified) = verify_nonce_dealings(
+                &context,
+                my_id,
+                &self.nonce_gen_messages,
+                self.replay_cache.as_ref(),
+            );
+            self.nonce_dealers = verified.iter().map(|(j, _)| *j).collect();
+            let nonces = combine_nonces(pp, my_id, self.my_cl_sk, verified, disqualified)?;
+            self.start_mta(nonces);
+        }
+
+        self.drop_equivocators()?;
+        if let PresignRound::MtAwc {
+            k_pvss,
+            gamma_pvss,
+            k_share,
+            nonce_digests,
+        } = &self.round
+        {
+            // the messages on the nonces we combined; past the deadline, a party without
+            // one counts with the last it sent, which then fails to verify
+            let on_our_nonces: BTreeMap<Id, &MtAwcMsg> = self
+                .mta_messages
+                .iter()
+                .filter_map(|(&j, msgs)| {
+                    let msg = msgs
+                        .iter()
+                        .find(|msg| msg.nonce_digests == *nonce_digests)?;
+                    Some((j, msg))
+                })
+                .collect();
+            if !self.heard_from_honest(&on_our_nonces)
+                && !heard_enough(pp, self.mta_messages.len(), self.deadline_passed)
+            {
+                return Ok(());
+            }
+            let received: BTreeMap<Id, &MtAwcMsg> = self
+                .mta_messages
+                .iter()
+                .filter_map(|(&j, msgs)| {
+                    let msg = on_our_nonces.get(&j).copied().or(msgs.last())?;
+                    Some((j, msg))
+                })
+                .collect();
+
+            // Round 2 processing: the first t parties whose opening and both MtA dealings verify,
+            // after checking all of them so that each bad sender is disqualified
+            let pub_shares = &self.key.tpk.pub_shares;
+            let mut disqualified = BTreeMap::new();
+            let verified: Vec<(Id, &MtAwcMsg)> = received
+                .iter()
+                .filter(|(&j, msg)| {
+                    let result = match (j == my_id, pub_shares.get(&j)) {
+                        (true, _) => Ok(()),
+                        (false, Some(x_pub)) => {
+                            let context = MtAwcContext {
+                                pp,
+                                h,
+                                session: &self.session,
+                                k_pvss,
+                                gamma_pvss,
+                                nonce_digests,
+                                sender: j,
+                                x_pub,
+                            };
+                            msg.verify(&context)
+                                .map_err(|err| blame(&err, DisqualReason::Mta))
+                        }
+                        (false, None) => Err(DisqualReason::NotQualifiedInDkg),
+                    };
+                    if let Err(reason) = result {
+                        let from = PartyId::from_share_index(j).to_wire_index();
+                        disqualified.insert(from, reason);
+                    }
+                    result.is_ok()
+                })
+                .map(|(&j, &msg)| (j, msg))
+                .collect();
+            disqualify(&mut self.disqualified, disqualified);
+            let qualified: BTreeMap<Id, &MtAwcMsg> = verified.into_iter().take(t).collect();
+
+            let lagrange_coeffs = pp
+                .lagrange_coeffs(qualified.keys().copied().collect())
+                .ok_or_else(|| StateError::TooFewQualified {
+                    got: qualified.len(),
+                    need: t,
+                    blamed: unqualified(pp, qualified.keys()),
+                })?;
+
+            let Gamma: G = qualified
+                .iter()
+                .map(|(j, msg)| &lagrange_coeffs[j] * &msg.gamma_pub.point)
+                .sum();
+
+            let mut delta_share = Zq::zero();
+            let mut chi_share = Zq::zero();
+            for (j, msg) in &qualified {
+                let decrypt = |dealing: &MtaDealing| {
+                    dealing
+                        .shares_ciphertext
+                        .decrypt(&pp.cl, my_id, self.my_cl_sk)
+                };
+                delta_share = delta_share + &lagrange_coeffs[j] * decrypt(&msg.k_gamma_dealing);
+                chi_share = chi_share + &lagrange_coeffs[j] * decrypt(&msg.k_x_dealing);
+            }
+
+            let mut delta_macs = BTreeMap::new();
+            let mut chi_macs = BTreeMap::new();
+            for id in 1..=pp.n {
+                let (delta_mac, chi_mac): (Vec<G>, Vec<G>) = qualified
+                    .iter()
+                    .map(|(j, msg)| {
+                        (
+                            &lagrange_coeffs[j] * &msg.k_gamma_dealing.curve_macs[&id],
+                            &lagrange_coeffs[j] * &msg.k_x_dealing.curve_macs[&id],
+                        )
+                    })
+                    .unzip();
+                delta_macs.insert(id, delta_mac.into_iter().sum());
+                chi_macs.insert(id, chi_mac.into_iter().sum());
+            }
+
+            // Round 3 interaction
+            let mta_digest = round_digest(MTA_ROUND, &qualified);
+            let presign_final_msg = PreSignFinalMsg {
+                prev_round_digest: mta_digest,
+                delta_share,
+                Gamma: Gamma.clone(),
+            };
+
+            self.outbox
+                .push(Outgoing::broadcast(PresignMsg::PreSignFinal(
+                    presign_final_msg.clone(),
+                )));
+            self.presign_final_messages.insert(my_id, presign_final_msg);
+            self.deadline_passed = false;
+            self.round = PresignRound::PreSignFinal {
+                k_share: k_share.clone(),
+                k_macs: k_pvss.curve_macs(),
+                mta_digest,
+                Gamma,
+                chi_share,
+                delta_macs,
+                chi_macs,
+            };
+        }
+
+        if let PresignRound::PreSignFinal {
+            k_share,
+            k_macs,
+            mta_digest,
+            Gamma,
+            chi_share,
+            delta_macs,
+            chi_macs,
+        } = &mut self.round
+        {
+            if self.output.is_some() {
+                return Ok(());
+            }
+
+            // Round 3 processing: any t shares of delta consistent with their MACs
+            let mut mismatched = BTreeMap::new();
+            let checked: BTreeMap<Id, (&Zq, Result<(), PresignError>)> = self
+                .presign_final_messages
+                .iter()
+                .filter_map(|(&j, msg)| {
+                    let context = PreSignFinalContext {
+                        h,
+                        delta_mac: delta_macs.get(&j)?,
+                        Gamma: &*Gamma,
+                        prev_round_digest: &*mta_digest,
+                    };
+                    let result = match msg.validate(pp) {
+                        Ok(()) => msg.verify(&context),
+                        Err(err) => Err(err.into()),
+                    };
+                    Some((j, (&msg.delta_share, result)))
+                })
+                .filter(|(j, (_, result))| {
+                    // a share of delta is only meaningful for the nonce it was computed
+                    // against, and one that matches its MAC but not our Γ is a lie
+                    let reason = match result {
+                        Err(PresignError::MacMismatch) => DisqualReason::DeltaShare,
+                        Err(PresignError::Malformed(_)) => DisqualReason::Malformed,
+                        Err(PresignError::ViewMismatch) => DisqualReason::ViewMismatch,
+                        Err(PresignError::NonceMismatch) => DisqualReason::NonceMismatch,
+                        _ => return true,
+                    };
+                    let from = PartyId::from_share_index(*j).to_wire_index();
+                    mismatched.insert(from, reason);
+                    false
+                })
+                .collect();
+
+            if checked.len() < t {
+                return Ok(());
+            }
+
+            let delta_shares: BTreeMap<Id, Zq> = checked
+                .into_iter()
+                .map(|(j, (share, _))| (j, share.clone()))
+                .collect();
+            let delta = pp.interpolate(&delta_shares).unwrap();
+            disqualify(&mut self.disqualified, mismatched);
+
+            // the round is over, so its MACs move into the presignature
+            let presignature = PreSignature::new(
+                Gamma.clone(),
+                delta,
+                k_share.clone(),
+                chi_share.clone(),
+                std::mem::take(k_macs),
+                std::mem::take(chi_macs),
+                std::mem::take(&mut self.disqualified),
+            )
+            .map_err(|_| StateError::DegenerateNonce)?;
+            self.output = Some(presignature);
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> StateMachine for PresignState<'a> {
+    type Msg = PresignMsg;
+    type Output = PreSignature;
+
+    fn handle_message(&mut self, from: PartyIndex, msg: PresignMsg) -> Result<(), StateError> {
+        let id = sender_id(self.pp, self.my_id, from)?;
+        if !self.key.tpk.pub_shares.contains_key(&id) {
+            // disqualified in the DKG: counting its dealings could split the views
+            disqualify(
+                &mut self.disqualified,
+                BTreeMap::from([(from, DisqualReason::NotQualifiedInDkg)]),
+            );
+            return Ok(());
+        }
+
+        match msg {
+            PresignMsg::NonceGen(msg) => {
+                insert_once(&mut self.nonce_gen_messages, id, msg, from)?;
+            }
+            PresignMsg::MtAwc(msg) => {
+                // a party deals the MtA round again only after dropping a dealer
+                let sent = self.mta_messages.entry(id).or_default();
+                if sent.len() >= self.pp.n as usize
+                    || sent.iter().any(|s| s.nonce_digests == msg.nonce_digests)
+                {
+                    return Err(StateError::DuplicateSender(from));
+                }
+                sent.push(msg);
+            }
+            PresignMsg::PreSignFinal(msg) => {
+                insert_once(&mut self.presign_final_messages, id, msg, from)?;
+            }
+        }
+        self.proceed()
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<PresignMsg>> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    fn try_finish(mut self) -> Result<PreSignature, Self> {
+        match self.output.take() {
+            Some(output) if self.outbox.is_empty() => Ok(output),
+            output => {
+                self.output = output;
+                Err(self)
+            }
+        }
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        if self.output.is_some() {
+            return Ok(());
+        }
+        let heard = match self.round {
+            PresignRound::NonceGen => self.nonce_gen_messages.keys().copied().collect(),
+            PresignRound::MtAwc { .. } => self.mta_messages.keys().copied().collect(),
+            // finishes with any t valid shares anyway
+            PresignRound::PreSignFinal { .. } => {
+                self.presign_final_messages.keys().copied().collect()
+            }
+        };
+        too_few_by_deadline(self.pp, heard)?;
+        self.deadline_passed = true;
+        self.proceed()
+    }
+}
+
+#[deprecated(note = "build a `Participant` and use `participant::presign`")]
+pub async fn presign<M>(
+    party: M,
+    my_id: Id, // in the range 1..=n, subtract one before use
+    pp: &PubParams,
+    h: &G,
+    my_cl_sk: &SecretKey,
+    key: &DkgOutput,
+) -> Result<PreSignature, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = PresignMsg>,
+{
+    check_keyring(pp)?;
+    run_within(
+        party,
+        PresignState::new(my_id, pp, h, my_cl_sk, key, PresignSession::default()),
+        &pp.limits,
+    )
+    .await
+}
+
+/// Presigns `count` nonces at once, in the same three rounds as a single one. The
+/// position in the batch tells the instances' proofs apart.
+pub async fn presign_batch<M>(
+    party: M,
+    count: usize,
+    my_id: Id, // in the range 1..=n, subtract one before use
+    pp: &PubParams,
+    h: &G,
+    my_cl_sk: &SecretKey,
+    key: &DkgOutput,
+) -> Result<Vec<PreSignature>, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = BatchMsg<PresignMsg>>,
+{
+    check_keyring(pp)?;
+    let instances = (0..count as u64)
+        .map(|counter| {
+            PresignState::new(
+                my_id,
+                pp,
+                h,
+                my_cl_sk,
+                key,
+                PresignSession::new(&[], counter),
+            )
+        })
+        .collect();
+    run_within(party, Batched::new(instances), &pp.limits).await
+}
+
+/// Only the first presigning round, to generate nonces ahead of the rest of presigning,
+/// e.g. in bulk on idle machines. Each party keeps its shares of the joint k and gamma as
+/// a `NoncePair`, for `PresignState::from_nonces` to spend later. It speaks `PresignMsg`
+/// and ignores messages of the later rounds.
+pub struct NonceGenState<'a> {
+    my_id: Id, // in the range 1..=n
+    pp: &'a PubParams,
+    h: &'a G,
+    my_cl_sk: &'a SecretKey,
+    session: PresignSession,
+    replay_cache: Option<SharedReplayCache>,
+    deadline_passed: bool,
+    nonce_gen_messages: BTreeMap<Id, NonceGenMsg>,
+    outbox: Vec<Outgoing<PresignMsg>>,
+    output: Option<NoncePair>,
+}
+
+impl<'a> NonceGenState<'a> {
+    /// Deals nonces bound to `session_id`, which all parties of the run must pass.
+    pub fn new(
+        my_id: Id,
+        pp: &'a PubParams,
+        h: &'a G,
+        my_cl_sk: &'a SecretKey,
+        session_id: &[u8],
+    ) -> Self {
+        debug_assert_eq!(
+            *h, pp.curve_generator,
+            "h must be the curve generator of pp"
+        );
+        // the counter tells presignatures apart, and only the MtA proofs bind it
+        let session = PresignSession::new(session_id, 0);
+        let nonce_gen_msg = NonceGenMsg::random_in_session(pp, &mut fresh_rng(), h, &session);
+
+        NonceGenState {
+            my_id,
+            pp,
+            h,
+            my_cl_sk,
+            session,
+            replay_cache: None,
+            deadline_passed: false,
+            nonce_gen_messages: BTreeMap::from([(my_id, nonce_gen_msg.clone())]),
+            outbox: vec![Outgoing::broadcast(PresignMsg::NonceGen(nonce_gen_msg))],
+            output: None,
+        }
+    }
+
+    /// See `PresignState::with_replay_cache`.
+    pub fn with_replay_cache(mut self, cache: SharedReplayCache) -> Self {
+        self.replay_cache = Some(cache);
+        self
+    }
+
+    fn proceed(&mut self) -> Result<(), StateError> {
+        let received = self.nonce_gen_messages.len();
+        if self.output.is_some() || !heard_enough(self.pp, received, self.deadline_passed) {
+            return Ok(());
+        }
+        let context = NonceGenContext {
+            pp: self.pp,
+            h: self.h,
+            session: &self.session,
+        };
+        self.output = Some(combine_nonce_dealings(
+            &context,
+            self.my_id,
+            self.my_cl_sk,
+            &self.nonce_gen_messages,
+            self.replay_cache.as_ref(),
+        )?);
+        Ok(())
+    }
+}
+
+impl<'a> StateMachine for NonceGenState<'a> {
+    type Msg = PresignMsg;
+    type Output = NoncePair;
+
+    fn handle_message(&mut self, from: PartyIndex, msg: PresignMsg) -> Result<(), StateError> {
+        let id = sender_id(self.pp, self.my_id, from)?;
+
+        match msg {
+            PresignMsg::NonceGen(msg) => {
+                insert_once(&mut self.nonce_gen_messages, id, msg, from)?;
+            }
+            PresignMsg::MtAwc(_) | PresignMsg::PreSignFinal(_) => return Ok(()),
+        }
+        self.proceed()
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<PresignMsg>> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    fn try_finish(mut self) -> Result<NoncePair, Self> {
+        match self.output.take() {
+            Some(output) if self.outbox.is_empty() => Ok(output),
+            output => {
+                self.output = output;
+                Err(self)
+            }
+        }
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        if self.output.is_some() {
+            return Ok(());
+        }
+        too_few_by_deadline(self.pp, self.nonce_gen_messages.keys().copied().collect())?;
+        self.deadline_passed = true;
+        self.proceed()
+    }
+}
+
+/// Generates `count` pairs of nonces in a single round, dealt in the session
+/// `session_id`, to be stored and spent later by `presign_from_nonces`.
+pub async fn nonce_gen<M>(
+    party: M,
+    count: usize,
+    my_id: Id, // in the range 1..=n, subtract one before use
+    pp: &PubParams,
+    h: &G,
+    my_cl_sk: &SecretKey,
+    session_id: &[u8],
+) -> Result<Vec<NoncePair>, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = BatchMsg<PresignMsg>>,
+{
+    check_keyring(pp)?;
+    let instances = (0..count)
+        .map(|_| NonceGenState::new(my_id, pp, h, my_cl_sk, session_id))
+        .collect();
+    run_within(party, Batched::new(instances), &pp.limits).await
+}
+
+/// Presigns with a pair of nonces from `nonce_gen`, in the last two presigning rounds.
+/// Fails with `ForeignNonces` before anything is sent if the nonces were generated by
+/// another committee.
+pub async fn presign_from_nonces<M>(
+    party: M,
+    my_id: Id, // in the range 1..=n, subtract one before use
+    pp: &PubParams,
+    h: &G,
+    my_cl_sk: &SecretKey,
+    key: &DkgOutput,
+    session: PresignSession,
+    nonces: NoncePair,
+) -> Result<PreSignature, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = PresignMsg>,
+{
+    let state = PresignState::from_nonces(my_id, pp, h, my_cl_sk, key, session, nonces)?;
+    run_within(party, state, &pp.limits).await
+}
+
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum SignMsg {
+    OnlineSign(OnlineSignMsg),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignatureECDSA {
+    pub r: Zq,
+    pub s: Zq,
+}
+
+impl SignatureECDSA {
+    /// Plain ECDSA verification over the curve with `message_hash` as the digest. It
+    /// needs nothing but the joint public key, so anyone can check a signature without
+    /// the DKG outputs, and any ECDSA verifier hashing with SHA-256 accepts the same
+    /// signatures. Both s and -s are accepted; low-s normalization is left to callers.
+    pub fn verify(&self, pk: &G, msg: &[u8]) -> bool {
+        self.verify_scalar(pk, &message_hash(msg))
+    }
+
+    /// Verification of a signature made with `SignState::new_with_aad`, which fails
+    /// for any other `aad`.
+    pub fn verify_with_aad(&self, pk: &G, msg: &[u8], aad: &[u8]) -> bool {
+        self.verify_scalar(pk, &message_hash_with_aad(msg, aad))
+    }
+
+    /// Verification against a digest the caller already computed, which is not hashed again.
+    pub fn verify_prehashed(&self, pk: &G, digest: &[u8; 32]) -> bool {
+        self.verify_scalar(pk, &digest_scalar(digest))
+    }
+
+    fn verify_scalar(&self, pk: &G, m: &Zq) -> bool {
+        if self.r.is_zero() || self.s.is_zero() {
+            return false;
+        }
+
+        let s_inv = self.s.invert().unwrap();
+        let u1 = m * &s_inv;
+        let u2 = &self.r * &s_inv;
+        let X = G::generator() * u1 + pk * u2;
+
+        match X.x_coord() {
+            Some(x) => Zq::from_bigint(&x) == self.r,
+            None => false,
+        }
+    }
+
+    /// Whether `other` is the same signature, up to the sign of s that `verify` ignores.
+    /// Signatures combined from one presignature over one message agree whichever t
+    /// parties combined them, as s is then interpolated from shares of the same k and
+    /// chi. Quorums that signed with different presignatures hold valid signatures
+    /// with different r, which do not agree; neither is to be preferred over the other.
+    pub fn agrees_with(&self, other: &SignatureECDSA) -> bool {
+        self.r == other.r && (self.s == other.s || self.s == -other.s.clone())
+    }
+
+    /// Combines signature shares collected outside `SignState`, keyed by the sender's
+    /// wire index. Each share is checked against its sender's MACs in `presignature`
+    /// first, and a single mismatch fails the combination with every offender named,
+    /// instead of yielding a signature that does not verify. Callers that want to sign
+    /// anyway drop the offenders and combine again if t shares are left.
+    pub fn try_from_with_blame(
+        pp: &PubParams,
+        h: &G,
+        context: &SigningContext,
+        presignature: &PreSignature,
+        msg: &[u8],
+        shares: &BTreeMap<PartyIndex, OnlineSignMsg>,
+    ) -> Result<Self, SignError> {
+        let digest: [u8; 32] = Sha256::digest(msg).into();
+        Self::try_from_digest_with_blame(pp, h, context, presignature, &digest, shares)
+    }
+
+    /// Like `try_from_with_blame`, for shares of a signature on `digest` as it is; see
+    /// `SignState::new_prehashed`.
+    pub fn try_from_digest_with_blame(
+        pp: &PubParams,
+        h: &G,
+        context: &SigningContext,
+        presignature: &PreSignature,
+        digest: &[u8; 32],
+        shares: &BTreeMap<PartyIndex, OnlineSignMsg>,
+    ) -> Result<Self, SignError> {
+        let r = Zq::from_bigint(&presignature.R.x_coord().unwrap());
+        let m = digest_scalar(digest);
+        let view_digest = presignature.view_digest();
+
+        let mut sig_shares = BTreeMap::new();
+        let mut parties = vec![];
+        for (&from, share) in shares {
+            match PartyId::from_wire_index(from).map(PartyId::to_share_index) {
+                Some(id)
+                    if share_matches_macs(
+                        h,
+                        &presignature.k_macs,
+                        &presignature.chi_macs,
+                        &m,
+                        &r,
+                        &view_digest,
+                        id,
+                        share,
+                    ) =>
+                {
+                    sig_shares.insert(id, share.sig_share.clone());
+                }
+                _ => parties.push(from),
+            }
+        }
+
+        if !parties.is_empty() {
+            return Err(SignError::InvalidPartial { parties });
+        }
+        if sig_shares.len() < pp.t as usize {
+            return Err(SignError::TooFewShares {
+                got: sig_shares.len(),
+                need: pp.t as usize,
+            });
+        }
+        Ok(SignatureECDSA {
+            s: context.interpolate(pp, &sig_shares).unwrap(),
+            r,
+        })
+    }
+
+    /// Like `try_from_with_blame`, but also returns the transcript of combining every
+    /// share in `shares` unchecked, for `SigningTranscript::find_bad_contributor` to
+    /// look into when the shares came from elsewhere than this crate's combiner.
+    #[cfg(feature = "signing-transcript")]
+    pub fn try_from_with_transcript(
+        pp: &PubParams,
+        h: &G,
+        context: &SigningContext,
+        presignature: &PreSignature,
+        msg: &[u8],
+        shares: &BTreeMap<PartyIndex, OnlineSignMsg>,
+    ) -> (Result<Self, SignError>, SigningTranscript) {
+        let signature = Self::try_from_with_blame(pp, h, context, presignature, msg, shares);
+
+        let shares: BTreeMap<Id, &OnlineSignMsg> = shares
+            .iter()
+            .filter_map(|(&from, share)| {
+                Some((PartyId::from_wire_index(from)?.to_share_index(), share))
+            })
+            .collect();
+        let coeffs = lagrange_coeffs_at_zero(&shares.keys().copied().collect::<Vec<_>>());
+        let r = Zq::from_bigint(&presignature.R.x_coord().unwrap());
+        let mut transcript =
+            SigningTranscript::new(h, &message_hash(msg), &r, presignature.view_digest());
+        for (id, share) in shares {
+            let (k_macs, chi_macs) = (&presignature.k_macs, &presignature.chi_macs);
+            transcript.insert(id, share, k_macs, chi_macs, coeffs.get(&id).cloned());
+        }
+        (signature, transcript)
+    }
+}
+
+/// Everything a signature was combined from, kept with the `signing-transcript` feature
+/// to find out afterwards who is to blame for a signature that does not verify.
+#[cfg(feature = "signing-transcript")]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SigningTranscript {
+    pub h: G,
+    pub m: Zq,
+    pub r: Zq,
+    /// Of the presignature the shares are to be made with.
+    pub view_digest: [u8; 32],
+    pub contributions: BTreeMap<Id, Contribution>,
+}
+
+/// A party's signature share, and what it was checked against and combined with.
+#[cfg(feature = "signing-transcript")]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Contribution {
+    pub sig_share: Zq,
+    /// Of the presignature the party made its share with.
+    pub view_digest: [u8; 32],
+    /// What h times the share must be: m times the party's MAC of k, plus r times its
+    /// MAC of chi. `None` if there are no MACs for the party.
+    pub claimed: Option<G>,
+    /// What the share was multiplied by in the signature, `None` if it was left out.
+    pub lagrange_coeff: Option<Zq>,
+}
+
+#[cfg(feature = "signing-transcript")]
+impl SigningTranscript {
+    fn new(h: &G, m: &Zq, r: &Zq, view_digest: [u8; 32]) -> Self {
+        SigningTranscript {
+            h: h.clone(),
+            m: m.clone(),
+            r: r.clone(),
+            view_digest,
+            contributions: BTreeMap::new(),
+        }
+    }
+
+    fn insert(
+        &mut self,
+        id: Id,
+        share: &OnlineSignMsg,
+        k_macs: &BTreeMap<Id, G>,
+        chi_macs: &BTreeMap<Id, G>,
+        lagrange_coeff: Option<Zq>,
+    ) {
+        let claimed = k_macs
+            .get(&id)
+            .zip(chi_macs.get(&id))
+            .map(|(k_mac, chi_mac)| k_mac * &self.m + chi_mac * &self.r);
+        let contribution = Contribution {
+            sig_share: share.sig_share.clone(),
+            view_digest: share.prev_round_digest,
+            claimed,
+            lagrange_coeff,
+        };
+        self.contributions.insert(id, contribution);
+    }
+
+    /// The signature combined from the shares with a Lagrange coefficient.
+    pub fn signature(&self) -> SignatureECDSA {
+        let s = self
+            .contributions
+            .values()
+            .filter_map(|c| Some(c.lagrange_coeff.as_ref()? * &c.sig_share))
+            .sum();
+        SignatureECDSA {
+            r: self.r.clone(),
+            s,
+        }
+    }
+
+    /// Recomputes the contribution of every share combined into the signature, and
+    /// returns the first party whose share was made with another presignature or does
+    /// not match its MACs. `None` if the signature verifies under `pk`, and also if
+    /// every share checks out, which leaves presigning to blame rather than a signer.
+    pub fn find_bad_contributor(&self, pk: &G) -> Option<Id> {
+        if self.signature().verify_scalar(pk, &self.m) {
+            return None;
+        }
+        self.contributions
+            .iter()
+            .filter(|(_, c)| c.lagrange_coeff.is_some())
+            .find(|(_, c)| {
+                c.view_digest != self.view_digest
+                    || c.claimed.as_ref() != Some(&(&self.h * &c.sig_share))
+            })
+            .map(|(&id, _)| id)
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SignError {
+    #[error("parties {parties:?} sent signature shares that do not match their MACs")]
+    InvalidPartial { parties: Vec<PartyIndex> },
+    #[error("only {got} signature shares, but {need} are required")]
+    TooFewShares { got: usize, need: usize },
+}
+
+/// Whether the signature share of party `id` is consistent with its MACs of k and chi.
+fn share_matches_macs(
+    h: &G,
+    k_macs: &BTreeMap<Id, G>,
+    chi_macs: &BTreeMap<Id, G>,
+    m: &Zq,
+    r: &Zq,
+    view_digest: &[u8; 32],
+    id: Id,
+    msg: &OnlineSignMsg,
+) -> bool {
+    k_macs
+        .get(&id)
+        .zip(chi_macs.get(&id))
+        .map_or(false, |(k_mac, chi_mac)| {
+            let context = OnlineSignContext {
+                h,
+                k_mac,
+                chi_mac,
+                m,
+                r,
+                prev_round_digest: view_digest,
+            };
+            msg.verify(&context).is_ok()
+        })
+}
+
+/// The signed digest: SHA-256 of the raw message bytes, with no prefix or length
+/// framing, reduced modulo the group order. Signing and `SignatureECDSA::verify` both
+/// go through it, so any byte string, including an empty one, signs unambiguously.
+pub fn message_hash(msg: &[u8]) -> Zq {
+    digest_scalar(&Sha256::digest(msg).into())
+}
+
+const AAD_DOMAIN: &[u8] = b"robust-threshold-ecdsa/aad/v1";
+
+/// The signed digest of `msg` bound to associated data `aad`, such as a chain id: SHA-256
+/// of a domain tag, the length-prefixed `aad`, then `msg`. A signature over it verifies
+/// only with the same `aad`, and never as a signature over `msg` alone. A plain ECDSA
+/// verifier does accept it for the whole hashed string, which starts with the tag, so
+/// applications must not sign such strings as plain messages.
+pub fn message_hash_with_aad(msg: &[u8], aad: &[u8]) -> Zq {
+    let digest = Sha256::new()
+        .chain_update(AAD_DOMAIN)
+        .chain_update((aad.len() as u64).to_be_bytes())
+        .chain_update(aad)
+        .chain_update(msg)
+        .finalize();
+    digest_scalar(&digest.into())
+}
+
+/// The scalar a 32-byte digest is signed as: the digest read big-endian, reduced mod q.
+pub fn digest_scalar(digest: &[u8; 32]) -> Zq {
+    Zq::from_bigint(&BigInt::from_bytes(digest))
+}
+
+/// Data that stays fixed across signatures under one key: the public key, the
+/// committee whose shares are combined by default, and its Lagrange coefficients at 0.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SigningContext {
+    pub pk: G,
+    pub committee: Vec<Id>,
+    lagrange_coeffs: BTreeMap<Id, Zq>,
+}
+
+impl SigningContext {
+    /// Takes the first t of `DkgOutput::honest_parties` as the committee.
+    pub fn from_dkg(pp: &PubParams, key: &DkgOutput) -> Self {
+        let committee: Vec<Id> = key
+            .honest_parties()
+            .into_iter()
+            .take(pp.t as usize)
+            .collect();
+        let lagrange_coeffs = pp.lagrange_coeffs(committee.clone()).unwrap();
+
+        SigningContext {
+            pk: key.tpk.pk.clone(),
+            committee,
+            lagrange_coeffs,
+        }
+    }
+
+    /// Interpolates at 0 with the cached coefficients if the whole committee is among
+    /// `shares`, and falls back to computing them for `shares` otherwise.
+    pub fn interpolate(&self, pp: &PubParams, shares: &BTreeMap<Id, Zq>) -> Option<Zq> {
+        if self.committee.iter().all(|id| shares.contains_key(id)) {
+            Some(
+                self.lagrange_coeffs
+                    .iter()
+                    .map(|(id, coeff)| coeff * &shares[id])
+                    .sum(),
+            )
+        } else {
+            pp.interpolate(shares)
+        }
+    }
+
+    /// The coefficients `interpolate` multiplies `shares` by.
+    #[cfg(feature = "signing-transcript")]
+    fn coeffs_for(&self, shares: &BTreeMap<Id, Zq>) -> BTreeMap<Id, Zq> {
+        if self.committee.iter().all(|id| shares.contains_key(id)) {
+            self.lagrange_coeffs.clone()
+        } else {
+            lagrange_coeffs_at_zero(&shares.keys().copied().collect::<Vec<_>>())
+        }
+    }
+}
+
+/// The result of signing: the signature, and which parties it came from. The
+/// participants are those whose signature shares had been verified when the party
+/// finished; `disqualified` holds everyone rejected in presigning or signing.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SigningOutcome {
+    pub signature: SignatureECDSA,
+    pub public_key: G,
+    pub participants: BTreeSet<PartyIndex>,
+    pub disqualified: BTreeMap<PartyIndex, DisqualReason>,
+    pub session_id: Option<Vec<u8>>,
+    /// Every share received, also those rejected, with the `signing-transcript` feature.
+    #[cfg(feature = "signing-transcript")]
+    pub transcript: SigningTranscript,
+}
+
+impl From<SigningOutcome> for SignatureECDSA {
+    fn from(outcome: SigningOutcome) -> Self {
+        outcome.signature
+    }
+}
+
+/// Online signing with a presignature: a single round that finishes as soon as
+/// t signature shares consistent with their MACs have arrived.
+pub struct SignState<'a> {
+    my_id: Id, // in the range 1..=n
+    pp: &'a PubParams,
+    h: &'a G,
+    context: &'a SigningContext,
+    r: Zq,
+    k_macs: BTreeMap<Id, G>,
+    chi_macs: BTreeMap<Id, G>,
+    m: Zq,
+    view_digest: [u8; 32], // of the presignature
+    session_id: Option<Vec<u8>>,
+    sign_messages: BTreeMap<Id, OnlineSignMsg>,
+    sig_shares: BTreeMap<Id, Zq>, // the verified ones
+    disqualified: BTreeMap<PartyIndex, DisqualReason>,
+    outbox: Vec<Outgoing<SignMsg>>,
+}
+
+impl<'a> SignState<'a> {
+    pub fn new(
+        my_id: Id,
+        pp: &'a PubParams,
+        h: &'a G,
+        context: &'a SigningContext,
+        presignature: PreSignature,
+        msg: &[u8],
+    ) -> Self {
+        Self::with_scalar(my_id, pp, h, context, presignature, message_hash(msg))
+    }
+
+    /// Signs `digest` as it is, for callers that hashed the message themselves. Passing
+    /// the message here instead of its digest produces a signature on the wrong value.
+    pub fn new_prehashed(
+        my_id: Id,
+        pp: &'a PubParams,
+        h: &'a G,
+        context: &'a SigningContext,
+        presignature: PreSignature,
+        digest: &[u8; 32],
+    ) -> Self {
+        Self::with_scalar(my_id, pp, h, context, presignature, digest_scalar(digest))
+    }
+
+    /// Signs `msg` bound to the associated data `aad`; see `message_hash_with_aad`. Every
+    /// signer must pass the same `aad`, and verifiers check with `verify_with_aad`.
+    pub fn new_with_aad(
+        my_id: Id,
+        pp: &'a PubParams,
+        h: &'a G,
+        context: &'a SigningContext,
+        presignature: PreSignature,
+        msg: &[u8],
+        aad: &[u8],
+    ) -> Self {
+        let m = message_hash_with_aad(msg, aad);
+        Self::with_scalar(my_id, pp, h, context, presignature, m)
+    }
+
+    fn with_scalar(
+        my_id: Id,
+        pp: &'a PubParams,
+        h: &'a G,
+        context: &'a SigningContext,
+        presignature: PreSignature,
+        m: Zq,
+    ) -> Self {
+        let r = Zq::from_bigint(&presignature.R.x_coord().unwrap());
+
+        let sig_share = &presignature.k_share * &m + &r * &presignature.chi_share;
+        let view_digest = presignature.view_digest();
+        let sign_msg = OnlineSignMsg {
+            prev_round_digest: view_digest,
+            sig_share: sig_share.clone(),
+        };
+
+        SignState {
+            my_id,
+            pp,
+            h,
+            context,
+            r,
+            k_macs: presignature.k_macs,
+            chi_macs: presignature.chi_macs,
+            m,
+            view_digest,
+            session_id: None,
+            sign_messages: BTreeMap::from([(my_id, sign_msg.clone())]),
+            sig_shares: BTreeMap::from([(my_id, sig_share)]),
+            disqualified: presignature.disqualified,
+            outbox: vec![Outgoing::broadcast(SignMsg::OnlineSign(sign_msg))],
+        }
+    }
+
+    /// Labels the outcome with `session_id`, for the application's records.
+    pub fn with_session_id(mut self, session_id: &[u8]) -> Self {
+        self.session_id = Some(session_id.to_vec());
+        self
+    }
+
+    fn check_share(&mut self, id: Id, from: PartyIndex) {
+        let msg = &self.sign_messages[&id];
+        let valid = share_matches_macs(
+            self.h,
+            &self.k_macs,
+            &self.chi_macs,
+            &self.m,
+            &self.r,
+            &self.view_digest,
+            id,
+            msg,
+        );
+
+        if valid {
+            self.sig_shares.insert(id, msg.sig_share.clone());
+        } else {
+            let reason = if msg.prev_round_digest != self.view_digest {
+                DisqualReason::ViewMismatch
+            } else {
+                DisqualReason::SignatureShare
+            };
+            self.disqualified.entry(from).or_insert(reason);
+        }
+    }
+}
+
+impl<'a> StateMachine for SignState<'a> {
+    type Msg = SignMsg;
+    type Output = SigningOutcome;
+
+    fn handle_message(&mut self, from: PartyIndex, msg: SignMsg) -> Result<(), StateError> {
+        let id = sender_id(self.pp, self.my_id, from)?;
+
+        match msg {
+            SignMsg::OnlineSign(msg) => {
+                insert_once(&mut self.sign_messages, id, msg, from)?;
+            }
+        }
+        self.check_share(id, from);
+        Ok(())
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<SignMsg>> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    fn try_finish(self) -> Result<SigningOutcome, Self> {
+        if self.sig_shares.len() < self.pp.t as usize || !self.outbox.is_empty() {
+            return Err(self);
+        }
+
+        #[cfg(feature = "signing-transcript")]
+        let transcript = {
+            let coeffs = self.context.coeffs_for(&self.sig_shares);
+            let mut transcript = SigningTranscript::new(self.h, &self.m, &self.r, self.view_digest);
+            for (&id, share) in &self.sign_messages {
+                let (k_macs, chi_macs) = (&self.k_macs, &self.chi_macs);
+                transcript.insert(id, share, k_macs, chi_macs, coeffs.get(&id).cloned());
+            }
+            transcript
+        };
+        let signature = SignatureECDSA {
+            s: self.context.interpolate(self.pp, &self.sig_shares).unwrap(),
+            r: self.r,
+        };
+        Ok(SigningOutcome {
+            signature,
+            public_key: self.context.pk.clone(),
+            participants: self
+                .sig_shares
+                .keys()
+                .map(|&id| PartyId::from_share_index(id).to_wire_index())
+                .collect(),
+            disqualified: self.disqualified,
+            session_id: self.session_id,
+            #[cfg(feature = "signing-transcript")]
+            transcript,
+        })
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        // the shares that check out are all there is to go on
+        too_few_by_deadline(self.pp, self.sig_shares.keys().copied().collect())
+    }
+}
+
+#[deprecated(note = "build a `Participant` and use `participant::sign`")]
+pub async fn sign<M>(
+    party: M,
+    my_id: Id, // in the range 1..=n, subtract one before use
+    pp: &PubParams,
+    h: &G,
+    context: &SigningContext,
+    presignature: PreSignature,
+    msg: &[u8],
+) -> Result<SigningOutcome, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = SignMsg>,
+{
+    run_within(
+        party,
+        SignState::new(my_id, pp, h, context, presignature, msg),
+        &pp.limits,
+    )
+    .await
+}
+
+/// Like `sign`, on a digest the caller already computed; see `SignState::new_prehashed`.
+pub async fn sign_prehashed<M>(
+    party: M,
+    my_id: Id, // in the range 1..=n, subtract one before use
+    pp: &PubParams,
+    h: &G,
+    context: &SigningContext,
+    presignature: PreSignature,
+    digest: &[u8; 32],
+) -> Result<SigningOutcome, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = SignMsg>,
+{
+    run_within(
+        party,
+        SignState::new_prehashed(my_id, pp, h, context, presignature, digest),
+        &pp.limits,
+    )
+    .await
+}
+
+/// Like `sign`, but first has `approve` look at the public part of the presignature.
+/// If it refuses, no signature share leaves the party.
+pub async fn sign_with_approval<M>(
+    party: M,
+    my_id: Id, // in the range 1..=n, subtract one before use
+    pp: &PubParams,
+    h: &G,
+    context: &SigningContext,
+    presignature: PreSignature,
+    msg: &[u8],
+    approve: impl FnOnce(&PresignatureAnnouncement) -> bool,
+) -> Result<SigningOutcome, Error<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = SignMsg>,
+{
+    if !approve(&presignature.announcement()) {
+        return Err(Error::PolicyRejected);
+    }
+    run_within(
+        party,
+        SignState::new(my_id, pp, h, context, presignature, msg),
+        &pp.limits,
+    )
+    .await
+}
+
+/// Wraps a state machine and rewrites (or drops, on `None`) everything it sends,
+/// to simulate misbehaving parties.
+pub struct Tampered<S: StateMachine> {
+    pub inner: S,
+    pub tamper: fn(S::Msg) -> Option<S::Msg>,
+}
+
+impl<S: StateMachine> Tampered<S> {
+    pub fn honest(inner: S) -> Self {
+        Tampered {
+            inner,
+            tamper: Some,
+        }
+    }
+}
+
+impl<S: StateMachine> StateMachine for Tampered<S> {
+    type Msg = S::Msg;
+    type Output = S::Output;
+
+    fn handle_message(&mut self, from: PartyIndex, msg: S::Msg) -> Result<(), StateError> {
+        self.inner.handle_message(from, msg)
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<S::Msg>> {
+        let tamper = self.tamper;
+        self.inner
+            .wants_to_send()
+            .into_iter()
+            .filter_map(|Outgoing { recipient, msg }| {
+                tamper(msg).map(|msg| Outgoing { recipient, msg })
+            })
+            .collect()
+    }
+
+    fn try_finish(self) -> Result<S::Output, Self> {
+        let tamper = self.tamper;
+        self.inner
+            .try_finish()
+            .map_err(|inner| Tampered { inner, tamper })
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        self.inner.on_timeout()
+    }
+
+    fn on_version(&mut self, version: u16) {
+        self.inner.on_version(version)
+    }
+}
+
+pub fn simulate_pp(n: Id, t: Id) -> (PubParams, BTreeMap<Id, SecretKey>) {
+    simulate_pp_seeded(n, t, &Mpz::from(&Zq::random()))
+}
+
+/// Level of the simulated class group: the toy one keeps the test suite fast.
+#[cfg(test)]
+const SIMULATION_LEVEL: SecurityLevel = SecurityLevel::Toy;
+#[cfg(not(test))]
+const SIMULATION_LEVEL: SecurityLevel = SecurityLevel::Bits128;
+
+/// Like `simulate_pp`, with the class group and the CL keys derived from `seed`.
+pub fn simulate_pp_seeded(n: Id, t: Id, seed: &Mpz) -> (PubParams, BTreeMap<Id, SecretKey>) {
+    let mut rng = RandGen::new();
+    rng.set_seed(seed);
+    PubParams::with_level(SIMULATION_LEVEL, n, t, &mut rng)
+        .expect("simulated committees stay within the default limits")
+}
+
+#[test]
+pub fn test_dkg() {
+    use crate::test_utils::simulation::run_dkg_simulation;
+
+    let keys = run_dkg_simulation(2, 3);
+    assert_eq!(keys.len(), 3);
+    assert!(keys.iter().all(|key| key.tpk == keys[0].tpk));
+    assert!(keys.iter().all(|key| key.qualified == keys[0].qualified));
+}
+
+#[test]
+pub fn test_new_many() {
+    let (pp, _) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let parallel = PvssDealing::new_many(4, &pp, &ForkableRng::fresh(), h);
+    let sequential: Vec<_> = (0..4)
+        .map(|_| PvssDealing::random(&pp, &mut rng, h))
+        .collect();
+
+    // independently seeded dealings must not repeat secrets or encryption randomness,
+    // neither among themselves nor against sequentially generated ones
+    let dealings: Vec<&PvssDealing> = parallel
+        .iter()
+        .chain(sequential.iter())
+        .map(|(dealing, ..)| dealing)
+        .collect();
+
+    for (i, a) in dealings.iter().enumerate() {
+        for b in &dealings[i + 1..] {
+            assert_ne!(a.curve_polynomial.coeffs[0], b.curve_polynomial.coeffs[0]);
+            assert_ne!(
+                a.shares_ciphertext.randomness,
+                b.shares_ciphertext.randomness
+            );
+        }
+    }
+
+    for (dealing, r, _, shares) in &parallel {
+        let proof = PvssNizk::prove_with_aux(&pp, dealing, r, shares, &mut rng, h, &[]);
+        assert!(proof.verify_with_aux(dealing, &pp, h, &[]));
+    }
+}
+
+#[test]
+pub fn test_forkable_rng_determinism() {
+    use crate::codec::encode;
+
+    fn sendable<T: Send + Sync>() {}
+    sendable::<ForkableRng>();
+
+    let (pp, _) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let seed = [7u8; 32];
+
+    // the same seed gives the same dealings, whichever thread made which
+    let dealings = |seed| {
+        PvssDealing::new_many(3, &pp, &ForkableRng::from_seed(seed), h)
+            .into_iter()
+            .map(|(dealing, ..)| encode(&dealing))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(dealings(seed), dealings(seed));
+    assert_ne!(dealings(seed), dealings([8u8; 32]));
+    let first = dealings(seed);
+    assert!(first[0] != first[1] && first[1] != first[2]);
+
+    let single = |rng: &mut ForkableRng| encode(&PvssDealing::random(&pp, rng, h).0);
+    let root = ForkableRng::from_seed(seed);
+    assert_eq!(single(&mut root.fork("pvss dealing", 0)), first[0]);
+
+    let pvss = JointPvssResult::new(
+        &pp,
+        vec![PvssDealing::random(&pp, &mut root.fork("k", 0), h).0],
+    );
+    let scalar = Zq::from(5u64);
+    let mta = |rng: &mut ForkableRng| MtaDealing::new(&pp, &pvss, &scalar, rng, h).1;
+    assert_eq!(mta(&mut root.fork("mta", 0)), mta(&mut root.fork("mta", 0)));
+}
+
+#[test]
+pub fn test_forkable_rng_siblings_differ() {
+    let root = ForkableRng::from_seed([7u8; 32]);
+    let stream = |mut rng: ForkableRng| -> Vec<u8> {
+        (0..64)
+            .flat_map(|_| rng.sample_scalar().to_bytes().to_vec())
+            .collect()
+    };
+    let a = stream(root.fork("sibling", 0));
+    let b = stream(root.fork("sibling", 1));
+    let c = stream(root.fork("other", 0));
+    assert_eq!(a, stream(root.fork("sibling", 0)));
+
+    // unrelated streams agree on about half of their bits
+    for (x, y) in [(&a, &b), (&a, &c), (&b, &c)] {
+        let bits = 8 * x.len() as f64;
+        let agreeing: u32 = x.iter().zip(y).map(|(x, y)| (!(x ^ y)).count_ones()).sum();
+        let ratio = agreeing as f64 / bits;
+        assert!((0.45..0.55).contains(&ratio), "{ratio}");
+    }
+
+    // and so do the bits within one stream
+    let ones: u32 = a.iter().map(|byte| byte.count_ones()).sum();
+    let ratio = ones as f64 / (8 * a.len()) as f64;
+    assert!((0.45..0.55).contains(&ratio), "{ratio}");
+}
+
+#[test]
+pub fn test_dkg_state_machines() {
+    use crate::test_utils::simulation::dkg_locally;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+
+    let outputs = dkg_locally(&pp, &secret_keys);
+    assert_eq!(outputs.len(), pp.n as usize);
+    assert!(outputs.values().all(|output| output.tpk == outputs[&0].tpk));
+}
+
+#[test]
+pub fn test_keyring_from_keygen() {
+    let (pp, _) = simulate_pp(2, 2);
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let (keyring, secret_keys) = CLKeyRing::from_keygen(&pp.cl, &mut rng, 5);
+
+    assert_eq!(keyring.len(), 5);
+    assert_eq!(keyring.ids().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    assert_eq!(
+        secret_keys.keys().copied().collect::<Vec<_>>(),
+        vec![1, 2, 3, 4, 5]
+    );
+
+    let keys: Vec<Vec<u8>> = keyring.iter().map(|(_, pk)| pk.to_bytes()).collect();
+    for (i, key) in keys.iter().enumerate() {
+        assert!(keys[i + 1..].iter().all(|other| other != key));
+    }
+    assert!(keyring.get(&0).is_none());
+}
+
+#[test]
+pub fn test_robust_presign() {
+    use crate::test_utils::simulation::dkg_locally;
+
+    let (pp, secret_keys) = simulate_pp(5, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    assert_eq!(keys.len(), pp.n as usize);
+
+    // party 2 sends its MtA dealings swapped, party 3 withholds its share of delta
+    let states = (1..=pp.n)
+        .map(|i| {
+            let state = PresignState::new(
+                i,
+                &pp,
+                h,
+                &secret_keys[&i],
+                &keys[&(PartyId::from_share_index(i).to_wire_index())],
+                PresignSession::default(),
+            );
+            let tamper: fn(PresignMsg) -> Option<PresignMsg> = match i {
+                2 => |msg| match msg {
+                    PresignMsg::MtAwc(mut msg) => {
+                        std::mem::swap(&mut msg.k_gamma_dealing, &mut msg.k_x_dealing);
+                        Some(PresignMsg::MtAwc(msg))
+                    }
+                    msg => Some(msg),
+                },
+                3 => |msg| match msg {
+                    PresignMsg::PreSignFinal(_) => None,
+                    msg => Some(msg),
+                },
+                _ => Some,
+            };
+            (
+                PartyId::from_share_index(i).to_wire_index(),
+                Tampered {
+                    inner: state,
+                    tamper,
+                },
+            )
+        })
+        .collect();
+
+    let presignatures = run_locally(states);
+    assert!(!presignatures.contains_key(&1));
+    assert!([0, 2, 3, 4].iter().all(|i| presignatures.contains_key(i)));
+
+    let msg = b"robust threshold ecdsa";
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let signatures = run_locally(
+        presignatures
+            .into_iter()
+            .map(|(i, presignature)| {
+                let state = SignState::new(
+                    PartyId::from_wire_index(i).unwrap().to_share_index(),
+                    &pp,
+                    h,
+                    &context,
+                    presignature,
+                    msg,
+                );
+                (i, state)
+            })
+            .collect(),
+    );
+
+    // every honest party saw all four shares and the same cheater
+    assert_eq!(signatures.len(), 4);
+    for outcome in signatures.values() {
+        assert!(outcome.signature.verify(&keys[&0].tpk.pk, msg));
+        assert_eq!(outcome.participants, BTreeSet::from([0, 2, 3, 4]));
+        assert_eq!(
+            outcome.disqualified,
+            BTreeMap::from([(1, DisqualReason::Mta)])
+        );
+    }
+}
+
+#[test]
+pub fn test_presign_batch() {
+    use crate::test_utils::simulation::dkg_locally;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+
+    static SENT: AtomicUsize = AtomicUsize::new(0);
+    let count_sent: fn(BatchMsg<PresignMsg>) -> Option<BatchMsg<PresignMsg>> = |msg| {
+        SENT.fetch_add(1, Ordering::Relaxed);
+        Some(msg)
+    };
+
+    let batch_size = 3;
+    let states = (1..=pp.n)
+        .map(|i| {
+            let key = &keys[&(PartyId::from_share_index(i).to_wire_index())];
+            let instances = (0..batch_size)
+                .map(|_| {
+                    PresignState::new(i, &pp, h, &secret_keys[&i], key, PresignSession::default())
+                })
+                .collect();
+            let state = Tampered {
+                inner: Batched::new(instances),
+                tamper: count_sent,
+            };
+            (PartyId::from_share_index(i).to_wire_index(), state)
+        })
+        .collect();
+
+    let presignatures = run_locally(states);
+    assert_eq!(presignatures.len(), pp.n as usize);
+    assert!(presignatures
+        .values()
+        .all(|batch| batch.len() == batch_size));
+
+    // three broadcasts per party, regardless of the batch size
+    assert_eq!(SENT.load(Ordering::Relaxed), 3 * pp.n as usize);
+
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let messages: [&[u8]; 3] = [b"first", b"second", b"third"];
+    for (nonce, msg) in messages.iter().enumerate() {
+        let signatures = run_locally(
+            presignatures
+                .iter()
+                .map(|(&i, batch)| {
+                    let state = SignState::new(
+                        PartyId::from_wire_index(i).unwrap().to_share_index(),
+                        &pp,
+                        h,
+                        &context,
+                        batch[nonce].clone(),
+                        msg,
+                    );
+                    (i, state)
+                })
+                .collect(),
+        );
+
+        assert_eq!(signatures.len(), pp.n as usize);
+        for outcome in signatures.values() {
+            assert!(outcome.signature.verify(&keys[&0].tpk.pk, msg));
+        }
+    }
+
+    let nonces: Vec<&G> = presignatures[&0].iter().map(|presig| &presig.R).collect();
+    assert!(nonces[0] != nonces[1] && nonces[1] != nonces[2] && nonces[0] != nonces[2]);
+}
+
+#[test]
+pub fn test_nonce_gen_then_presign() {
+    use crate::test_utils::simulation::dkg_locally;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+
+    // the pool is generated, and kept in memory, before any presigning
+    let mut pools: BTreeMap<PartyIndex, Vec<NoncePair>> = run_locally(
+        (1..=pp.n)
+            .map(|i| {
+                let instances = (0..2)
+                    .map(|_| NonceGenState::new(i, &pp, h, &secret_keys[&i], &[]))
+                    .collect();
+                (
+                    PartyId::from_share_index(i).to_wire_index(),
+                    Batched::new(instances),
+                )
+            })
+            .collect(),
+    );
+    assert_eq!(pools.len(), pp.n as usize);
+
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let mut nonces = vec![];
+    for (counter, msg) in [b"first", b"other"].into_iter().enumerate() {
+        let presignatures = run_locally(
+            (1..=pp.n)
+                .map(|i| {
+                    let wire = PartyId::from_share_index(i).to_wire_index();
+                    let pair = pools.get_mut(&wire).unwrap().remove(0);
+                    let session = PresignSession::new(b"from the pool", counter as u64);
+                    let state = PresignState::from_nonces(
+                        i,
+                        &pp,
+                        h,
+                        &secret_keys[&i],
+                        &keys[&wire],
+                        session,
+                        pair,
+                    )
+                    .unwrap();
+                    (wire, state)
+                })
+                .collect(),
+        );
+        assert_eq!(presignatures.len(), pp.n as usize);
+        nonces.push(presignatures[&0].R.clone());
+
+        let signatures = run_locally(
+            presignatures
+                .into_iter()
+                .map(|(i, presignature)| {
+                    let my_id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                    let state = SignState::new(my_id, &pp, h, &context, presignature, msg);
+                    (i, state)
+                })
+                .collect(),
+        );
+        assert_eq!(signatures.len(), pp.n as usize);
+        assert!(signatures
+            .values()
+            .all(|outcome| outcome.signature.verify(&context.pk, msg)));
+    }
+    assert!(nonces[0] != nonces[1]);
+
+    // nonces of another committee are refused up front
+    let (other_pp, _) = simulate_pp(3, 2);
+    let pair = run_locally(
+        (1..=pp.n)
+            .map(|i| {
+                let state = NonceGenState::new(i, &pp, h, &secret_keys[&i], &[]);
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    )
+    .remove(&0)
+    .unwrap();
+    assert_eq!(pair.fingerprint(), &pp.fingerprint());
+    assert!(matches!(
+        PresignState::from_nonces(
+            1,
+            &other_pp,
+            h,
+            &secret_keys[&1],
+            &keys[&0],
+            PresignSession::default(),
+            pair
+        ),
+        Err(StateError::ForeignNonces)
+    ));
+}
+
+#[test]
+pub fn test_lazy_curve_macs() {
+    let (pp, _) = simulate_pp(4, 3);
+    let h = G::base_point2();
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let dealings: Vec<PvssDealing> = (0..3)
+        .map(|_| PvssDealing::random(&pp, &mut rng, h).0)
+        .collect();
+    let pvss_result = JointPvssResult::new(&pp, dealings.clone());
+
+    // the MACs used to be stored as the sum of every dealer's evaluation
+    let stored: BTreeMap<Id, G> = (1..=pp.n)
+        .map(|id| {
+            let mac = dealings
+                .iter()
+                .map(|d| d.curve_polynomial.eval(&party_eval_point(id)))
+                .sum();
+            (id, mac)
+        })
+        .collect();
+
+    for id in 1..=pp.n {
+        assert_eq!(pvss_result.curve_mac(id), stored[&id]);
+    }
+    assert_eq!(pvss_result.curve_macs(), stored);
+}
+
+#[test]
+pub fn test_pvss_shares_commitments() {
+    let (pp, _) = simulate_pp(4, 3);
+    let h = G::base_point2();
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    // h raised to each share, as the PVSS proof needs them
+    let (dealing, r, _, shares) = PvssDealing::random(&pp, &mut rng, h);
+    let commitments = dealing.shares_commitments(pp.n);
+    let expected: BTreeMap<Id, G> = shares.iter().map(|(&id, share)| (id, h * share)).collect();
+    assert_eq!(commitments, expected);
+
+    // the MACs of a single dealing, once combined
+    let pvss_result = JointPvssResult::new(&pp, vec![dealing.clone()]);
+    assert_eq!(pvss_result.curve_macs(), commitments);
+
+    // and the proof still verifies against them
+    let proof = PvssNizk::prove_with_aux(&pp, &dealing, &r, &shares, &mut rng, h, b"aux");
+    assert!(proof.verify_with_aux(&dealing, &pp, h, b"aux"));
+}
+
+#[tokio::test]
+pub async fn test_dkg_key_confirmation() {
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let h = G::base_point2();
+    let session_id = b"key confirmation test";
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let malformed = PvssMsg::malformed(&pp, &mut rng, h);
+
+    // party 2 skips verification and so accepts the malformed dealing of party 1,
+    // which makes both of them combine a different set of dealers than parties 3 and 4
+    let mut simulation = Simulation::<DkgMsg>::new();
+    let mut party_output = vec![];
+
+    for i in 1..=pp.n {
+        let party = simulation.add_party();
+        let state = match i {
+            1 => DkgState::with_pvss_msg(i, &pp, h, &secret_keys[&i], false, malformed.clone()),
+            _ => DkgState::new(i, &pp, h, &secret_keys[&i], i == 2),
+        };
+        party_output.push(run(party, state.with_key_confirmation(session_id)));
+    }
+
+    let outputs = futures::future::join_all(party_output).await;
+    for output in &outputs[2..] {
+        match output {
+            Err(Error::KeyDisagreement { differing }) => assert_eq!(differing, &vec![0, 1]),
+            _ => panic!("key disagreement went unnoticed"),
+        }
+    }
+}
+
+#[tokio::test]
+pub async fn test_dkg_confirmed() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let mut simulation = Simulation::<DkgMsg>::new();
+    let mut party_output = vec![];
+
+    for i in 1..=pp.n {
+        let party = simulation.add_party();
+        party_output.push(dkg_confirmed(
+            party,
+            i,
+            &pp,
+            h,
+            &secret_keys[&i],
+            false,
+            b"session",
+        ));
+    }
+
+    let outputs = futures::future::try_join_all(party_output).await.unwrap();
+    assert!(outputs.iter().all(|output| output.tpk == outputs[0].tpk));
+}
+
+#[tokio::test]
+#[allow(deprecated)] // the keyring check of the positional entry point
+pub async fn test_dkg_keyring_check() {
+    let h = G::base_point2();
+    let with_keys = |edit: &dyn Fn(&mut BTreeMap<Id, PublicKey>)| {
+        let (mut pp, secret_keys) = simulate_pp(3, 2);
+        let mut keys: BTreeMap<Id, PublicKey> = pp
+            .cl_keyring
+            .iter()
+            .map(|(&id, pk)| (id, pk.clone()))
+            .collect();
+        edit(&mut keys);
+        pp.cl_keyring = CLKeyRing::from(keys);
+        (pp, secret_keys)
+    };
+
+    // a keyring without party 3 fails before anything is dealt, naming the party
+    let (pp, secret_keys) = with_keys(&|keys| {
+        keys.remove(&3);
+    });
+    assert_eq!(pp.cl_keyring.missing(pp.n), vec![3]);
+    let mut simulation = Simulation::<DkgMsg>::new();
+    for i in 1..=pp.n {
+        let outcome = dkg(simulation.add_party(), i, &pp, h, &secret_keys[&i], false).await;
+        match outcome {
+            Err(Error::MissingClKey { ids }) => assert_eq!(ids, vec![3]),
+            _ => panic!("the missing key went unnoticed"),
+        }
+    }
+
+    // a key for someone outside the committee is ignored
+    let (pp, secret_keys) = with_keys(&|keys| {
+        keys.insert(4, keys[&1].clone());
+    });
+    assert_eq!(pp.cl_keyring.extra(pp.n), vec![4]);
+    let mut simulation = Simulation::<DkgMsg>::new();
+    let party_output: Vec<_> = (1..=pp.n)
+        .map(|i| dkg(simulation.add_party(), i, &pp, h, &secret_keys[&i], false))
+        .collect();
+    let outputs = futures::future::try_join_all(party_output).await.unwrap();
+    assert!(outputs.iter().all(|output| output.tpk == outputs[0].tpk));
+}
+
+#[test]
+pub fn test_signing_context() {
+    use crate::test_utils::simulation::dkg_locally;
+
+    let (pp, secret_keys) = simulate_pp(5, 3);
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    assert_eq!(context.pk, keys[&0].tpk.pk);
+    assert_eq!(context.committee, vec![1, 2, 3]);
+
+    // the cached coefficients combine shares as the recomputed ones do
+    let share_sets: Vec<BTreeMap<Id, Zq>> = (0..10)
+        .map(|_| {
+            let poly = Polynomial {
+                coeffs: (0..pp.t).map(|_| Zq::random()).collect(),
+            };
+            (1..=pp.t)
+                .map(|id| (id, poly.eval(&party_eval_point(id))))
+                .collect()
+        })
+        .collect();
+
+    let cached: Vec<Zq> = share_sets
+        .iter()
+        .map(|shares| context.interpolate(&pp, shares).unwrap())
+        .collect();
+    let recomputed: Vec<Zq> = share_sets
+        .iter()
+        .map(|shares| pp.interpolate(shares).unwrap())
+        .collect();
+    assert_eq!(cached, recomputed);
+
+    // shares from outside the committee are still combined correctly
+    let shares: BTreeMap<Id, Zq> = (2..=4)
+        .map(|id| {
+            (
+                id,
+                Polynomial {
+                    coeffs: vec![Zq::from(7u64), Zq::from(3u64)],
+                }
+                .eval(&party_eval_point(id)),
+            )
+        })
+        .collect();
+    assert_eq!(context.interpolate(&pp, &shares), Some(Zq::from(7u64)));
+}
+
+#[test]
+pub fn test_sign_prehashed() {
+    use crate::test_utils::simulation::{dkg_locally, presign_locally};
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let presignatures = presign_locally(&pp, &secret_keys, &keys);
+
+    let msg = b"abc";
+    let digest: [u8; 32] = Sha256::digest(msg).into();
+    let sign_with = |prehashed: bool| {
+        run_locally(
+            presignatures
+                .iter()
+                .map(|(&i, presignature)| {
+                    let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                    let presignature = presignature.clone();
+                    let state = if prehashed {
+                        SignState::new_prehashed(id, &pp, h, &context, presignature, &digest)
+                    } else {
+                        SignState::new(id, &pp, h, &context, presignature, msg)
+                    };
+                    (i, state)
+                })
+                .collect(),
+        )
+    };
+
+    // the same nonce over the same value gives the same signature
+    let hashed = sign_with(false);
+    let prehashed = sign_with(true);
+    assert_eq!(hashed.len(), pp.n as usize);
+    for (i, outcome) in &prehashed {
+        assert_eq!(outcome.signature, hashed[i].signature);
+        assert!(outcome.signature.verify(&context.pk, msg));
+        assert!(outcome.signature.verify_prehashed(&context.pk, &digest));
+    }
+
+    // a digest is not hashed again
+    let signature = &prehashed[&0].signature;
+    assert!(!signature.verify(&context.pk, &digest));
+
+    // nor when shares of it are combined outside `SignState`
+    let shares: BTreeMap<PartyIndex, OnlineSignMsg> = presignatures
+        .iter()
+        .map(|(&i, presignature)| {
+            let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+            let mut state =
+                SignState::new_prehashed(id, &pp, h, &context, presignature.clone(), &digest);
+            let SignMsg::OnlineSign(share) = state.wants_to_send().remove(0).msg;
+            (i, share)
+        })
+        .collect();
+    let presignature = &presignatures[&0];
+    let combined = SignatureECDSA::try_from_digest_with_blame(
+        &pp,
+        h,
+        &context,
+        presignature,
+        &digest,
+        &shares,
+    );
+    assert_eq!(combined.as_ref(), Ok(signature));
+    let combine = |msg: &[u8]| {
+        SignatureECDSA::try_from_with_blame(&pp, h, &context, presignature, msg, &shares)
+    };
+    assert_eq!(combine(msg).as_ref(), Ok(signature));
+    assert_eq!(
+        combine(&digest),
+        Err(SignError::InvalidPartial {
+            parties: shares.keys().copied().collect()
+        })
+    );
+}
+
+#[test]
+pub fn test_sign_with_aad() {
+    use crate::test_utils::simulation::{dkg_locally, presign_locally};
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let msg = b"transfer 1 coin";
+    let sign = |aad: &[u8]| {
+        let presignatures = presign_locally(&pp, &secret_keys, &keys);
+        let outcomes = run_locally(
+            presignatures
+                .into_iter()
+                .map(|(i, presignature)| {
+                    let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                    let state =
+                        SignState::new_with_aad(id, &pp, h, &context, presignature, msg, aad);
+                    (i, state)
+                })
+                .collect(),
+        );
+        outcomes[&0].signature.clone()
+    };
+
+    // the same message in two contexts: neither signature stands in for the other
+    let on_main = sign(b"chain 1");
+    let on_test = sign(b"chain 2");
+    assert!(on_main.verify_with_aad(&context.pk, msg, b"chain 1"));
+    assert!(on_test.verify_with_aad(&context.pk, msg, b"chain 2"));
+    assert!(!on_main.verify_with_aad(&context.pk, msg, b"chain 2"));
+    assert!(!on_test.verify_with_aad(&context.pk, msg, b"chain 1"));
+    assert!(!on_main.verify(&context.pk, msg));
+
+    // an empty aad is still bound, and differs from signing without one
+    let unbound = sign(b"");
+    assert!(unbound.verify_with_aad(&context.pk, msg, b""));
+    assert!(!unbound.verify(&context.pk, msg));
+
+    // the length prefix keeps bytes from moving between aad and message
+    assert_ne!(
+        message_hash_with_aad(b"bc", b"a"),
+        message_hash_with_aad(b"c", b"ab")
+    );
+}
+
+#[test]
+pub fn test_sign_with_noncontiguous_signers() {
+    use crate::test_utils::simulation::{dkg_locally, presign_locally};
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let presignatures = presign_locally(&pp, &secret_keys, &keys);
+
+    // wire indices 0 and 2 are ids 1 and 3, outside the cached committee {1, 2}
+    assert_eq!(context.committee, vec![1, 2]);
+    let msg = b"signed by the first and the last";
+    let signers: [PartyIndex; 2] = [0, 2];
+    let outcomes = run_locally(
+        signers
+            .iter()
+            .map(|&i| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                let presignature = presignatures[&i].clone();
+                (i, SignState::new(id, &pp, h, &context, presignature, msg))
+            })
+            .collect(),
+    );
+    assert_eq!(outcomes.len(), 2);
+    for outcome in outcomes.values() {
+        assert!(outcome.signature.verify(&context.pk, msg));
+        assert_eq!(outcome.signature, outcomes[&0].signature);
+    }
+}
+
+#[test]
+pub fn test_disjoint_quorums_agree() {
+    use crate::test_utils::simulation::{dkg_locally, presign_locally};
+
+    let (pp, secret_keys) = simulate_pp(5, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let presign = || presign_locally(&pp, &secret_keys, &keys);
+    let presignatures = presign();
+
+    let msg = b"signed by two quorums";
+    let sign = |presignatures: &BTreeMap<PartyIndex, PreSignature>, signers: &[PartyIndex]| {
+        let outcomes = run_locally(
+            signers
+                .iter()
+                .map(|&i| {
+                    let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                    let presignature = presignatures[&i].clone();
+                    (i, SignState::new(id, &pp, h, &context, presignature, msg))
+                })
+                .collect(),
+        );
+        assert_eq!(outcomes.len(), signers.len());
+        SignatureECDSA::from(outcomes[&signers[0]].clone())
+    };
+
+    let first = sign(&presignatures, &[0, 1]);
+    let second = sign(&presignatures, &[2, 3]);
+    assert!(first.verify(&context.pk, msg));
+    assert!(second.verify(&context.pk, msg));
+    assert_eq!(first, second);
+    assert!(first.agrees_with(&second));
+    assert!(first.agrees_with(&SignatureECDSA {
+        r: first.r.clone(),
+        s: -first.s.clone(),
+    }));
+
+    // another presignature signs the same message validly, but differently
+    let other = sign(&presign(), &[3, 4]);
+    assert!(other.verify(&context.pk, msg));
+    assert!(!other.agrees_with(&first));
+}
+
+#[test]
+pub fn test_protocol_without_tokio() {
+    use crate::test_utils::simulation::run_full_protocol;
+
+    // a plain executor with no reactor is enough for every phase
+    let msg = b"no tokio here";
+    let outcomes = run_full_protocol(2, 3, msg);
+    assert_eq!(outcomes.len(), 3);
+    assert!(outcomes
+        .iter()
+        .all(|(signature, pk)| *pk == outcomes[0].1 && signature.verify(pk, msg)));
+}
+
+#[test]
+pub fn test_sign_with_approval() {
+    use crate::test_utils::simulation::{dkg_locally, presign_locally};
+    use futures::executor::block_on;
+    use futures::future::{join_all, try_join_all};
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let presignatures = presign_locally(&pp, &secret_keys, &keys);
+
+    let presignature = &presignatures[&0];
+    let announcement = presignature.announcement();
+    assert_eq!(announcement.R, presignature.r_point());
+    assert_eq!(announcement.r, presignature.r_scalar());
+    assert_eq!(&announcement.k_commitments, presignature.commitments());
+
+    // the shares stay with the party
+    let json = serde_json::to_string(&announcement).unwrap();
+    assert!(!json.contains("share"));
+    for share in [&presignature.k_share, &presignature.chi_share] {
+        assert!(!json.contains(&serde_json::to_string(share).unwrap()));
+    }
+
+    let msg = b"approved by policy";
+    let sign_all = |approve: bool| {
+        let mut simulation = Simulation::<SignMsg>::new();
+        block_on(join_all(presignatures.values().zip(1..=pp.n).map(
+            |(presignature, i)| {
+                let party = simulation.add_party();
+                sign_with_approval(
+                    party,
+                    i,
+                    &pp,
+                    h,
+                    &context,
+                    presignature.clone(),
+                    msg,
+                    |announcement| approve && announcement.R == presignature.R,
+                )
+            },
+        )))
+    };
+
+    assert!(sign_all(true).iter().all(|outcome| outcome
+        .as_ref()
+        .map_or(false, |outcome| outcome.signature.verify(&context.pk, msg))));
+    assert!(sign_all(false)
+        .iter()
+        .all(|outcome| matches!(outcome, Err(Error::PolicyRejected))));
+}
+
+#[test]
+pub fn test_presignature_validate() {
+    use crate::test_utils::simulation::{dkg_locally, presign_locally};
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let mut presignatures = presign_locally(&pp, &secret_keys, &keys);
+
+    let presignature = presignatures.remove(&0).unwrap();
+    let context = PreSignatureContext { h, my_id: 1 };
+    let validate = |presignature: &PreSignature| presignature.validate(&context, &pp, &keys[&0]);
+    assert_eq!(validate(&presignature), Ok(()));
+
+    let mut corrupted = presignature.clone();
+    corrupted.R = &corrupted.R + G::generator();
+    assert_eq!(
+        validate(&corrupted),
+        Err(PresignError::NonceCommitmentMismatch)
+    );
+
+    let mut corrupted = presignature.clone();
+    corrupted.k_share = &corrupted.k_share + Zq::from(1);
+    assert_eq!(
+        validate(&corrupted),
+        Err(PresignError::OwnMacMismatch { share: "k" })
+    );
+
+    let mut corrupted = presignature.clone();
+    let mac = corrupted.chi_macs.get_mut(&3).unwrap();
+    *mac = &*mac + h;
+    assert_eq!(
+        validate(&corrupted),
+        Err(PresignError::InconsistentMacs { share: "chi" })
+    );
+
+    let mut corrupted = presignature.clone();
+    corrupted.k_macs.remove(&2);
+    assert_eq!(
+        validate(&corrupted),
+        Err(PresignError::MissingParties { parties: vec![2] })
+    );
+
+    // a party disqualified while presigning is not expected to have MACs
+    corrupted.disqualified.insert(
+        PartyId::from_share_index(2).to_wire_index(),
+        DisqualReason::Mta,
+    );
+    assert_eq!(validate(&corrupted), Ok(()));
+}
+
+#[test]
+pub fn test_presignature_rerandomize() {
+    use crate::test_utils::simulation::{dkg_locally, presign_locally};
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let presignatures = presign_locally(&pp, &secret_keys, &keys);
+
+    // signing twice from one presignature gives the key away; it is done here only to
+    // compare the two re-randomized nonces
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+    let msg = b"unlinkable";
+    let sign_with = |tweak: &Zq| {
+        let outcomes = run_locally(
+            presignatures
+                .iter()
+                .map(|(&i, presignature)| {
+                    let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                    let mut presignature = presignature.clone();
+                    presignature.rerandomize(tweak).unwrap();
+                    let presig_context = PreSignatureContext { h, my_id: id };
+                    assert_eq!(
+                        presignature.validate(&presig_context, &pp, &keys[&i]),
+                        Ok(())
+                    );
+                    let state = SignState::new(id, &pp, h, &context, presignature, msg);
+                    (i, state)
+                })
+                .collect(),
+        );
+        assert_eq!(outcomes.len(), pp.n as usize);
+        let signature = outcomes[&0].signature.clone();
+        assert!(signature.verify(&keys[&0].tpk.pk, msg));
+        signature
+    };
+
+    let (first, second) = (sign_with(&Zq::random()), sign_with(&Zq::random()));
+    let base_r = presignatures[&0].r_scalar();
+    assert!(first.r != second.r && first.r != base_r && second.r != base_r);
+
+    let mut presignature = presignatures[&0].clone();
+    assert_eq!(
+        presignature.rerandomize(&Zq::zero()),
+        Err(PresignError::DegenerateNonce)
+    );
+    assert_eq!(presignature, presignatures[&0]);
+}
+
+#[test]
+pub fn test_sign_raw_bytes() {
+    use crate::test_utils::simulation::{dkg_locally, presign_locally};
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+    let context = SigningContext::from_dkg(&pp, &keys[&0]);
+
+    let messages: [&[u8]; 2] = [b"", b"nul\0in the\0middle\0"];
+    for msg in messages {
+        let presignatures = presign_locally(&pp, &secret_keys, &keys);
+        let signatures = run_locally(
+            presignatures
+                .into_iter()
+                .map(|(i, presignature)| {
+                    let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                    (i, SignState::new(id, &pp, h, &context, presignature, msg))
+                })
+                .collect(),
+        );
+
+        assert_eq!(signatures.len(), pp.n as usize);
+        for outcome in signatures.values() {
+            assert!(outcome.signature.verify(&context.pk, msg));
+        }
+    }
+
+    // the bytes are hashed as they are, so trailing NULs make a different message
+    assert_ne!(message_hash(b"msg"), message_hash(b"msg\0"));
+}
+
+#[test]
+pub fn test_qf_polynomial_eval_with_powers() {
+    let (pp, _) = simulate_pp(2, 2);
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    for degree in [0, 1, 2, 5, 8] {
+        let poly = QFPolynomial {
+            coeffs: (0..=degree)
+                .map(|_| {
+                    pp.cl
+                        .power_of_h(&rng.random_mpz(&pp.cl.encrypt_randomness_bound()))
+                })
+                .collect(),
+        };
+        let x = Zq::random();
+        let powers = QFPolynomial::powers(&x, poly.coeffs.len());
+
+        assert_eq!(
+            poly.eval_with_powers(&pp.cl, &powers),
+            poly.eval(&pp.cl, &x)
+        );
+    }
+}
+
+#[test]
+pub fn test_qf_polynomial_eval() {
+    let (pp, _) = simulate_pp(2, 2);
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let poly = QFPolynomial {
+        coeffs: (0..=32)
+            .map(|_| {
+                pp.cl
+                    .power_of_h(&rng.random_mpz(&pp.cl.encrypt_randomness_bound()))
+            })
+            .collect(),
+    };
+    // challenges in the NIZKs are 128 bits long
+    let x = Zq::from_bigint(&BigInt::sample(128));
+
+    let powers = QFPolynomial::powers(&x, poly.coeffs.len());
+    assert_eq!(
+        poly.eval(&pp.cl, &x),
+        poly.eval_with_powers(&pp.cl, &powers)
+    );
+}
+
+#[test]
+pub fn test_mta_restrict_to() {
+    let (pp, _) = simulate_pp(4, 2);
+    let h = G::base_point2();
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let dealings: Vec<PvssDealing> = (0..2)
+        .map(|_| PvssDealing::random(&pp, &mut rng, h).0)
+        .collect();
+    let pvss_result = JointPvssResult::new(&pp, dealings);
+
+    // disqualify party 2
+    let parties = BTreeSet::from([1, 3, 4]);
+    let restricted = pvss_result.restrict_to(&parties);
+    assert_eq!(
+        restricted
+            .curve_macs()
+            .keys()
+            .copied()
+            .collect::<BTreeSet<_>>(),
+        parties
+    );
+    assert_eq!(restricted.curve_mac(3), pvss_result.curve_mac(3));
+
+    let scalar = Zq::random();
+    let (dealing, masks) = MtaDealing::new(&pp, &restricted, &scalar, &mut rng, h);
+    let proof = MtaNizk::prove_with_aux(
+        &pp,
+        &restricted,
+        &dealing,
+        h,
+        &mut rng,
+        &scalar,
+        &masks,
+        &[],
+    )
+    .unwrap();
+    assert!(proof.verify_with_aux(
+        &pp,
+        &restricted,
+        &dealing,
+        h,
+        &(G::generator() * &scalar),
+        &[]
+    ));
+
+    // the two sides of the exchange restricted differently
+    assert_eq!(
+        proof.verify_detailed_with_aux(
+            &pp,
+            &pvss_result,
+            &dealing,
+            h,
+            &(G::generator() * &scalar),
+            &[]
+        ),
+        Err(NizkError::PartySetMismatch)
+    );
+    let (full_dealing, full_shares) = MtaDealing::new(&pp, &pvss_result, &scalar, &mut rng, h);
+    assert_eq!(
+        MtaNizk::prove_with_aux(
+            &pp,
+            &restricted,
+            &full_dealing,
+            h,
+            &mut rng,
+            &scalar,
+            &full_shares,
+            &[]
+        ),
+        Err(NizkError::PartySetMismatch)
+    );
+    assert_eq!(full_dealing.restrict_to(&parties).curve_macs.len(), 3);
+}
+
+#[test]
+pub fn test_presign_nonce_mismatch() {
+    use crate::test_utils::simulation::dkg_locally;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let keys = dkg_locally(&pp, &secret_keys);
+
+    // party 1 reveals a valid share of delta, but claims a different nonce
+    let presignatures = run_locally(
+        (1..=pp.n)
+            .map(|i| {
+                let wire = PartyId::from_share_index(i).to_wire_index();
+                let state = PresignState::new(
+                    i,
+                    &pp,
+                    h,
+                    &secret_keys[&i],
+                    &keys[&wire],
+                    PresignSession::default(),
+                );
+                let tamper: fn(PresignMsg) -> Option<PresignMsg> = match i {
+                    1 => |msg| match msg {
+                        PresignMsg::PreSignFinal(mut msg) => {
+                            msg.Gamma = msg.Gamma + G::generator();
+                            Some(PresignMsg::PreSignFinal(msg))
+                        }
+                        msg => Some(msg),
+                    },
+                    _ => Some,
+                };
+                (
+                    wire,
+                    Tampered {
+                        inner: state,
+                        tamper,
+                    },
+                )
+            })
+            .collect(),
+    );
+
+    // the others leave it out and presign without it
+    for receiver in [1, 2] {
+        let presignature = &presignatures[&receiver];
+        assert_eq!(
+            presignature.disqualified,
+            BTreeMap::from([(0, DisqualReason::NonceMismatch)])
+        );
+        assert_eq!(presignature.R, presignatures[&2].R);
+    }
+}
+
+#[test]
+pub fn test_party_eval_points() {
+    let mut points: Vec<Vec<u8>> = (1..=MAX_PARTIES as Id)
+        .map(party_eval_point)
+        .inspect(|point| assert!(!point.is_zero()))
+        .map(|point| point.to_bytes().to_vec())
+        .collect();
+    points.sort();
+    points.dedup();
+    assert_eq!(points.len(), MAX_PARTIES);
+}
+
+#[test]
+pub fn test_mta_outputs_combine_to_product() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let (dealing, _, k_poly, _) = PvssDealing::random(&pp, &mut rng, h);
+    let k_pvss = JointPvssResult::new(&pp, vec![dealing]);
+    let k = k_poly.coeffs[0].clone();
+    let gamma = Zq::random();
+
+    let (mta_dealing, _) = MtaDealing::new(&pp, &k_pvss, &gamma, &mut rng, h);
+
+    // any pair of recipients recovers k * gamma from their outputs alone
+    for pair in [[1, 2], [1, 3], [2, 3]] {
+        let outputs: BTreeMap<Id, Zq> = pair
+            .iter()
+            .map(|&id| {
+                let output = mta_dealing
+                    .shares_ciphertext
+                    .decrypt(&pp.cl, id, &secret_keys[&id]);
+                (id, output)
+            })
+            .collect();
+        assert_eq!(pp.interpolate(&outputs).unwrap(), &k * &gamma);
+    }
+}
+
+#[test]
+pub fn test_dual_mta() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let (dealing, _, k_poly, _) = PvssDealing::random(&pp, &mut rng, h);
+    let k_pvss = JointPvssResult::new(&pp, vec![dealing]);
+    let k_shares: BTreeMap<Id, Zq> = (1..=pp.n)
+        .map(|id| {
+            let share = k_pvss
+                .shares_ciphertext
+                .decrypt(&pp.cl, id, &secret_keys[&id]);
+            (id, share)
+        })
+        .collect();
+    let (gamma, x) = (Zq::random(), Zq::random());
+    let pubs = [G::generator() * &gamma, G::generator() * &x];
+
+    let [(gamma_dealing, gamma_masks), (x_dealing, x_masks)] =
+        MtaDealing::new_dual(&pp, &k_pvss, [&gamma, &x], &mut rng, h);
+
+    // each recipient decrypts its share of k times the scalar, plus its mask
+    for (dealing, scalar, masks) in [
+        (&gamma_dealing, &gamma, &gamma_masks),
+        (&x_dealing, &x, &x_masks),
+    ] {
+        let outputs: BTreeMap<Id, Zq> = (1..=pp.n)
+            .map(|id| {
+                let output = dealing
+                    .shares_ciphertext
+                    .decrypt(&pp.cl, id, &secret_keys[&id]);
+                assert_eq!(&output - &masks[&id], scalar * &k_shares[&id]);
+                (id, output)
+            })
+            .collect();
+        assert_eq!(
+            pp.interpolate(&outputs).unwrap(),
+            scalar * &k_poly.coeffs[0]
+        );
+    }
+
+    let dealings = [&gamma_dealing, &x_dealing];
+    let proof = DualMtaNizk::prove(
+        &pp,
+        &k_pvss,
+        dealings,
+        h,
+        &mut rng,
+        [&gamma, &x],
+        [&gamma_masks, &x_masks],
+        b"dual",
+    )
+    .unwrap();
+    assert!(proof.verify(&pp, &k_pvss, dealings, h, [&pubs[0], &pubs[1]], b"dual"));
+
+    // the dealings or the public scalars in the wrong order, or another context
+    assert_eq!(
+        proof.verify_detailed(
+            &pp,
+            &k_pvss,
+            [&x_dealing, &gamma_dealing],
+            h,
+            [&pubs[0], &pubs[1]],
+            b"dual"
+        ),
+        Err(NizkError::ChallengeMismatch)
+    );
+    assert!(!proof.verify(&pp, &k_pvss, dealings, h, [&pubs[1], &pubs[0]], b"dual"));
+    assert!(!proof.verify(&pp, &k_pvss, dealings, h, [&pubs[0], &pubs[1]], b"other"));
+
+    // nor does a proof for one dealing carry over to a pair with another one
+    let (other_dealing, _) = MtaDealing::new(&pp, &k_pvss, &x, &mut rng, h);
+    assert!(!proof.verify(
+        &pp,
+        &k_pvss,
+        [&gamma_dealing, &other_dealing],
+        h,
+        [&pubs[0], &pubs[1]],
+        b"dual"
+    ));
+}
+
+#[test]
+pub fn test_presign_delta_from_primitives() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    // every party deals both nonces
+    let k_dealings: Vec<_> = (1..=pp.n)
+        .map(|_| PvssDealing::random(&pp, &mut rng, h))
+        .collect();
+    let gamma_dealings: Vec<_> = (1..=pp.n)
+        .map(|_| PvssDealing::random(&pp, &mut rng, h))
+        .collect();
+    let k: Zq = k_dealings
+        .iter()
+        .map(|(_, _, poly, _)| poly.coeffs[0].clone())
+        .sum();
+    let gamma: Zq = gamma_dealings
+        .iter()
+        .map(|(_, _, poly, _)| poly.coeffs[0].clone())
+        .sum();
+
+    let k_pvss = JointPvssResult::new(&pp, k_dealings.into_iter().map(|d| d.0).collect());
+    let gamma_pvss = JointPvssResult::new(&pp, gamma_dealings.into_iter().map(|d| d.0).collect());
+
+    let decrypt = |pvss: &JointPvssResult, id: Id| {
+        pvss.shares_ciphertext
+            .decrypt(&pp.cl, id, &secret_keys[&id])
+    };
+
+    // each party multiplies k by its share of gamma
+    let mta_dealings: BTreeMap<Id, MtaDealing> = (1..=pp.n)
+        .map(|i| {
+            (
+                i,
+                MtaDealing::new(&pp, &k_pvss, &decrypt(&gamma_pvss, i), &mut rng, h).0,
+            )
+        })
+        .collect();
+
+    let lagrange_coeffs = pp.lagrange_coeffs((1..=pp.n).collect()).unwrap();
+    let delta_shares: BTreeMap<Id, Zq> = (1..=pp.n)
+        .map(|j| {
+            let delta_share = mta_dealings
+                .iter()
+                .map(|(i, dealing)| {
+                    &lagrange_coeffs[i]
+                        * dealing
+                            .shares_ciphertext
+                            .decrypt(&pp.cl, j, &secret_keys[&j])
+                })
+                .sum();
+            (j, delta_share)
+        })
+        .collect();
+
+    assert_eq!(pp.interpolate(&delta_shares).unwrap(), &k * &gamma);
+}
+
+#[test]
+pub fn test_dkg_transcript() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let pvss_msgs: BTreeMap<Id, PvssMsg> = (1..=pp.n)
+        .map(|i| (i, PvssMsg::random(&pp, &mut rng, h)))
+        .collect();
+    let keys = run_locally(
+        pvss_msgs
+            .iter()
+            .map(|(&i, msg)| {
+                let state =
+                    DkgState::with_pvss_msg(i, &pp, h, &secret_keys[&i], false, msg.clone())
+                        .with_transcript();
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+    let transcript = keys[&0].transcript.clone().unwrap();
+    assert_eq!(transcript.pk, keys[&0].tpk.pk);
+    assert!(keys
+        .values()
+        .all(|key| key.transcript.as_ref() == Some(&transcript)));
+
+    let dealings: BTreeMap<Id, PvssDealing> = pvss_msgs
+        .into_iter()
+        .map(|(i, msg)| (i, msg.dealing))
+        .collect();
+    assert!(transcript.verify(&dealings, &pp, h));
+
+    // a substituted dealing, or a key the dealings do not lead to
+    let dealer = transcript.qualified[0];
+    let mut substituted = dealings.clone();
+    substituted.insert(dealer, PvssDealing::random(&pp, &mut rng, h).0);
+    assert!(!transcript.verify(&substituted, &pp, h));
+
+    let wrong_pk = DkgTranscript {
+        pk: &transcript.pk + G::generator(),
+        ..transcript.clone()
+    };
+    assert!(!wrong_pk.verify(&dealings, &pp, h));
+}
+
+#[test]
+pub fn test_joint_pvss_accumulator() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = fresh_rng();
+
+    let mut shares_of_1 = Zq::zero();
+    let dealings: Vec<PvssDealing> = (0..3)
+        .map(|_| {
+            let (dealing, _, _, shares) = PvssDealing::random(&pp, &mut rng, h);
+            shares_of_1 = &shares_of_1 + &shares[&1];
+            dealing
+        })
+        .collect();
+    let batch = JointPvssResult::new(&pp, dealings.clone());
+
+    let mut acc = JointPvssAccumulator::new(&pp);
+    for (dealer, dealing) in (1..).zip(&dealings) {
+        acc.add_dealing(dealer, dealing).unwrap();
+    }
+    assert_eq!(
+        acc.add_dealing(2, &dealings[1]),
+        Err(AggregationError::DuplicateDealer { dealer: 2 })
+    );
+    assert_eq!(acc.finish().unwrap(), batch);
+
+    // in another order, the same sharing
+    let mut acc = JointPvssAccumulator::new(&pp);
+    for (dealer, dealing) in (1..).zip(&dealings).rev() {
+        acc.add_dealing(dealer, dealing).unwrap();
+    }
+    let reversed = acc.finish().unwrap();
+    assert_eq!(reversed.curve_polynomial, batch.curve_polynomial);
+    let decrypt =
+        |pvss: &JointPvssResult| pvss.shares_ciphertext.decrypt(&pp.cl, 1, &secret_keys[&1]);
+    assert_eq!(decrypt(&reversed), shares_of_1);
+    assert_eq!(decrypt(&batch), shares_of_1);
+
+    assert!(matches!(
+        JointPvssAccumulator::new(&pp).finish(),
+        Err(AggregationError::NoDealings)
+    ));
+}
+
+#[test]
+pub fn test_joint_pvss_homomorphic_sum() {
+    let (pp, secret_keys) = simulate_pp(4, 3);
+    let h = G::base_point2();
+    let mut rng = fresh_rng();
+
+    let dealings: Vec<PvssDealing> = (0..pp.n)
+        .map(|_| PvssDealing::random(&pp, &mut rng, h).0)
+        .collect();
+    let aggregate = JointPvssResult::new(&pp, dealings.clone());
+    assert!(verify_homomorphic_sum(
+        &dealings,
+        &aggregate,
+        &pp,
+        &secret_keys
+    ));
+
+    // the sum of other dealings is not
+    let other = JointPvssResult::new(&pp, dealings[1..].to_vec());
+    assert!(!verify_homomorphic_sum(
+        &dealings,
+        &other,
+        &pp,
+        &secret_keys
+    ));
+}
+
+#[test]
+pub fn test_keyless_combiner() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    // the dealers, each on its own
+    let mut secrets = BTreeMap::new();
+    let pvss_msgs: BTreeMap<Id, PvssMsg> = (1..=pp.n)
+        .map(|i| {
+            let (dealing, r, poly, shares) = PvssDealing::random(&pp, &mut rng, h);
+            let proof = PvssNizk::prove_with_aux(&pp, &dealing, &r, &shares, &mut rng, h, &[]);
+            secrets.insert(i, poly.coeffs[0].clone());
+            let msg = PvssMsg::new(dealing, proof).with_membership_proof(
+                &pp,
+                i,
+                &secret_keys[&i],
+                &mut rng,
+            );
+            (i, msg)
+        })
+        .collect();
+
+    // the combiner holds no secret key, the share holders only open their shares
+    let combined = combine_dealings(&pp, h, &pvss_msgs).unwrap();
+    let openings: BTreeMap<Id, OpenPowerMsg> = (1..=pp.n)
+        .map(|i| {
+            let (_, opening) =
+                open_share(&pp, h, i, &secret_keys[&i], &combined.pvss_result, &mut rng);
+            (i, opening)
+        })
+        .collect();
+    let tpk = combine_openings(&pp, h, &combined.pvss_result, &openings).unwrap();
+
+    let secret = combined
+        .qualified
+        .iter()
+        .fold(Zq::zero(), |acc, j| acc + &secrets[j]);
+    assert_eq!(tpk.pk, G::generator() * secret);
+
+    // the committee arrives at the same key from the same dealings
+    let keys = run_locally(
+        pvss_msgs
+            .iter()
+            .map(|(&i, msg)| {
+                let state =
+                    DkgState::with_pvss_msg(i, &pp, h, &secret_keys[&i], false, msg.clone());
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+    assert!(keys.values().all(|key| key.tpk == tpk));
+    assert!(keys.values().all(|key| key.qualified == combined.qualified));
+
+    // a dealing with a bad proof is passed over, and too few good ones is an error
+    let mut forged = pvss_msgs.clone();
+    let malformed = |i: Id, rng: &mut RandGen| {
+        PvssMsg::malformed(&pp, rng, h).with_membership_proof(&pp, i, &secret_keys[&i], rng)
+    };
+    forged.insert(1, malformed(1, &mut rng));
+    assert_eq!(
+        combine_dealings(&pp, h, &forged).unwrap().qualified,
+        vec![2, 3]
+    );
+    forged.insert(2, malformed(2, &mut rng));
+    assert!(matches!(
+        combine_dealings(&pp, h, &forged),
+        Err(StateError::InsufficientQualifiedDealings { got: 1, need: 2 })
+    ));
+}
+
+#[test]
+pub fn test_unauthorized_dealer() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = fresh_rng();
+    let outsider_sk = pp.cl.secret_key_gen(&mut rng);
+
+    // a valid dealing signed by someone outside the keyring, posing as party 1
+    let impostor = PvssMsg::random(&pp, &mut rng, h);
+    assert_eq!(
+        impostor.verify_membership(&pp, 1),
+        Err(NizkError::MissingMembershipProof)
+    );
+    let impostor = impostor.with_membership_proof(&pp, 1, &outsider_sk, &mut rng);
+    assert_eq!(
+        impostor.verify_membership(&pp, 1),
+        Err(NizkError::ChallengeMismatch)
+    );
+
+    // a member's signature holds for its own id only
+    let signed =
+        PvssMsg::random(&pp, &mut rng, h).with_membership_proof(&pp, 2, &secret_keys[&2], &mut rng);
+    assert!(signed.verify_membership(&pp, 2).is_ok());
+    assert!(signed.verify_membership(&pp, 3).is_err());
+    assert_eq!(
+        signed.verify_membership(&pp, 4),
+        Err(NizkError::NotInKeyring { id: 4 })
+    );
+
+    let unsigned = PvssMsg::random(&pp, &mut rng, h);
+    let mut dealings = BTreeMap::from([
+        (1, impostor.clone()),
+        (2, signed),
+        (
+            3,
+            unsigned
+                .clone()
+                .with_membership_proof(&pp, 3, &secret_keys[&3], &mut rng),
+        ),
+    ]);
+    let combined = combine_dealings(&pp, h, &dealings).unwrap();
+    assert_eq!(combined.qualified, vec![2, 3]);
+    assert_eq!(
+        combined.rejected,
+        vec![(1, DealerDisqualReason::Membership)]
+    );
+
+    // the combiner takes no dealing without a membership proof
+    dealings.insert(3, unsigned);
+    assert!(matches!(
+        combine_dealings(&pp, h, &dealings),
+        Err(StateError::InsufficientQualifiedDealings { got: 1, need: 2 })
+    ));
+
+    // the members pass over the impostor's dealing and settle on the others
+    let run = run_locally_detailed(
+        (1..=pp.n)
+            .map(|i| {
+                let state = if i == 1 {
+                    DkgState::with_pvss_msg(i, &pp, h, &secret_keys[&i], false, impostor.clone())
+                } else {
+                    DkgState::new(i, &pp, h, &secret_keys[&i], false)
+                        .with_membership_proofs()
+                        .unwrap()
+                };
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+    let members: Vec<&DkgOutput> = [2, 3]
+        .iter()
+        .map(|&i| &run.outputs[&PartyId::from_share_index(i).to_wire_index()])
+        .collect();
+    assert!(members.iter().all(|key| key.qualified == vec![2, 3]));
+    assert_eq!(members[0].tpk, members[1].tpk);
+}
+
+#[test]
+pub fn test_dealer_disqual_reasons() {
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let h = G::base_point2();
+    let mut rng = fresh_rng();
+    let honest = PvssMsg::random(&pp, &mut rng, h);
+    let check = |j: Id, msg: &PvssMsg| check_dealing(&pp, h, j, msg, false, true);
+    assert_eq!(check(1, &honest), Ok(()));
+    assert_eq!(check(5, &honest), Err(DealerDisqualReason::UnknownDealer));
+
+    let mut short = honest.clone();
+    short.dealing.curve_polynomial.coeffs.pop();
+    assert_eq!(
+        check(1, &short),
+        Err(DealerDisqualReason::WrongDegree {
+            got: 1,
+            expected: 2
+        })
+    );
+    let mut identity = honest.clone();
+    identity.dealing.curve_polynomial.coeffs[1] = G::zero();
+    assert_eq!(
+        check(1, &identity),
+        Err(DealerDisqualReason::IdentityCommitment)
+    );
+    let mut missing = honest.clone();
+    missing.dealing.shares_ciphertext.encryption.remove(&3);
+    assert_eq!(
+        check(1, &missing),
+        Err(DealerDisqualReason::MalformedCiphertexts)
+    );
+
+    let malformed = PvssMsg::malformed(&pp, &mut rng, h);
+    assert_eq!(
+        check(1, &malformed),
+        Err(DealerDisqualReason::ChallengeMismatch)
+    );
+    let mut oversized = honest.clone();
+    oversized.proof.z1 = oversized.proof.z1.clone() * Mpz::from_bytes(&[0xff; 64]);
+    assert_eq!(
+        check(1, &oversized),
+        Err(DealerDisqualReason::UndecodableProof)
+    );
+    assert_eq!(
+        check_dealing(&pp, h, 1, &honest, true, true),
+        Err(DealerDisqualReason::Membership)
+    );
+
+    // the combiner reports the dealings it passed over
+    let mut sign =
+        |i: Id, msg: PvssMsg| msg.with_membership_proof(&pp, i, &secret_keys[&i], &mut rng);
+    let dealings = BTreeMap::from([
+        (1, sign(1, malformed)),
+        (2, short),
+        (3, sign(3, honest)),
+        (4, sign(4, PvssMsg::random(&pp, &mut fresh_rng(), h))),
+    ]);
+    let combined = combine_dealings(&pp, h, &dealings).unwrap();
+    assert_eq!(combined.qualified, vec![3, 4]);
+    assert_eq!(
+        combined.rejected,
+        vec![
+            (1, DealerDisqualReason::ChallengeMismatch),
+            (
+                2,
+                DealerDisqualReason::WrongDegree {
+                    got: 1,
+                    expected: 2
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+pub fn test_dealer_disqual_reason_from_proof() {
+    for (err, reason) in [
+        (
+            NizkError::DegreeMismatch {
+                got: 1,
+                expected: 2,
+            },
+            DealerDisqualReason::WrongDegree {
+                got: 1,
+                expected: 2,
+            },
+        ),
+        (
+            NizkError::PartySetMismatch,
+            DealerDisqualReason::WrongParties,
+        ),
+        (
+            NizkError::DiscriminantMismatch,
+            DealerDisqualReason::MalformedCiphertexts,
+        ),
+        (
+            NizkError::ChallengeMismatch,
+            DealerDisqualReason::ChallengeMismatch,
+        ),
+        (
+            NizkError::DeserializationError,
+            DealerDisqualReason::UndecodableProof,
+        ),
+        (
+            NizkError::MembershipMismatch,
+            DealerDisqualReason::MissingShare,
+        ),
+        (NizkError::ShareMismatch, DealerDisqualReason::ShareMismatch),
+        (
+            NizkError::NotInKeyring { id: 5 },
+            DealerDisqualReason::UnknownDealer,
+        ),
+        (
+            NizkError::MissingMembershipProof,
+            DealerDisqualReason::Membership,
+        ),
+    ] {
+        let message = err.to_string();
+        assert_eq!(DealerDisqualReason::from_proof(err), reason, "{message}");
+    }
+}
+
+#[test]
+pub fn test_light_dealing() {
+    let (pp, secret_keys) = simulate_pp(5, 3);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let (dealing, r, _, shares) = PvssDealing::random(&pp, &mut rng, h);
+    let proof = PvssNizk::prove_with_aux(&pp, &dealing, &r, &shares, &mut rng, h, &[]);
+    let pvss_msg = PvssMsg::new(dealing, proof);
+
+    // every entry is under the root, and under its own id only
+    let ciphertext = &pvss_msg.dealing.shares_ciphertext;
+    let root = ciphertext.merkle_root();
+    for (&id, enc) in &ciphertext.encryption {
+        let path = ciphertext.membership_proof(id).unwrap();
+        assert!(CLMultiRecvCiphertext::verify_membership(
+            &root, id, enc, &path
+        ));
+        assert!(!CLMultiRecvCiphertext::verify_membership(
+            &root,
+            id % pp.n + 1,
+            enc,
+            &path
+        ));
+    }
+    assert!(ciphertext.membership_proof(pp.n + 1).is_none());
+
+    // a recipient gets its share from the header and its leaf alone
+    let (header, leaves) = pvss_msg.split();
+    for id in 1..=pp.n {
+        let share = header.open(&pp, h, &leaves[&id], &secret_keys[&id]);
+        assert_eq!(share.unwrap(), shares[&id]);
+    }
+
+    // a leaf from another dealing does not open under this header
+    let (other, _, _, _) = PvssDealing::random(&pp, &mut rng, h);
+    let forged = PvssLeaf {
+        ciphertext: other.shares_ciphertext.encryption[&1].clone(),
+        ..leaves[&1].clone()
+    };
+    assert!(matches!(
+        header.open(&pp, h, &forged, &secret_keys[&1]),
+        Err(NizkError::MembershipMismatch)
+    ));
+
+    let keys = run_locally(
+        (1..=pp.n)
+            .map(|i| {
+                let state = DkgState::new(i, &pp, h, &secret_keys[&i], false)
+                    .with_light_dealing(b"light dealing test")
+                    .unwrap();
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+    assert_eq!(keys.len(), pp.n as usize);
+    assert!(keys.values().all(|key| key.tpk == keys[&0].tpk));
+    for (&i, key) in &keys {
+        let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+        assert!(key.verify_share(id, &key.share));
+    }
+}
+
+#[test]
+pub fn test_light_dealing_cheating_some() {
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let h = G::base_point2();
+    let mut rng = fresh_rng();
+
+    // the cheater encrypts garbage for the victim alone, under a header that commits to
+    // it, so only the victim's leaf fails to open to a share matching its MAC
+    let (cheater, victim): (Id, Id) = (1, 2);
+    let mut cheating = PvssMsg::random(&pp, &mut rng, h);
+    let (other, _, _, _) = PvssDealing::random(&pp, &mut rng, h);
+    cheating
+        .dealing
+        .shares_ciphertext
+        .encryption
+        .insert(victim, other.shares_ciphertext.encryption[&victim].clone());
+    let run = run_locally_detailed(
+        (1..=pp.n)
+            .map(|i| {
+                let state = if i == cheater {
+                    DkgState::with_pvss_msg(i, &pp, h, &secret_keys[&i], false, cheating.clone())
+                } else {
+                    DkgState::new(i, &pp, h, &secret_keys[&i], false)
+                };
+                let state = state.with_light_dealing(b"split test").unwrap();
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+
+    // as in compact mode, nobody keeps a key the victim does not hold
+    assert!(run.outputs.is_empty());
+    assert_eq!(
+        run.aborted.keys().copied().collect::<Vec<_>>(),
+        vec![PartyId::from_share_index(victim).to_wire_index()]
+    );
+    assert_eq!(
+        DkgState::new(1, &pp, h, &secret_keys[&1], false)
+            .with_light_dealing(b"split test")
+            .unwrap()
+            .with_membership_proofs()
+            .err(),
+        Some(DkgModeError::Incompatible {
+            mode: "membership proofs",
+            other: "light dealing"
+        })
+    );
+}
+
+#[test]
+pub fn test_masked_dealing() {
+    use crate::test_utils::simulation::{dkg_locally, dkg_locally_with, presign_locally};
+
+    let (pp, secret_keys) = simulate_pp(5, 3);
+    let pp = pp.with_compact_pvss();
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    // each recipient unmasks its share into the dealing it would get in full mode
+    let (dealing, shares) = MaskedPvssDealing::random(&pp, &mut rng, h);
+    assert_eq!(dealing.validate(&pp), Ok(()));
+    for id in 1..=pp.n {
+        let opened = dealing.open(&pp, h, id, &secret_keys[&id]).unwrap();
+        assert_eq!(opened.curve_polynomial, dealing.curve_polynomial);
+        let share = opened
+            .shares_ciphertext
+            .decrypt(&pp.cl, id, &secret_keys[&id]);
+        assert_eq!(share, shares[&id]);
+    }
+    assert!(matches!(
+        dealing.open(&pp, h, 1, &secret_keys[&2]),
+        Err(NizkError::ShareMismatch)
+    ));
+    let mut tampered = dealing.clone();
+    let masked = &dealing.masked_shares[&1] + Zq::from(1u64);
+    tampered.masked_shares.insert(1, masked);
+    assert!(matches!(
+        tampered.open(&pp, h, 1, &secret_keys[&1]),
+        Err(NizkError::ShareMismatch)
+    ));
+
+    // keys from either mode hold shares of the same kind, and sign alike
+    for compact in [false, true] {
+        let keys = if compact {
+            dkg_locally_with(&pp, &secret_keys, |state| {
+                state.with_compact_dealing(b"compact dealing test").unwrap()
+            })
+        } else {
+            dkg_locally(&pp, &secret_keys)
+        };
+        assert_eq!(keys.len(), pp.n as usize);
+        assert!(keys.values().all(|key| key.tpk == keys[&0].tpk));
+        let shares: BTreeMap<Id, Zq> = keys
+            .iter()
+            .map(|(&i, key)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                assert!(key.verify_share(id, &key.share));
+                (id, key.share.clone())
+            })
+            .collect();
+        assert_eq!(
+            G::generator() * pp.interpolate(&shares).unwrap(),
+            keys[&0].tpk.pk
+        );
+
+        let context = SigningContext::from_dkg(&pp, &keys[&0]);
+        let presignatures = presign_locally(&pp, &secret_keys, &keys);
+        let msg = b"signed with a key from compact dealing";
+        let outcomes = run_locally(
+            presignatures
+                .into_iter()
+                .map(|(i, presignature)| {
+                    let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                    (i, SignState::new(id, &pp, h, &context, presignature, msg))
+                })
+                .collect(),
+        );
+        assert_eq!(outcomes.len(), pp.n as usize);
+        assert!(outcomes
+            .values()
+            .all(|outcome| outcome.signature.verify(&context.pk, msg)));
+    }
+}
+
+#[test]
+pub fn test_masked_dealing_size() {
+    let (pp, _) = simulate_pp(32, 17);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let pvss_msg = PvssMsg::random(&pp, &mut rng, h);
+    let (masked, _) = MaskedPvssDealing::random(&pp, &mut rng, h);
+    let full = crate::codec::encode(&DkgMsg::Pvss(pvss_msg.clone()));
+    let compact = crate::codec::encode(&DkgMsg::MaskedPvss(masked.clone()));
+
+    // each encrypted share shrinks to a scalar, no longer than -1 encodes, and the PVSS
+    // proof is dropped on top
+    let forms: usize = pvss_msg
+        .dealing
+        .shares_ciphertext
+        .encryption
+        .values()
+        .map(|form| crate::codec::encode(form).len())
+        .sum();
+    let scalars: usize = masked
+        .masked_shares
+        .values()
+        .map(|share| crate::codec::encode(share).len())
+        .sum();
+    let scalar = crate::codec::encode(&-Zq::from(1u64)).len();
+    assert_eq!(masked.masked_shares.len(), pp.n as usize);
+    assert!(scalars <= pp.n as usize * scalar);
+    assert!(scalars < forms);
+    assert!(compact.len() + forms - scalars < full.len());
+}
+
+#[test]
+pub fn test_masked_dealing_negotiation() {
+    use crate::version::{VersionRange, Versioned, COMPACT_PVSS_VERSION};
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let pp = pp.with_compact_pvss();
+    let h = G::base_point2();
+    let before = COMPACT_PVSS_VERSION - 1;
+    let both = VersionRange {
+        min: before,
+        max: COMPACT_PVSS_VERSION,
+    };
+
+    // the transcript is left out in compact mode, which tells the modes apart
+    let run_with = |ranges: [VersionRange; 3], confirmed: bool| {
+        run_locally(
+            (1..=pp.n)
+                .map(|i| {
+                    let wire = PartyId::from_share_index(i).to_wire_index();
+                    let state = DkgState::new(i, &pp, h, &secret_keys[&i], false).with_transcript();
+                    let state = if confirmed {
+                        state.with_key_confirmation(b"negotiation test")
+                    } else {
+                        state
+                    };
+                    (
+                        wire,
+                        Versioned::negotiate(state, pp.n, ranges[wire as usize]),
+                    )
+                })
+                .collect(),
+        )
+    };
+    let run = |ranges| run_with(ranges, true);
+
+    let compact = run([both; 3]);
+    assert_eq!(compact.len(), pp.n as usize);
+    for (version, key) in compact.values() {
+        assert_eq!(*version, COMPACT_PVSS_VERSION);
+        assert!(key.transcript.is_none());
+        assert_eq!(key.tpk, compact[&0].1.tpk);
+    }
+
+    // a party without the compact mode keeps everyone to full dealings
+    let full = run([both, both, VersionRange::single(before)]);
+    assert_eq!(full.len(), pp.n as usize);
+    for (version, key) in full.values() {
+        assert_eq!(*version, before);
+        assert!(key.transcript.is_some());
+        assert_eq!(key.tpk, full[&0].1.tpk);
+    }
+
+    // nor is the compact mode taken up by a DKG that does not confirm its key
+    let unconfirmed = run_with([both; 3], false);
+    assert_eq!(unconfirmed.len(), pp.n as usize);
+    for (version, key) in unconfirmed.values() {
+        assert_eq!(*version, COMPACT_PVSS_VERSION);
+        assert!(key.transcript.is_some());
+    }
+}
+
+#[test]
+pub fn test_masked_dealing_cheating_some() {
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let h = G::base_point2();
+    assert_eq!(
+        DkgState::new(1, &pp, h, &secret_keys[&1], false)
+            .with_compact_dealing(b"split test")
+            .err(),
+        Some(DkgModeError::CompactDisabled)
+    );
+    let pp = pp.with_compact_pvss();
+    assert_eq!(
+        DkgState::new(1, &pp, h, &secret_keys[&1], false)
+            .with_membership_proofs()
+            .unwrap()
+            .with_compact_dealing(b"split test")
+            .err(),
+        Some(DkgModeError::Incompatible {
+            mode: "compact dealing",
+            other: "membership proofs"
+        })
+    );
+
+    // the cheater's share for the victim does not match its MAC, everyone else's does,
+    // so the victim alone passes over the cheater's dealing
+    let (cheater, victim): (Id, Id) = (1, 2);
+    let run = run_locally_detailed(
+        (1..=pp.n)
+            .map(|i| {
+                let mut state = DkgState::new(i, &pp, h, &secret_keys[&i], false)
+                    .with_compact_dealing(b"split test")
+                    .unwrap();
+                if i == cheater {
+                    for Outgoing { msg, .. } in &mut state.outbox {
+                        if let DkgMsg::MaskedPvss(dealing) = msg {
+                            let masked = &dealing.masked_shares[&victim] + Zq::from(1u64);
+                            dealing.masked_shares.insert(victim, masked);
+                        }
+                    }
+                }
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+
+    // the victim cannot open the others' public shares; the others, who would have kept
+    // a key the victim does not hold, wait for its key confirmation instead
+    assert!(run.outputs.is_empty());
+    assert_eq!(
+        run.aborted.keys().copied().collect::<Vec<_>>(),
+        vec![PartyId::from_share_index(victim).to_wire_index()]
+    );
+}
+
+#[test]
+pub fn test_pub_params_fingerprint() {
+    let seed = Mpz::from(&Zq::random());
+    let (pp, _) = simulate_pp_seeded(3, 2, &seed);
+    let fingerprint = pp.fingerprint();
+    assert_eq!(simulate_pp_seeded(3, 2, &seed).0.fingerprint(), fingerprint);
+
+    let other_seed = Mpz::from(&Zq::random());
+    let (other, _) = simulate_pp_seeded(3, 2, &other_seed);
+    assert_ne!(other.cl.discriminant(), pp.cl.discriminant());
+    assert_ne!(other.fingerprint(), fingerprint);
+
+    let (mut drifted, _) = simulate_pp_seeded(3, 2, &seed);
+    drifted.t = 3;
+    assert_ne!(drifted.fingerprint(), fingerprint);
+
+    let (mut drifted, _) = simulate_pp_seeded(3, 2, &seed);
+    drifted.n = 4;
+    assert_ne!(drifted.fingerprint(), fingerprint);
+
+    // the same keys, assigned to different parties
+    let (mut drifted, _) = simulate_pp_seeded(3, 2, &seed);
+    let mut keys: BTreeMap<Id, PublicKey> = drifted
+        .cl_keyring
+        .iter()
+        .map(|(&id, pk)| (id, pk.clone()))
+        .collect();
+    let first = keys.insert(1, keys[&2].clone()).unwrap();
+    keys.insert(2, first);
+    drifted.cl_keyring = CLKeyRing::from(keys);
+    assert_ne!(drifted.fingerprint(), fingerprint);
+
+    // a party dealing on another curve generator
+    assert_eq!(pp.curve_generator, *G::base_point2());
+    let (drifted, _) = simulate_pp_seeded(3, 2, &seed);
+    let drifted = drifted.with_curve_generator(G::generator() * Zq::random());
+    assert_ne!(drifted.fingerprint(), fingerprint);
+}
+
+#[test]
+pub fn test_protocol_limits() {
+    let limits = ProtocolLimits {
+        max_n: 4,
+        max_t: 3,
+        max_message_bytes: 1 << 20,
+    };
+    assert_eq!(limits.check(4, 3), Ok(()));
+    assert_eq!(
+        limits.check(5, 3),
+        Err(LimitError::TooManyParties { n: 5, max: 4 })
+    );
+    assert_eq!(
+        limits.check(4, 4),
+        Err(LimitError::ThresholdTooLarge { t: 4, max: 3 })
+    );
+    assert_eq!(
+        limits.check(2, 3),
+        Err(LimitError::InvalidThreshold { t: 3, n: 2 })
+    );
+    assert_eq!(
+        limits.check(3, 0),
+        Err(LimitError::InvalidThreshold { t: 0, n: 3 })
+    );
+
+    // the defaults hold before any key is generated
+    let max = ProtocolLimits::default().max_n;
+    assert_eq!(
+        PubParams::with_level(SecurityLevel::Toy, max + 1, 2, &mut fresh_rng()).err(),
+        Some(LimitError::TooManyParties { n: max + 1, max })
+    );
+
+    // nor can a class group over another q hold the shares of a curve scalar
+    let mut rng = fresh_rng();
+    let foreign = CL_HSMqk::with_qnbits_rand_gen(50, 1, 150, &mut rng, &Mpz::from(0i64), false);
+    assert_eq!(
+        PubParams::with_cl(foreign, 3, 2, &mut rng).err(),
+        Some(LimitError::ForeignMessageSpace)
+    );
+
+    let (pp, _) = simulate_pp(4, 3);
+    assert_eq!(pp.limits, ProtocolLimits::default());
+    assert_eq!(pp.with_limits(limits).unwrap().limits, limits);
+    let (pp, _) = simulate_pp(5, 3);
+    assert_eq!(
+        pp.with_limits(limits).err(),
+        Some(LimitError::TooManyParties { n: 5, max: 4 })
+    );
+}
+
+#[test]
+pub fn test_ciphertext_validate() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let pvss_msg = PvssMsg::random(&pp, &mut rng, h);
+    let ciphertext = &pvss_msg.dealing.shares_ciphertext;
+    assert_eq!(ciphertext.validate(pp.n), Ok(()));
+
+    let mut extra = ciphertext.clone();
+    extra
+        .encryption
+        .insert(pp.n + 1, ciphertext.encryption[&1].clone());
+    assert_eq!(
+        extra.validate(pp.n),
+        Err(CiphertextError::UnknownRecipient { id: pp.n + 1 })
+    );
+    let mut extra = ciphertext.clone();
+    extra
+        .encryption
+        .insert(0, ciphertext.encryption[&1].clone());
+    assert_eq!(
+        extra.validate(pp.n),
+        Err(CiphertextError::UnknownRecipient { id: 0 })
+    );
+
+    let mut missing = ciphertext.clone();
+    missing.encryption.remove(&3);
+    assert_eq!(
+        missing.validate(pp.n),
+        Err(CiphertextError::MissingRecipient { id: 3 })
+    );
+
+    // even without checking the proofs, the parties leave out a dealing that is short
+    // of a share
+    let mut short = pvss_msg.clone();
+    short.dealing.shares_ciphertext = missing;
+    let run = run_locally_detailed(
+        (1..=pp.n)
+            .map(|i| {
+                let pvss_msg = match i {
+                    1 => short.clone(),
+                    _ => PvssMsg::random(&pp, &mut rng, h),
+                };
+                let state = DkgState::with_pvss_msg(i, &pp, h, &secret_keys[&i], true, pvss_msg);
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect(),
+    );
+    for i in [1, 2] {
+        assert_eq!(run.outputs[&i].qualified, vec![2, 3]);
+    }
+}
+
+#[test]
+pub fn test_public_share_recovery() {
+    use crate::test_utils::simulation::dkg_locally;
+
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let mut keys = dkg_locally(&pp, &secret_keys);
+    let mut key = keys.remove(&0).unwrap();
+
+    assert!(key.verify_share(1, &key.share));
+    assert!(!key.verify_share(1, &(&key.share + Zq::from(1))));
+
+    let pub_shares = key.tpk.pub_shares.clone();
+    let parties = [1, 3, 4];
+    assert_eq!(
+        recover_public_share(&parties, &pub_shares, 2),
+        pub_shares[&2]
+    );
+
+    key.tpk.pub_shares.remove(&3);
+    assert_eq!(key.public_share_of(3), Some(pub_shares[&3].clone()));
+    assert!(key.verify_share(1, &key.share));
+}
+
+#[test]
+pub fn test_duplicate_sender_rejected() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let mut receiver = DkgState::new(1, &pp, h, &secret_keys[&1], false);
+    let msg = DkgState::new(2, &pp, h, &secret_keys[&2], false)
+        .wants_to_send()
+        .remove(0)
+        .msg;
+    let from = PartyId::from_share_index(2).to_wire_index();
+
+    receiver.handle_message(from, msg.clone()).unwrap();
+    assert!(matches!(
+        receiver.handle_message(from, msg),
+        Err(StateError::DuplicateSender(sender)) if sender == from
+    ));
+}
+
+#[test]
+pub fn test_mta_dealing_parallel_matches_sequential() {
+    let (pp, _) = simulate_pp(5, 3);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let dealings = (0..2)
+        .map(|_| PvssDealing::random(&pp, &mut rng, h).0)
+        .collect();
+    let pvss = JointPvssResult::new(&pp, dealings);
+    let scalar = Zq::random();
+    let (dealing, masks) = MtaDealing::new(&pp, &pvss, &scalar, &mut rng, h);
+
+    // recipient by recipient, as MtaDealing::new used to
+    let encryption: BTreeMap<Id, QFI> = pvss
+        .shares_ciphertext
+        .encryption
+        .iter()
+        .map(|(&id, E)| {
+            let res = E
+                .exp(&pp.cl, &Mpz::from(&scalar))
+                .compose(&pp.cl, &pp.cl.power_of_f(&Mpz::from(&masks[&id])));
+            (id, res)
+        })
+        .collect();
+    let curve_macs: BTreeMap<Id, G> = pvss
+        .curve_macs()
+        .iter()
+        .map(|(&id, mac)| (id, &scalar * mac + h * &masks[&id]))
+        .collect();
+
+    assert_eq!(dealing.shares_ciphertext.encryption, encryption);
+    assert_eq!(dealing.curve_macs, curve_macs);
+    assert_eq!(
+        MtaDealing::with_masks(&pp, &pvss, &scalar, &masks, h),
+        dealing
+    );
+}
+
+#[test]
+pub fn test_party_id_wire_index_zero_decrypts() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let (dealing, _, poly, _) = PvssDealing::random(&pp, &mut rng, h);
+
+    let party = PartyId::from_wire_index(0).unwrap();
+    assert_eq!(party.to_share_index(), 1);
+    assert_eq!(party.to_wire_index(), 0);
+
+    let share = dealing.shares_ciphertext.decrypt(
+        &pp.cl,
+        party.to_share_index(),
+        &secret_keys[&party.to_share_index()],
+    );
+    assert_eq!(share, poly.eval(&party.eval_point()));
+
+    assert!(PartyId::from_wire_index(MAX_PARTIES as PartyIndex).is_none());
+}
+
+#[test]
+pub fn test_key_confirm_digest_vector() {
+    // ids are absorbed as two big-endian bytes each
+    let digest = KeyConfirmMsg::digest(&G::generator().to_point(), &[1, 2, 300], b"session");
+    let expected: [u8; 32] = [
+        0x74, 0xa7, 0x2a, 0x0e, 0x03, 0x77, 0x7d, 0x7b, 0x06, 0xd0, 0x89, 0x58, 0x40, 0x3b, 0xc1,
+        0x18, 0x34, 0x85, 0x3f, 0x77, 0x4f, 0x8c, 0x98, 0x96, 0x87, 0x32, 0x26, 0xd9, 0x17, 0xbb,
+        0xa2, 0x94,
+    ];
+    assert_eq!(digest, expected);
+}
+
+#[test]
+pub fn test_challenge_binds_threshold() {
+    let discriminant = Mpz::from(-23i64);
+    let expected_2_of_3: [u8; 32] = [
+        0x72, 0x0d, 0x9e, 0xd0, 0x52, 0xf5, 0x0f, 0x6e, 0x02, 0x64, 0xbd, 0x7a, 0x6f, 0xe0, 0x4b,
+        0xfd, 0xd8, 0x20, 0x84, 0x54, 0x9d, 0x30, 0x70, 0x0c, 0xa3, 0xa7, 0x3f, 0x63, 0x32, 0x67,
+        0x66, 0x46,
+    ];
+    let expected_3_of_3: [u8; 32] = [
+        0xe5, 0xf3, 0x04, 0xe8, 0x3a, 0x1d, 0x63, 0x36, 0x4d, 0x0f, 0x46, 0x50, 0xbd, 0x69, 0x97,
+        0x26, 0x4b, 0xb5, 0x7f, 0xde, 0xcb, 0xa9, 0x29, 0xa1, 0xcb, 0xec, 0x84, 0x98, 0x5a, 0xc6,
+        0x87, 0xaf,
+    ];
+    assert_eq!(instance_digest(&discriminant, 2, 3), expected_2_of_3);
+    assert_eq!(instance_digest(&discriminant, 3, 3), expected_3_of_3);
+
+    // a proof made for a 2-of-3 committee, checked with the same keys at 3-of-3
+    let (pp, _) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let (dealing, r, _, shares) = PvssDealing::random(&pp, &mut rng, h);
+    let proof = PvssNizk::prove_with_aux(&pp, &dealing, &r, &shares, &mut rng, h, &[]);
+    assert!(proof.verify_with_aux(&dealing, &pp, h, &[]));
+
+    let pp_3_of_3 = PubParams {
+        cl: pp.cl.clone(),
+        t: 3,
+        n: pp.n,
+        cl_keyring: pp.cl_keyring.clone(),
+        curve_generator: pp.curve_generator.clone(),
+        limits: pp.limits,
+        compact_pvss: pp.compact_pvss,
+        qualification: pp.qualification,
+    };
+    assert!(!proof.verify_with_aux(&dealing, &pp_3_of_3, h, &[]));
+}
+
+#[test]
+pub fn test_transcript_hasher_separates_fields() {
+    // a field boundary that moved, as between the bytes of a key and of the next id
+    let split_early: [&[u8]; 2] = [&[0x01, 0x02], &[0x03, 0x00, 0x02]];
+    let split_late: [&[u8]; 2] = [&[0x01, 0x02, 0x03], &[0x00, 0x02]];
+    let raw = |fields: [&[u8]; 2]| {
+        fields
+            .iter()
+            .fold(Sha256::new(), |hasher, field| hasher.chain_update(field))
+            .finalize()
+    };
+    assert_eq!(raw(split_early), raw(split_late));
+
+    let framed = |labels: [&str; 2], fields: [&[u8]; 2]| {
+        let mut hasher = TranscriptHasher::new("test");
+        for (label, field) in labels.iter().zip(fields) {
+            hasher.append(label, field);
+        }
+        hasher.challenge()
+    };
+    let labels = ["cl_pk", "id"];
+    assert_ne!(framed(labels, split_early), framed(labels, split_late));
+
+    // the same bytes under other labels, or in another domain
+    assert_ne!(
+        framed(labels, split_early),
+        framed(["id", "cl_pk"], split_early)
+    );
+    let mut other_domain = TranscriptHasher::new("other");
+    other_domain
+        .append("cl_pk", split_early[0])
+        .append("id", split_early[1]);
+    assert_ne!(other_domain.challenge(), framed(labels, split_early));
+}
+
+#[test]
+pub fn test_pvss_beyond_255_parties() {
+    let (pp, secret_keys) = simulate_pp(300, 2);
+    let h = G::base_point2();
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let (dealing, r, poly, shares) = PvssDealing::random(&pp, &mut rng, h);
+    let proof = PvssNizk::prove_with_aux(&pp, &dealing, &r, &shares, &mut rng, h, &[]);
+    assert!(proof.verify_with_aux(&dealing, &pp, h, &[]));
+
+    let pvss_result = JointPvssResult::new(&pp, vec![dealing]);
+    for id in [1, 255, 256, 300] {
+        let share = pvss_result
+            .shares_ciphertext
+            .decrypt(&pp.cl, id, &secret_keys[&id]);
+        assert_eq!(share, poly.eval(&party_eval_point(id)));
+        assert_eq!(pvss_result.curve_mac(id), h * &share);
+    }
+}
+
+#[test]
+pub fn test_mta_awc_msg_verify() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let joint = |rng: &mut RandGen| {
+        let dealings = (0..2).map(|_| PvssDealing::random(&pp, rng, h).0).collect();
+        JointPvssResult::new(&pp, dealings)
+    };
+    let k_pvss = joint(&mut rng);
+    let gamma_pvss = joint(&mut rng);
+
+    let sender: Id = 2;
+    let gamma_share = gamma_pvss
+        .shares_ciphertext
+        .decrypt(&pp.cl, sender, &secret_keys[&sender]);
+    let x_share = Zq::random();
+    let x_pub = G::generator() * &x_share;
+
+    let msg = MtAwcMsg::new(
+        &pp,
+        h,
+        &mut rng,
+        &k_pvss,
+        &gamma_pvss,
+        sender,
+        &gamma_share,
+        &x_share,
+        &PresignSession::default(),
+        BTreeMap::from([(1, [1; 32])]),
+    );
+    let nonce_digests = BTreeMap::from([(1, [1; 32])]);
+    let context = MtAwcContext {
+        pp: &pp,
+        h,
+        session: &PresignSession::default(),
+        k_pvss: &k_pvss,
+        gamma_pvss: &gamma_pvss,
+        nonce_digests: &nonce_digests,
+        sender,
+        x_pub: &x_pub,
+    };
+    assert_eq!(msg.verify(&context), Ok(()));
+    assert!(msg.mta_proof.verify(
+        &pp,
+        &k_pvss,
+        [&msg.k_gamma_dealing, &msg.k_x_dealing],
+        h,
+        [&msg.gamma_pub.point, &x_pub],
+        &PresignSession::default().aux(MTA_ROUND, "k*gamma,k*x")
+    ));
+
+    // wrong public share, wrong sender, or the dealings in the wrong order
+    let other_pub = G::generator() * Zq::random();
+    assert!(matches!(
+        msg.verify(&MtAwcContext {
+            x_pub: &other_pub,
+            ..context
+        }),
+        Err(PresignError::Mta { .. })
+    ));
+    assert_eq!(
+        msg.verify(&MtAwcContext {
+            sender: 1,
+            ..context
+        }),
+        Err(PresignError::GammaOpening)
+    );
+
+    // a response to other first round messages than the receiver's
+    for other in [
+        BTreeMap::from([(1, [2; 32])]),
+        BTreeMap::from([(2, [1; 32])]),
+    ] {
+        assert_eq!(
+            msg.verify(&MtAwcContext {
+                nonce_digests: &other,
+                ..context
+            }),
+            Err(PresignError::ViewMismatch)
+        );
+    }
+
+    let mut tampered = msg.clone();
+    std::mem::swap(&mut tampered.k_gamma_dealing, &mut tampered.k_x_dealing);
+    assert!(matches!(
+        tampered.verify(&context),
+        Err(PresignError::Mta { .. })
+    ));
+}
+
+#[test]
+pub fn test_nizk_aux_binding() {
+    use crate::test_utils::simulation::dkg_locally;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = fresh_rng();
+
+    let (dealing, r, _, shares) = PvssDealing::random(&pp, &mut rng, h);
+    let proof = PvssNizk::prove_with_aux(&pp, &dealing, &r, &shares, &mut rng, h, b"A");
+    assert!(proof.verify_with_aux(&dealing, &pp, h, b"A"));
+    assert_eq!(
+        proof.verify_detailed_with_aux(&dealing, &pp, h, b"B"),
+        Err(NizkError::ChallengeMismatch)
+    );
+    assert!(!proof.verify_with_aux(&dealing, &pp, h, &[]));
+
+    let pvss_result = JointPvssResult::new(&pp, vec![dealing.clone()]);
+    let scalar = Zq::random();
+    let scalar_pub = G::generator() * &scalar;
+    let (mta_dealing, masks) = MtaDealing::new(&pp, &pvss_result, &scalar, &mut rng, h);
+    let prove = |rng: &mut RandGen, aux: &[u8]| {
+        MtaNizk::prove_with_aux(
+            &pp,
+            &pvss_result,
+            &mta_dealing,
+            h,
+            rng,
+            &scalar,
+            &masks,
+            aux,
+        )
+        .unwrap()
+    };
+    let proof = prove(&mut rng, b"A");
+    assert!(proof.verify_with_aux(&pp, &pvss_result, &mta_dealing, h, &scalar_pub, b"A"));
+    assert_eq!(
+        proof.verify_detailed_with_aux(&pp, &pvss_result, &mta_dealing, h, &scalar_pub, b"B"),
+        Err(NizkError::ChallengeMismatch)
+    );
+
+    // without context, the proofs are those of the deprecated calls
+    #[allow(deprecated)]
+    {
+        let proof = PvssNizk::prove(&pp, &dealing, &r, &shares, &mut rng, h);
+        assert!(proof.verify_with_aux(&dealing, &pp, h, &[]));
+        let proof = prove(&mut rng, &[]);
+        assert!(proof.verify(&pp, &pvss_result, &mta_dealing, h, &scalar_pub));
+    }
+
+    // the two MtA instances of a presignature, and presignatures of a session, differ
+    let session = PresignSession::new(b"aux test", 0);
+    let aux = session.aux(MTA_ROUND, "k*gamma");
+    assert_ne!(aux, session.aux(MTA_ROUND, "k*x"));
+    assert_ne!(
+        aux,
+        PresignSession::new(b"aux test", 1).aux(MTA_ROUND, "k*gamma")
+    );
+
+    // a party presigning under another counter has its MtA proofs rejected
+    let keys = dkg_locally(&pp, &secret_keys);
+    let run = run_locally_detailed(
+        (1..=pp.n)
+            .map(|i| {
+                let wire = PartyId::from_share_index(i).to_wire_index();
+                let counter = if i == 3 { 1 } else { 0 };
+                let state = PresignState::new(
+                    i,
+                    &pp,
+                    h,
+                    &secret_keys[&i],
+                    &keys[&wire],
+                    PresignSession::new(b"aux test", counter),
+                );
+                (wire, state)
+            })
+            .collect(),
+    );
+    let third = PartyId::from_share_index(3).to_wire_index();
+    for i in [1, 2] {
+        let presignature = &run.outputs[&PartyId::from_share_index(i).to_wire_index()];
+        assert_eq!(
+            presignature.disqualified.get(&third),
+            Some(&DisqualReason::Mta)
+        );
+    }
+}
+
+#[test]
+pub fn test_presign_rejects_replayed_dealing() {
+    use crate::replay::{LruReplayCache, SharedReplayCache};
+    use crate::test_utils::simulation::dkg_locally;
+
+    let (pp, secret_keys) = simulate_pp(4, 2);
+    let h = G::base_point2();
+    let replayer: Id = 2;
+    let keys = dkg_locally(&pp, &secret_keys);
+    let caches: BTreeMap<Id, SharedReplayCache> = (1..=pp.n)
+        .map(|i| (i, LruReplayCache::shared(64)))
+        .collect();
+    let party = |i: Id| {
+        let wire = PartyId::from_share_index(i).to_wire_index();
+        PresignState::new(
+            i,
+            &pp,
+            h,
+            &secret_keys[&i],
+            &keys[&wire],
+            PresignSession::default(),
+        )
+        .with_replay_cache(caches[&i].clone())
+    };
+
+    // honest sessions on the same key deal afresh, so the caches let them through
+    let mut stale = None;
+    for _ in 0..2 {
+        let states = (1..=pp.n)
+            .map(|i| {
+                let state = party(i);
+                if i == replayer {
+                    stale = Some(state.nonce_gen_messages[&i].clone());
+                }
+                (PartyId::from_share_index(i).to_wire_index(), state)
+            })
+            .collect();
+        let run = run_locally_detailed(states);
+        assert!(run.aborted.is_empty(), "{:?}", run.aborted);
+        for presignature in run.outputs.values() {
+            assert!(presignature.disqualified.is_empty());
+        }
+    }
+
+    // a byte-identical dealing of the last session is disqualified
+    let stale = stale.unwrap();
+    let states = (1..=pp.n)
+        .map(|i| {
+            let mut state = party(i);
+            if i == replayer {
+                state.nonce_gen_messages.insert(i, stale.clone());
+                state.outbox = vec![Outgoing::broadcast(PresignMsg::NonceGen(stale.clone()))];
+            }
+            (PartyId::from_share_index(i).to_wire_index(), state)
+        })
+        .collect();
+    let run = run_locally_detailed(states);
+    let wire = PartyId::from_share_index(replayer).to_wire_index();
+    for i in (1..=pp.n).filter(|&i| i != replayer) {
+        let presignature = &run.outputs[&PartyId::from_share_index(i).to_wire_index()];
+        assert_eq!(
+            presignature.disqualified.get(&wire),
+            Some(&DisqualReason::ReplayedDealing)
+        );
+    }
+
+    // without a cache, the dealing still fails in a session with another id
+    let other = PresignSession::new(b"another session", 0);
+    assert!(matches!(
+        stale.verify(&NonceGenContext {
+            pp: &pp,
+            h,
+            session: &other,
+        }),
+        Err(PresignError::Dealing { nonce: "k", .. })
+    ));
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let fresh = NonceGenMsg::random_in_session(&pp, &mut rng, h, &other);
+    assert_eq!(
+        fresh.verify(&NonceGenContext {
+            pp: &pp,
+            h,
+            session: &other,
+        }),
+        Ok(())
+    );
+}
+
+#[test]
+pub fn test_session_from_contributions() {
+    let contributions: BTreeMap<Id, [u8; 32]> = (1..=3).map(|i| (i, [i as u8; 32])).collect();
+    let session = PresignSession::from_contributions(&contributions, 0);
+    assert_eq!(
+        session,
+        PresignSession::from_contributions(&contributions, 0)
+    );
+
+    // every party's contribution changes the session, and with it the dealing aux
+    for i in 1..=3 {
+        let mut changed = contributions.clone();
+        changed.get_mut(&i).unwrap()[0] ^= 1;
+        let other = PresignSession::from_contributions(&changed, 0);
+        assert_ne!(other.session_id, session.session_id);
+        assert_ne!(other.dealing_aux("k"), session.dealing_aux("k"));
+    }
+}
+
+#[test]
+pub fn test_presign_message_verify() {
+    let (pp, _) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let nonce_gen = NonceGenMsg::random(&pp, &mut rng, h);
+    let session = PresignSession::default();
+    let context = NonceGenContext {
+        pp: &pp,
+        h,
+        session: &session,
+    };
+    assert_eq!(nonce_gen.verify(&context), Ok(()));
+    let mut tampered = nonce_gen.clone();
+    tampered.gamma = PvssMsg::malformed(&pp, &mut rng, h);
+    assert!(matches!(
+        tampered.verify(&context),
+        Err(PresignError::Dealing { nonce: "gamma", .. })
+    ));
+    let mut foreign = nonce_gen.clone();
+    foreign.prev_round_digest = [0; 32];
+    assert_eq!(foreign.verify(&context), Err(PresignError::ViewMismatch));
+
+    let (delta_share, Gamma) = (Zq::random(), G::generator() * Zq::random());
+    let delta_mac = h * &delta_share;
+    let final_msg = PreSignFinalMsg {
+        prev_round_digest: [1; 32],
+        delta_share: delta_share.clone(),
+        Gamma: Gamma.clone(),
+    };
+    let context = PreSignFinalContext {
+        h,
+        delta_mac: &delta_mac,
+        Gamma: &Gamma,
+        prev_round_digest: &[1; 32],
+    };
+    assert_eq!(final_msg.verify(&context), Ok(()));
+    assert_eq!(
+        final_msg.verify(&PreSignFinalContext {
+            prev_round_digest: &[2; 32],
+            ..context
+        }),
+        Err(PresignError::ViewMismatch)
+    );
+    let other_Gamma = &Gamma + G::generator();
+    assert_eq!(
+        final_msg.verify(&PreSignFinalContext {
+            Gamma: &other_Gamma,
+            ..context
+        }),
+        Err(PresignError::NonceMismatch)
+    );
+    let wrong_share = PreSignFinalMsg {
+        prev_round_digest: [1; 32],
+        delta_share: delta_share + Zq::from(1),
+        Gamma,
+    };
+    assert_eq!(wrong_share.verify(&context), Err(PresignError::MacMismatch));
+
+    let (k_share, chi_share) = (Zq::random(), Zq::random());
+    let (R, delta) = (G::generator() * Zq::random(), Zq::random());
+    let presignature = PreSignature {
+        Gamma: &R * &delta,
+        R,
+        delta,
+        k_share: k_share.clone(),
+        chi_share: chi_share.clone(),
+        k_macs: BTreeMap::from([(1, h * &k_share)]),
+        chi_macs: BTreeMap::from([(1, h * &chi_share)]),
+        disqualified: BTreeMap::new(),
+    };
+    assert_eq!(
+        presignature.verify(&PreSignatureContext { h, my_id: 1 }),
+        Ok(())
+    );
+    assert_eq!(
+        presignature.verify(&PreSignatureContext { h, my_id: 2 }),
+        Err(PresignError::MacMismatch)
+    );
+
+    let (m, r) = (Zq::random(), Zq::random());
+    let view_digest = presignature.view_digest();
+    let sign_msg = OnlineSignMsg {
+        prev_round_digest: view_digest,
+        sig_share: &k_share * &m + &r * &chi_share,
+    };
+    let context = OnlineSignContext {
+        h,
+        k_mac: &presignature.k_macs[&1],
+        chi_mac: &presignature.chi_macs[&1],
+        m: &m,
+        r: &r,
+        prev_round_digest: &view_digest,
+    };
+    assert_eq!(sign_msg.verify(&context), Ok(()));
+    assert_eq!(
+        sign_msg.verify(&OnlineSignContext { m: &r, ..context }),
+        Err(PresignError::MacMismatch)
+    );
+
+    // a share made with another presignature
+    let mut other = presignature.clone();
+    other.R = &other.R + G::generator();
+    assert_eq!(
+        sign_msg.verify(&OnlineSignContext {
+            prev_round_digest: &other.view_digest(),
+            ..context
+        }),
+        Err(PresignError::ViewMismatch)
+    );
+}
+
+#[test]
+#[cfg(not(feature = "curve-p256"))]
+pub fn test_degenerate_nonce() {
+    // the curve point whose x coordinate is the group order, so that r = 0
+    let R = G::from_bytes(
+        &hex::decode("02fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141").unwrap(),
+    )
+    .unwrap();
+    let delta = Zq::random();
+    let presign = |Gamma: G, delta: Zq| {
+        PreSignature::new(
+            Gamma,
+            delta,
+            Zq::random(),
+            Zq::random(),
+            BTreeMap::new(),
+            BTreeMap::new(),
+            BTreeMap::new(),
+        )
+    };
+
+    assert_eq!(
+        presign(&R * &delta, delta.clone()).map(|_| ()),
+        Err(PresignError::DegenerateNonce)
+    );
+    assert_eq!(
+        presign(G::generator() * Zq::random(), Zq::zero()).map(|_| ()),
+        Err(PresignError::DegenerateNonce)
+    );
+
+    let presignature = presign(G::generator() * Zq::random(), delta).unwrap();
+    assert_eq!(&presignature.R * &presignature.delta, presignature.Gamma);
+}
+
+#[test]
+pub fn test_mpz_to_scalar_ct() {
+    let (pp, _) = simulate_pp(3, 2);
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let len = MtaNizk::response_len(&pp);
+    let bound = pp.cl.encrypt_randomness_bound();
+    for _ in 0..1000 {
+        // shaped like the z1 response of an MtA proof
+        let e = Zq::from_bigint(&BigInt::sample(128));
+        let z1 = &rng.random_mpz(&bound) + Mpz::from(&e) * Mpz::from(&Zq::random());
+
+        let padded = mpz_to_fixed_bytes(&z1, len);
+        assert_eq!(padded.len(), len);
+        assert_eq!(mpz_to_scalar_ct(&z1, len), mpz_to_scalar(&z1));
+    }
+
+    let a = Zq::random();
+    assert!(scalar_ct_eq(&a, &a.clone()));
+    assert!(!scalar_ct_eq(&a, &(&a + Zq::from(1u64))));
+}
+
+#[test]
+pub fn test_mpz_to_scalar() {
+    let q = Mpz::from_bytes(&Zq::group_order().to_bytes());
+    let a = Zq::random();
+    let x = Mpz::from(&a);
+    assert_eq!(Mpz::from(-5i64).sign(), -1);
+    assert_eq!(Mpz::from(0u64).sign(), 0);
+    assert_eq!(q.sign(), 1);
+
+    assert_eq!(mpz_to_scalar(&x), a);
+    assert_eq!(mpz_to_scalar(&-x.clone()), -a.clone());
+    assert_eq!(mpz_to_scalar(&Mpz::from(-1i64)), -Zq::from(1u64));
+    assert_eq!(mpz_to_scalar(&Mpz::from(0u64)), Zq::zero());
+    assert_eq!(mpz_to_scalar(&q), Zq::zero());
+    assert_eq!(mpz_to_scalar(&-q.clone()), Zq::zero());
+
+    // far above q, as the responses of the CL proofs are
+    let large = x.clone() + q.clone().pow(5) * Mpz::from(7u64);
+    assert_eq!(mpz_to_scalar(&large), a);
+    assert_eq!(mpz_to_scalar(&-large), -a.clone());
+
+    // a response minus a larger one, as in the verification equations
+    let (u, e) = (Zq::random(), Zq::random());
+    let z = Mpz::from(&u) + Mpz::from(&e) * Mpz::from(&a);
+    let difference = z + -(q.pow(3) * Mpz::from(&e));
+    assert_eq!(mpz_to_scalar(&difference), &u + &e * &a);
+}
+
+/// Seeded source that records every exponent it hands out.
+struct LoggedRandomness {
+    rng: RandGen,
+    drawn: Vec<Mpz>,
+}
+
+impl LoggedRandomness {
+    fn new(seed: u64) -> Self {
+        LoggedRandomness {
+            rng: seeded_rng(&Mpz::from(seed)),
+            drawn: vec![],
+        }
+    }
+}
+
+impl ClRandomness for LoggedRandomness {
+    fn sample_encrypt_randomness(&mut self, cl: &CL_HSMqk) -> Mpz {
+        let r = self.rng.sample_encrypt_randomness(cl);
+        self.drawn.push(r.clone());
+        r
+    }
+}
+
+#[test]
+pub fn test_cl_randomness_injection() {
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let plaintexts: BTreeMap<Id, Zq> = (1..=pp.n).map(|id| (id, Zq::from(id as u64))).collect();
+
+    // the same seed reproduces the ciphertext, and the returned randomness is what was drawn
+    let mut first = LoggedRandomness::new(7);
+    let mut second = LoggedRandomness::new(7);
+    let (ct1, r1) =
+        CLMultiRecvCiphertext::random(&pp.cl, &mut first, &pp.cl_keyring, &plaintexts).unwrap();
+    let (ct2, _) =
+        CLMultiRecvCiphertext::random(&pp.cl, &mut second, &pp.cl_keyring, &plaintexts).unwrap();
+    assert_eq!(ct1, ct2);
+    assert_eq!(first.drawn, vec![r1]);
+    assert_eq!(ct1.decrypt(&pp.cl, 2, &secret_keys[&2]), Zq::from(2u64));
+
+    // a dealing and its proof draw once each, all below the bound
+    let mut logged = LoggedRandomness::new(8);
+    let (dealing, r, _, shares) = PvssDealing::random(&pp, &mut logged, h);
+    let proof = PvssNizk::prove_with_aux(&pp, &dealing, &r, &shares, &mut logged, h, &[]);
+    assert!(proof.verify_with_aux(&dealing, &pp, h, &[]));
+    assert_eq!(logged.drawn.len(), 2);
+
+    let bound = pp.cl.encrypt_randomness_bound().to_bytes();
+    for x in &logged.drawn {
+        let x = x.to_bytes();
+        assert!((x.len(), &x) < (bound.len(), &bound));
+    }
+}
+
+#[test]
+pub fn test_pvss_dealing_compact() {
+    let (pp, _) = simulate_pp(32, 17);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    let (dealing, ..) = PvssDealing::random(&pp, &mut rng, h);
+    let compact = PvssDealingCompact::from(dealing.clone());
+    assert!(compact.ids.is_empty());
+
+    let full_len = bincode::serialize(&dealing).unwrap().len();
+    let compact_len = bincode::serialize(&compact).unwrap().len();
+    // at least the two id bytes per share are saved
+    assert!(compact_len + 2 * pp.n as usize <= full_len);
+
+    let wire: PvssDealingCompact =
+        bincode::deserialize(&bincode::serialize(&compact).unwrap()).unwrap();
+    assert_eq!(PvssDealing::try_from(wire).unwrap(), dealing);
+
+    // ids that are not 1..=n travel along
+    let restricted = PvssDealing {
+        shares_ciphertext: CLMultiRecvCiphertext {
+            encryption: dealing
+                .shares_ciphertext
+                .encryption
+                .iter()
+                .filter(|(&id, _)| id % 2 == 0)
+                .map(|(&id, enc)| (id, enc.clone()))
+                .collect(),
+            ..dealing.shares_ciphertext.clone()
+        },
+        ..dealing.clone()
+    };
+    let compact = PvssDealingCompact::from(restricted.clone());
+    assert_eq!(compact.ids.len(), 16);
+    assert_eq!(PvssDealing::try_from(compact.clone()).unwrap(), restricted);
+
+    // a share for the same party twice would otherwise silently drop one of them
+    let mut duplicated = compact;
+    duplicated.ids[1] = duplicated.ids[0];
+    assert_eq!(
+        PvssDealing::try_from(duplicated),
+        Err(CompactDealingError::DuplicateId { id: 2 })
+    );
+
+    let mut broken = PvssDealingCompact::from(dealing);
+    broken.curve_coeffs.pop();
+    assert_eq!(
+        PvssDealing::try_from(broken),
+        Err(CompactDealingError::InvalidPoint)
+    );
+}
+
+#[test]
+pub fn test_nizk_error_variants() {
+    let (pp, _) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let oversized = |z1: &Mpz| z1.clone() * Mpz::from_bytes(&[0xff; 64]);
+
+    // PVSS
+    let (dealing, r, _, shares) = PvssDealing::random(&pp, &mut rng, h);
+    let proof = PvssNizk::prove_with_aux(&pp, &dealing, &r, &shares, &mut rng, h, &[]);
+    assert_eq!(
+        proof.verify_detailed_with_aux(&dealing, &pp, h, &[]),
+        Ok(())
+    );
+
+    let mut high_degree = dealing.clone();
+    high_degree
+        .curve_polynomial
+        .coeffs
+        .push(G::generator() * Zq::random());
+    assert_eq!(
+        proof.verify_detailed_with_aux(&high_degree, &pp, h, &[]),
+        Err(NizkError::DegreeMismatch {
+            got: 3,
+            expected: 2
+        })
+    );
+    let wrong_e = PvssNizk {
+        e: &proof.e + Zq::from(1u64),
+        ..proof.clone()
+    };
+    assert_eq!(
+        wrong_e.verify_detailed_with_aux(&dealing, &pp, h, &[]),
+        Err(NizkError::ChallengeMismatch)
+    );
+    let long_z1 = PvssNizk {
+        z1: oversized(&proof.z1),
+        ..proof.clone()
+    };
+    assert_eq!(
+        long_z1.verify_detailed_with_aux(&dealing, &pp, h, &[]),
+        Err(NizkError::DeserializationError)
+    );
+
+    // MtA
+    let pvss_result = JointPvssResult::new(&pp, vec![dealing]);
+    let scalar = Zq::random();
+    let scalar_pub = G::generator() * &scalar;
+    let (mta_dealing, masks) = MtaDealing::new(&pp, &pvss_result, &scalar, &mut rng, h);
+    let proof = MtaNizk::prove_with_aux(
+        &pp,
+        &pvss_result,
+        &mta_dealing,
+        h,
+        &mut rng,
+        &scalar,
+        &masks,
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        proof.verify_detailed_with_aux(&pp, &pvss_result, &mta_dealing, h, &scalar_pub, &[]),
+        Ok(())
+    );
+    assert_eq!(
+        proof.verify_detailed_with_aux(&pp, &pvss_result, &mta_dealing, h, &G::generator(), &[]),
+        Err(NizkError::ChallengeMismatch)
+    );
+    let long_z1 = MtaNizk {
+        z1: oversized(&proof.z1),
+        ..proof.clone()
+    };
+    assert_eq!(
+        long_z1.verify_detailed_with_aux(&pp, &pvss_result, &mta_dealing, h, &scalar_pub, &[]),
+        Err(NizkError::DeserializationError)
+    );
+    let mut high_degree = pvss_result.clone();
+    high_degree
+        .curve_polynomial
+        .coeffs
+        .push(G::generator().to_point());
+    assert_eq!(
+        proof.verify_detailed_with_aux(&pp, &high_degree, &mta_dealing, h, &scalar_pub, &[]),
+        Err(NizkError::DegreeMismatch {
+            got: 3,
+            expected: 2
+        })
+    );
+
+    // DLEQ
+    let x = Zq::random();
+    let dleq = DleqNizk::prove(
+        &G::generator(),
+        &(G::generator() * &x),
+        h,
+        &(h * &x),
+        &x,
+        &mut rng,
+    );
+    assert_eq!(
+        dleq.verify_detailed(&G::generator(), &(G::generator() * &x), h, &(h * &x)),
+        Ok(())
+    );
+    assert_eq!(
+        dleq.verify_detailed(&G::generator(), &G::generator(), h, &(h * &x)),
+        Err(NizkError::ChallengeMismatch)
+    );
+}
+
+#[test]
+pub fn test_polynomial_arithmetic() {
+    let random_poly = |len: usize| Polynomial {
+        coeffs: (0..len).map(|_| Zq::random()).collect(),
+    };
+    let a = random_poly(3);
+    let b = random_poly(5);
+    let x = Zq::random();
+    let c = Zq::random();
+
+    let sum = &a + &b;
+    assert_eq!(sum.coeffs.len(), 5);
+    assert_eq!(sum.eval(&x), a.eval(&x) + b.eval(&x));
+    assert_eq!(&b + &a, sum);
+    assert_eq!(a.clone() + Polynomial { coeffs: vec![] }, a);
+    assert_eq!((&a * &c).eval(&x), a.eval(&x) * &c);
+
+    let g = &G::generator().to_point();
+    let (ca, cb) = (
+        CurvePolynomial::from_exp(&a, g),
+        CurvePolynomial::from_exp(&b, g),
+    );
+    let curve_sum = &ca + &cb;
+    assert_eq!(curve_sum, CurvePolynomial::from_exp(&sum, g));
+    assert_eq!(curve_sum.eval(&x), ca.eval(&x) + cb.eval(&x));
+    assert_eq!((&cb * &c).eval(&x), cb.eval(&x) * &c);
+}
+
+#[test]
+pub fn test_polynomials_over_p256() {
+    use curv::elliptic::curves::{Point, Scalar, Secp256r1};
+
+    let poly = Polynomial::<Secp256r1> {
+        coeffs: (0..3).map(|_| Scalar::random()).collect(),
+    };
+    let generator = Point::<Secp256r1>::generator().to_point();
+    let curve_poly = CurvePolynomial::from_exp(&poly, &generator);
+
+    let x = Scalar::<Secp256r1>::from(5u64);
+    assert_eq!(curve_poly.eval(&x), &generator * poly.eval(&x));
+    assert_eq!((&poly + &poly).eval(&x), poly.eval(&x) * Scalar::from(2u64));
+}
+
+#[test]
+pub fn test_polynomial_zeroize() {
+    use zeroize::{Zeroize, ZeroizeOnDrop};
+
+    fn wiped_on_drop<T: ZeroizeOnDrop>() {}
+    wiped_on_drop::<Polynomial>();
+    wiped_on_drop::<NoncePair>();
+
+    let (pp, _) = simulate_pp(3, 2);
+    let (_, _, mut poly, mut shares) = PvssDealing::random(&pp, &mut fresh_rng(), G::base_point2());
+    assert!(poly.coeffs.iter().any(|coeff| !coeff.is_zero()));
+
+    poly.zeroize();
+    assert_eq!(poly.coeffs.len(), pp.t as usize);
+    assert!(poly.coeffs.iter().all(|coeff| coeff.is_zero()));
+
+    zeroize_shares(&mut shares);
+    assert_eq!(shares.len(), pp.n as usize);
+    assert!(shares.values().all(|share| share.is_zero()));
 }
@@ -0,0 +1,5 @@
+//! Helpers shared by the tests: whole protocol runs among simulated parties, and
+//! parties that misbehave.
+
+pub mod adversary;
+pub mod simulation;
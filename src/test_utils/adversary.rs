@@ -0,0 +1,179 @@
+//! A wrapper that makes one party misbehave in chosen rounds. It is itself a
+//! `StateMachine`, so it runs under `run_locally` as well as over a `Simulation`
+//! party through `run`.
+
+use std::collections::BTreeMap;
+
+use round_based::{MessageDestination, Outgoing, PartyIndex, ProtocolMessage};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::spdz::{StateError, StateMachine};
+
+/// What the adversary does with its messages in a corrupted round.
+#[derive(Clone, Debug)]
+pub enum Corruption<M> {
+    /// Sends nothing.
+    Drop,
+    /// Sends its honest message with one bit flipped, at a position where the
+    /// result still decodes. A message without such a position fails the test rather
+    /// than going out honestly.
+    BitFlip,
+    /// Sends its honest message to the parties with an odd wire index, and a
+    /// bit-flipped one to the others.
+    Equivocate,
+    /// Sends the given message instead, typically its own from an earlier session.
+    Replay(M),
+    /// Sends the given message to the parties in `to`, and its honest one to the
+    /// others, so that their views of the round differ without any message failing
+    /// to verify.
+    Splice { to: Vec<PartyIndex>, msg: M },
+}
+
+pub struct AdversarialParty<S: StateMachine> {
+    id: PartyIndex,
+    n: u16,
+    inner: S,
+    corruptions: BTreeMap<u16, Corruption<S::Msg>>,
+}
+
+impl<S> AdversarialParty<S>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage + Clone + PartialEq + Serialize + DeserializeOwned,
+{
+    /// Wraps the honest state of party `id` in a committee of `n`; it behaves
+    /// honestly until rounds are corrupted.
+    pub fn new(id: PartyIndex, n: u16, inner: S) -> Self {
+        AdversarialParty {
+            id,
+            n,
+            inner,
+            corruptions: BTreeMap::new(),
+        }
+    }
+
+    /// Misbehaves in round `round`, as numbered by `ProtocolMessage::round`.
+    pub fn corrupt_round(mut self, round: u16, corruption: Corruption<S::Msg>) -> Self {
+        self.corruptions.insert(round, corruption);
+        self
+    }
+
+    fn corrupt(&self, outgoing: Outgoing<S::Msg>) -> Vec<Outgoing<S::Msg>> {
+        let Outgoing { recipient, msg } = outgoing;
+        match self.corruptions.get(&msg.round()) {
+            None => vec![Outgoing { recipient, msg }],
+            Some(Corruption::Drop) => vec![],
+            Some(Corruption::BitFlip) => {
+                let msg = must_flip(&msg);
+                vec![Outgoing { recipient, msg }]
+            }
+            Some(Corruption::Replay(stale)) => vec![Outgoing {
+                recipient,
+                msg: stale.clone(),
+            }],
+            Some(Corruption::Splice { to, msg: spliced }) => self
+                .recipients(recipient)
+                .into_iter()
+                .map(|j| Outgoing {
+                    recipient: MessageDestination::OneParty(j),
+                    msg: if to.contains(&j) {
+                        spliced.clone()
+                    } else {
+                        msg.clone()
+                    },
+                })
+                .collect(),
+            Some(Corruption::Equivocate) => {
+                let flipped = must_flip(&msg);
+                self.recipients(recipient)
+                    .into_iter()
+                    .map(|j| Outgoing {
+                        recipient: MessageDestination::OneParty(j),
+                        msg: if j % 2 == 1 {
+                            msg.clone()
+                        } else {
+                            flipped.clone()
+                        },
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    fn recipients(&self, recipient: MessageDestination) -> Vec<PartyIndex> {
+        match recipient {
+            MessageDestination::AllParties => (0..self.n).filter(|&j| j != self.id).collect(),
+            MessageDestination::OneParty(j) => vec![j],
+        }
+    }
+}
+
+impl<S> StateMachine for AdversarialParty<S>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage + Clone + PartialEq + Serialize + DeserializeOwned,
+{
+    type Msg = S::Msg;
+    type Output = S::Output;
+
+    fn handle_message(&mut self, from: PartyIndex, msg: S::Msg) -> Result<(), StateError> {
+        self.inner.handle_message(from, msg)
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<S::Msg>> {
+        self.inner
+            .wants_to_send()
+            .into_iter()
+            .flat_map(|outgoing| self.corrupt(outgoing))
+            .collect()
+    }
+
+    fn try_finish(self) -> Result<S::Output, Self> {
+        let AdversarialParty {
+            id,
+            n,
+            inner,
+            corruptions,
+        } = self;
+        inner.try_finish().map_err(|inner| AdversarialParty {
+            id,
+            n,
+            inner,
+            corruptions,
+        })
+    }
+
+    fn on_version(&mut self, version: u16) {
+        self.inner.on_version(version)
+    }
+}
+
+/// `bit_flipped`, for a corruption that would otherwise quietly behave honestly.
+fn must_flip<M>(msg: &M) -> M
+where
+    M: ProtocolMessage + PartialEq + Serialize + DeserializeOwned,
+{
+    bit_flipped(msg).unwrap_or_else(|| {
+        panic!(
+            "no bit of the round {} message flips to another that decodes",
+            msg.round()
+        )
+    })
+}
+
+/// `msg` with a single bit of its encoding flipped, searching from the end, where the
+/// messages keep their proofs and curve points rather than length prefixes. None if
+/// no flip decodes to a different message.
+pub fn bit_flipped<M>(msg: &M) -> Option<M>
+where
+    M: PartialEq + Serialize + DeserializeOwned,
+{
+    let bytes = bincode::serialize(msg).ok()?;
+    (0..bytes.len() * 8).rev().find_map(|bit| {
+        let mut flipped = bytes.clone();
+        flipped[bit / 8] ^= 1 << (bit % 8);
+        bincode::deserialize::<M>(&flipped)
+            .ok()
+            .filter(|decoded| decoded != msg)
+    })
+}
@@ -0,0 +1,179 @@
+//! Whole protocol runs among honest parties over a `round_based` simulation, returning
+//! every party's output for the test to assert on.
+
+use std::collections::BTreeMap;
+
+use bicycl::SecretKey;
+use futures::executor::block_on;
+use futures::future::try_join_all;
+use round_based::simulation::Simulation;
+use round_based::PartyIndex;
+
+use crate::participant::{self, Participant};
+use crate::presign::PreSignature;
+use crate::spdz::{
+    presign, run, run_locally, sign, simulate_pp, DkgMsg, DkgOutput, DkgState, PresignMsg,
+    PresignSession, PresignState, SignMsg, SignatureECDSA, SigningContext,
+};
+use crate::utils::{Id, PartyId, PubParams, G};
+
+/// Runs the DKG among a fresh committee of `n` with threshold `t`, and returns the
+/// outputs of parties 1..=n in order.
+pub fn run_dkg_simulation(t: Id, n: Id) -> Vec<DkgOutput> {
+    let (pp, secret_keys) = simulate_pp(n, t);
+    dkg_among(&pp, &secret_keys)
+}
+
+/// Runs the DKG, presigning and signing of `msg` among a fresh committee of `n` with
+/// threshold `t`, and returns each party's signature with the public key it signed
+/// under, for parties 1..=n in order. Goes through the positional entry points, which
+/// `run_with_participants` is checked against.
+#[allow(deprecated)]
+pub fn run_full_protocol(t: Id, n: Id, msg: &[u8]) -> Vec<(SignatureECDSA, G)> {
+    let (pp, secret_keys) = simulate_pp(n, t);
+    let (pp, secret_keys) = (&pp, &secret_keys);
+    let h = &pp.curve_generator;
+    let keys = dkg_among(pp, secret_keys);
+
+    let mut simulation = Simulation::<PresignMsg>::new();
+    let presignatures = block_on(try_join_all(keys.iter().zip(1..=pp.n).map(|(key, i)| {
+        let party = simulation.add_party();
+        presign(party, i, pp, h, &secret_keys[&i], key)
+    })))
+    .unwrap();
+
+    let context = SigningContext::from_dkg(pp, &keys[0]);
+    let mut simulation = Simulation::<SignMsg>::new();
+    let outcomes = block_on(try_join_all(presignatures.into_iter().zip(1..=pp.n).map(
+        |(presignature, i)| {
+            let party = simulation.add_party();
+            sign(party, i, pp, h, &context, presignature, msg)
+        },
+    )))
+    .unwrap();
+
+    outcomes
+        .into_iter()
+        .map(|outcome| (outcome.signature, outcome.public_key))
+        .collect()
+}
+
+/// Runs the DKG among the committee of `pp`.
+pub fn dkg_among(pp: &PubParams, secret_keys: &BTreeMap<Id, SecretKey>) -> Vec<DkgOutput> {
+    let h = &pp.curve_generator;
+    let mut simulation = Simulation::<DkgMsg>::new();
+    block_on(try_join_all((1..=pp.n).map(|i| {
+        let party = simulation.add_party();
+        run(party, DkgState::new(i, pp, h, &secret_keys[&i], false))
+    })))
+    .unwrap()
+}
+
+/// The DKG state machines of the committee of `pp`, keyed by wire index, for tests
+/// that drive them some other way than `dkg_locally`.
+pub fn dkg_states<'a>(
+    pp: &'a PubParams,
+    secret_keys: &'a BTreeMap<Id, SecretKey>,
+) -> BTreeMap<PartyIndex, DkgState<'a>> {
+    let h = &pp.curve_generator;
+    (1..=pp.n)
+        .map(|i| {
+            let state = DkgState::new(i, pp, h, &secret_keys[&i], false);
+            (PartyId::from_share_index(i).to_wire_index(), state)
+        })
+        .collect()
+}
+
+/// Like `dkg_among`, with the state machines driven by `run_locally`, so the outputs
+/// come keyed by wire index.
+pub fn dkg_locally(
+    pp: &PubParams,
+    secret_keys: &BTreeMap<Id, SecretKey>,
+) -> BTreeMap<PartyIndex, DkgOutput> {
+    dkg_locally_with(pp, secret_keys, |state| state)
+}
+
+/// Like `dkg_locally`, with every party's state passed through `configure` first, such
+/// as to switch on a dealing mode.
+pub fn dkg_locally_with<'a>(
+    pp: &'a PubParams,
+    secret_keys: &'a BTreeMap<Id, SecretKey>,
+    configure: impl Fn(DkgState<'a>) -> DkgState<'a>,
+) -> BTreeMap<PartyIndex, DkgOutput> {
+    run_locally(
+        dkg_states(pp, secret_keys)
+            .into_iter()
+            .map(|(i, state)| (i, configure(state)))
+            .collect(),
+    )
+}
+
+/// Presigns with every party holding one of `keys`, keyed by wire index as
+/// `dkg_locally` returns them.
+pub fn presign_locally(
+    pp: &PubParams,
+    secret_keys: &BTreeMap<Id, SecretKey>,
+    keys: &BTreeMap<PartyIndex, DkgOutput>,
+) -> BTreeMap<PartyIndex, PreSignature> {
+    let h = &pp.curve_generator;
+    run_locally(
+        keys.iter()
+            .map(|(&i, key)| {
+                let id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                (
+                    i,
+                    PresignState::new(id, pp, h, &secret_keys[&id], key, PresignSession::default()),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// The committee of `pp` as `Participant`s, in order.
+pub fn participants(pp: &PubParams, secret_keys: &BTreeMap<Id, SecretKey>) -> Vec<Participant> {
+    (1..=pp.n)
+        .map(|i| {
+            Participant::builder()
+                .index(PartyId::from_share_index(i).to_wire_index())
+                .threshold(pp.t)
+                .parties(pp.n)
+                .cl_group(pp.cl.clone())
+                .cl_keyring(pp.cl_keyring.clone())
+                .cl_secret(secret_keys[&i].clone())
+                .build()
+                .unwrap()
+        })
+        .collect()
+}
+
+/// Like `run_full_protocol`, among `committee` in the order of their indices.
+pub fn run_with_participants(committee: &[Participant], msg: &[u8]) -> Vec<(SignatureECDSA, G)> {
+    let mut simulation = Simulation::<DkgMsg>::new();
+    let keys = block_on(try_join_all(
+        committee
+            .iter()
+            .map(|me| participant::dkg(simulation.add_party(), me, false)),
+    ))
+    .unwrap();
+
+    let mut simulation = Simulation::<PresignMsg>::new();
+    let presignatures =
+        block_on(try_join_all(committee.iter().zip(&keys).map(
+            |(me, key)| participant::presign(simulation.add_party(), me, key, 0),
+        )))
+        .unwrap();
+
+    let context = SigningContext::from_dkg(committee[0].pp(), &keys[0]);
+    let mut simulation = Simulation::<SignMsg>::new();
+    let outcomes = block_on(try_join_all(committee.iter().zip(presignatures).map(
+        |(me, presignature)| {
+            participant::sign(simulation.add_party(), me, &context, presignature, msg)
+        },
+    )))
+    .unwrap();
+
+    outcomes
+        .into_iter()
+        .map(|outcome| (outcome.signature, outcome.public_key))
+        .collect()
+}
@@ -0,0 +1,539 @@
+//! Transcripts for audit. A party running its protocols inside `Recorded` keeps every
+//! broadcast message it sent or received, the parties each phase settled on, the key and
+//! the signature, chained by hash from an `Anchor` naming the run. The JSON form can be
+//! checked offline against the public parameters with `verify_transcript`.
+
+use round_based::{Outgoing, PartyIndex, ProtocolMessage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::codec::{decode, encode, DecodeError};
+use crate::spdz::{
+    DkgMsg, DkgOutput, DkgState, NonceGenContext, PreSignature, PresignMsg, PresignSession,
+    PresignState, SignMsg, SignState, SignatureECDSA, SigningOutcome, StateError, StateMachine,
+};
+use crate::utils::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Phase {
+    Dkg,
+    Presign,
+    Sign,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Entry {
+    /// A broadcast message, encoded as it went over the wire.
+    Message {
+        phase: Phase,
+        sender: PartyIndex,
+        round: u16,
+        bytes: Vec<u8>,
+    },
+    /// The parties whose contributions a phase ended up using.
+    Qualified {
+        phase: Phase,
+        parties: Vec<PartyIndex>,
+    },
+    PublicKey(G),
+    Signature(SignatureECDSA),
+}
+
+/// An entry, with the hash of the chain up to and including it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Link {
+    pub entry: Entry,
+    pub chain: [u8; 32],
+}
+
+/// What a transcript's hash chain starts from: the id of the session it records and the
+/// `PubParams::fingerprint` of the committee that ran it. A verifier who expects the
+/// anchor of its own run cannot be handed the transcript of another.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Anchor {
+    pub session_id: Vec<u8>,
+    pub fingerprint: [u8; 32],
+}
+
+impl Anchor {
+    pub fn new(session_id: &[u8], pp: &PubParams) -> Self {
+        Anchor {
+            session_id: session_id.to_vec(),
+            fingerprint: pp.fingerprint(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub anchor: Anchor,
+    /// SHA-256 of each party's CL public key, in the order of the party ids.
+    pub keyring: Vec<(Id, [u8; 32])>,
+    pub links: Vec<Link>,
+}
+
+fn keyring_digests(pp: &PubParams) -> Vec<(Id, [u8; 32])> {
+    pp.cl_keyring
+        .iter()
+        .map(|(&id, pk)| (id, Sha256::digest(pk.to_bytes()).into()))
+        .collect()
+}
+
+impl Transcript {
+    pub fn new(session_id: &[u8], pp: &PubParams) -> Self {
+        Transcript {
+            anchor: Anchor::new(session_id, pp),
+            keyring: keyring_digests(pp),
+            links: vec![],
+        }
+    }
+
+    /// The hash the chain starts from, binding the anchor and the keyring.
+    fn genesis(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new()
+            .chain_update(b"transcript")
+            .chain_update((self.anchor.session_id.len() as u64).to_be_bytes())
+            .chain_update(&self.anchor.session_id)
+            .chain_update(self.anchor.fingerprint);
+        for (id, digest) in &self.keyring {
+            hasher.update(id.to_be_bytes());
+            hasher.update(digest);
+        }
+        hasher.finalize().into()
+    }
+
+    fn extend(chain: &[u8; 32], entry: &Entry) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(chain)
+            .chain_update(encode(entry))
+            .finalize()
+            .into()
+    }
+
+    pub fn append(&mut self, entry: Entry) {
+        let chain = Self::extend(&self.digest(), &entry);
+        self.links.push(Link { entry, chain });
+    }
+
+    /// The hash of the whole chain, which commits to every entry.
+    pub fn digest(&self) -> [u8; 32] {
+        self.links
+            .last()
+            .map_or_else(|| self.genesis(), |link| link.chain)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("transcripts serialize to JSON")
+    }
+}
+
+/// A protocol whose runs can be recorded: the phase its messages belong to, and what
+/// its output adds to the transcript.
+pub trait Audited: StateMachine {
+    const PHASE: Phase;
+
+    fn audit(output: &Self::Output) -> Vec<Entry>;
+}
+
+impl<'a> Audited for DkgState<'a> {
+    const PHASE: Phase = Phase::Dkg;
+
+    fn audit(output: &DkgOutput) -> Vec<Entry> {
+        vec![
+            Entry::Qualified {
+                phase: Phase::Dkg,
+                parties: output
+                    .qualified
+                    .iter()
+                    .map(|&id| PartyId::from_share_index(id).to_wire_index())
+                    .collect(),
+            },
+            Entry::PublicKey(output.tpk.pk.clone()),
+        ]
+    }
+}
+
+impl<'a> Audited for PresignState<'a> {
+    const PHASE: Phase = Phase::Presign;
+
+    fn audit(output: &PreSignature) -> Vec<Entry> {
+        vec![Entry::Qualified {
+            phase: Phase::Presign,
+            parties: output
+                .k_macs
+                .keys()
+                .map(|&id| PartyId::from_share_index(id).to_wire_index())
+                .filter(|from| !output.disqualified.contains_key(from))
+                .collect(),
+        }]
+    }
+}
+
+impl<'a> Audited for SignState<'a> {
+    const PHASE: Phase = Phase::Sign;
+
+    fn audit(output: &SigningOutcome) -> Vec<Entry> {
+        vec![
+            Entry::Qualified {
+                phase: Phase::Sign,
+                parties: output.participants.iter().copied().collect(),
+            },
+            Entry::Signature(output.signature.clone()),
+        ]
+    }
+}
+
+/// Runs a protocol while recording it into a transcript, which comes out along with the
+/// output. Only the messages the protocol accepted are recorded. Passing the transcript of one phase into the next records a whole ceremony.
+pub struct Recorded<S> {
+    me: PartyIndex,
+    inner: S,
+    transcript: Transcript,
+}
+
+impl<S: Audited> Recorded<S> {
+    pub fn new(me: PartyIndex, inner: S, transcript: Transcript) -> Self {
+        Recorded {
+            me,
+            inner,
+            transcript,
+        }
+    }
+}
+
+impl<S> Recorded<S>
+where
+    S: Audited,
+    S::Msg: ProtocolMessage + Serialize,
+{
+    fn entry(sender: PartyIndex, msg: &S::Msg) -> Entry {
+        Entry::Message {
+            phase: S::PHASE,
+            sender,
+            round: msg.round(),
+            bytes: encode(msg),
+        }
+    }
+}
+
+impl<S> StateMachine for Recorded<S>
+where
+    S: Audited,
+    S::Msg: ProtocolMessage + Serialize,
+{
+    type Msg = S::Msg;
+    type Output = (S::Output, Transcript);
+
+    fn handle_message(&mut self, from: PartyIndex, msg: S::Msg) -> Result<(), StateError> {
+        let entry = Self::entry(from, &msg);
+        self.inner.handle_message(from, msg)?;
+        self.transcript.append(entry);
+        Ok(())
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<S::Msg>> {
+        let outgoing = self.inner.wants_to_send();
+        for Outgoing { msg, .. } in &outgoing {
+            self.transcript.append(Self::entry(self.me, msg));
+        }
+        outgoing
+    }
+
+    fn try_finish(self) -> Result<Self::Output, Self> {
+        let Recorded {
+            me,
+            inner,
+            mut transcript,
+        } = self;
+        match inner.try_finish() {
+            Ok(output) => {
+                for entry in S::audit(&output) {
+                    transcript.append(entry);
+                }
+                Ok((output, transcript))
+            }
+            Err(inner) => Err(Recorded {
+                me,
+                inner,
+                transcript,
+            }),
+        }
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        self.inner.on_timeout()
+    }
+
+    fn on_version(&mut self, version: u16) {
+        self.inner.on_version(version)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TranscriptError {
+    #[error("the transcript is not valid JSON: {0}")]
+    Malformed(serde_json::Error),
+    #[error("the transcript was recorded in another session or committee")]
+    AnchorMismatch,
+    #[error("the transcript was recorded under different CL keys")]
+    KeyringMismatch,
+    #[error("entry {index} does not extend the hash chain")]
+    BrokenChain { index: usize },
+    #[error("message {index} does not decode: {source}")]
+    Undecodable { index: usize, source: DecodeError },
+    #[error("message {index} carries a qualified dealing whose proof does not verify")]
+    InvalidProof { index: usize },
+    #[error("the transcript does not record the given public key")]
+    KeyMismatch,
+    #[error("the transcript does not record the given signature")]
+    SignatureMismatch,
+    #[error("the signature does not verify under the public key")]
+    InvalidSignature,
+}
+
+/// What a transcript is expected to end with: `signature`, on `msg` under `pk`.
+#[derive(Clone, Copy, Debug)]
+pub struct Signed<'a> {
+    pub pk: &'a G,
+    pub msg: &'a [u8],
+    pub signature: &'a SignatureECDSA,
+}
+
+/// Checks a transcript in its JSON form: that it starts from `anchor` and was recorded
+/// under the CL keys of `pp`, that every entry extends the hash chain, that every
+/// recorded message decodes and the PVSS dealings of the parties each phase qualified
+/// verify, the nonce dealings in the presigning `session` the transcript was recorded
+/// in, and that the key and signature it ends with are those of `signed`, a signature
+/// that verifies on its message. Dealings the parties rejected, e.g.
+/// of a dealer the DKG disqualified, are recorded too, and need not verify. The MtA
+/// proofs and openings are checked against the joint dealings while the protocol runs,
+/// and are not checked again here.
+pub fn verify_transcript(
+    json: &str,
+    anchor: &Anchor,
+    pp: &PubParams,
+    h: &G,
+    session: &PresignSession,
+    signed: Signed,
+) -> Result<Transcript, TranscriptError> {
+    let transcript: Transcript = serde_json::from_str(json).map_err(TranscriptError::Malformed)?;
+    if transcript.anchor != *anchor {
+        return Err(TranscriptError::AnchorMismatch);
+    }
+    if transcript.keyring != keyring_digests(pp) {
+        return Err(TranscriptError::KeyringMismatch);
+    }
+
+    // recorded after the messages they settle on
+    let qualified = |phase: Phase, sender: PartyIndex| {
+        transcript.links.iter().any(|link| match &link.entry {
+            Entry::Qualified { phase: p, parties } => *p == phase && parties.contains(&sender),
+            _ => false,
+        })
+    };
+
+    let mut chain = transcript.genesis();
+    let (mut keys, mut signatures) = (vec![], vec![]);
+    for (index, link) in transcript.links.iter().enumerate() {
+        chain = Transcript::extend(&chain, &link.entry);
+        if chain != link.chain {
+            return Err(TranscriptError::BrokenChain { index });
+        }
+
+        let undecodable = |source| TranscriptError::Undecodable { index, source };
+        let valid = match &link.entry {
+            Entry::Message {
+                phase: Phase::Dkg,
+                sender,
+                bytes,
+                ..
+            } => match decode(bytes).map_err(undecodable)? {
+                DkgMsg::Pvss(msg) => !qualified(Phase::Dkg, *sender) || msg.verify(pp, h).is_ok(),
+                _ => true,
+            },
+            Entry::Message {
+                phase: Phase::Presign,
+                sender,
+                bytes,
+                ..
+            } => match decode(bytes).map_err(undecodable)? {
+                PresignMsg::NonceGen(msg) => {
+                    !qualified(Phase::Presign, *sender)
+                        || msg.verify(&NonceGenContext { pp, h, session }).is_ok()
+                }
+                _ => true,
+            },
+            Entry::Message {
+                phase: Phase::Sign,
+                bytes,
+                ..
+            } => decode::<SignMsg>(bytes)
+                .map(|_| true)
+                .map_err(undecodable)?,
+            Entry::Qualified { .. } => true,
+            Entry::PublicKey(key) => {
+                keys.push(key);
+                true
+            }
+            Entry::Signature(recorded) => {
+                signatures.push(recorded);
+                true
+            }
+        };
+        if !valid {
+            return Err(TranscriptError::InvalidProof { index });
+        }
+    }
+
+    if keys.is_empty() || keys.iter().any(|&key| key != signed.pk) {
+        return Err(TranscriptError::KeyMismatch);
+    }
+    if !signatures.contains(&signed.signature) {
+        return Err(TranscriptError::SignatureMismatch);
+    }
+    if !signed.signature.verify(signed.pk, signed.msg) {
+        return Err(TranscriptError::InvalidSignature);
+    }
+    Ok(transcript)
+}
+
+#[test]
+pub fn test_transcript() {
+    use crate::spdz::{run_locally, simulate_pp, SigningContext};
+    use std::collections::BTreeMap;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let id = |i: PartyIndex| PartyId::from_wire_index(i).unwrap().to_share_index();
+
+    let session = PresignSession::new(b"ceremony 1", 0);
+    let keys = run_locally(
+        (0..pp.n)
+            .map(|i| {
+                let state = DkgState::new(id(i), &pp, h, &secret_keys[&id(i)], false);
+                let transcript = Transcript::new(b"ceremony 1", &pp);
+                (i, Recorded::new(i, state, transcript))
+            })
+            .collect(),
+    );
+    let presignatures = run_locally(
+        keys.iter()
+            .map(|(&i, (key, transcript))| {
+                let state =
+                    PresignState::new(id(i), &pp, h, &secret_keys[&id(i)], key, session.clone());
+                (i, Recorded::new(i, state, transcript.clone()))
+            })
+            .collect(),
+    );
+
+    let msg = b"audited";
+    let context = SigningContext::from_dkg(&pp, &keys[&0].0);
+    let outcomes: BTreeMap<PartyIndex, (SigningOutcome, Transcript)> = run_locally(
+        presignatures
+            .into_iter()
+            .map(|(i, (presignature, transcript))| {
+                let state = SignState::new(id(i), &pp, h, &context, presignature, msg);
+                (i, Recorded::new(i, state, transcript))
+            })
+            .collect(),
+    );
+
+    let (outcome, transcript) = &outcomes[&0];
+    let json = transcript.to_json();
+    let anchor = Anchor::new(b"ceremony 1", &pp);
+    let signed = Signed {
+        pk: &context.pk,
+        msg,
+        signature: &outcome.signature,
+    };
+    let verified = verify_transcript(&json, &anchor, &pp, h, &session, signed).unwrap();
+    assert_eq!(verified.digest(), transcript.digest());
+
+    // every phase left its messages and the parties it settled on
+    for phase in [Phase::Dkg, Phase::Presign, Phase::Sign] {
+        let recorded = |entry: &Entry| match entry {
+            Entry::Message { phase: p, .. } | Entry::Qualified { phase: p, .. } => *p == phase,
+            _ => false,
+        };
+        assert!(
+            transcript
+                .links
+                .iter()
+                .filter(|link| recorded(&link.entry))
+                .count()
+                > pp.t as usize
+        );
+    }
+
+    // flipping a single recorded byte breaks the chain at that entry
+    let mut tampered: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let byte = &mut tampered["links"][0]["entry"]["Message"]["bytes"][0];
+    *byte = (byte.as_u64().unwrap() ^ 1).into();
+    assert!(matches!(
+        verify_transcript(&tampered.to_string(), &anchor, &pp, h, &session, signed),
+        Err(TranscriptError::BrokenChain { index: 0 })
+    ));
+
+    // a transcript of another session, or another committee, is not the one expected
+    let (other_pp, _) = simulate_pp(3, 2);
+    for other in [
+        Anchor::new(b"ceremony 2", &pp),
+        Anchor::new(b"ceremony 1", &other_pp),
+    ] {
+        assert!(matches!(
+            verify_transcript(&json, &other, &pp, h, &session, signed),
+            Err(TranscriptError::AnchorMismatch)
+        ));
+    }
+
+    let other_key = Signed {
+        pk: &keys[&0].0.tpk.pub_shares[&1],
+        ..signed
+    };
+    assert!(matches!(
+        verify_transcript(&json, &anchor, &pp, h, &session, other_key),
+        Err(TranscriptError::KeyMismatch)
+    ));
+    let other_msg = Signed {
+        msg: b"not audited",
+        ..signed
+    };
+    assert!(matches!(
+        verify_transcript(&json, &anchor, &pp, h, &session, other_msg),
+        Err(TranscriptError::InvalidSignature)
+    ));
+    assert!(matches!(
+        verify_transcript(&json, &anchor, &other_pp, h, &session, signed),
+        Err(TranscriptError::KeyringMismatch)
+    ));
+
+    // the nonce dealings verify only in the session they were dealt in
+    let other = PresignSession::default();
+    assert!(matches!(
+        verify_transcript(&json, &anchor, &pp, h, &other, signed),
+        Err(TranscriptError::InvalidProof { .. })
+    ));
+}
+
+#[test]
+pub fn test_transcript_skips_rejected_messages() {
+    use crate::spdz::simulate_pp;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let state = DkgState::new(1, &pp, h, &secret_keys[&1], false);
+    let mut recorded = Recorded::new(0, state, Transcript::new(b"ceremony 1", &pp));
+    let outgoing = recorded.wants_to_send();
+    assert_eq!(recorded.transcript.links.len(), outgoing.len());
+    let digest = recorded.transcript.digest();
+
+    // a party outside the committee, and a dealing sent twice, are turned away unrecorded
+    let Outgoing { msg, .. } = outgoing.into_iter().next().unwrap();
+    assert!(recorded.handle_message(pp.n, msg.clone()).is_err());
+    assert_eq!(recorded.transcript.digest(), digest);
+    recorded.handle_message(1, msg.clone()).unwrap();
+    let digest = recorded.transcript.digest();
+    assert!(recorded.handle_message(1, msg).is_err());
+    assert_eq!(recorded.transcript.digest(), digest);
+}
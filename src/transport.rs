@@ -0,0 +1,742 @@
+//! Message transport behind a trait, so that the protocols can run over any network
+//! layer, such as a gossip network, and not only over `round_based`. Most messages of
+//! this crate's protocols are broadcasts; the few addressed to one party, such as the
+//! leaves of light dealing, go to that party alone.
+
+use std::future::Future;
+
+use futures::future::{select, Either};
+use futures::{SinkExt, StreamExt};
+use round_based::{
+    Delivery, MessageDestination, Mpc, MpcParty, Outgoing, PartyIndex, ProtocolMessage,
+};
+
+use serde::Serialize;
+
+use crate::codec::encoded_len;
+use crate::spdz::{Error, StateError, StateMachine};
+use crate::utils::ProtocolLimits;
+
+pub trait Transport<M> {
+    type SendError;
+    type ReceiveError;
+
+    /// Sends `msg`, the sender's message for `round`, to every other party.
+    fn broadcast(
+        &mut self,
+        round: u16,
+        msg: M,
+    ) -> impl Future<Output = Result<(), Self::SendError>>;
+
+    /// Sends `msg`, the sender's message for `round`, to the party at wire index
+    /// `recipient` alone.
+    fn send_to(
+        &mut self,
+        round: u16,
+        recipient: PartyIndex,
+        msg: M,
+    ) -> impl Future<Output = Result<(), Self::SendError>>;
+
+    /// Waits for messages of `round` that were not returned before, each with the wire
+    /// index of its sender. Late messages of earlier rounds may come along. An empty
+    /// result means that no more messages will arrive.
+    fn receive(
+        &mut self,
+        round: u16,
+    ) -> impl Future<Output = Result<Vec<(usize, M)>, Self::ReceiveError>>;
+}
+
+/// Sends `outgoing` to its recipient, or to everyone if it is a broadcast.
+pub(crate) async fn deliver<M, T>(
+    transport: &mut T,
+    outgoing: Outgoing<M>,
+) -> Result<(), T::SendError>
+where
+    M: ProtocolMessage,
+    T: Transport<M>,
+{
+    let Outgoing { recipient, msg } = outgoing;
+    match recipient {
+        MessageDestination::AllParties => transport.broadcast(msg.round(), msg).await,
+        MessageDestination::OneParty(j) => transport.send_to(msg.round(), j, msg).await,
+    }
+}
+
+/// What a driver built on `drive` adds to the loop of `run_over`: a timer for each
+/// round, and what to do when it fires.
+pub(crate) trait Hooks<S> {
+    type Timer: Future<Output = ()>;
+
+    /// Starts the timer of a round the state has just sent in, or another period of
+    /// it. `None` waits for messages alone.
+    fn start_timer(&mut self) -> Option<Self::Timer>;
+
+    /// Called when the timer fires. Returns whether to start another period of it;
+    /// otherwise what arrives later in the round is handled as usual. Messages the
+    /// state queued are sent even if this fails, as far as they get through.
+    fn on_timer(&mut self, state: &mut S) -> Result<bool, StateError>;
+}
+
+/// The hooks of `run_over`, which waits for messages alone.
+struct Untimed;
+
+impl<S> Hooks<S> for Untimed {
+    type Timer = futures::future::Pending<()>;
+
+    fn start_timer(&mut self) -> Option<Self::Timer> {
+        None
+    }
+
+    fn on_timer(&mut self, _state: &mut S) -> Result<bool, StateError> {
+        Ok(false)
+    }
+}
+
+/// The loop every driver of this crate shares. The state is in the round of the last
+/// message it sent, and is handed whatever arrives for that round until it finishes.
+/// Each message goes to its recipient, and one from a wire index beyond the range of
+/// `PartyIndex` fails the run.
+pub(crate) async fn drive<S, T, H>(
+    transport: &mut T,
+    mut state: S,
+    hooks: &mut H,
+) -> Result<S::Output, Error<T::ReceiveError, T::SendError>>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage,
+    T: Transport<S::Msg>,
+    H: Hooks<S>,
+{
+    let mut round = 0;
+    let mut timed_round = None; // the round the timer was last started for
+    let mut timer = None;
+    loop {
+        for outgoing in state.wants_to_send() {
+            round = round.max(outgoing.msg.round());
+            deliver(transport, outgoing).await.map_err(Error::Send)?;
+        }
+        if timed_round.map_or(true, |timed| timed < round) {
+            timed_round = Some(round);
+            timer = hooks.start_timer().map(Box::pin);
+        }
+
+        state = match state.try_finish() {
+            Ok(output) => return Ok(output),
+            Err(state) => state,
+        };
+
+        let received = match &mut timer {
+            // the timer goes first, so that a transport that keeps delivering does
+            // not hold off a timer that has fired
+            Some(pending) => {
+                match select(pending.as_mut(), Box::pin(transport.receive(round))).await {
+                    Either::Left(_) => None,
+                    Either::Right((received, _)) => Some(received.map_err(Error::Recv)?),
+                }
+            }
+            None => Some(transport.receive(round).await.map_err(Error::Recv)?),
+        };
+        let received = match received {
+            Some(received) => received,
+            None => {
+                let fired = hooks.on_timer(&mut state);
+                if let Err(err) = fired {
+                    for outgoing in state.wants_to_send() {
+                        let _ = deliver(transport, outgoing).await;
+                    }
+                    return Err(Error::from(err));
+                }
+                timer = match fired {
+                    Ok(true) => hooks.start_timer().map(Box::pin),
+                    _ => None,
+                };
+                continue;
+            }
+        };
+        if received.is_empty() {
+            return Err(Error::UnexpectedEof);
+        }
+        for (from, msg) in received {
+            let from =
+                PartyIndex::try_from(from).map_err(|_| Error::SenderOutOfRange { index: from })?;
+            state.handle_message(from, msg).map_err(Error::from)?;
+        }
+    }
+}
+
+/// Drives a state machine over `transport`. The state is in the round of the last
+/// message it sent, and is handed whatever arrives for that round until it finishes.
+pub async fn run_over<S, T>(
+    transport: &mut T,
+    state: S,
+) -> Result<S::Output, Error<T::ReceiveError, T::SendError>>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage,
+    T: Transport<S::Msg>,
+{
+    drive(transport, state, &mut Untimed).await
+}
+
+/// Like `run_over`, but a message longer than the committee's `max_message_bytes` fails
+/// the run before the state is handed it, as `Encoded::with_limits` does for encoded
+/// messages. The protocol functions run within `PubParams::limits`.
+pub async fn run_over_within<S, T>(
+    transport: &mut T,
+    state: S,
+    limits: &ProtocolLimits,
+) -> Result<S::Output, Error<T::ReceiveError, T::SendError>>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage + Serialize,
+    T: Transport<S::Msg>,
+{
+    let state = WithinLimits {
+        inner: state,
+        max_bytes: limits.max_message_bytes,
+    };
+    drive(transport, state, &mut Untimed).await
+}
+
+/// The state of `run_over_within`, which turns away messages over `max_bytes`.
+struct WithinLimits<S> {
+    inner: S,
+    max_bytes: u64,
+}
+
+impl<S> StateMachine for WithinLimits<S>
+where
+    S: StateMachine,
+    S::Msg: Serialize,
+{
+    type Msg = S::Msg;
+    type Output = S::Output;
+
+    fn handle_message(&mut self, from: PartyIndex, msg: S::Msg) -> Result<(), StateError> {
+        if encoded_len(&msg) > self.max_bytes {
+            return Err(StateError::MessageTooLong {
+                from,
+                max: self.max_bytes,
+            });
+        }
+        self.inner.handle_message(from, msg)
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<S::Msg>> {
+        self.inner.wants_to_send()
+    }
+
+    fn try_finish(self) -> Result<S::Output, Self> {
+        let WithinLimits { inner, max_bytes } = self;
+        inner
+            .try_finish()
+            .map_err(|inner| WithinLimits { inner, max_bytes })
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        self.inner.on_timeout()
+    }
+
+    fn on_version(&mut self, version: u16) {
+        self.inner.on_version(version)
+    }
+}
+
+/// Like `run_over`, but a robust round does not wait for all n parties indefinitely:
+/// each round the state sends in starts a timer from `deadline`, and once it fires the
+/// state goes ahead with the messages it has, or fails if they are fewer than t. The
+/// timer is any future, e.g. `|| tokio::time::sleep(ROUND_TIMEOUT)`, which keeps the
+/// crate free of a particular runtime.
+///
+/// Parties whose deadlines pass at different times may go ahead with different
+/// messages, and so with different qualified sets. A DKG therefore only goes ahead at
+/// a deadline with `DkgState::with_key_confirmation`, whose round tells them apart, and
+/// the nonce checks of presigning do the same there, failing the run rather than
+/// producing outputs that do not fit together.
+pub async fn run_with_deadlines<S, T, D, F>(
+    transport: &mut T,
+    state: S,
+    deadline: D,
+) -> Result<S::Output, Error<T::ReceiveError, T::SendError>>
+where
+    S: StateMachine,
+    S::Msg: ProtocolMessage,
+    T: Transport<S::Msg>,
+    D: FnMut() -> F,
+    F: Future<Output = ()>,
+{
+    drive(transport, state, &mut Deadlines(deadline)).await
+}
+
+/// The hooks of `run_with_deadlines`: once per round, the state times out.
+struct Deadlines<D>(D);
+
+impl<S, D, F> Hooks<S> for Deadlines<D>
+where
+    S: StateMachine,
+    D: FnMut() -> F,
+    F: Future<Output = ()>,
+{
+    type Timer = F;
+
+    fn start_timer(&mut self) -> Option<F> {
+        Some((self.0)())
+    }
+
+    fn on_timer(&mut self, state: &mut S) -> Result<bool, StateError> {
+        state.on_timeout().map(|()| false)
+    }
+}
+
+/// A `round_based` party as a transport. Messages of rounds the party has not reached
+/// yet are held back until it asks for them.
+pub struct RoundBasedTransport<M: Mpc> {
+    incoming: <M::Delivery as Delivery<M::ProtocolMessage>>::Receive,
+    outgoing: <M::Delivery as Delivery<M::ProtocolMessage>>::Send,
+    early: Vec<(PartyIndex, M::ProtocolMessage)>,
+}
+
+impl<M: Mpc> RoundBasedTransport<M> {
+    pub fn new(party: M) -> Self {
+        let MpcParty { delivery, .. } = party.into_party();
+        let (incoming, outgoing) = delivery.split();
+        RoundBasedTransport {
+            incoming,
+            outgoing,
+            early: vec![],
+        }
+    }
+}
+
+impl<M> Transport<M::ProtocolMessage> for RoundBasedTransport<M>
+where
+    M: Mpc,
+    M::ProtocolMessage: ProtocolMessage,
+{
+    type SendError = M::SendError;
+    type ReceiveError = M::ReceiveError;
+
+    async fn broadcast(
+        &mut self,
+        _round: u16,
+        msg: M::ProtocolMessage,
+    ) -> Result<(), M::SendError> {
+        self.outgoing.send(Outgoing::broadcast(msg)).await
+    }
+
+    async fn send_to(
+        &mut self,
+        _round: u16,
+        recipient: PartyIndex,
+        msg: M::ProtocolMessage,
+    ) -> Result<(), M::SendError> {
+        self.outgoing.send(Outgoing::p2p(recipient, msg)).await
+    }
+
+    async fn receive(
+        &mut self,
+        round: u16,
+    ) -> Result<Vec<(usize, M::ProtocolMessage)>, M::ReceiveError> {
+        let (ready, early): (Vec<_>, Vec<_>) = std::mem::take(&mut self.early)
+            .into_iter()
+            .partition(|(_, msg)| msg.round() <= round);
+        self.early = early;
+        if !ready.is_empty() {
+            return Ok(ready
+                .into_iter()
+                .map(|(from, msg)| (from.into(), msg))
+                .collect());
+        }
+
+        while let Some(incoming) = self.incoming.next().await {
+            let incoming = incoming?;
+            if incoming.msg.round() <= round {
+                return Ok(vec![(incoming.sender.into(), incoming.msg)]);
+            }
+            self.early.push((incoming.sender, incoming.msg));
+        }
+        Ok(vec![])
+    }
+}
+
+/// A committee sharing one in-memory board of messages, each posted with its round,
+/// sender and recipient, if it has one. A party waiting for a round nobody has posted
+/// to yet yields to the executor and looks again.
+#[cfg(test)]
+pub(crate) struct MemoryTransport<M> {
+    me: usize,
+    board: std::rc::Rc<std::cell::RefCell<Vec<Posted<M>>>>,
+    delivered: usize, // how much of the board has been looked at
+    skipped: Vec<(u16, usize, M)>,
+}
+
+#[cfg(test)]
+type Posted<M> = (u16, usize, Option<usize>, M);
+
+#[cfg(test)]
+impl<M: Clone> MemoryTransport<M> {
+    pub(crate) fn committee(n: usize) -> Vec<Self> {
+        let board = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        (0..n)
+            .map(|me| MemoryTransport {
+                me,
+                board: board.clone(),
+                delivered: 0,
+                skipped: vec![],
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl<M: Clone> Transport<M> for MemoryTransport<M> {
+    type SendError = std::convert::Infallible;
+    type ReceiveError = std::convert::Infallible;
+
+    async fn broadcast(&mut self, round: u16, msg: M) -> Result<(), Self::SendError> {
+        self.board.borrow_mut().push((round, self.me, None, msg));
+        Ok(())
+    }
+
+    async fn send_to(
+        &mut self,
+        round: u16,
+        recipient: PartyIndex,
+        msg: M,
+    ) -> Result<(), Self::SendError> {
+        let recipient = Some(recipient.into());
+        self.board
+            .borrow_mut()
+            .push((round, self.me, recipient, msg));
+        Ok(())
+    }
+
+    async fn receive(&mut self, round: u16) -> Result<Vec<(usize, M)>, Self::ReceiveError> {
+        loop {
+            let posted: Vec<_> = self.board.borrow()[self.delivered..].to_vec();
+            self.delivered += posted.len();
+            self.skipped.extend(
+                posted
+                    .into_iter()
+                    .filter(|&(_, from, to, _)| {
+                        from != self.me && to.map_or(true, |to| to == self.me)
+                    })
+                    .map(|(round, from, _, msg)| (round, from, msg)),
+            );
+
+            let (ready, later): (Vec<_>, Vec<_>) = std::mem::take(&mut self.skipped)
+                .into_iter()
+                .partition(|&(r, _, _)| r <= round);
+            self.skipped = later;
+            if !ready.is_empty() {
+                return Ok(ready
+                    .into_iter()
+                    .map(|(_, from, msg)| (from, msg))
+                    .collect());
+            }
+
+            let mut yielded = false;
+            futures::future::poll_fn(|cx| {
+                if yielded {
+                    std::task::Poll::Ready(())
+                } else {
+                    yielded = true;
+                    cx.waker().wake_by_ref();
+                    std::task::Poll::Pending
+                }
+            })
+            .await;
+        }
+    }
+}
+
+#[test]
+pub fn test_protocol_over_memory_transport() {
+    use crate::spdz::{
+        simulate_pp, DkgState, PresignSession, PresignState, SignState, SigningContext,
+    };
+    use crate::utils::G;
+    use futures::executor::block_on;
+    use futures::future::try_join_all;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let (pp, secret_keys) = (&pp, &secret_keys);
+    let h = G::base_point2();
+    let ids = 1..=pp.n;
+
+    // the wire index of share index i is i - 1, which is also its place on the board
+    let keys = block_on(try_join_all(
+        MemoryTransport::committee(pp.n.into())
+            .into_iter()
+            .zip(ids.clone())
+            .map(|(mut transport, i)| async move {
+                let state = DkgState::new(i, pp, h, &secret_keys[&i], false);
+                run_over(&mut transport, state).await
+            }),
+    ))
+    .unwrap();
+    assert!(keys.iter().all(|key| key.tpk == keys[0].tpk));
+
+    let presignatures = block_on(try_join_all(
+        MemoryTransport::committee(pp.n.into())
+            .into_iter()
+            .zip(ids.clone())
+            .zip(&keys)
+            .map(|((mut transport, i), key)| async move {
+                let state =
+                    PresignState::new(i, pp, h, &secret_keys[&i], key, PresignSession::default());
+                run_over(&mut transport, state).await
+            }),
+    ))
+    .unwrap();
+
+    let msg = b"gossiped, not simulated";
+    let context = SigningContext::from_dkg(pp, &keys[0]);
+    let outcomes = block_on(try_join_all(
+        MemoryTransport::committee(pp.n.into())
+            .into_iter()
+            .zip(ids)
+            .zip(presignatures)
+            .map(|((mut transport, i), presignature)| {
+                let context = &context;
+                async move {
+                    let state = SignState::new(i, pp, h, context, presignature, msg);
+                    run_over(&mut transport, state).await
+                }
+            }),
+    ))
+    .unwrap();
+
+    assert_eq!(outcomes.len(), pp.n as usize);
+    assert!(outcomes
+        .iter()
+        .all(|outcome| outcome.signature.verify(&context.pk, msg)));
+}
+
+#[test]
+pub fn test_run_within_limits() {
+    use crate::spdz::{simulate_pp, DkgState};
+    use crate::utils::G;
+    use futures::executor::block_on;
+    use futures::future::join_all;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let (pp, secret_keys) = (&pp, &secret_keys);
+    let h = G::base_point2();
+    let dkg = |limits: ProtocolLimits| {
+        block_on(join_all(
+            MemoryTransport::committee(pp.n.into())
+                .into_iter()
+                .zip(1..=pp.n)
+                .map(|(mut transport, i)| async move {
+                    let state = DkgState::new(i, pp, h, &secret_keys[&i], false);
+                    run_over_within(&mut transport, state, &limits).await
+                }),
+        ))
+    };
+
+    let keys = dkg(pp.limits);
+    assert!(keys.iter().all(|key| key.is_ok()));
+
+    // every dealing is longer than 64 bytes, so nobody gets to see one
+    let tight = ProtocolLimits {
+        max_message_bytes: 64,
+        ..pp.limits
+    };
+    for outcome in dkg(tight) {
+        assert!(matches!(
+            outcome,
+            Err(Error::State(StateError::MessageTooLong { max: 64, .. }))
+        ));
+    }
+}
+
+#[test]
+pub fn test_deadline_proceeds_without_laggard() {
+    use crate::spdz::{
+        simulate_pp, DkgState, PresignSession, PresignState, SignState, SigningContext, StateError,
+    };
+    use crate::utils::G;
+    use futures::executor::block_on;
+    use futures::future::{poll_fn, try_join_all};
+    use std::task::Poll;
+
+    // counted in polls rather than time, so that the test does not depend on how fast
+    // the machine is; every honest message is on the board long before it runs out
+    let deadline = || {
+        let mut polls = 0;
+        poll_fn(move |cx| {
+            polls += 1;
+            if polls > 100 {
+                return Poll::Ready(());
+            }
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+    };
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let (pp, secret_keys) = (&pp, &secret_keys);
+    let h = G::base_point2();
+    let ids = 1..pp.n; // party 3 is too late for every round
+
+    let keys = block_on(try_join_all(
+        MemoryTransport::committee(pp.n.into())
+            .into_iter()
+            .zip(ids.clone())
+            .map(|(mut transport, i)| async move {
+                let state = DkgState::new(i, pp, h, &secret_keys[&i], false)
+                    .with_key_confirmation(b"without party 3");
+                run_with_deadlines(&mut transport, state, deadline).await
+            }),
+    ))
+    .unwrap();
+    assert_eq!(keys[0].tpk, keys[1].tpk);
+    assert_eq!(keys[0].qualified, vec![1, 2]);
+
+    let presignatures = block_on(try_join_all(
+        MemoryTransport::committee(pp.n.into())
+            .into_iter()
+            .zip(ids.clone())
+            .zip(&keys)
+            .map(|((mut transport, i), key)| async move {
+                let state =
+                    PresignState::new(i, pp, h, &secret_keys[&i], key, PresignSession::default());
+                run_with_deadlines(&mut transport, state, deadline).await
+            }),
+    ))
+    .unwrap();
+
+    let msg = b"signed by the punctual";
+    let context = SigningContext::from_dkg(pp, &keys[0]);
+    let outcomes = block_on(try_join_all(
+        MemoryTransport::committee(pp.n.into())
+            .into_iter()
+            .zip(ids)
+            .zip(presignatures)
+            .map(|((mut transport, i), presignature)| {
+                let context = &context;
+                async move {
+                    let state = SignState::new(i, pp, h, context, presignature, msg);
+                    run_with_deadlines(&mut transport, state, deadline).await
+                }
+            }),
+    ))
+    .unwrap();
+    assert!(outcomes
+        .iter()
+        .all(|outcome| outcome.signature.verify(&context.pk, msg)));
+
+    // alone, a party cannot go ahead
+    let mut transport = MemoryTransport::committee(pp.n.into()).remove(0);
+    let state = DkgState::new(1, pp, h, &secret_keys[&1], false).with_key_confirmation(b"alone");
+    assert!(matches!(
+        block_on(run_with_deadlines(&mut transport, state, deadline)),
+        Err(Error::State(StateError::TooFewQualified {
+            got: 1,
+            need: 2,
+            ..
+        }))
+    ));
+
+    // nor does a DKG without key confirmation, even with enough messages
+    let keys = block_on(try_join_all(
+        MemoryTransport::committee(pp.n.into())
+            .into_iter()
+            .zip(1..pp.n)
+            .map(|(mut transport, i)| async move {
+                let state = DkgState::new(i, pp, h, &secret_keys[&i], false);
+                run_with_deadlines(&mut transport, state, deadline).await
+            }),
+    ));
+    assert!(matches!(
+        keys,
+        Err(Error::State(StateError::KeyConfirmationRequired))
+    ));
+}
+
+#[test]
+pub fn test_point_to_point_over_memory_transport() {
+    use crate::spdz::{simulate_pp, DkgState};
+    use crate::utils::G;
+    use futures::executor::block_on;
+    use futures::future::try_join_all;
+
+    // a message for one party reaches it alone
+    let mut committee = MemoryTransport::committee(3);
+    block_on(committee[0].send_to(0, 1, "for party 1")).unwrap();
+    block_on(committee[0].broadcast(0, "for everyone")).unwrap();
+    assert_eq!(
+        block_on(committee[1].receive(0)).unwrap(),
+        vec![(0, "for party 1"), (0, "for everyone")]
+    );
+    assert_eq!(
+        block_on(committee[2].receive(0)).unwrap(),
+        vec![(0, "for everyone")]
+    );
+
+    // so light dealing, whose leaves go point-to-point, runs over it
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let (pp, secret_keys) = (&pp, &secret_keys);
+    let h = G::base_point2();
+    let keys = block_on(try_join_all(
+        MemoryTransport::committee(pp.n.into())
+            .into_iter()
+            .zip(1..=pp.n)
+            .map(|(mut transport, i)| async move {
+                let state = DkgState::new(i, pp, h, &secret_keys[&i], false)
+                    .with_light_dealing(b"point to point test")
+                    .unwrap();
+                run_over(&mut transport, state).await
+            }),
+    ))
+    .unwrap();
+    assert!(keys.iter().all(|key| key.tpk == keys[0].tpk));
+}
+
+#[test]
+pub fn test_sender_out_of_range() {
+    use crate::spdz::{simulate_pp, DkgState};
+    use crate::utils::G;
+    use futures::executor::block_on;
+    use std::convert::Infallible;
+
+    /// Hands out `msg` as if sent by a wire index no party can have.
+    struct Stranger<M> {
+        msg: M,
+    }
+
+    impl<M: Clone> Transport<M> for Stranger<M> {
+        type SendError = Infallible;
+        type ReceiveError = Infallible;
+
+        async fn broadcast(&mut self, _round: u16, _msg: M) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        async fn send_to(
+            &mut self,
+            _round: u16,
+            _to: PartyIndex,
+            _msg: M,
+        ) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        async fn receive(&mut self, _round: u16) -> Result<Vec<(usize, M)>, Infallible> {
+            Ok(vec![(usize::from(PartyIndex::MAX) + 1, self.msg.clone())])
+        }
+    }
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let msg = DkgState::new(2, &pp, h, &secret_keys[&2], false)
+        .wants_to_send()
+        .remove(0)
+        .msg;
+    let state = DkgState::new(1, &pp, h, &secret_keys[&1], false);
+    assert!(matches!(
+        block_on(run_over(&mut Stranger { msg }, state)),
+        Err(Error::SenderOutOfRange { index }) if index == usize::from(PartyIndex::MAX) + 1
+    ));
+}
@@ -1,8 +1,7 @@
 use bicycl::{CL_HSMqk, CipherText, ClearText, Mpz, PublicKey, RandGen, SecretKey, QFI};
 use curv::{
     arithmetic::{BasicOps, Converter, Samplable},
-    cryptographic_primitives::hashing::merkle_tree::Proof,
-    elliptic::curves::{Point, Scalar, Secp256k1},
+    elliptic::curves::{Curve, Point, Scalar, Secp256k1, Secp256r1},
     BigInt,
 };
 use ecdsa::elliptic_curve::point;
@@ -13,28 +12,127 @@ use round_based::{
 };
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::{collections::BTreeMap, ops::Deref};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Deref,
+};
 use std::{
     error::Error,
     ops::{Add, Mul},
 };
 use thiserror::Error;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::cl_backend::ClBackend;
+use crate::cl_setup::{ClFromLevel, SecurityLevel};
+use crate::interpolation::{interpolate_at_zero, lagrange_coeffs_at_zero};
+
+use crate::par::*;
+
+/// The curve everything is computed over: secp256k1, or P-256 with the `curve-p256`
+/// feature. The CL message space must be the scalar field of this curve.
+#[cfg(not(feature = "curve-p256"))]
+pub type ProtocolCurve = Secp256k1;
+#[cfg(feature = "curve-p256")]
+pub type ProtocolCurve = Secp256r1;
+
+pub type Zq = Scalar<ProtocolCurve>;
+pub type G = Point<ProtocolCurve>;
+/// Party id in the range 1..=n, which caps committees at `MAX_PARTIES` parties.
+/// Ids enter transcripts as `to_be_bytes`, so changing the width changes every challenge.
+pub type Id = u16;
+
+pub const MAX_PARTIES: usize = Id::MAX as usize;
+
+/// A committee member. Protocol drivers address parties by their 0-based `PartyIndex`
+/// on the wire, while shares are evaluated at the 1-based `Id`; this is the only place
+/// that converts between the two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct PartyId(Id);
+
+impl PartyId {
+    pub fn from_share_index(id: Id) -> Self {
+        assert_ne!(id, 0, "share indices start at 1");
+        PartyId(id)
+    }
+
+    /// Returns `None` for indices beyond `MAX_PARTIES`.
+    pub fn from_wire_index(index: PartyIndex) -> Option<Self> {
+        let id = Id::try_from(index.checked_add(1)?).ok()?;
+        Some(PartyId(id))
+    }
+
+    /// The 1-based id keying shares, ciphertexts and MACs.
+    pub fn to_share_index(self) -> Id {
+        self.0
+    }
+
+    /// The 0-based index used by `round_based`.
+    pub fn to_wire_index(self) -> PartyIndex {
+        PartyIndex::from(self.0 - 1)
+    }
+
+    /// The nonzero point at which this party's share is evaluated.
+    pub fn eval_point(self) -> Zq {
+        party_eval_point(self.0)
+    }
+}
+
+/// The point at which the polynomials are evaluated for party `id`'s share. Ids are
+/// mapped to themselves, which keeps the points of 1..=MAX_PARTIES distinct and nonzero.
+pub fn party_eval_point(id: Id) -> Zq {
+    assert_ne!(id, 0, "party ids start at 1, 0 is where the secret sits");
+    Zq::from(id as u64)
+}
 
-use rayon::prelude::*;
+/// Big-endian encoding of the nonnegative `x`, left-padded with zeros to exactly `len` bytes.
+pub fn mpz_to_fixed_bytes(x: &Mpz, len: usize) -> Vec<u8> {
+    let bytes = x.to_bytes();
+    assert!(bytes.len() <= len, "integer does not fit in {len} bytes");
+    let mut padded = vec![0u8; len];
+    padded[len - bytes.len()..].copy_from_slice(&bytes);
+    padded
+}
+
+/// Reduces `x` mod q, whatever its sign or size. BICYCL encodes an `Mpz` by its
+/// magnitude alone, so a negative `x`, e.g. a difference of responses, is reduced as
+/// -x and then negated; reducing its encoding as is would give the scalar of -x.
+pub fn mpz_to_scalar(x: &Mpz) -> Zq {
+    let magnitude = Zq::from_bigint(&BigInt::from_bytes(&x.to_bytes()));
+    if x.sign() < 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
 
-pub type Zq = Scalar<Secp256k1>;
-pub type G = Point<Secp256k1>;
-pub type Id = u8;
+/// Reduces the nonnegative `x` mod q through a buffer of `len` bytes, so that the reduction
+/// always works on the same number of limbs however many leading zeros `x` has. This is
+/// best effort: the integer arithmetic underneath does not promise constant time.
+pub fn mpz_to_scalar_ct(x: &Mpz, len: usize) -> Zq {
+    debug_assert!(x.sign() >= 0, "mpz_to_scalar_ct takes nonnegative integers");
+    Zq::from_bigint(&BigInt::from_bytes(&mpz_to_fixed_bytes(x, len)))
+}
+
+/// Compares two scalars on their fixed-length encodings without exiting early.
+pub fn scalar_ct_eq(a: &Zq, b: &Zq) -> bool {
+    let (a, b) = (a.to_bytes(), b.to_bytes());
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
 
-/// Polynomial defined over Zq, with coefficients in ascending order
+/// Polynomial defined over the scalars of `E`, with coefficients in ascending order
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-pub struct Polynomial {
-    pub coeffs: Vec<Zq>,
+#[serde(bound = "")]
+pub struct Polynomial<E: Curve = ProtocolCurve> {
+    pub coeffs: Vec<Scalar<E>>,
 }
 
-impl Polynomial {
-    pub fn new(degree: Id, some_coeffs: &BTreeMap<Id, Zq>) -> Self {
-        let mut coeffs = vec![Zq::zero(); degree as usize + 1];
+impl<E: Curve> Polynomial<E> {
+    pub fn new(degree: Id, some_coeffs: &BTreeMap<Id, Scalar<E>>) -> Self {
+        let mut coeffs = vec![Scalar::zero(); degree as usize + 1];
         some_coeffs
             .iter()
             .take_while(|(&id, _)| id <= degree)
@@ -42,8 +140,8 @@ impl Polynomial {
         Self { coeffs }
     }
 
-    pub fn eval(&self, x: &Zq) -> Zq {
-        let mut result = Zq::zero();
+    pub fn eval(&self, x: &Scalar<E>) -> Scalar<E> {
+        let mut result = Scalar::zero();
         for i in (0..self.coeffs.len()).rev() {
             result = result * x + &self.coeffs[i];
         }
@@ -51,16 +149,86 @@ impl Polynomial {
     }
 }
 
+/// Overwrites the coefficients with zeros. A scalar wipes its memory when it is dropped,
+/// so replacing each coefficient leaves no copy of the secret behind.
+impl<E: Curve> Zeroize for Polynomial<E> {
+    fn zeroize(&mut self) {
+        for coeff in self.coeffs.iter_mut() {
+            *coeff = Scalar::zero();
+        }
+    }
+}
+
+impl<E: Curve> Drop for Polynomial<E> {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl<E: Curve> ZeroizeOnDrop for Polynomial<E> {}
+
+/// Overwrites secret shares, such as those of a fresh dealing once they are encrypted
+/// and proven, in the same way as `Polynomial::zeroize`.
+pub fn zeroize_shares(shares: &mut BTreeMap<Id, Zq>) {
+    for share in shares.values_mut() {
+        *share = Zq::zero();
+    }
+}
+
+/// Coefficient-wise sum, as long as the longer operand.
+impl<'a, E: Curve> Add<&'a Polynomial<E>> for &'a Polynomial<E> {
+    type Output = Polynomial<E>;
+
+    fn add(self, other: &Polynomial<E>) -> Polynomial<E> {
+        Polynomial {
+            coeffs: zip_longest(&self.coeffs, &other.coeffs, |a, b| a + b),
+        }
+    }
+}
+
+impl<E: Curve> Add for Polynomial<E> {
+    type Output = Polynomial<E>;
+
+    fn add(self, other: Polynomial<E>) -> Polynomial<E> {
+        &self + &other
+    }
+}
+
+impl<'a, E: Curve> Mul<&'a Scalar<E>> for &'a Polynomial<E> {
+    type Output = Polynomial<E>;
+
+    fn mul(self, scalar: &Scalar<E>) -> Polynomial<E> {
+        Polynomial {
+            coeffs: self.coeffs.iter().map(|c| c * scalar).collect(),
+        }
+    }
+}
+
+/// Combines two coefficient vectors position by position, taking the missing
+/// coefficients of the shorter one as they are.
+fn zip_longest<T: Clone>(a: &[T], b: &[T], f: impl Fn(&T, &T) -> T) -> Vec<T> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    long.iter()
+        .enumerate()
+        .map(|(i, x)| match short.get(i) {
+            Some(y) => f(x, y),
+            None => x.clone(),
+        })
+        .collect()
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-pub struct CurvePolynomial {
-    pub coeffs: Vec<G>,
+#[serde(bound = "")]
+pub struct CurvePolynomial<E: Curve = ProtocolCurve> {
+    #[serde(with = "crate::codec::compressed_points")]
+    pub coeffs: Vec<Point<E>>,
 }
 
-impl CurvePolynomial {
+impl<E: Curve> CurvePolynomial<E> {
     // trivial constructor makes very little sense.
     // should refactor to take a BTreeMap<Id, G> instead
-    pub fn new(degree: Id, some_coeffs: &BTreeMap<Id, G>) -> Self {
-        let mut coeffs = vec![G::zero(); degree as usize + 1];
+    pub fn new(degree: Id, some_coeffs: &BTreeMap<Id, Point<E>>) -> Self {
+        let mut coeffs = vec![Point::zero(); degree as usize + 1];
         some_coeffs
             .iter()
             .take_while(|(&id, _)| id <= degree)
@@ -68,34 +236,97 @@ impl CurvePolynomial {
         Self { coeffs }
     }
 
-    pub fn from_exp(polynomial: &Polynomial, generator: &G) -> Self {
+    pub fn from_exp(polynomial: &Polynomial<E>, generator: &Point<E>) -> Self {
         Self {
             coeffs: polynomial
                 .coeffs
-                .par_iter()
+                .as_slice()
+                .maybe_into_par_iter()
                 .map(|x| generator * x)
                 .collect(),
         }
     }
 
-    pub fn eval(&self, x: &Zq) -> G {
-        let mut result = G::zero();
+    pub fn eval(&self, x: &Scalar<E>) -> Point<E> {
+        crate::metrics::count_ec_mults(self.coeffs.len());
+        let mut result = Point::zero();
         for i in (0..self.coeffs.len()).rev() {
             result = result * x + &self.coeffs[i];
         }
         result
     }
+
+    /// Checks that every coefficient is a point of the prime-order group, which on
+    /// secp256k1 and P-256 (both of cofactor 1) means not the identity: curv builds points on the curve
+    /// only, and the decoder rejects received bytes that are not the canonical encoding
+    /// of such a point. The commitment to a random coefficient is the identity only
+    /// with negligible probability.
+    pub fn validate_points(&self) -> Result<(), PointError> {
+        match self.coeffs.iter().position(|coeff| coeff.is_zero()) {
+            Some(index) => Err(PointError::Identity { index }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PointError {
+    #[error("coefficient {index} is the point at infinity")]
+    Identity { index: usize },
+}
+
+impl CurvePolynomial {
+    /// The evaluations at the points of `ids`, in parallel: the commitments to their
+    /// shares when the polynomial is that of a sharing.
+    pub fn eval_at_parties(&self, ids: impl Iterator<Item = Id>) -> BTreeMap<Id, G> {
+        let ids: Vec<Id> = ids.collect();
+        ids.maybe_into_par_iter()
+            .map(|id| (id, self.eval(&party_eval_point(id))))
+            .collect()
+    }
+}
+
+/// Coefficient-wise sum, as long as the longer operand.
+impl<'a, E: Curve> Add<&'a CurvePolynomial<E>> for &'a CurvePolynomial<E> {
+    type Output = CurvePolynomial<E>;
+
+    fn add(self, other: &CurvePolynomial<E>) -> CurvePolynomial<E> {
+        CurvePolynomial {
+            coeffs: zip_longest(&self.coeffs, &other.coeffs, |a, b| a + b),
+        }
+    }
+}
+
+impl<E: Curve> Add for CurvePolynomial<E> {
+    type Output = CurvePolynomial<E>;
+
+    fn add(self, other: CurvePolynomial<E>) -> CurvePolynomial<E> {
+        &self + &other
+    }
+}
+
+impl<'a, E: Curve> Mul<&'a Scalar<E>> for &'a CurvePolynomial<E> {
+    type Output = CurvePolynomial<E>;
+
+    fn mul(self, scalar: &Scalar<E>) -> CurvePolynomial<E> {
+        CurvePolynomial {
+            coeffs: self.coeffs.iter().map(|c| c * scalar).collect(),
+        }
+    }
 }
 
 /// TODO: refactor to use the `CurvePolynomial` struct
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-pub struct QFPolynomial {
-    pub coeffs: Vec<QFI>,
+pub struct QFPolynomial<F = QFI> {
+    pub coeffs: Vec<F>,
 }
 
-impl QFPolynomial {
+impl<F: Clone> QFPolynomial<F> {
     /// Creates a class group polynomial with only some coefficients specified.
-    pub fn new(cl: &CL_HSMqk, degree: Id, some_coeffs: &BTreeMap<Id, QFI>) -> Self {
+    pub fn new<B>(cl: &B, degree: Id, some_coeffs: &BTreeMap<Id, F>) -> Self
+    where
+        B: ClBackend<Form = F>,
+    {
         let mut coeffs = vec![cl.one(); degree as usize + 1];
         some_coeffs
             .iter()
@@ -104,16 +335,69 @@ impl QFPolynomial {
         Self { coeffs }
     }
 
-    pub fn eval(&self, cl: &CL_HSMqk, x: &Zq) -> QFI {
+    /// Horner evaluation. The powers of `x` are never reduced, as the class group order is
+    /// unknown, so this stays cheaper than multi-exponentiating with precomputed powers.
+    pub fn eval<B: ClBackend<Form = F>>(&self, cl: &B, x: &Zq) -> F {
         let mut result = cl.one();
         let x = Mpz::from(x);
         for i in (0..self.coeffs.len()).rev() {
-            result = result.exp(cl, &x).compose(cl, &self.coeffs[i]);
+            result = cl.compose(&cl.exp(&result, &x), &self.coeffs[i]);
+        }
+        result
+    }
+
+    /// Evaluates with the given powers of the point, using one simultaneous exponentiation
+    /// with a 4-bit window shared by all coefficients.
+    pub fn eval_with_powers<B: ClBackend<Form = F>>(&self, cl: &B, powers: &[Mpz]) -> F {
+        assert_eq!(powers.len(), self.coeffs.len());
+
+        // big-endian exponent digits, and the first 16 powers of each coefficient
+        let digits: Vec<Vec<u8>> = powers
+            .iter()
+            .map(|e| {
+                e.to_bytes()
+                    .iter()
+                    .flat_map(|b| [b >> 4, b & 0xf])
+                    .collect()
+            })
+            .collect();
+        let tables: Vec<Vec<F>> = self
+            .coeffs
+            .iter()
+            .map(|coeff| {
+                let mut table = vec![cl.one(), coeff.clone()];
+                for d in 2..16 {
+                    table.push(cl.compose(&table[d - 1], coeff));
+                }
+                table
+            })
+            .collect();
+
+        let len = digits.iter().map(Vec::len).max().unwrap_or(0);
+        let mut result = cl.one();
+        for pos in 0..len {
+            for _ in 0..4 {
+                result = cl.compose(&result, &result);
+            }
+            for (digits, table) in digits.iter().zip(&tables) {
+                let offset = len - digits.len();
+                if pos >= offset && digits[pos - offset] != 0 {
+                    result = cl.compose(&result, &table[digits[pos - offset] as usize]);
+                }
+            }
         }
         result
     }
 }
 
+impl QFPolynomial {
+    /// The integer powers 1, x, x^2, ... to be passed to `eval_with_powers`.
+    pub fn powers(x: &Zq, count: usize) -> Vec<Mpz> {
+        let x = Mpz::from(x);
+        (0..count).map(|i| x.pow(i as u64)).collect()
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CLMultiRecvCiphertext {
     pub randomness: QFI,
@@ -121,13 +405,18 @@ pub struct CLMultiRecvCiphertext {
 }
 
 impl CLMultiRecvCiphertext {
+    /// Encrypts each plaintext to the key of its recipient in `keyring`. Fails on the
+    /// first recipient without a key.
     pub fn random(
         cl: &CL_HSMqk,
-        rng: &mut RandGen,
+        rng: &mut impl ClRandomness,
         keyring: &CLKeyRing,
         plaintexts: &BTreeMap<Id, Zq>,
-    ) -> (Self, Mpz) {
-        let r = rng.random_mpz(&cl.encrypt_randomness_bound());
+    ) -> Result<(Self, Mpz), CiphertextError> {
+        if let Some(&id) = plaintexts.keys().find(|id| keyring.get(id).is_none()) {
+            return Err(CiphertextError::MissingClKey { id });
+        }
+        let r = rng.sample_encrypt_randomness(cl);
 
         let randomness = cl.power_of_h(&r);
 
@@ -140,17 +429,287 @@ impl CLMultiRecvCiphertext {
             })
             .collect();
 
-        (
+        Ok((
             Self {
                 randomness,
                 encryption,
             },
             r,
-        )
+        ))
+    }
+
+    /// Decrypts the entry addressed to `id`.
+    pub fn decrypt(&self, cl: &CL_HSMqk, id: Id, sk: &SecretKey) -> Zq {
+        let ciphertext = CipherText::new(&self.randomness, &self.encryption[&id]);
+        mpz_to_scalar_ct(&cl.decrypt(sk, &ciphertext).mpz(), 32)
+    }
+
+    /// Checks that there is exactly one entry for each of the parties 1..=n. A dealing
+    /// that leaves someone out would otherwise have the neutral form stand in for that
+    /// share when it is combined with others.
+    pub fn validate(&self, n: Id) -> Result<(), CiphertextError> {
+        if let Some(&id) = self.encryption.keys().find(|&&id| !(1..=n).contains(&id)) {
+            return Err(CiphertextError::UnknownRecipient { id });
+        }
+        match (1..=n).find(|id| !self.encryption.contains_key(id)) {
+            Some(id) => Err(CiphertextError::MissingRecipient { id }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that every form has the discriminant of `cl`. Forms decoded from a peer can
+    /// have any discriminant, and composing them with ours is meaningless.
+    pub fn check_discriminant(&self, cl: &CL_HSMqk) -> Result<(), NizkError> {
+        let discriminant = cl.discriminant();
+        if std::iter::once(&self.randomness)
+            .chain(self.encryption.values())
+            .all(|form| form.discriminant() == discriminant)
+        {
+            Ok(())
+        } else {
+            Err(NizkError::DiscriminantMismatch)
+        }
     }
 }
 
-type CLKeyRing = BTreeMap<Id, PublicKey>;
+/// A sibling on the way from a leaf up to a Merkle root, on the side where it is hashed in.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MerkleSibling {
+    Left([u8; 32]),
+    Right([u8; 32]),
+}
+
+/// The siblings from a leaf up to the root. A level where the node has no sibling, as
+/// the last node of a level of odd length moves up unchanged, adds none.
+pub type MerklePath = Vec<MerkleSibling>;
+
+fn merkle_leaf(id: Id, ciphertext: &QFI) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([0u8])
+        .chain_update(id.to_be_bytes())
+        .chain_update(ciphertext.to_bytes())
+        .finalize()
+        .into()
+}
+
+fn merkle_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([1u8])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+impl CLMultiRecvCiphertext {
+    /// The levels of the Merkle tree over the (id, ciphertext) entries in the order of
+    /// ids, from the leaves up to the root.
+    fn merkle_levels(&self) -> Vec<Vec<[u8; 32]>> {
+        let mut levels = vec![self
+            .encryption
+            .iter()
+            .map(|(&id, ciphertext)| merkle_leaf(id, ciphertext))
+            .collect::<Vec<_>>()];
+        while levels[levels.len() - 1].len() > 1 {
+            let next = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => merkle_node(left, right),
+                    [last] => *last,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        levels
+    }
+
+    /// Commits to the encrypted shares, so that a recipient can check its own entry
+    /// against the commitment without seeing the others.
+    pub fn merkle_root(&self) -> [u8; 32] {
+        self.merkle_levels()
+            .last()
+            .and_then(|root| root.first().copied())
+            .unwrap_or_else(|| Sha256::digest(b"").into())
+    }
+
+    /// The path from the entry of `id` to the root, if there is such an entry.
+    pub fn membership_proof(&self, id: Id) -> Option<MerklePath> {
+        let mut index = self.encryption.keys().position(|&j| j == id)?;
+        let levels = self.merkle_levels();
+        let mut path = vec![];
+        for level in &levels[..levels.len() - 1] {
+            match index % 2 {
+                0 if index + 1 < level.len() => path.push(MerkleSibling::Right(level[index + 1])),
+                1 => path.push(MerkleSibling::Left(level[index - 1])),
+                _ => {}
+            }
+            index /= 2;
+        }
+        Some(path)
+    }
+
+    /// Checks that `ciphertext` is the entry of `id` under `root`.
+    pub fn verify_membership(root: &[u8; 32], id: Id, ciphertext: &QFI, path: &MerklePath) -> bool {
+        let node = path
+            .iter()
+            .fold(merkle_leaf(id, ciphertext), |node, sibling| match sibling {
+                MerkleSibling::Left(left) => merkle_node(left, &node),
+                MerkleSibling::Right(right) => merkle_node(&node, right),
+            });
+        node == *root
+    }
+}
+
+/// CL public keys of the committee, indexed by party id in the range 1..=n
+#[derive(Clone)]
+pub struct CLKeyRing(BTreeMap<Id, PublicKey>);
+
+impl CLKeyRing {
+    /// Generates key pairs for parties 1..=n in one go.
+    pub fn from_keygen(cl: &CL_HSMqk, rng: &mut RandGen, n: Id) -> (Self, BTreeMap<Id, SecretKey>) {
+        let mut public_keys = BTreeMap::new();
+        let mut secret_keys = BTreeMap::new();
+
+        for id in 1..=n {
+            let sk = cl.secret_key_gen(rng);
+            public_keys.insert(id, cl.public_key_gen(&sk));
+            secret_keys.insert(id, sk);
+        }
+
+        (Self(public_keys), secret_keys)
+    }
+
+    pub fn get(&self, id: &Id) -> Option<&PublicKey> {
+        self.0.get(id)
+    }
+
+    /// Registers `pk` for `id`, returning the key it replaces.
+    pub fn insert(&mut self, id: Id, pk: PublicKey) -> Option<PublicKey> {
+        self.0.insert(id, pk)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = Id> + '_ {
+        self.0.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Id, &PublicKey)> + '_ {
+        self.0.iter()
+    }
+
+    /// The keys of the committee 1..=n. Keys of anyone else the keyring may hold are
+    /// left out, so that they take no part in the dealings and their proofs.
+    pub fn committee(&self, n: Id) -> impl Iterator<Item = (&Id, &PublicKey)> + '_ {
+        self.0.range(1..=n)
+    }
+
+    /// The parties of the committee 1..=n that have no key.
+    pub fn missing(&self, n: Id) -> Vec<Id> {
+        (1..=n).filter(|id| !self.0.contains_key(id)).collect()
+    }
+
+    /// The parties with a key that are not in the committee 1..=n.
+    pub fn extra(&self, n: Id) -> Vec<Id> {
+        self.ids().filter(|id| !(1..=n).contains(id)).collect()
+    }
+}
+
+impl From<BTreeMap<Id, PublicKey>> for CLKeyRing {
+    fn from(keys: BTreeMap<Id, PublicKey>) -> Self {
+        Self(keys)
+    }
+}
+
+impl std::ops::Index<&Id> for CLKeyRing {
+    type Output = PublicKey;
+
+    fn index(&self, id: &Id) -> &PublicKey {
+        &self.0[id]
+    }
+}
+
+/// Bounds on what a committee may ask of its members. Every party allocates and computes
+/// in proportion to n and t, so they are capped before any key is generated or any
+/// message is received. The defaults leave room for any committee the protocol is
+/// practical for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProtocolLimits {
+    pub max_n: Id,
+    pub max_t: Id,
+    /// The longest encoded message a party accepts.
+    pub max_message_bytes: u64,
+}
+
+impl Default for ProtocolLimits {
+    fn default() -> Self {
+        ProtocolLimits {
+            max_n: 1024,
+            max_t: 1024,
+            max_message_bytes: crate::codec::MAX_MESSAGE_LEN,
+        }
+    }
+}
+
+impl ProtocolLimits {
+    /// Checks a t-of-n committee against the limits, and t against n.
+    pub fn check(&self, n: Id, t: Id) -> Result<(), LimitError> {
+        if n > self.max_n {
+            return Err(LimitError::TooManyParties { n, max: self.max_n });
+        }
+        if t > self.max_t {
+            return Err(LimitError::ThresholdTooLarge { t, max: self.max_t });
+        }
+        if t == 0 || t > n {
+            return Err(LimitError::InvalidThreshold { t, n });
+        }
+        Ok(())
+    }
+}
+
+/// How many dealings must verify before the DKG or a nonce generation combines the
+/// first t of them. With t - 1 corrupt parties at most, t dealings hold an honest one,
+/// which is what keeps the secret hidden; requiring 2t - 1 also makes sure the honest
+/// dealings outnumber the corrupt ones, and aborts the ceremony otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Qualification {
+    /// t dealings, the (t - 1) + 1 of an honest majority.
+    #[default]
+    HonestMajority,
+    /// 2t - 1 dealings, the 2(t - 1) + 1 of the robust setting.
+    Robust,
+}
+
+impl Qualification {
+    /// The number of dealings that must verify in a t-of-n committee.
+    pub fn min_qualified(self, t: Id) -> usize {
+        match self {
+            Qualification::HonestMajority => t as usize,
+            Qualification::Robust => 2 * t as usize - 1,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LimitError {
+    #[error("{n} parties exceed the limit of {max}")]
+    TooManyParties { n: Id, max: Id },
+    #[error("a threshold of {t} exceeds the limit of {max}")]
+    ThresholdTooLarge { t: Id, max: Id },
+    #[error("the threshold must be between 1 and {n}, got {t}")]
+    InvalidThreshold { t: Id, n: Id },
+    #[error("{need} dealings must verify, but there are only {n} parties")]
+    UnreachableQualification { need: usize, n: Id },
+    #[error("the class group must encode messages mod the order of the curve")]
+    ForeignMessageSpace,
+}
 
 pub struct PubParams {
     pub cl: CL_HSMqk,
@@ -158,37 +717,135 @@ pub struct PubParams {
     // any polynomial should be of degree t-1
     pub n: Id,
     pub cl_keyring: CLKeyRing,
+    // h, the base of the curve polynomials and MACs; passed to the protocol as `h` or
+    // `curve_generator`, and independent of the base point g
+    pub curve_generator: G,
+    pub limits: ProtocolLimits,
+    /// Whether the DKG deals in the compact PVSS mode, see `MaskedPvssDealing`, once a
+    /// hello round settles on `COMPACT_PVSS_VERSION` or later.
+    pub compact_pvss: bool,
+    /// How many dealings must verify before they are combined; a local policy, which
+    /// the fingerprint leaves out.
+    pub qualification: Qualification,
 }
 
 impl PubParams {
+    /// Sets up a fresh class group at `level`, with CL keys for parties 1..=n. Fails
+    /// if the committee exceeds the default limits.
+    pub fn with_level(
+        level: SecurityLevel,
+        n: Id,
+        t: Id,
+        rng: &mut RandGen,
+    ) -> Result<(Self, BTreeMap<Id, SecretKey>), LimitError> {
+        ProtocolLimits::default().check(n, t)?;
+        Self::with_cl(CL_HSMqk::from_level(level, rng), n, t, rng)
+    }
+
+    /// Generates CL keys for parties 1..=n in an existing class group, such as one
+    /// loaded from a setup file. Fails if the class group encodes messages mod another
+    /// q than the order of the curve, or if the committee exceeds the default limits.
+    pub fn with_cl(
+        cl: CL_HSMqk,
+        n: Id,
+        t: Id,
+        rng: &mut RandGen,
+    ) -> Result<(Self, BTreeMap<Id, SecretKey>), LimitError> {
+        if cl.q() != Mpz::from_bytes(&Zq::group_order().to_bytes()) {
+            return Err(LimitError::ForeignMessageSpace);
+        }
+        let limits = ProtocolLimits::default();
+        limits.check(n, t)?;
+        let (cl_keyring, secret_keys) = CLKeyRing::from_keygen(&cl, rng, n);
+        Ok((
+            PubParams {
+                cl,
+                t,
+                n,
+                cl_keyring,
+                curve_generator: G::base_point2().clone(),
+                limits,
+                compact_pvss: false,
+                qualification: Qualification::default(),
+            },
+            secret_keys,
+        ))
+    }
+
+    /// Holds the committee to other limits than the defaults, typically tighter ones.
+    pub fn with_limits(mut self, limits: ProtocolLimits) -> Result<Self, LimitError> {
+        limits.check(self.n, self.t)?;
+        self.limits = limits;
+        Ok(self)
+    }
+
+    /// Uses another base than the default `G::base_point2()` for the curve polynomials.
+    /// Nobody may know its discrete log to base g.
+    pub fn with_curve_generator(mut self, curve_generator: G) -> Self {
+        self.curve_generator = curve_generator;
+        self
+    }
+
+    /// Lets the DKG deal in the compact PVSS mode, for committees large enough that the
+    /// n forms of each dealing dominate the traffic. Committees whose members need to
+    /// check the dealings to everyone else, e.g. for a transcript, keep to the full mode.
+    /// A DKG deals compactly only with key confirmation, see `DkgState::with_compact_dealing`.
+    pub fn with_compact_pvss(mut self) -> Self {
+        self.compact_pvss = true;
+        self
+    }
+
+    /// Requires as many verified dealings as `qualification` does. Fails if the
+    /// committee is too small to ever meet it.
+    pub fn with_qualification(mut self, qualification: Qualification) -> Result<Self, LimitError> {
+        let need = qualification.min_qualified(self.t);
+        if need > self.n as usize {
+            return Err(LimitError::UnreachableQualification { need, n: self.n });
+        }
+        self.qualification = qualification;
+        Ok(self)
+    }
+
+    /// The number of dealings that must verify, see `Qualification`.
+    pub fn min_qualified(&self) -> usize {
+        self.qualification.min_qualified(self.t)
+    }
+
+    /// A digest of everything the committee must agree on: the class group, the
+    /// threshold, the committee size, the keyring, the curve generator and the PVSS
+    /// mode. Parties compare it out of band to catch a configuration that has drifted
+    /// apart.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let mut hasher =
+            Sha256::new().chain_update(instance_digest(&self.cl.discriminant(), self.t, self.n));
+        for (id, pk) in self.cl_keyring.iter() {
+            let pk = pk.to_bytes();
+            hasher.update(id.to_be_bytes());
+            hasher.update((pk.len() as u64).to_be_bytes());
+            hasher.update(&pk);
+        }
+        hasher.update(self.curve_generator.to_bytes(true));
+        // left out when off, which keeps the fingerprints from before the compact mode
+        if self.compact_pvss {
+            hasher.update(b"compact pvss");
+        }
+        hasher.finalize().into()
+    }
+
+    /// The Lagrange coefficients of `parties` at zero, or `None` if they are fewer than t.
     pub fn lagrange_coeffs(&self, parties: Vec<Id>) -> Option<BTreeMap<Id, Zq>> {
         if parties.len() < self.t as usize {
             return None;
         }
-
-        let mut coeffs = BTreeMap::new();
-        for i in &parties {
-            let mut num = Zq::from(1u64);
-            let mut den = Zq::from(1u64);
-            for j in &parties {
-                if i != j {
-                    num = num * Zq::from(*j as u64);
-                    den = den * Zq::from(*j as i32 - *i as i32);
-                }
-            }
-            coeffs.insert(*i, num * den.invert().unwrap());
-        }
-        Some(coeffs)
+        Some(lagrange_coeffs_at_zero(&parties))
     }
 
+    /// The secret behind `shares`, or `None` if they are fewer than t.
     pub fn interpolate(&self, shares: &BTreeMap<Id, Zq>) -> Option<Zq> {
-        let lagrange_coeffs = self.lagrange_coeffs(shares.keys().cloned().collect())?;
-        Some(
-            shares
-                .iter()
-                .map(|(i, share)| &lagrange_coeffs[i] * share)
-                .sum(),
-        )
+        if shares.len() < self.t as usize {
+            return None;
+        }
+        Some(interpolate_at_zero(shares))
     }
 }
 
@@ -206,25 +863,35 @@ pub struct PvssNizk {
 }
 
 impl PvssDealing {
+    /// The commitments to the shares of parties 1..=n, read off the curve polynomial.
+    /// The PVSS proof checks the ciphertexts against them, and they are the MACs a
+    /// single dealing contributes to a `JointPvssResult`. Callers that check several
+    /// things against one dealing should evaluate them once and keep the map.
+    pub fn shares_commitments(&self, n: Id) -> BTreeMap<Id, G> {
+        self.curve_polynomial.eval_at_parties(1..=n)
+    }
+
     pub fn random(
         pp: &PubParams,
-        rng: &mut RandGen,
+        rng: &mut impl ClRandomness,
         curve_generator: &G,
     ) -> (Self, Mpz, Polynomial, BTreeMap<Id, Zq>) {
         // make coefficients of a (t-1)-degree polynomial, and derive the shares
         let poly = Polynomial {
-            coeffs: (0..pp.t).map(|_| Zq::random()).collect(),
+            coeffs: (0..pp.t).map(|_| rng.sample_scalar()).collect(),
         };
 
         let shares = (1..=pp.n)
             .into_iter()
-            .map(|id| (id, poly.eval(&Zq::from(id as u64))))
+            .map(|id| (id, poly.eval(&party_eval_point(id))))
             .collect();
 
         let curve_polynomial = CurvePolynomial::from_exp(&poly, &curve_generator);
 
+        // the drivers check the keyring before anyone deals, see `CLKeyRing::missing`
         let (encrypted_shares, r) =
-            CLMultiRecvCiphertext::random(&pp.cl, rng, &pp.cl_keyring, &shares);
+            CLMultiRecvCiphertext::random(&pp.cl, rng, &pp.cl_keyring, &shares)
+                .unwrap_or_else(|err| panic!("cannot deal to the committee: {err}"));
 
         (
             Self {
@@ -236,29 +903,420 @@ impl PvssDealing {
             shares,
         )
     }
+
+    /// Like `random`, but publishes the curve polynomial of a different secret than
+    /// the one whose shares are encrypted. Every field is well-formed, only the
+    /// PVSS proof gives the dealer away.
+    #[cfg(test)]
+    pub fn new_malformed(
+        pp: &PubParams,
+        rng: &mut RandGen,
+        curve_generator: &G,
+    ) -> (Self, Mpz, Polynomial, BTreeMap<Id, Zq>) {
+        let (dealing, r, poly, shares) = Self::random(pp, rng, curve_generator);
+
+        let other_poly = Polynomial {
+            coeffs: (0..pp.t).map(|_| Zq::random()).collect(),
+        };
+
+        (
+            Self {
+                curve_polynomial: CurvePolynomial::from_exp(&other_poly, curve_generator),
+                ..dealing
+            },
+            r,
+            poly,
+            shares,
+        )
+    }
+
+    /// Creates `count` independent dealings concurrently.
+    /// Dealing i draws from the child of `rng` forked for it, so no generator state is
+    /// shared between threads, and the dealings only depend on the seed of `rng`.
+    pub fn new_many(
+        count: usize,
+        pp: &PubParams,
+        rng: &ForkableRng,
+        curve_generator: &G,
+    ) -> Vec<(Self, Mpz, Polynomial, BTreeMap<Id, Zq>)> {
+        (0..count as u64)
+            .maybe_into_par_iter()
+            .map(|i| Self::random(pp, &mut rng.fork("pvss dealing", i), curve_generator))
+            .collect()
+    }
+}
+
+/// Source of the exponents below the CL encryption randomness bound, used for the
+/// encryption randomness and for the matching NIZK commitments, and of the coefficients
+/// of secret polynomials. Tests can plug in a logging or deterministic source.
+pub trait ClRandomness {
+    fn sample_encrypt_randomness(&mut self, cl: &CL_HSMqk) -> Mpz;
+
+    /// A scalar for a secret polynomial. Unless the source overrides it, this is drawn
+    /// from the operating system, as `Zq::random` is.
+    fn sample_scalar(&mut self) -> Zq {
+        Zq::random()
+    }
+
+    /// A mask for a Schnorr-style response `u + e * w` whose witness `w` is below the
+    /// encryption randomness bound, drawn from `response_mask_bound`. Unless the source
+    /// overrides it, this is drawn from the operating system.
+    fn sample_response_mask(&mut self, cl: &CL_HSMqk) -> Mpz {
+        fresh_rng().random_mpz(&response_mask_bound(cl))
+    }
+}
+
+/// Bits by which a response mask outgrows the witness: the 128 bits of the challenge
+/// plus the statistical security parameter, as in `fud_factor`.
+const RESPONSE_MASK_SLACK_BITS: u64 = 128 + 40;
+
+/// Bound of the masks of the proofs on CL secret keys and encryption randomness. The
+/// mask has to outgrow `e * w` by the statistical security parameter for the response
+/// to hide the witness; a mask below the witness bound leaks it.
+pub fn response_mask_bound(cl: &CL_HSMqk) -> Mpz {
+    cl.encrypt_randomness_bound() * Mpz::from(2u64).pow(RESPONSE_MASK_SLACK_BITS)
+}
+
+/// Byte length bound of a response `u + e * w` with `u` below `response_mask_bound`.
+pub(crate) fn masked_response_len(pp: &PubParams) -> usize {
+    response_mask_bound(&pp.cl).to_bytes().len() + 1
+}
+
+impl ClRandomness for RandGen {
+    fn sample_encrypt_randomness(&mut self, cl: &CL_HSMqk) -> Mpz {
+        self.random_mpz(&cl.encrypt_randomness_bound())
+    }
+
+    fn sample_response_mask(&mut self, cl: &CL_HSMqk) -> Mpz {
+        self.random_mpz(&response_mask_bound(cl))
+    }
+
+    /// Reduces 512 bits, as `ForkableRng` does, so that a seeded generator draws its
+    /// scalars from the seed too.
+    fn sample_scalar(&mut self) -> Zq {
+        mpz_to_scalar(&self.random_mpz(&Mpz::from(2u64).pow(512)))
+    }
+}
+
+/// Creates a generator seeded from the platform's randomness source, which is the
+/// browser's `crypto.getRandomValues` on wasm.
+pub fn fresh_rng() -> RandGen {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).expect("the platform provides no randomness");
+    seeded_rng(&Mpz::from_bytes(&seed))
+}
+
+/// Creates a fresh generator from a seed, typically one drawn from another generator
+/// so that the two can be used on different threads.
+pub fn seeded_rng(seed: &Mpz) -> RandGen {
+    let mut rng = RandGen::new();
+    rng.set_seed(seed);
+    rng
+}
+
+/// A generator that hands out independent children instead of being shared. A child is
+/// seeded by a KDF over the parent's seed, a label and a counter, so work split across
+/// threads draws the same values whatever order it runs in, and a run is reproducible
+/// from the root seed. The state is just the seed and a draw counter, so the generator
+/// can be sent to another thread; every draw seeds a `RandGen` of its own.
+pub struct ForkableRng {
+    seed: [u8; 32],
+    draws: u64,
+}
+
+impl ForkableRng {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        ForkableRng { seed, draws: 0 }
+    }
+
+    /// A root generator seeded from the platform's randomness source.
+    pub fn fresh() -> Self {
+        let mut seed = [0u8; 32];
+        getrandom::getrandom(&mut seed).expect("the platform provides no randomness");
+        Self::from_seed(seed)
+    }
+
+    /// The child for `label` and `counter`. Forking does not advance the parent, and
+    /// children with different labels or counters draw unrelated streams.
+    pub fn fork(&self, label: &str, counter: u64) -> ForkableRng {
+        let mut hasher = TranscriptHasher::new("rng/fork");
+        hasher
+            .append("seed", &self.seed)
+            .append("label", label.as_bytes())
+            .append("counter", &counter.to_be_bytes());
+        Self::from_seed(hasher.finalize())
+    }
+
+    fn next_seed(&mut self) -> [u8; 32] {
+        let mut hasher = TranscriptHasher::new("rng/draw");
+        hasher
+            .append("seed", &self.seed)
+            .append("draw", &self.draws.to_be_bytes());
+        self.draws += 1;
+        hasher.finalize()
+    }
+
+    /// A uniform value below `bound`.
+    pub fn random_mpz(&mut self, bound: &Mpz) -> Mpz {
+        self.rand_gen().random_mpz(bound)
+    }
+
+    /// A `RandGen` seeded from the next draw, for code that takes one.
+    pub fn rand_gen(&mut self) -> RandGen {
+        seeded_rng(&Mpz::from_bytes(&self.next_seed()))
+    }
+}
+
+impl ClRandomness for ForkableRng {
+    fn sample_encrypt_randomness(&mut self, cl: &CL_HSMqk) -> Mpz {
+        self.random_mpz(&cl.encrypt_randomness_bound())
+    }
+
+    fn sample_response_mask(&mut self, cl: &CL_HSMqk) -> Mpz {
+        self.random_mpz(&response_mask_bound(cl))
+    }
+
+    /// Reduces 512 bits, so the bias towards small scalars is negligible.
+    fn sample_scalar(&mut self) -> Zq {
+        let wide = [self.next_seed(), self.next_seed()].concat();
+        Zq::from_bigint(&BigInt::from_bytes(&wide))
+    }
+}
+
+impl Drop for ForkableRng {
+    fn drop(&mut self) {
+        self.seed.zeroize();
+    }
+}
+
+/// Wire form of a `PvssDealing` without the parts the receiver can fill in: the curve
+/// coefficients are packed as 33-byte compressed points with no per-point curve tag,
+/// and the share ids are left out when they are just 1..=n.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PvssDealingCompact {
+    pub curve_coeffs: Vec<u8>,
+    pub randomness: QFI,
+    pub encryption: Vec<QFI>,
+    /// Ids of `encryption` in order; empty means 1..=encryption.len()
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ids: Vec<Id>,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CompactDealingError {
+    #[error("curve coefficients are not a sequence of compressed points")]
+    InvalidPoint,
+    #[error("{ids} ids given for {shares} encrypted shares")]
+    IdCountMismatch { ids: usize, shares: usize },
+    #[error("there are two encrypted shares for party {id}")]
+    DuplicateId { id: Id },
+}
+
+const COMPRESSED_POINT_LEN: usize = 33;
+
+impl From<PvssDealing> for PvssDealingCompact {
+    fn from(dealing: PvssDealing) -> Self {
+        let curve_coeffs = dealing
+            .curve_polynomial
+            .coeffs
+            .iter()
+            .flat_map(|coeff| coeff.to_bytes(true).to_vec())
+            .collect();
+
+        let CLMultiRecvCiphertext {
+            randomness,
+            encryption,
+        } = dealing.shares_ciphertext;
+        let ids: Vec<Id> = encryption.keys().copied().collect();
+        let contiguous = ids.iter().copied().eq(1..=ids.len() as Id);
+
+        PvssDealingCompact {
+            curve_coeffs,
+            randomness,
+            encryption: encryption.into_values().collect(),
+            ids: if contiguous { vec![] } else { ids },
+        }
+    }
+}
+
+impl TryFrom<PvssDealingCompact> for PvssDealing {
+    type Error = CompactDealingError;
+
+    fn try_from(compact: PvssDealingCompact) -> Result<Self, Self::Error> {
+        if compact.curve_coeffs.len() % COMPRESSED_POINT_LEN != 0 {
+            return Err(CompactDealingError::InvalidPoint);
+        }
+        let coeffs = compact
+            .curve_coeffs
+            .chunks(COMPRESSED_POINT_LEN)
+            .map(|bytes| G::from_bytes(bytes).map_err(|_| CompactDealingError::InvalidPoint))
+            .collect::<Result<Vec<G>, _>>()?;
+
+        let ids = if compact.ids.is_empty() {
+            (1..=compact.encryption.len() as Id).collect()
+        } else {
+            compact.ids
+        };
+        if ids.len() != compact.encryption.len() {
+            return Err(CompactDealingError::IdCountMismatch {
+                ids: ids.len(),
+                shares: compact.encryption.len(),
+            });
+        }
+        let mut encryption = BTreeMap::new();
+        for (id, share) in ids.into_iter().zip(compact.encryption) {
+            if encryption.insert(id, share).is_some() {
+                return Err(CompactDealingError::DuplicateId { id });
+            }
+        }
+
+        Ok(PvssDealing {
+            curve_polynomial: CurvePolynomial { coeffs },
+            shares_ciphertext: CLMultiRecvCiphertext {
+                randomness: compact.randomness,
+                encryption,
+            },
+        })
+    }
+}
+
+/// A dealing of the compact PVSS mode. Where a full dealing encrypts share s_j as the
+/// form f^s_j * pk_j^r, this one masks it as the scalar s_j + H(pk_j^r), which the
+/// recipient unmasks with pk_j^r = (h^r)^sk_j. An entry thus takes a scalar instead of
+/// a form. Nobody but the recipient can check it though, against its MAC on the curve
+/// polynomial, so the dealing comes without a PVSS proof.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MaskedPvssDealing {
+    pub curve_polynomial: CurvePolynomial,
+    pub randomness: QFI,
+    pub masked_shares: BTreeMap<Id, Zq>,
+}
+
+/// The mask of recipient `id`'s share, from the key pk_id^r it shares with the dealer.
+fn share_mask(id: Id, shared_key: &QFI) -> Zq {
+    let mut hasher = TranscriptHasher::new("pvss/share-mask");
+    hasher
+        .append("recipient", &id.to_be_bytes())
+        .append("shared key", &shared_key.to_bytes());
+    Zq::from_bigint(&BigInt::from_bytes(&hasher.finalize()))
+}
+
+impl MaskedPvssDealing {
+    /// Deals a random secret to parties 1..=n, returning their shares as well.
+    pub fn random(
+        pp: &PubParams,
+        rng: &mut impl ClRandomness,
+        curve_generator: &G,
+    ) -> (Self, BTreeMap<Id, Zq>) {
+        let poly = Polynomial {
+            coeffs: (0..pp.t).map(|_| rng.sample_scalar()).collect(),
+        };
+        let shares: BTreeMap<Id, Zq> = (1..=pp.n)
+            .map(|id| (id, poly.eval(&party_eval_point(id))))
+            .collect();
+
+        let r = rng.sample_encrypt_randomness(&pp.cl);
+        // the drivers check the keyring before anyone deals, see `CLKeyRing::missing`
+        let masked_shares = shares
+            .iter()
+            .map(|(&id, share)| {
+                let pk = pp
+                    .cl_keyring
+                    .get(&id)
+                    .unwrap_or_else(|| panic!("cannot deal to the committee: no CL key of {id}"));
+                (id, share + share_mask(id, &pk.exponentiation(&pp.cl, &r)))
+            })
+            .collect();
+
+        let dealing = MaskedPvssDealing {
+            curve_polynomial: CurvePolynomial::from_exp(&poly, curve_generator),
+            randomness: pp.cl.power_of_h(&r),
+            masked_shares,
+        };
+        (dealing, shares)
+    }
+
+    /// Unmasks the share of recipient `id` and checks it against its MAC. The dealing
+    /// comes back as the recipient would have it from a full dealing restricted to
+    /// itself, with the share encrypted as f^s_id * pk_id^r, so that it combines with
+    /// others into a `JointPvssResult` as in the full mode.
+    pub fn open(
+        &self,
+        pp: &PubParams,
+        curve_generator: &G,
+        id: Id,
+        cl_sk: &SecretKey,
+    ) -> Result<PvssDealing, NizkError> {
+        if self.curve_polynomial.coeffs.len() != pp.t as usize {
+            return Err(NizkError::DegreeMismatch {
+                got: self.curve_polynomial.coeffs.len(),
+                expected: pp.t as usize,
+            });
+        }
+        if self.randomness.discriminant() != pp.cl.discriminant() {
+            return Err(NizkError::DiscriminantMismatch);
+        }
+        let masked = self
+            .masked_shares
+            .get(&id)
+            .ok_or(NizkError::MembershipMismatch)?;
+
+        let shared_key = self.randomness.exp(&pp.cl, &cl_sk.mpz());
+        let share = masked - share_mask(id, &shared_key);
+        if curve_generator * &share != self.curve_polynomial.eval(&party_eval_point(id)) {
+            return Err(NizkError::ShareMismatch);
+        }
+
+        let encrypted_share = pp
+            .cl
+            .power_of_f(&Mpz::from(&share))
+            .compose(&pp.cl, &shared_key);
+        Ok(PvssDealing {
+            curve_polynomial: self.curve_polynomial.clone(),
+            shares_ciphertext: CLMultiRecvCiphertext {
+                randomness: self.randomness.clone(),
+                encryption: BTreeMap::from([(id, encrypted_share)]),
+            },
+        })
+    }
 }
 
 impl PvssNizk {
+    #[deprecated(note = "bind the proof to its context with `prove_with_aux`")]
     pub fn prove(
         pp: &PubParams,
         dealing: &PvssDealing,
         r: &Mpz,
         shares: &BTreeMap<Id, Zq>,
-        rng: &mut RandGen,
+        rng: &mut impl ClRandomness,
+        curve_generator: &G,
+    ) -> Self {
+        Self::prove_with_aux(pp, dealing, r, shares, rng, curve_generator, &[])
+    }
+
+    /// Proves the dealing in the context `aux`, e.g. a session id, which the verifier
+    /// must pass as well. An empty `aux` gives the proofs of `prove`.
+    pub fn prove_with_aux(
+        pp: &PubParams,
+        dealing: &PvssDealing,
+        r: &Mpz,
+        shares: &BTreeMap<Id, Zq>,
+        rng: &mut impl ClRandomness,
         curve_generator: &G,
+        aux: &[u8],
     ) -> Self {
-        let u1 = rng.random_mpz(&pp.cl.encrypt_randomness_bound());
-        let u2 = Zq::random();
+        let u1 = rng.sample_encrypt_randomness(&pp.cl);
+        let u2 = rng.sample_scalar();
         let U1 = &pp.cl.power_of_h(&u1);
         let U2 = curve_generator * &u2;
 
-        let gamma = PvssNizk::challenge1(pp, dealing, curve_generator);
+        let gamma = PvssNizk::challenge1(pp, dealing, curve_generator, aux);
 
         let U3 = QFPolynomial::new(
             &pp.cl,
             pp.n,
             &pp.cl_keyring
-                .iter()
+                .committee(pp.n)
                 .map(|(&id, pk)| (id, pk.elt()))
                 .collect(),
         )
@@ -274,8 +1332,62 @@ impl PvssNizk {
         Self { e, z1, z2 }
     }
 
+    #[deprecated(note = "check the proof in its context with `verify_with_aux`")]
     pub fn verify(&self, dealing: &PvssDealing, pp: &PubParams, curve_generator: &G) -> bool {
-        let gamma = Self::challenge1(pp, dealing, curve_generator);
+        self.verify_with_aux(dealing, pp, curve_generator, &[])
+    }
+
+    #[deprecated(note = "check the proof in its context with `verify_detailed_with_aux`")]
+    pub fn verify_detailed(
+        &self,
+        dealing: &PvssDealing,
+        pp: &PubParams,
+        curve_generator: &G,
+    ) -> Result<(), NizkError> {
+        self.verify_detailed_with_aux(dealing, pp, curve_generator, &[])
+    }
+
+    pub fn verify_with_aux(
+        &self,
+        dealing: &PvssDealing,
+        pp: &PubParams,
+        curve_generator: &G,
+        aux: &[u8],
+    ) -> bool {
+        self.verify_detailed_with_aux(dealing, pp, curve_generator, aux)
+            .is_ok()
+    }
+
+    /// Like `verify_with_aux`, telling which check failed. Besides the proof itself, the
+    /// dealing must share to the whole keyring with a polynomial of degree t-1.
+    pub fn verify_detailed_with_aux(
+        &self,
+        dealing: &PvssDealing,
+        pp: &PubParams,
+        curve_generator: &G,
+        aux: &[u8],
+    ) -> Result<(), NizkError> {
+        let degree = dealing.curve_polynomial.coeffs.len();
+        if degree != pp.t as usize {
+            return Err(NizkError::DegreeMismatch {
+                got: degree,
+                expected: pp.t as usize,
+            });
+        }
+        if !dealing
+            .shares_ciphertext
+            .encryption
+            .keys()
+            .eq(pp.cl_keyring.committee(pp.n).map(|(id, _)| id))
+        {
+            return Err(NizkError::PartySetMismatch);
+        }
+        dealing.shares_ciphertext.check_discriminant(&pp.cl)?;
+        if self.z1.to_bytes().len() > Self::response_len(pp) {
+            return Err(NizkError::DeserializationError);
+        }
+
+        let gamma = Self::challenge1(pp, dealing, curve_generator, aux);
 
         // U1
         let U1d = &dealing
@@ -285,12 +1397,7 @@ impl PvssNizk {
         let U1 = &pp.cl.power_of_h(&self.z1).compose(&pp.cl, &U1d);
 
         // U2
-        // curve polynomial defined by shares; O(tn), profile to decide whether to optimize
-        let shares_on_curve = (1..=pp.n)
-            .into_iter()
-            .map(|id| (id, dealing.curve_polynomial.eval(&Zq::from(id as u64))))
-            .collect();
-        let shares_curve_poly = CurvePolynomial::new(pp.n, &shares_on_curve);
+        let shares_curve_poly = CurvePolynomial::new(pp.n, &dealing.shares_commitments(pp.n));
         let U2 = curve_generator * &self.z2 - shares_curve_poly.eval(&gamma) * &self.e;
 
         // U3
@@ -302,7 +1409,7 @@ impl PvssNizk {
             &pp.cl,
             pp.n,
             &pp.cl_keyring
-                .iter()
+                .committee(pp.n)
                 .map(|(&id, pk)| (id, pk.elt()))
                 .collect(),
         )
@@ -312,38 +1419,176 @@ impl PvssNizk {
         .compose(&pp.cl, &U3d);
 
         let e = Self::challenge2(&gamma, &U1, &U2, &U3);
-        let result = e == self.e;
-        assert!(result);
-        result
+        if scalar_ct_eq(&e, &self.e) {
+            Ok(())
+        } else {
+            Err(NizkError::ChallengeMismatch)
+        }
+    }
+
+    /// Byte length bound of z1 = u1 + e * r, with u1 and r below the encryption
+    /// randomness bound and e below 2^128.
+    pub(crate) fn response_len(pp: &PubParams) -> usize {
+        pp.cl.encrypt_randomness_bound().to_bytes().len() + 16 + 1
+    }
+
+    fn challenge1(
+        pp: &PubParams,
+        pvss_dealing: &PvssDealing,
+        curve_generator: &G,
+        aux: &[u8],
+    ) -> Zq {
+        let mut hasher = TranscriptHasher::new("pvss/challenge1");
+        hasher.append(
+            "instance",
+            &instance_digest(&pp.cl.discriminant(), pp.t, pp.n),
+        );
+        for (id, pk) in pp.cl_keyring.committee(pp.n) {
+            hasher
+                .append("id", &id.to_be_bytes())
+                .append("cl_pk", &pk.to_bytes());
+        }
+        hasher.append(
+            "randomness",
+            &pvss_dealing.shares_ciphertext.randomness.to_bytes(),
+        );
+        for (id, enc) in &pvss_dealing.shares_ciphertext.encryption {
+            hasher
+                .append("id", &id.to_be_bytes())
+                .append("encryption", &enc.to_bytes());
+        }
+        hasher.append("curve_generator", &curve_generator.to_bytes(false));
+        for coeff in &pvss_dealing.curve_polynomial.coeffs {
+            hasher.append("coeff", &coeff.to_bytes(false));
+        }
+        append_aux(&mut hasher, aux);
+        hasher.challenge()
+    }
+
+    fn challenge2(gamma: &Zq, U1: &QFI, U2: &G, U3: &QFI) -> Zq {
+        let mut hasher = TranscriptHasher::new("pvss/challenge2");
+        hasher
+            .append("gamma", &gamma.to_bytes())
+            .append("U1", &U1.to_bytes())
+            .append("U2", &U2.to_bytes(false))
+            .append("U3", &U3.to_bytes());
+        hasher.challenge()
+    }
+}
+
+/// Proof that a dealing comes from the holder of the CL secret key registered for the
+/// dealer in the keyring: a Schnorr proof of knowledge of the key's discrete log to base
+/// h, with the dealing in the challenge, so that it signs the dealing.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MembershipProof {
+    pub e: Zq,
+    pub z: Mpz,
+}
+
+impl MembershipProof {
+    pub fn prove(
+        pp: &PubParams,
+        dealer: Id,
+        dealing: &PvssDealing,
+        cl_sk: &SecretKey,
+        rng: &mut impl ClRandomness,
+    ) -> Self {
+        let u = rng.sample_response_mask(&pp.cl);
+        let U = pp.cl.power_of_h(&u);
+        let e = Self::challenge(pp, dealer, dealing, &U);
+        let z = u + Mpz::from(&e) * &cl_sk.mpz();
+
+        Self { e, z }
+    }
+
+    pub fn verify(
+        &self,
+        pp: &PubParams,
+        dealer: Id,
+        dealing: &PvssDealing,
+    ) -> Result<(), NizkError> {
+        let pk = pp
+            .cl_keyring
+            .get(&dealer)
+            .ok_or(NizkError::NotInKeyring { id: dealer })?;
+        if self.z.to_bytes().len() > masked_response_len(pp) {
+            return Err(NizkError::DeserializationError);
+        }
+
+        let U = pp
+            .cl
+            .power_of_h(&self.z)
+            .compose(&pp.cl, &pk.elt().exp(&pp.cl, &-Mpz::from(&self.e)));
+
+        let e = Self::challenge(pp, dealer, dealing, &U);
+        if scalar_ct_eq(&e, &self.e) {
+            Ok(())
+        } else {
+            Err(NizkError::ChallengeMismatch)
+        }
+    }
+
+    fn challenge(pp: &PubParams, dealer: Id, dealing: &PvssDealing, U: &QFI) -> Zq {
+        let mut hasher = TranscriptHasher::new("membership");
+        hasher
+            .append(
+                "instance",
+                &instance_digest(&pp.cl.discriminant(), pp.t, pp.n),
+            )
+            .append("dealer", &dealer.to_be_bytes())
+            .append("cl_pk", &pp.cl_keyring[&dealer].to_bytes())
+            .append("dealing", &crate::codec::encode(dealing))
+            .append("U", &U.to_bytes());
+        hasher.challenge()
+    }
+}
+
+/// What the first challenge of the PVSS and MtA proofs opens with: the class group,
+/// the threshold and the committee size. A proof made for one committee thus says
+/// nothing in another that shares its class group or keys.
+pub(crate) fn instance_digest(discriminant: &Mpz, t: Id, n: Id) -> [u8; 32] {
+    let mut hasher = TranscriptHasher::new("instance");
+    hasher
+        .append("discriminant", &discriminant.to_bytes())
+        .append("t", &t.to_be_bytes())
+        .append("n", &n.to_be_bytes());
+    hasher.finalize()
+}
+
+/// Hashes the caller's context into a first challenge. Nothing is hashed for an empty
+/// one, which keeps the challenges of proofs made without context.
+fn append_aux(hasher: &mut TranscriptHasher, aux: &[u8]) {
+    if !aux.is_empty() {
+        hasher.append("aux", aux);
+    }
+}
+
+/// Fiat-Shamir hashing that labels every field and prefixes it with its length, so that
+/// two distinct sequences of fields never feed the hash the same bytes.
+pub(crate) struct TranscriptHasher(Sha256);
+
+impl TranscriptHasher {
+    pub(crate) fn new(domain: &str) -> Self {
+        let mut hasher = TranscriptHasher(Sha256::new());
+        hasher.append("domain", domain.as_bytes());
+        hasher
     }
 
-    fn challenge1(pp: &PubParams, pvss_dealing: &PvssDealing, curve_generator: &G) -> Zq {
-        let mut hasher = Sha256::new();
-        hasher.update(&pp.cl.discriminant().to_bytes());
-        for (id, pk) in &pp.cl_keyring {
-            hasher.update(&id.to_be_bytes());
-            hasher.update(&pk.to_bytes());
+    pub(crate) fn append(&mut self, label: &str, bytes: &[u8]) -> &mut Self {
+        for part in [label.as_bytes(), bytes] {
+            self.0.update((part.len() as u32).to_le_bytes());
+            self.0.update(part);
         }
-        hasher.update(&pvss_dealing.shares_ciphertext.randomness.to_bytes());
-        for (id, enc) in &pvss_dealing.shares_ciphertext.encryption {
-            hasher.update(&id.to_be_bytes());
-            hasher.update(&enc.to_bytes());
-        }
-        hasher.update(&curve_generator.to_bytes(false));
-        for coeff in &pvss_dealing.curve_polynomial.coeffs {
-            hasher.update(&coeff.to_bytes(false));
-        }
-        Zq::from_bigint(&BigInt::from_bytes(&hasher.finalize()[..16]))
+        self
     }
 
-    fn challenge2(gamma: &Zq, U1: &QFI, U2: &G, U3: &QFI) -> Zq {
-        let hash = Sha256::new()
-            .chain_update(&gamma.to_bigint().to_bytes())
-            .chain_update(&U1.to_bytes())
-            .chain_update(&U2.to_bytes(false))
-            .chain_update(&U3.to_bytes())
-            .finalize();
-        Zq::from_bigint(&BigInt::from_bytes(&hash[..16]))
+    pub(crate) fn finalize(self) -> [u8; 32] {
+        self.0.finalize().into()
+    }
+
+    /// The 128-bit challenge all proofs of this crate use.
+    pub(crate) fn challenge(self) -> Zq {
+        Zq::from_bigint(&BigInt::from_bytes(&self.finalize()[..16]))
     }
 }
 
@@ -352,125 +1597,324 @@ impl PvssNizk {
 pub struct JointPvssResult {
     pub shares_ciphertext: CLMultiRecvCiphertext,
     pub curve_polynomial: CurvePolynomial,
-    pub curve_macs: BTreeMap<Id, G>,
 }
 
 impl<'a> JointPvssResult {
+    /// Combines at least one dealing; see `JointPvssAccumulator` for one at a time.
     pub fn new(pp: &PubParams, dealings: Vec<PvssDealing>) -> Self {
-        let mut curve_coeffs = vec![G::zero(); pp.t as usize];
-        for dealing in &dealings {
-            for (i, coeff) in dealing.curve_polynomial.coeffs.iter().enumerate() {
-                curve_coeffs[i] = &curve_coeffs[i] + coeff;
-            }
-        } // a tiny bit of care for cache locality
+        let mut acc = JointPvssAccumulator::new(pp);
+        for (dealer, dealing) in (1..).zip(&dealings) {
+            acc.add_dealing(dealer, dealing)
+                .expect("the dealers are numbered apart");
+        }
+        acc.finish().expect("at least one dealing")
+    }
 
-        let curve_polynomial = CurvePolynomial {
-            coeffs: curve_coeffs,
-        };
+    /// The MAC of party `id`'s share, evaluated on demand from the joint curve polynomial.
+    pub fn curve_mac(&self, id: Id) -> G {
+        self.curve_polynomial.eval(&party_eval_point(id))
+    }
 
-        let randomness = dealings
-            .iter()
-            .map(|d| d.shares_ciphertext.randomness.clone())
-            .reduce(|acc, R| acc.compose(&pp.cl, &R))
-            .unwrap()
-            .clone();
+    /// The MACs of all recipients at once, for callers that use each of them repeatedly.
+    pub fn curve_macs(&self) -> BTreeMap<Id, G> {
+        // the ciphertexts are not Sync, so only the polynomial is shared across threads
+        self.curve_polynomial
+            .eval_at_parties(self.shares_ciphertext.encryption.keys().copied())
+    }
 
-        let encryption = (1..=pp.n)
-            .into_iter()
-            .map(|id| {
-                let sum = dealings
+    /// Drops the shares of parties outside `parties`; the MACs follow the remaining shares.
+    pub fn restrict_to(&self, parties: &BTreeSet<Id>) -> JointPvssResult {
+        JointPvssResult {
+            shares_ciphertext: CLMultiRecvCiphertext {
+                randomness: self.shares_ciphertext.randomness.clone(),
+                encryption: self
+                    .shares_ciphertext
+                    .encryption
                     .iter()
-                    .map(|d| {
-                        d.shares_ciphertext
-                            .encryption
-                            .get(&id)
-                            .unwrap_or(&pp.cl.one())
-                            .clone()
-                    })
-                    .reduce(|acc, E| acc.compose(&pp.cl, &E))
-                    .unwrap()
-                    .clone();
-                (id, sum)
-            })
-            .collect();
+                    .filter(|(id, _)| parties.contains(id))
+                    .map(|(&id, enc)| (id, enc.clone()))
+                    .collect(),
+            },
+            curve_polynomial: self.curve_polynomial.clone(),
+        }
+    }
+}
 
-        let curve_macs = (1..=pp.n)
-            .into_iter()
-            .map(|id| {
-                let sum = dealings
-                    .iter()
-                    .map(|d| d.curve_polynomial.eval(&Zq::from(id as u64)))
-                    .reduce(|acc, M| acc + M)
-                    .unwrap();
-                (id, sum)
-            })
-            .collect();
+/// Checks that `aggregate` holds the sum of `dealings` under encryption, as
+/// `JointPvssResult::new` relies on the additive homomorphism of CL: for a recipient
+/// drawn at random, its share of the aggregate decrypts to the sum of its shares of the
+/// dealings. A BICYCL that composes forms otherwise fails this.
+#[cfg(test)]
+pub fn verify_homomorphic_sum(
+    dealings: &[PvssDealing],
+    aggregate: &JointPvssResult,
+    pp: &PubParams,
+    secret_keys: &BTreeMap<Id, SecretKey>,
+) -> bool {
+    use rand::Rng;
+
+    let id = rand::thread_rng().gen_range(1..=pp.n);
+    let sum = dealings.iter().fold(Zq::zero(), |sum, dealing| {
+        sum + dealing
+            .shares_ciphertext
+            .decrypt(&pp.cl, id, &secret_keys[&id])
+    });
+    aggregate
+        .shares_ciphertext
+        .decrypt(&pp.cl, id, &secret_keys[&id])
+        == sum
+}
 
-        Self {
+#[derive(Debug, Error, PartialEq)]
+pub enum AggregationError {
+    #[error("the dealing of party {dealer} was added already")]
+    DuplicateDealer { dealer: Id },
+    #[error("no dealings were added")]
+    NoDealings,
+}
+
+/// Builds a `JointPvssResult` one dealing at a time, so that each can be dropped once it
+/// is folded in, e.g. as it arrives. The result is that of `JointPvssResult::new` on the
+/// dealings in the order they were added.
+pub struct JointPvssAccumulator<'a> {
+    pp: &'a PubParams,
+    dealers: BTreeSet<Id>,
+    curve_polynomial: CurvePolynomial,
+    randomness: Option<QFI>,
+    encryption: BTreeMap<Id, QFI>,
+}
+
+impl<'a> JointPvssAccumulator<'a> {
+    pub fn new(pp: &'a PubParams) -> Self {
+        JointPvssAccumulator {
+            pp,
+            dealers: BTreeSet::new(),
+            curve_polynomial: CurvePolynomial {
+                coeffs: vec![G::zero(); pp.t as usize],
+            },
+            randomness: None,
+            encryption: BTreeMap::new(),
+        }
+    }
+
+    /// Folds in the dealing of `dealer`, which must not have dealt before. The dealing
+    /// is not checked otherwise.
+    pub fn add_dealing(
+        &mut self,
+        dealer: Id,
+        dealing: &PvssDealing,
+    ) -> Result<(), AggregationError> {
+        if !self.dealers.insert(dealer) {
+            return Err(AggregationError::DuplicateDealer { dealer });
+        }
+        let cl = &self.pp.cl;
+
+        self.curve_polynomial = &self.curve_polynomial + &dealing.curve_polynomial;
+        let randomness = &dealing.shares_ciphertext.randomness;
+        self.randomness = Some(match self.randomness.take() {
+            Some(acc) => acc.compose(cl, randomness),
+            None => randomness.clone(),
+        });
+
+        // a dealing without a share for `id` contributes the neutral form
+        let one = cl.one();
+        for id in 1..=self.pp.n {
+            let part = dealing
+                .shares_ciphertext
+                .encryption
+                .get(&id)
+                .unwrap_or(&one);
+            let folded = match self.encryption.remove(&id) {
+                Some(acc) => acc.compose(cl, part),
+                None => part.clone(),
+            };
+            self.encryption.insert(id, folded);
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<JointPvssResult, AggregationError> {
+        let randomness = self.randomness.ok_or(AggregationError::NoDealings)?;
+        Ok(JointPvssResult {
             shares_ciphertext: CLMultiRecvCiphertext {
                 randomness,
-                encryption,
+                encryption: self.encryption,
             },
-            curve_polynomial,
-            curve_macs,
-        }
+            curve_polynomial: self.curve_polynomial,
+        })
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MtaDealing {
     pub shares_ciphertext: CLMultiRecvCiphertext,
+    #[serde(with = "crate::codec::compressed_point_map")]
     pub curve_macs: BTreeMap<Id, G>,
 }
 
 impl MtaDealing {
-    /// the caller should remove disqualified parties from pvss_result
-    /// the pairwise shares returned should be negated when later used
+    /// Multiplies the shared secret of `pvss` by `scalar`: recipient j decrypts
+    /// `scalar * share_j + mask_j`, and the masks are returned for the proof.
+    /// The masks interpolate to zero, so the recipients' values can be combined as they
+    /// are: nothing has to be added back or negated by the caller.
+    /// The caller should remove disqualified parties from pvss_result, see `restrict_to`.
     pub fn new(
         pp: &PubParams,
         pvss: &JointPvssResult,
         scalar: &Zq,
+        rng: &mut impl ClRandomness,
         curve_generator: &G,
     ) -> (Self, BTreeMap<Id, Zq>) {
+        // the pairwise shares lie on a random polynomial with zero constant term,
+        // so they cancel out when the recipients' values are interpolated
+        let mut mask = Polynomial {
+            coeffs: (0..pp.t).map(|_| rng.sample_scalar()).collect(),
+        };
+        mask.coeffs[0] = Zq::zero();
+
+        let masks: BTreeMap<Id, Zq> = pvss
+            .shares_ciphertext
+            .encryption
+            .keys()
+            .map(|&id| (id, mask.eval(&party_eval_point(id))))
+            .collect();
+
+        (
+            Self::with_masks(pp, pvss, scalar, &masks, curve_generator),
+            masks,
+        )
+    }
+
+    /// The dealings of `pvss` by two scalars, as a presigning party multiplies the joint
+    /// k by gamma_i and by x_i, each with its masks. They are the dealings of two calls
+    /// of `new`: the exponentiations of the ciphertexts cannot be shared, the saving of
+    /// a pair is in proving it with `DualMtaNizk`.
+    pub fn new_dual(
+        pp: &PubParams,
+        pvss: &JointPvssResult,
+        scalars: [&Zq; 2],
+        rng: &mut impl ClRandomness,
+        curve_generator: &G,
+    ) -> [(Self, BTreeMap<Id, Zq>); 2] {
+        scalars.map(|scalar| Self::new(pp, pvss, scalar, rng, curve_generator))
+    }
+
+    /// The dealing for masks sampled beforehand. The recipients are processed in
+    /// parallel; since nothing is sampled here, the result does not depend on the order.
+    pub fn with_masks(
+        pp: &PubParams,
+        pvss: &JointPvssResult,
+        scalar: &Zq,
+        masks: &BTreeMap<Id, Zq>,
+        curve_generator: &G,
+    ) -> Self {
         let randomness = pvss
             .shares_ciphertext
             .randomness
             .exp(&pp.cl, &Mpz::from(scalar));
 
-        let multienc = &pvss.shares_ciphertext.encryption;
-
-        let pairwise_shares: BTreeMap<Id, Zq> =
-            multienc.iter().map(|(&id, _)| (id, Zq::random())).collect();
+        // the forms are not Sync, so every thread gets its own copy
+        let multienc: Vec<(Id, QFI)> = pvss
+            .shares_ciphertext
+            .encryption
+            .iter()
+            .map(|(&id, E)| (id, E.clone()))
+            .collect();
 
         let encryption = multienc
-            .iter()
+            .maybe_into_par_iter()
             .map(|(id, E)| {
                 let res = E
                     .exp(&pp.cl, &Mpz::from(scalar))
-                    .compose(&pp.cl, &pp.cl.power_of_f(&Mpz::from(&pairwise_shares[id])));
-                (*id, res)
+                    .compose(&pp.cl, &pp.cl.power_of_f(&Mpz::from(&masks[&id])));
+                (id, res)
             })
             .collect();
 
         let curve_macs = pvss
-            .curve_macs
-            .iter()
-            .map(|(&id, mac)| (id, scalar * mac + curve_generator * &pairwise_shares[&id]))
+            .curve_macs()
+            .maybe_into_par_iter()
+            .map(|(id, mac)| (id, scalar * mac + curve_generator * &masks[&id]))
             .collect();
 
-        (
-            MtaDealing {
-                shares_ciphertext: CLMultiRecvCiphertext {
-                    randomness,
-                    encryption,
-                },
-                curve_macs,
+        MtaDealing {
+            shares_ciphertext: CLMultiRecvCiphertext {
+                randomness,
+                encryption,
             },
-            pairwise_shares,
-        )
+            curve_macs,
+        }
+    }
+
+    /// Checks `proof` for this dealing in the context `aux`, where `scalar_pub` is
+    /// g^scalar for the scalar the dealer multiplied `pvss_result` by.
+    pub fn verify(
+        &self,
+        proof: &MtaNizk,
+        pp: &PubParams,
+        pvss_result: &JointPvssResult,
+        curve_generator: &G,
+        scalar_pub: &G,
+        aux: &[u8],
+    ) -> bool {
+        proof.verify_with_aux(pp, pvss_result, self, curve_generator, scalar_pub, aux)
+    }
+
+    /// Drops the entries of parties outside `parties`.
+    pub fn restrict_to(&self, parties: &BTreeSet<Id>) -> MtaDealing {
+        MtaDealing {
+            shares_ciphertext: CLMultiRecvCiphertext {
+                randomness: self.shares_ciphertext.randomness.clone(),
+                encryption: self
+                    .shares_ciphertext
+                    .encryption
+                    .iter()
+                    .filter(|(id, _)| parties.contains(id))
+                    .map(|(&id, enc)| (id, enc.clone()))
+                    .collect(),
+            },
+            curve_macs: self
+                .curve_macs
+                .iter()
+                .filter(|(id, _)| parties.contains(id))
+                .map(|(&id, mac)| (id, mac.clone()))
+                .collect(),
+        }
     }
 }
 
+#[derive(Debug, Error, PartialEq)]
+pub enum CiphertextError {
+    #[error("there is an entry for party {id}, which is not in the committee")]
+    UnknownRecipient { id: Id },
+    #[error("there is no entry for party {id}")]
+    MissingRecipient { id: Id },
+    #[error("the keyring has no CL public key for party {id}")]
+    MissingClKey { id: Id },
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum NizkError {
+    #[error("the PVSS result and the MtA dealing were restricted to different parties")]
+    PartySetMismatch,
+    #[error("the recomputed challenge differs from the one in the proof")]
+    ChallengeMismatch,
+    #[error("the response is outside the range an honest prover produces")]
+    DeserializationError,
+    #[error("the curve polynomial has {got} coefficients, expected {expected}")]
+    DegreeMismatch { got: usize, expected: usize },
+    #[error("a ciphertext is not an element of the class group")]
+    DiscriminantMismatch,
+    #[error("the ciphertext is not part of the dealing")]
+    MembershipMismatch,
+    #[error("the decrypted share does not match its MAC")]
+    ShareMismatch,
+    #[error("party {id} is not in the keyring")]
+    NotInKeyring { id: Id },
+    #[error("the dealing carries no proof that its dealer is in the committee")]
+    MissingMembershipProof,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MtaNizk {
     pub e: Zq,
@@ -479,27 +1923,58 @@ pub struct MtaNizk {
 }
 
 impl MtaNizk {
+    #[deprecated(note = "bind the proof to its context with `prove_with_aux`")]
     pub fn prove(
         pp: &PubParams,
         pvss_result: &JointPvssResult,
         mta_dealing: &MtaDealing,
         curve_generator: &G,
-        rng: &mut RandGen,
+        rng: &mut impl ClRandomness,
         scalar: &Zq,
-        pairwise_shares: &BTreeMap<Id, Zq>,
-    ) -> Self {
+        masks: &BTreeMap<Id, Zq>,
+    ) -> Result<Self, NizkError> {
+        Self::prove_with_aux(
+            pp,
+            pvss_result,
+            mta_dealing,
+            curve_generator,
+            rng,
+            scalar,
+            masks,
+            &[],
+        )
+    }
+
+    /// Proves the dealing in the context `aux`, such as the session, presignature and
+    /// round it belongs to, which the verifier must pass as well. An empty `aux` gives
+    /// the proofs of `prove`.
+    pub fn prove_with_aux(
+        pp: &PubParams,
+        pvss_result: &JointPvssResult,
+        mta_dealing: &MtaDealing,
+        curve_generator: &G,
+        rng: &mut impl ClRandomness,
+        scalar: &Zq,
+        masks: &BTreeMap<Id, Zq>,
+        aux: &[u8],
+    ) -> Result<Self, NizkError> {
+        Self::check_parties(pvss_result, mta_dealing)?;
+
+        let pvss_macs = pvss_result.curve_macs();
         let gamma = Self::challenge1(
             pp,
             pvss_result,
+            &pvss_macs,
             mta_dealing,
             curve_generator,
-            &(curve_generator * scalar),
+            &(G::generator() * scalar),
+            aux,
         );
 
-        let u1 = rng.random_mpz(&pp.cl.encrypt_randomness_bound());
-        let u2 = Zq::random();
+        let u1 = rng.sample_encrypt_randomness(&pp.cl);
+        let u2 = rng.sample_scalar();
 
-        let u1_modq = Zq::from(BigInt::from_bytes(&u1.to_bytes()) % Zq::group_order());
+        let u1_modq = mpz_to_scalar_ct(&u1, Self::response_len(pp));
         let U1 = G::generator() * &u1_modq;
         let U2 = pvss_result.shares_ciphertext.randomness.exp(&pp.cl, &u1);
 
@@ -510,16 +1985,19 @@ impl MtaNizk {
 
         // compute original macs from pvss_result.curve_polynomial
         // TODO: profile, and may make sense to reuse what's previously computed
-        let U4 = CurvePolynomial::new(pp.n, &pvss_result.curve_macs).eval(&gamma) * &u1_modq
-            + curve_generator * &u2;
+        let U4 =
+            CurvePolynomial::new(pp.n, &pvss_macs).eval(&gamma) * &u1_modq + curve_generator * &u2;
 
         let e = Self::challenge2(&gamma, &U1, &U2, &U3, &U4);
-        let z1 = &u1 + Mpz::from(&(&e * scalar));
-        let z2 = Polynomial::new(pp.n, pairwise_shares).eval(&gamma) * &e + &u2;
+        // the response lives in the class group exponent, so e * scalar must not be reduced mod q;
+        // both responses are plain sums, with no branching on the secret
+        let z1 = &u1 + Mpz::from(&e) * Mpz::from(scalar);
+        let z2 = Polynomial::new(pp.n, masks).eval(&gamma) * &e + &u2;
 
-        Self { e, z1, z2 }
+        Ok(Self { e, z1, z2 })
     }
 
+    #[deprecated(note = "check the proof in its context with `verify_with_aux`")]
     pub fn verify(
         &self,
         pp: &PubParams,
@@ -528,10 +2006,92 @@ impl MtaNizk {
         curve_generator: &G,
         scalar_pub: &G,
     ) -> bool {
-        let gamma = Self::challenge1(pp, pvss_result, mta_dealing, curve_generator, scalar_pub);
+        self.verify_with_aux(
+            pp,
+            pvss_result,
+            mta_dealing,
+            curve_generator,
+            scalar_pub,
+            &[],
+        )
+    }
+
+    #[deprecated(note = "check the proof in its context with `verify_detailed_with_aux`")]
+    pub fn verify_detailed(
+        &self,
+        pp: &PubParams,
+        pvss_result: &JointPvssResult,
+        mta_dealing: &MtaDealing,
+        curve_generator: &G,
+        scalar_pub: &G,
+    ) -> Result<(), NizkError> {
+        self.verify_detailed_with_aux(
+            pp,
+            pvss_result,
+            mta_dealing,
+            curve_generator,
+            scalar_pub,
+            &[],
+        )
+    }
+
+    pub fn verify_with_aux(
+        &self,
+        pp: &PubParams,
+        pvss_result: &JointPvssResult,
+        mta_dealing: &MtaDealing,
+        curve_generator: &G,
+        scalar_pub: &G,
+        aux: &[u8],
+    ) -> bool {
+        self.verify_detailed_with_aux(
+            pp,
+            pvss_result,
+            mta_dealing,
+            curve_generator,
+            scalar_pub,
+            aux,
+        )
+        .is_ok()
+    }
+
+    /// Like `verify_with_aux`, telling which check failed.
+    pub fn verify_detailed_with_aux(
+        &self,
+        pp: &PubParams,
+        pvss_result: &JointPvssResult,
+        mta_dealing: &MtaDealing,
+        curve_generator: &G,
+        scalar_pub: &G,
+        aux: &[u8],
+    ) -> Result<(), NizkError> {
+        Self::check_parties(pvss_result, mta_dealing)?;
+        mta_dealing.shares_ciphertext.check_discriminant(&pp.cl)?;
+        let degree = pvss_result.curve_polynomial.coeffs.len();
+        if degree != pp.t as usize {
+            return Err(NizkError::DegreeMismatch {
+                got: degree,
+                expected: pp.t as usize,
+            });
+        }
+
+        let pvss_macs = pvss_result.curve_macs();
+        let gamma = Self::challenge1(
+            pp,
+            pvss_result,
+            &pvss_macs,
+            mta_dealing,
+            curve_generator,
+            scalar_pub,
+            aux,
+        );
 
-        let z1_modq = Zq::from(BigInt::from_bytes(&self.z1.to_bytes()) % Zq::group_order());
-        let U1 = G::generator() * z1_modq - scalar_pub * &self.e;
+        // z1 is public, but reject it before it overflows the fixed-length conversion
+        if self.z1.to_bytes().len() > Self::response_len(pp) {
+            return Err(NizkError::DeserializationError);
+        }
+        let z1_modq = mpz_to_scalar_ct(&self.z1, Self::response_len(pp));
+        let U1 = G::generator() * &z1_modq - scalar_pub * &self.e;
 
         let U2 = pvss_result
             .shares_ciphertext
@@ -557,61 +2117,330 @@ impl MtaNizk {
             .compose(&pp.cl, &U3d);
 
         // U4
-        let z1_modq = Zq::from(BigInt::from_bytes(&self.z1.to_bytes()) % Zq::group_order());
-
         let U4 = curve_generator * &self.z2
-            + CurvePolynomial::new(pp.n, &pvss_result.curve_macs).eval(&gamma) * &z1_modq
+            + CurvePolynomial::new(pp.n, &pvss_macs).eval(&gamma) * &z1_modq
             - CurvePolynomial::new(pp.n, &mta_dealing.curve_macs).eval(&gamma) * &self.e;
 
         let e = Self::challenge2(&gamma, &U1, &U2, &U3, &U4);
-        let result = e == self.e;
-        assert!(result);
-        result
+        if scalar_ct_eq(&e, &self.e) {
+            Ok(())
+        } else {
+            Err(NizkError::ChallengeMismatch)
+        }
+    }
+
+    /// Byte length of the buffer through which u1 and z1 are reduced mod q: z1 = u1 + e * scalar
+    /// with u1 below the encryption randomness bound, e below 2^128 and scalar below 2^256.
+    pub fn response_len(pp: &PubParams) -> usize {
+        pp.cl.encrypt_randomness_bound().to_bytes().len() + 16 + 32 + 1
+    }
+
+    fn check_parties(
+        pvss_result: &JointPvssResult,
+        mta_dealing: &MtaDealing,
+    ) -> Result<(), NizkError> {
+        let pvss_parties = pvss_result.shares_ciphertext.encryption.keys();
+        let dealing_parties = mta_dealing.shares_ciphertext.encryption.keys();
+
+        if pvss_parties.clone().eq(dealing_parties)
+            && pvss_parties.eq(mta_dealing.curve_macs.keys())
+        {
+            Ok(())
+        } else {
+            Err(NizkError::PartySetMismatch)
+        }
     }
 
     fn challenge1(
         pp: &PubParams,
         pvss_result: &JointPvssResult,
+        pvss_macs: &BTreeMap<Id, G>,
         mta_dealing: &MtaDealing,
         curve_generator: &G,
         scalar_pub: &G,
+        aux: &[u8],
     ) -> Zq {
-        let mut hasher = Sha256::new();
-        hasher.update(pp.cl.discriminant().to_bytes());
-        hasher.update(pvss_result.shares_ciphertext.randomness.to_bytes());
+        let mut hasher = TranscriptHasher::new("mta/challenge1");
+        hasher
+            .append(
+                "instance",
+                &instance_digest(&pp.cl.discriminant(), pp.t, pp.n),
+            )
+            .append(
+                "pvss_randomness",
+                &pvss_result.shares_ciphertext.randomness.to_bytes(),
+            );
         for (id, enc) in &pvss_result.shares_ciphertext.encryption {
-            hasher.update(&id.to_be_bytes());
-            hasher.update(&enc.to_bytes());
+            hasher
+                .append("id", &id.to_be_bytes())
+                .append("pvss_encryption", &enc.to_bytes());
         }
-        hasher.update(mta_dealing.shares_ciphertext.randomness.to_bytes());
+        hasher.append(
+            "mta_randomness",
+            &mta_dealing.shares_ciphertext.randomness.to_bytes(),
+        );
         for (id, enc) in &mta_dealing.shares_ciphertext.encryption {
-            hasher.update(&id.to_be_bytes());
-            hasher.update(&enc.to_bytes());
+            hasher
+                .append("id", &id.to_be_bytes())
+                .append("mta_encryption", &enc.to_bytes());
         }
-        for (id, mac) in &pvss_result.curve_macs {
-            hasher.update(&id.to_be_bytes());
-            hasher.update(&mac.to_bytes(false));
+        for (id, mac) in pvss_macs {
+            hasher
+                .append("id", &id.to_be_bytes())
+                .append("pvss_mac", &mac.to_bytes(false));
         }
         for (id, mac) in &mta_dealing.curve_macs {
-            hasher.update(&id.to_be_bytes());
-            hasher.update(&mac.to_bytes(false));
+            hasher
+                .append("id", &id.to_be_bytes())
+                .append("mta_mac", &mac.to_bytes(false));
         }
-        hasher.update(curve_generator.to_bytes(true));
-        hasher.update(scalar_pub.to_bytes(true));
-
-        Zq::from_bigint(&BigInt::from_bytes(&hasher.finalize()[..16]))
+        hasher
+            .append("curve_generator", &curve_generator.to_bytes(true))
+            .append("scalar_pub", &scalar_pub.to_bytes(true));
+        append_aux(&mut hasher, aux);
+        hasher.challenge()
     }
 
     fn challenge2(gamma: &Zq, U1: &G, U2: &QFI, U3: &QFI, U4: &G) -> Zq {
-        let hash = Sha256::new()
-            .chain_update(&gamma.to_bigint().to_bytes())
-            .chain_update(&U1.to_bytes(false))
-            .chain_update(&U2.to_bytes())
-            .chain_update(&U3.to_bytes())
-            .chain_update(&U4.to_bytes(false))
-            .finalize();
-        Zq::from_bigint(&BigInt::from_bytes(&hash[..16]))
+        let mut hasher = TranscriptHasher::new("mta/challenge2");
+        hasher
+            .append("gamma", &gamma.to_bytes())
+            .append("U1", &U1.to_bytes(false))
+            .append("U2", &U2.to_bytes())
+            .append("U3", &U3.to_bytes())
+            .append("U4", &U4.to_bytes(false));
+        hasher.challenge()
+    }
+}
+
+/// One proof for the two MtA dealings of `MtaDealing::new_dual`: the statements of two
+/// `MtaNizk`s under a single challenge. Both evaluate the PVSS encryptions at the same
+/// point, a multi-exponentiation over all n recipients that each `MtaNizk` does on its
+/// own, so the prover does it once instead of twice and the verifier three times
+/// instead of four.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DualMtaNizk {
+    pub e: Zq,
+    pub z1: [Mpz; 2],
+    pub z2: [Zq; 2],
+}
+
+impl DualMtaNizk {
+    /// Proves that `dealings[i]` multiplies `pvss_result` by `scalars[i]` with
+    /// `masks[i]`, for both i, in the context `aux`.
+    pub fn prove(
+        pp: &PubParams,
+        pvss_result: &JointPvssResult,
+        dealings: [&MtaDealing; 2],
+        curve_generator: &G,
+        rng: &mut impl ClRandomness,
+        scalars: [&Zq; 2],
+        masks: [&BTreeMap<Id, Zq>; 2],
+        aux: &[u8],
+    ) -> Result<Self, NizkError> {
+        for dealing in dealings {
+            MtaNizk::check_parties(pvss_result, dealing)?;
+        }
 
+        let pvss_macs = pvss_result.curve_macs();
+        let scalar_pubs = scalars.map(|scalar| G::generator() * scalar);
+        let gamma = Self::challenge1(
+            pp,
+            pvss_result,
+            &pvss_macs,
+            dealings,
+            curve_generator,
+            [&scalar_pubs[0], &scalar_pubs[1]],
+            aux,
+        );
+
+        // the PVSS side, shared by both statements
+        let pvss_at_gamma =
+            QFPolynomial::new(&pp.cl, pp.n, &pvss_result.shares_ciphertext.encryption)
+                .eval(&pp.cl, &gamma);
+        let macs_at_gamma = CurvePolynomial::new(pp.n, &pvss_macs).eval(&gamma);
+
+        let len = MtaNizk::response_len(pp);
+        let mut nonces = vec![];
+        let mut commitments = vec![];
+        for _ in 0..2 {
+            let u1 = rng.sample_encrypt_randomness(&pp.cl);
+            let u2 = rng.sample_scalar();
+            let u1_modq = mpz_to_scalar_ct(&u1, len);
+            commitments.push((
+                G::generator() * &u1_modq,
+                pvss_result.shares_ciphertext.randomness.exp(&pp.cl, &u1),
+                pvss_at_gamma
+                    .exp(&pp.cl, &u1)
+                    .compose(&pp.cl, &pp.cl.power_of_f(&Mpz::from(&u2))),
+                &macs_at_gamma * &u1_modq + curve_generator * &u2,
+            ));
+            nonces.push((u1, u2));
+        }
+
+        let e = Self::challenge2(&gamma, &commitments);
+        let z1 = [0, 1].map(|i| &nonces[i].0 + Mpz::from(&e) * Mpz::from(scalars[i]));
+        let z2 = [0, 1].map(|i| Polynomial::new(pp.n, masks[i]).eval(&gamma) * &e + &nonces[i].1);
+
+        Ok(Self { e, z1, z2 })
+    }
+
+    /// Checks both statements, where `scalar_pubs[i]` is g^scalar for the scalar of
+    /// `dealings[i]`, telling which check failed. The proof says nothing of either
+    /// dealing on its own: if it fails, both are rejected.
+    pub fn verify_detailed(
+        &self,
+        pp: &PubParams,
+        pvss_result: &JointPvssResult,
+        dealings: [&MtaDealing; 2],
+        curve_generator: &G,
+        scalar_pubs: [&G; 2],
+        aux: &[u8],
+    ) -> Result<(), NizkError> {
+        for dealing in dealings {
+            MtaNizk::check_parties(pvss_result, dealing)?;
+            dealing.shares_ciphertext.check_discriminant(&pp.cl)?;
+        }
+        let degree = pvss_result.curve_polynomial.coeffs.len();
+        if degree != pp.t as usize {
+            return Err(NizkError::DegreeMismatch {
+                got: degree,
+                expected: pp.t as usize,
+            });
+        }
+
+        let pvss_macs = pvss_result.curve_macs();
+        let gamma = Self::challenge1(
+            pp,
+            pvss_result,
+            &pvss_macs,
+            dealings,
+            curve_generator,
+            scalar_pubs,
+            aux,
+        );
+
+        // z1 is public, but reject it before it overflows the fixed-length conversion
+        let len = MtaNizk::response_len(pp);
+        if self.z1.iter().any(|z1| z1.to_bytes().len() > len) {
+            return Err(NizkError::DeserializationError);
+        }
+
+        let pvss_at_gamma =
+            QFPolynomial::new(&pp.cl, pp.n, &pvss_result.shares_ciphertext.encryption)
+                .eval(&pp.cl, &gamma);
+        let macs_at_gamma = CurvePolynomial::new(pp.n, &pvss_macs).eval(&gamma);
+        let minus_e = -Mpz::from(&self.e);
+
+        let commitments: Vec<(G, QFI, QFI, G)> = (0..2)
+            .map(|i| {
+                let (dealing, z1, z2) = (dealings[i], &self.z1[i], &self.z2[i]);
+                let z1_modq = mpz_to_scalar_ct(z1, len);
+                let U1 = G::generator() * &z1_modq - scalar_pubs[i] * &self.e;
+                let U2 = pvss_result
+                    .shares_ciphertext
+                    .randomness
+                    .exp(&pp.cl, z1)
+                    .compose(
+                        &pp.cl,
+                        &dealing.shares_ciphertext.randomness.exp(&pp.cl, &minus_e),
+                    );
+                let U3d = QFPolynomial::new(&pp.cl, pp.n, &dealing.shares_ciphertext.encryption)
+                    .eval(&pp.cl, &gamma)
+                    .exp(&pp.cl, &minus_e);
+                let U3 = pvss_at_gamma
+                    .exp(&pp.cl, z1)
+                    .compose(&pp.cl, &pp.cl.power_of_f(&Mpz::from(z2)))
+                    .compose(&pp.cl, &U3d);
+                let U4 = curve_generator * z2 + &macs_at_gamma * &z1_modq
+                    - CurvePolynomial::new(pp.n, &dealing.curve_macs).eval(&gamma) * &self.e;
+                (U1, U2, U3, U4)
+            })
+            .collect();
+
+        let e = Self::challenge2(&gamma, &commitments);
+        if scalar_ct_eq(&e, &self.e) {
+            Ok(())
+        } else {
+            Err(NizkError::ChallengeMismatch)
+        }
+    }
+
+    pub fn verify(
+        &self,
+        pp: &PubParams,
+        pvss_result: &JointPvssResult,
+        dealings: [&MtaDealing; 2],
+        curve_generator: &G,
+        scalar_pubs: [&G; 2],
+        aux: &[u8],
+    ) -> bool {
+        self.verify_detailed(pp, pvss_result, dealings, curve_generator, scalar_pubs, aux)
+            .is_ok()
+    }
+
+    fn challenge1(
+        pp: &PubParams,
+        pvss_result: &JointPvssResult,
+        pvss_macs: &BTreeMap<Id, G>,
+        dealings: [&MtaDealing; 2],
+        curve_generator: &G,
+        scalar_pubs: [&G; 2],
+        aux: &[u8],
+    ) -> Zq {
+        let mut hasher = TranscriptHasher::new("mta-dual/challenge1");
+        hasher
+            .append(
+                "instance",
+                &instance_digest(&pp.cl.discriminant(), pp.t, pp.n),
+            )
+            .append(
+                "pvss_randomness",
+                &pvss_result.shares_ciphertext.randomness.to_bytes(),
+            );
+        for (id, enc) in &pvss_result.shares_ciphertext.encryption {
+            hasher
+                .append("id", &id.to_be_bytes())
+                .append("pvss_encryption", &enc.to_bytes());
+        }
+        for (id, mac) in pvss_macs {
+            hasher
+                .append("id", &id.to_be_bytes())
+                .append("pvss_mac", &mac.to_bytes(false));
+        }
+        for (dealing, scalar_pub) in dealings.into_iter().zip(scalar_pubs) {
+            hasher.append(
+                "mta_randomness",
+                &dealing.shares_ciphertext.randomness.to_bytes(),
+            );
+            for (id, enc) in &dealing.shares_ciphertext.encryption {
+                hasher
+                    .append("id", &id.to_be_bytes())
+                    .append("mta_encryption", &enc.to_bytes());
+            }
+            for (id, mac) in &dealing.curve_macs {
+                hasher
+                    .append("id", &id.to_be_bytes())
+                    .append("mta_mac", &mac.to_bytes(false));
+            }
+            hasher.append("scalar_pub", &scalar_pub.to_bytes(true));
+        }
+        hasher.append("curve_generator", &curve_generator.to_bytes(true));
+        append_aux(&mut hasher, aux);
+        hasher.challenge()
+    }
+
+    fn challenge2(gamma: &Zq, commitments: &[(G, QFI, QFI, G)]) -> Zq {
+        let mut hasher = TranscriptHasher::new("mta-dual/challenge2");
+        hasher.append("gamma", &gamma.to_bytes());
+        for (U1, U2, U3, U4) in commitments {
+            hasher
+                .append("U1", &U1.to_bytes(false))
+                .append("U2", &U2.to_bytes())
+                .append("U3", &U3.to_bytes())
+                .append("U4", &U4.to_bytes(false));
+        }
+        hasher.challenge()
     }
 }
 
@@ -622,8 +2451,15 @@ pub struct DleqNizk {
 }
 
 impl DleqNizk {
-    pub fn prove(gen1: &G, pow1: &G, gen2: &G, pow2: &G, x: &Zq) -> Self {
-        let u = Zq::random();
+    pub fn prove(
+        gen1: &G,
+        pow1: &G,
+        gen2: &G,
+        pow2: &G,
+        x: &Zq,
+        rng: &mut impl ClRandomness,
+    ) -> Self {
+        let u = rng.sample_scalar();
         let U1 = gen1 * &u;
         let U2 = gen2 * &u;
         let e = Self::challenge(gen1, pow1, gen2, pow2, &U1, &U2);
@@ -636,16 +2472,30 @@ impl DleqNizk {
         let U2 = gen2 * &self.z - pow2 * &self.e;
         let e = Self::challenge(gen1, pow1, gen2, pow2, &U1, &U2);
 
-        let result = e == self.e;
-        assert!(result);
-        result
+        scalar_ct_eq(&e, &self.e)
+    }
+
+    /// Like `verify`; a DLEQ proof has nothing to check besides the challenge.
+    pub fn verify_detailed(&self, gen1: &G, pow1: &G, gen2: &G, pow2: &G) -> Result<(), NizkError> {
+        if self.verify(gen1, pow1, gen2, pow2) {
+            Ok(())
+        } else {
+            Err(NizkError::ChallengeMismatch)
+        }
     }
 
     fn challenge(gen1: &G, pow1: &G, gen2: &G, pow2: &G, U1: &G, U2: &G) -> Zq {
-        let mut hasher = Sha256::new();
-        for point in &[gen1, pow1, gen2, pow2, U1, U2] {
-            hasher.update(point.to_bytes(false));
+        let mut hasher = TranscriptHasher::new("dleq/challenge");
+        for (label, point) in [
+            ("gen1", gen1),
+            ("pow1", pow1),
+            ("gen2", gen2),
+            ("pow2", pow2),
+            ("U1", U1),
+            ("U2", U2),
+        ] {
+            hasher.append(label, &point.to_bytes(false));
         }
-        Zq::from_bigint(&BigInt::from_bytes(&hasher.finalize()[..16]))
+        hasher.challenge()
     }
 }
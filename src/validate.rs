@@ -0,0 +1,665 @@
+//! Structural checks on messages from untrusted peers, run before any of their contents
+//! are used. Decoding already rejects what cannot be a curve point, a reduced form or a
+//! scalar below q, but a well-encoded message can still carry the point at infinity
+//! where a commitment belongs, a class group element of another discriminant, or maps
+//! and vectors of a shape the committee does not have. The processing that follows
+//! indexes into them, so a message that fails here disqualifies its sender instead.
+//!
+//! Scalars need no check of their own: curv decodes them only if they are below q.
+
+use bicycl::QFI;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+use crate::presign::{MtAwcMsg, NonceGenMsg, OnlineSignMsg, PreSignFinalMsg};
+use crate::schnorr::{PartialSchnorrMsg, SchnorrMsg};
+use crate::spdz::{
+    DkgMsg, KeyConfirmMsg, OpenPowerMsg, PresignMsg, PvssHeader, PvssLeaf, PvssMsg, SignMsg,
+};
+use crate::utils::{
+    CLMultiRecvCiphertext, CurvePolynomial, Id, JointPvssResult, MaskedPvssDealing, MtaDealing,
+    PointError, PubParams, PvssDealing, G,
+};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ValidationError {
+    #[error("{field} is the point at infinity")]
+    IdentityPoint { field: &'static str },
+    #[error("{field} has {got} entries, expected {expected}")]
+    WrongLength {
+        field: &'static str,
+        got: usize,
+        expected: usize,
+    },
+    #[error("{field} has {got} entries, at most {max} are allowed")]
+    TooLong {
+        field: &'static str,
+        got: usize,
+        max: usize,
+    },
+    #[error("{field} has an entry for party {id}, who is not in the committee")]
+    UnknownParty { field: &'static str, id: Id },
+    #[error("{field} has no entry for party {id}")]
+    MissingParty { field: &'static str, id: Id },
+    #[error("{field} is not an element of the class group")]
+    NotInClassGroup { field: &'static str },
+}
+
+/// A message that can be checked for well-formedness against the public parameters
+/// alone, before the receiver verifies its proofs or combines it with others.
+pub trait Validate {
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError>;
+}
+
+fn no_identity<'a>(
+    field: &'static str,
+    mut points: impl Iterator<Item = &'a G>,
+) -> Result<(), ValidationError> {
+    if points.any(|point| point.is_zero()) {
+        return Err(ValidationError::IdentityPoint { field });
+    }
+    Ok(())
+}
+
+fn exact_len(field: &'static str, got: usize, expected: usize) -> Result<(), ValidationError> {
+    if got != expected {
+        return Err(ValidationError::WrongLength {
+            field,
+            got,
+            expected,
+        });
+    }
+    Ok(())
+}
+
+fn in_committee(field: &'static str, id: Id, n: Id) -> Result<(), ValidationError> {
+    if !(1..=n).contains(&id) {
+        return Err(ValidationError::UnknownParty { field, id });
+    }
+    Ok(())
+}
+
+/// Checks that a map keyed by party has an entry for each of the parties 1..=n and
+/// for no one else.
+fn whole_committee<V>(
+    field: &'static str,
+    entries: &BTreeMap<Id, V>,
+    n: Id,
+) -> Result<(), ValidationError> {
+    for &id in entries.keys() {
+        in_committee(field, id, n)?;
+    }
+    match (1..=n).find(|id| !entries.contains_key(id)) {
+        Some(id) => Err(ValidationError::MissingParty { field, id }),
+        None => Ok(()),
+    }
+}
+
+fn in_class_group<'a>(
+    field: &'static str,
+    pp: &PubParams,
+    mut forms: impl Iterator<Item = &'a QFI>,
+) -> Result<(), ValidationError> {
+    let discriminant = pp.cl.discriminant();
+    if !forms.all(|form| form.discriminant() == discriminant) {
+        return Err(ValidationError::NotInClassGroup { field });
+    }
+    Ok(())
+}
+
+/// A commitment to a polynomial of degree t - 1, each of whose coefficients is a point
+/// of the curve other than the identity.
+fn validate_polynomial(
+    field: &'static str,
+    polynomial: &CurvePolynomial,
+    pp: &PubParams,
+) -> Result<(), ValidationError> {
+    exact_len(field, polynomial.coeffs.len(), pp.t as usize)?;
+    polynomial.validate_points().map_err(|err| match err {
+        PointError::Identity { .. } => ValidationError::IdentityPoint { field },
+    })
+}
+
+/// Encrypted shares for each of the parties 1..=n, all in the class group of `pp`.
+fn validate_ciphertext(
+    field: &'static str,
+    ciphertext: &CLMultiRecvCiphertext,
+    pp: &PubParams,
+) -> Result<(), ValidationError> {
+    whole_committee(field, &ciphertext.encryption, pp.n)?;
+    in_class_group(
+        field,
+        pp,
+        std::iter::once(&ciphertext.randomness).chain(ciphertext.encryption.values()),
+    )
+}
+
+impl Validate for PvssDealing {
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        validate_polynomial("curve polynomial", &self.curve_polynomial, pp)?;
+        validate_ciphertext("encrypted shares", &self.shares_ciphertext, pp)
+    }
+}
+
+impl Validate for JointPvssResult {
+    /// As combined from dealings to the whole committee, before any restriction.
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        validate_polynomial("curve polynomial", &self.curve_polynomial, pp)?;
+        validate_ciphertext("encrypted shares", &self.shares_ciphertext, pp)
+    }
+}
+
+impl Validate for PvssMsg {
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        self.dealing.validate(pp)
+    }
+}
+
+impl Validate for PvssHeader {
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        validate_polynomial("curve polynomial", &self.curve_polynomial, pp)?;
+        in_class_group("randomness", pp, std::iter::once(&self.randomness))
+    }
+}
+
+impl Validate for PvssLeaf {
+    /// The path leads up a tree with n leaves, so it is no longer than its depth.
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        in_committee("recipient", self.recipient, pp.n)?;
+        in_class_group("encrypted share", pp, std::iter::once(&self.ciphertext))?;
+        let depth = (pp.n as usize).next_power_of_two().trailing_zeros() as usize;
+        if self.path.len() > depth {
+            return Err(ValidationError::TooLong {
+                field: "Merkle path",
+                got: self.path.len(),
+                max: depth,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Validate for MaskedPvssDealing {
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        validate_polynomial("curve polynomial", &self.curve_polynomial, pp)?;
+        whole_committee("masked shares", &self.masked_shares, pp.n)?;
+        in_class_group("randomness", pp, std::iter::once(&self.randomness))
+    }
+}
+
+impl Validate for OpenPowerMsg {
+    fn validate(&self, _pp: &PubParams) -> Result<(), ValidationError> {
+        no_identity("opened point", std::iter::once(&self.point))
+    }
+}
+
+impl Validate for KeyConfirmMsg {
+    fn validate(&self, _pp: &PubParams) -> Result<(), ValidationError> {
+        exact_len("digest", self.digest.len(), 32)
+    }
+}
+
+impl Validate for DkgMsg {
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        match self {
+            DkgMsg::Pvss(msg) => msg.validate(pp),
+            DkgMsg::PowOpen(msg) => msg.validate(pp),
+            DkgMsg::KeyConfirm(msg) => msg.validate(pp),
+            DkgMsg::PvssHeader(msg) => msg.validate(pp),
+            DkgMsg::PvssLeaf(msg) => msg.validate(pp),
+            DkgMsg::MaskedPvss(msg) => msg.validate(pp),
+        }
+    }
+}
+
+impl Validate for NonceGenMsg {
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        self.k.validate(pp)?;
+        self.gamma.validate(pp)
+    }
+}
+
+impl Validate for MtaDealing {
+    /// As sent in an `MtAwcMsg`, where it is addressed to the whole committee.
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        validate_ciphertext("encrypted products", &self.shares_ciphertext, pp)?;
+        whole_committee("curve MACs", &self.curve_macs, pp.n)?;
+        no_identity("curve MACs", self.curve_macs.values())
+    }
+}
+
+impl Validate for MtAwcMsg {
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        // the nonces are combined from the first t dealings
+        let dealers = self.nonce_digests.len();
+        if dealers > pp.t as usize {
+            return Err(ValidationError::TooLong {
+                field: "dealing digests",
+                got: dealers,
+                max: pp.t as usize,
+            });
+        }
+        for &id in self.nonce_digests.keys() {
+            in_committee("dealing digests", id, pp.n)?;
+        }
+        self.gamma_pub.validate(pp)?;
+        self.k_gamma_dealing.validate(pp)?;
+        self.k_x_dealing.validate(pp)
+    }
+}
+
+impl Validate for PreSignFinalMsg {
+    fn validate(&self, _pp: &PubParams) -> Result<(), ValidationError> {
+        no_identity("Γ", std::iter::once(&self.Gamma))
+    }
+}
+
+impl Validate for PresignMsg {
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        match self {
+            PresignMsg::NonceGen(msg) => msg.validate(pp),
+            PresignMsg::MtAwc(msg) => msg.validate(pp),
+            PresignMsg::PreSignFinal(msg) => msg.validate(pp),
+        }
+    }
+}
+
+impl Validate for OnlineSignMsg {
+    /// A signature share is a lone scalar, which decoding has already checked.
+    fn validate(&self, _pp: &PubParams) -> Result<(), ValidationError> {
+        Ok(())
+    }
+}
+
+impl Validate for SignMsg {
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        match self {
+            SignMsg::OnlineSign(msg) => msg.validate(pp),
+        }
+    }
+}
+
+impl Validate for PartialSchnorrMsg {
+    fn validate(&self, _pp: &PubParams) -> Result<(), ValidationError> {
+        Ok(())
+    }
+}
+
+impl Validate for SchnorrMsg {
+    fn validate(&self, pp: &PubParams) -> Result<(), ValidationError> {
+        match self {
+            SchnorrMsg::PartialSig(msg) => msg.validate(pp),
+        }
+    }
+}
+
+/// Replaces the one occurrence of `old` in an encoding with `new`.
+#[cfg(test)]
+fn splice(bytes: &[u8], old: &[u8], new: &[u8]) -> Vec<u8> {
+    let at = bytes
+        .windows(old.len())
+        .position(|window| window == old)
+        .expect("the bytes to replace are part of the encoding");
+    [&bytes[..at], new, &bytes[at + old.len()..]].concat()
+}
+
+#[test]
+pub fn test_validate_pvss_msg() {
+    use crate::codec::{decode, encode};
+    use crate::spdz::simulate_pp;
+    use crate::utils::Zq;
+    use bicycl::{Mpz, RandGen};
+
+    let (pp, _) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let msg = PvssMsg::random(&pp, &mut rng, h);
+    let bytes = encode(&msg);
+    let validate = |bytes: &[u8]| decode::<PvssMsg>(bytes).unwrap().validate(&pp);
+    assert_eq!(validate(&bytes), Ok(()));
+
+    // the message starts with the curve polynomial: a length, then t compressed points
+    let first = msg.dealing.curve_polynomial.coeffs[0].to_bytes(true);
+    assert_eq!(&bytes[8..41], &first[..]);
+    let mut identity = bytes.clone();
+    identity[8..41].fill(0);
+    assert_eq!(
+        validate(&identity),
+        Err(ValidationError::IdentityPoint {
+            field: "curve polynomial"
+        })
+    );
+    let mut short = bytes.clone();
+    short.drain(8..41);
+    short[..8].copy_from_slice(&(pp.t as u64 - 1).to_le_bytes());
+    assert_eq!(
+        validate(&short),
+        Err(ValidationError::WrongLength {
+            field: "curve polynomial",
+            got: 1,
+            expected: 2
+        })
+    );
+
+    // an encrypted share filed under someone outside the committee, or under another
+    // recipient's id, which leaves a gap
+    let ciphertext = &msg.dealing.shares_ciphertext;
+    let rekey = |from: Id, to: Id| {
+        let share = encode(&ciphertext.encryption[&from]);
+        let entry = |id: Id| [&id.to_le_bytes()[..], &share].concat();
+        splice(&bytes, &entry(from), &entry(to))
+    };
+    assert_eq!(
+        validate(&rekey(3, 4)),
+        Err(ValidationError::UnknownParty {
+            field: "encrypted shares",
+            id: 4
+        })
+    );
+    assert_eq!(
+        validate(&rekey(3, 2)),
+        Err(ValidationError::MissingParty {
+            field: "encrypted shares",
+            id: 3
+        })
+    );
+
+    // a form of another class group decodes, but does not validate
+    let (other_pp, _) = simulate_pp(3, 2);
+    let (other, _, _, _) = PvssDealing::random(&other_pp, &mut rng, h);
+    let foreign = splice(
+        &bytes,
+        &encode(&ciphertext.randomness),
+        &encode(&other.shares_ciphertext.randomness),
+    );
+    assert_eq!(
+        validate(&foreign),
+        Err(ValidationError::NotInClassGroup {
+            field: "encrypted shares"
+        })
+    );
+}
+
+#[test]
+pub fn test_validate_dkg_msgs() {
+    use crate::codec::{decode, encode};
+    use crate::spdz::simulate_pp;
+    use crate::utils::{DleqNizk, Zq};
+    use bicycl::{Mpz, RandGen};
+
+    let (pp, _) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let (_, leaves) = PvssMsg::random(&pp, &mut rng, h).split();
+
+    // the recipient leads the leaf
+    let leaf = &leaves[&1];
+    let bytes = encode(leaf);
+    let validate = |bytes: &[u8]| decode::<PvssLeaf>(bytes).unwrap().validate(&pp);
+    assert_eq!(validate(&bytes), Ok(()));
+    let mut stranger = bytes.clone();
+    stranger[..2].copy_from_slice(&(pp.n + 1).to_le_bytes());
+    assert_eq!(
+        validate(&stranger),
+        Err(ValidationError::UnknownParty {
+            field: "recipient",
+            id: 4
+        })
+    );
+
+    // a path one sibling longer than a tree of three leaves is deep
+    let path = encode(&leaf.path);
+    let longer = [
+        &(leaf.path.len() as u64 + 1).to_le_bytes()[..],
+        &path[8..],
+        &encode(&leaf.path[0]),
+    ]
+    .concat();
+    assert_eq!(
+        validate(&splice(&bytes, &path, &longer)),
+        Err(ValidationError::TooLong {
+            field: "Merkle path",
+            got: 3,
+            max: 2
+        })
+    );
+
+    // the digest leads a key confirmation, after its length
+    let x = Zq::random();
+    let confirm = KeyConfirmMsg {
+        digest: KeyConfirmMsg::digest(&(G::generator() * &x), &[1, 2], b"validate"),
+        proof: DleqNizk::prove(
+            h,
+            &(h * &x),
+            &G::generator(),
+            &(G::generator() * &x),
+            &x,
+            &mut rng,
+        ),
+    };
+    let mut truncated = encode(&confirm);
+    truncated.remove(8);
+    truncated[..8].copy_from_slice(&31u64.to_le_bytes());
+    assert_eq!(
+        decode::<KeyConfirmMsg>(&truncated).unwrap().validate(&pp),
+        Err(ValidationError::WrongLength {
+            field: "digest",
+            got: 31,
+            expected: 32
+        })
+    );
+}
+
+#[test]
+pub fn test_validate_presign_msgs() {
+    use crate::codec::{decode, encode};
+    use crate::presign::{NonceGenContext, PresignError, PresignSession};
+    use crate::spdz::simulate_pp;
+    use crate::utils::{JointPvssResult, Zq};
+    use bicycl::{Mpz, RandGen};
+    use curv::arithmetic::Converter;
+
+    let (pp, _) = simulate_pp(3, 2);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+
+    // a dealing with a point at infinity fails before its proof is checked
+    let nonce_gen = NonceGenMsg::random(&pp, &mut rng, h);
+    let first = nonce_gen.gamma.dealing.curve_polynomial.coeffs[0].to_bytes(true);
+    let identity = splice(&encode(&nonce_gen), &first, &[0; 33]);
+    assert_eq!(
+        decode::<NonceGenMsg>(&identity)
+            .unwrap()
+            .verify(&NonceGenContext {
+                pp: &pp,
+                h,
+                session: &PresignSession::default(),
+            }),
+        Err(PresignError::Malformed(ValidationError::IdentityPoint {
+            field: "curve polynomial"
+        }))
+    );
+
+    let mut joint_dealing = || {
+        let dealings = (0..pp.t)
+            .map(|_| PvssDealing::random(&pp, &mut rng, h).0)
+            .collect();
+        JointPvssResult::new(&pp, dealings)
+    };
+    let (k_pvss, gamma_pvss) = (joint_dealing(), joint_dealing());
+    let msg = MtAwcMsg::new(
+        &pp,
+        h,
+        &mut rng,
+        &k_pvss,
+        &gamma_pvss,
+        1,
+        &Zq::random(),
+        &Zq::random(),
+        &PresignSession::default(),
+        BTreeMap::new(),
+    );
+    let bytes = encode(&msg);
+    let validate = |bytes: &[u8]| decode::<MtAwcMsg>(bytes).unwrap().validate(&pp);
+    assert_eq!(validate(&bytes), Ok(()));
+
+    // the opened Γ_i follows the length of the empty dealing digests
+    let mut identity = bytes.clone();
+    identity[8..41].fill(0);
+    assert_eq!(
+        validate(&identity),
+        Err(ValidationError::IdentityPoint {
+            field: "opened point"
+        })
+    );
+
+    // a MAC for party 0, and one at infinity
+    let mac = msg.k_x_dealing.curve_macs[&1].to_bytes(true);
+    let entry = |id: Id, mac: &[u8]| [&id.to_le_bytes()[..], mac].concat();
+    assert_eq!(
+        validate(&splice(&bytes, &entry(1, &mac), &entry(0, &mac))),
+        Err(ValidationError::UnknownParty {
+            field: "curve MACs",
+            id: 0
+        })
+    );
+    assert_eq!(
+        validate(&splice(&bytes, &entry(1, &mac), &entry(1, &[0; 33]))),
+        Err(ValidationError::IdentityPoint {
+            field: "curve MACs"
+        })
+    );
+
+    let final_msg = PreSignFinalMsg {
+        prev_round_digest: [0; 32],
+        delta_share: -Zq::from(1u64),
+        Gamma: G::generator() * Zq::random(),
+    };
+    let bytes = encode(&final_msg);
+    let identity = splice(&bytes, &final_msg.Gamma.to_bytes(true), &[0; 33]);
+    assert_eq!(
+        decode::<PreSignFinalMsg>(&identity).unwrap().validate(&pp),
+        Err(ValidationError::IdentityPoint { field: "Γ" })
+    );
+
+    // q - 1 turned into q, which is no scalar: curv writes a scalar as its bytes or
+    // as their hex, depending on the format
+    let hex = |bytes: &[u8]| -> Vec<u8> {
+        bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+            .into_bytes()
+    };
+    let (below_q, q) = (
+        final_msg.delta_share.to_bytes().to_vec(),
+        Zq::group_order().to_bytes(),
+    );
+    let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|window| window == needle);
+    let non_canonical = if contains(&below_q) {
+        splice(&bytes, &below_q, &q)
+    } else {
+        splice(&bytes, &hex(&below_q), &hex(&q))
+    };
+    assert!(decode::<PreSignFinalMsg>(&non_canonical).is_err());
+}
+
+#[test]
+pub fn test_malformed_sender_disqualified() {
+    use crate::codec::{decode, encode};
+    use crate::presign::{DisqualReason, PresignMsg};
+    use crate::spdz::{run_locally, simulate_pp, PresignSession, PresignState, Tampered};
+    use crate::test_utils::simulation::dkg_among;
+    use crate::utils::PartyId;
+
+    let (pp, secret_keys) = simulate_pp(3, 2);
+    let h = &pp.curve_generator;
+    let keys = dkg_among(&pp, &secret_keys);
+
+    // party 1 opens its Γ_i as the point at infinity, which the others come across
+    // first as they go through the senders in order. Having counted its own opening,
+    // it settles on another Γ, and its share of delta for that one is left out too
+    let tamper: fn(PresignMsg) -> Option<PresignMsg> = |msg| match msg {
+        PresignMsg::MtAwc(msg) => {
+            let mut bytes = encode(&msg);
+            bytes[..33].fill(0);
+            Some(PresignMsg::MtAwc(decode(&bytes).unwrap()))
+        }
+        msg => Some(msg),
+    };
+    let presignatures = run_locally(
+        (1..=pp.n)
+            .map(|i| {
+                let wire = PartyId::from_share_index(i).to_wire_index();
+                let state = PresignState::new(
+                    i,
+                    &pp,
+                    h,
+                    &secret_keys[&i],
+                    &keys[wire as usize],
+                    PresignSession::default(),
+                );
+                let tamper = if i == 1 { tamper } else { Some };
+                (
+                    wire,
+                    Tampered {
+                        inner: state,
+                        tamper,
+                    },
+                )
+            })
+            .collect(),
+    );
+
+    let cheater = PartyId::from_share_index(1).to_wire_index();
+    for i in [2, 3] {
+        let presignature = &presignatures[&PartyId::from_share_index(i).to_wire_index()];
+        assert_eq!(
+            presignature.disqualified,
+            std::collections::BTreeMap::from([(cheater, DisqualReason::Malformed)])
+        );
+    }
+}
+
+#[test]
+pub fn test_curve_polynomial_validate_points() {
+    use crate::spdz::simulate_pp;
+    use crate::utils::Zq;
+    use bicycl::{Mpz, RandGen};
+
+    let (pp, _) = simulate_pp(4, 3);
+    let h = G::base_point2();
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let (dealing, ..) = PvssDealing::random(&pp, &mut rng, h);
+    assert_eq!(dealing.curve_polynomial.validate_points(), Ok(()));
+
+    // the identity injected as the constant or the leading coefficient
+    for index in [0, pp.t as usize - 1] {
+        let mut injected = dealing.clone();
+        injected.curve_polynomial.coeffs[index] = G::zero();
+        assert_eq!(
+            injected.curve_polynomial.validate_points(),
+            Err(PointError::Identity { index })
+        );
+        assert_eq!(
+            injected.validate(&pp),
+            Err(ValidationError::IdentityPoint {
+                field: "curve polynomial"
+            })
+        );
+        let joint = JointPvssResult {
+            shares_ciphertext: injected.shares_ciphertext.clone(),
+            curve_polynomial: injected.curve_polynomial.clone(),
+        };
+        assert_eq!(
+            joint.validate(&pp),
+            Err(ValidationError::IdentityPoint {
+                field: "curve polynomial"
+            })
+        );
+    }
+}
@@ -0,0 +1,459 @@
+//! Versioning of the wire format. Protocol messages travel inside a `VersionedMsg`
+//! stamped with the version they were encoded for, and a committee can optionally
+//! open with a hello round to agree on the highest version all of its members speak.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use round_based::{Outgoing, PartyIndex, ProtocolMessage};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::spdz::{StateError, StateMachine};
+
+/// Version of the current wire format; bump it with any change to the message encoding.
+/// Version 6 proves both MtA dealings of an `MtAwcMsg` with one `DualMtaNizk`, and
+/// version 7 starts presigning and signing messages with the digests of the round
+/// they respond to. Version 8 drops the dealer nonce of `NonceGenMsg`, whose dealings
+/// are bound to the session alone.
+pub const PROTOCOL_VERSION: u16 = 8;
+
+/// The first version with compact PVSS dealings, which a DKG under `PubParams::compact_pvss`
+/// deals once its committee agrees on it, if it confirms its key.
+pub const COMPACT_PVSS_VERSION: u16 = 5;
+
+/// Leading tag of every versioned message. Unversioned messages start with a small enum
+/// variant index instead, so they fail to decode rather than being misread.
+const WIRE_MAGIC: u32 = 0x5254_4543;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct WireMagic;
+
+impl Serialize for WireMagic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(WIRE_MAGIC)
+    }
+}
+
+impl<'de> Deserialize<'de> for WireMagic {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match u32::deserialize(deserializer)? {
+            WIRE_MAGIC => Ok(WireMagic),
+            _ => Err(de::Error::custom("not a versioned protocol message")),
+        }
+    }
+}
+
+/// The wire format versions a party can speak, both ends included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRange {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl VersionRange {
+    pub fn single(version: u16) -> Self {
+        VersionRange {
+            min: version,
+            max: version,
+        }
+    }
+
+    /// The versions both ranges contain, if there are any.
+    pub fn intersect(self, other: VersionRange) -> Option<VersionRange> {
+        let range = VersionRange {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+        };
+        (range.min <= range.max).then_some(range)
+    }
+}
+
+impl Default for VersionRange {
+    fn default() -> Self {
+        VersionRange::single(PROTOCOL_VERSION)
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..={}", self.min, self.max)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VersionedMsg<M> {
+    magic: WireMagic,
+    pub body: VersionedBody<M>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum VersionedBody<M> {
+    /// The optional zeroth round: the versions the sender can speak.
+    Hello(VersionRange),
+    /// A protocol message encoded for `version`.
+    Payload { version: u16, payload: M },
+}
+
+impl<M> VersionedMsg<M> {
+    pub fn hello(supported: VersionRange) -> Self {
+        VersionedMsg {
+            magic: WireMagic,
+            body: VersionedBody::Hello(supported),
+        }
+    }
+
+    pub fn payload(version: u16, payload: M) -> Self {
+        VersionedMsg {
+            magic: WireMagic,
+            body: VersionedBody::Payload { version, payload },
+        }
+    }
+}
+
+impl<M: ProtocolMessage> ProtocolMessage for VersionedMsg<M> {
+    fn round(&self) -> u16 {
+        match &self.body {
+            VersionedBody::Hello(_) => 0,
+            VersionedBody::Payload { payload, .. } => payload.round() + 1,
+        }
+    }
+}
+
+/// Runs a protocol over versioned messages, rejecting any message stamped with a
+/// version other than the one in use. Its output comes with that version.
+pub struct Versioned<S: StateMachine> {
+    inner: S,
+    n: u16,
+    supported: VersionRange,
+    common: VersionRange,
+    hellos: BTreeSet<PartyIndex>,
+    agreed: Option<u16>, // None until the hello round is over
+    // a peer with no version in common, and the versions it speaks
+    mismatch: Option<(PartyIndex, VersionRange)>,
+    pending: Vec<(PartyIndex, u16, S::Msg)>,
+    outbox: Vec<Outgoing<VersionedMsg<S::Msg>>>,
+}
+
+impl<S: StateMachine> Versioned<S> {
+    /// Speaks `version` only, without a hello round. Peers that send a hello are rejected.
+    pub fn fixed(mut inner: S, version: u16) -> Self {
+        inner.on_version(version);
+        Versioned {
+            inner,
+            n: 0,
+            supported: VersionRange::single(version),
+            common: VersionRange::single(version),
+            hellos: BTreeSet::new(),
+            agreed: Some(version),
+            mismatch: None,
+            pending: vec![],
+            outbox: vec![],
+        }
+    }
+
+    /// Opens with a hello round among the `n` parties, then runs `inner` at the highest
+    /// version all of them support. The inner messages are held back until then. A
+    /// peer that speaks none of our versions fails the run with `VersionMismatch`, but
+    /// only once our own hello is out: it is what shows the others the mismatch, who
+    /// would otherwise wait for it forever.
+    pub fn negotiate(inner: S, n: u16, supported: VersionRange) -> Self {
+        Versioned {
+            inner,
+            n,
+            supported,
+            common: supported,
+            hellos: BTreeSet::new(),
+            agreed: None,
+            mismatch: None,
+            pending: vec![],
+            outbox: vec![Outgoing::broadcast(VersionedMsg::hello(supported))],
+        }
+    }
+
+    fn handle_payload(
+        &mut self,
+        from: PartyIndex,
+        version: u16,
+        payload: S::Msg,
+        agreed: u16,
+    ) -> Result<(), StateError> {
+        if version != agreed {
+            return Err(StateError::VersionMismatch {
+                peer: from,
+                theirs: VersionRange::single(version),
+                ours: VersionRange::single(agreed),
+            });
+        }
+        self.inner.handle_message(from, payload)
+    }
+
+    /// The error for a peer without a version in common, once our hello has gone out.
+    fn check_mismatch(&self) -> Result<(), StateError> {
+        match self.mismatch {
+            Some((peer, theirs)) if self.outbox.is_empty() => Err(StateError::VersionMismatch {
+                peer,
+                theirs,
+                ours: self.supported,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<S: StateMachine> StateMachine for Versioned<S> {
+    type Msg = VersionedMsg<S::Msg>;
+    type Output = (u16, S::Output);
+
+    fn handle_message(&mut self, from: PartyIndex, msg: Self::Msg) -> Result<(), StateError> {
+        self.check_mismatch()?;
+        if self.mismatch.is_some() {
+            return Ok(());
+        }
+        match (msg.body, self.agreed) {
+            (VersionedBody::Hello(theirs), None) => {
+                if !self.hellos.insert(from) {
+                    return Err(StateError::DuplicateSender(from));
+                }
+                self.common = match self.common.intersect(theirs) {
+                    Some(common) => common,
+                    None => {
+                        self.mismatch = Some((from, theirs));
+                        return self.check_mismatch();
+                    }
+                };
+
+                if self.hellos.len() + 1 == self.n as usize {
+                    let agreed = self.common.max;
+                    self.agreed = Some(agreed);
+                    self.inner.on_version(agreed);
+                    for (from, version, payload) in std::mem::take(&mut self.pending) {
+                        self.handle_payload(from, version, payload, agreed)?;
+                    }
+                }
+                Ok(())
+            }
+            (VersionedBody::Hello(theirs), Some(_)) => Err(StateError::VersionMismatch {
+                peer: from,
+                theirs,
+                ours: self.supported,
+            }),
+            (VersionedBody::Payload { version, payload }, None) => {
+                self.pending.push((from, version, payload));
+                Ok(())
+            }
+            (VersionedBody::Payload { version, payload }, Some(agreed)) => {
+                self.handle_payload(from, version, payload, agreed)
+            }
+        }
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<Self::Msg>> {
+        let mut outgoing = std::mem::take(&mut self.outbox);
+        if let Some(version) = self.agreed {
+            outgoing.extend(self.inner.wants_to_send().into_iter().map(
+                |Outgoing { recipient, msg }| Outgoing {
+                    recipient,
+                    msg: VersionedMsg::payload(version, msg),
+                },
+            ));
+        }
+        outgoing
+    }
+
+    fn try_finish(self) -> Result<Self::Output, Self> {
+        let version = match self.agreed {
+            Some(version) if self.outbox.is_empty() => version,
+            _ => return Err(self),
+        };
+        let Versioned {
+            inner,
+            n,
+            supported,
+            common,
+            hellos,
+            agreed,
+            mismatch,
+            pending,
+            outbox,
+        } = self;
+        inner
+            .try_finish()
+            .map(|output| (version, output))
+            .map_err(|inner| Versioned {
+                inner,
+                n,
+                supported,
+                common,
+                hellos,
+                agreed,
+                mismatch,
+                pending,
+                outbox,
+            })
+    }
+
+    fn on_timeout(&mut self) -> Result<(), StateError> {
+        self.check_mismatch()?;
+        // a version is agreed by the whole committee or not at all
+        match self.agreed {
+            Some(_) => self.inner.on_timeout(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Broadcasts its index once and finishes when it has heard from everyone else.
+#[cfg(test)]
+struct PingState {
+    n: u16,
+    heard: BTreeSet<PartyIndex>,
+    outbox: Vec<Outgoing<Ping>>,
+}
+
+#[cfg(test)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Ping(PartyIndex);
+
+#[cfg(test)]
+impl ProtocolMessage for Ping {
+    fn round(&self) -> u16 {
+        0
+    }
+}
+
+#[cfg(test)]
+impl PingState {
+    fn new(i: PartyIndex, n: u16) -> Self {
+        PingState {
+            n,
+            heard: BTreeSet::new(),
+            outbox: vec![Outgoing::broadcast(Ping(i))],
+        }
+    }
+}
+
+#[cfg(test)]
+impl StateMachine for PingState {
+    type Msg = Ping;
+    type Output = usize;
+
+    fn handle_message(&mut self, from: PartyIndex, _: Ping) -> Result<(), StateError> {
+        self.heard.insert(from);
+        Ok(())
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<Ping>> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    fn try_finish(self) -> Result<usize, Self> {
+        if self.heard.len() + 1 == self.n as usize && self.outbox.is_empty() {
+            Ok(self.heard.len())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+#[test]
+pub fn test_version_negotiation() {
+    use crate::spdz::run_locally_detailed;
+
+    let v1 = VersionRange::single(1);
+    let v1_to_2 = VersionRange { min: 1, max: 2 };
+    let negotiate = |ranges: [VersionRange; 3]| {
+        run_locally_detailed(
+            (0..3)
+                .map(|i| {
+                    (
+                        i,
+                        Versioned::negotiate(PingState::new(i, 3), 3, ranges[i as usize]),
+                    )
+                })
+                .collect(),
+        )
+    };
+
+    // two parties at version 2 that still speak 1, and one at version 1
+    let run = negotiate([v1_to_2, v1_to_2, v1]);
+    assert!(run.aborted.is_empty());
+    assert_eq!(run.outputs.len(), 3);
+    assert!(run
+        .outputs
+        .values()
+        .all(|&(version, heard)| version == 1 && heard == 2));
+
+    let run = negotiate([v1_to_2; 3]);
+    assert!(run.outputs.values().all(|&(version, _)| version == 2));
+
+    // a party that dropped version 1 cannot talk to one that only has it; the latter
+    // sees it on the first hello, but sends its own before it gives up, so that the
+    // others see it too rather than waiting for it
+    let v2 = VersionRange::single(2);
+    let run = negotiate([v1, v1_to_2, v2]);
+    assert!(run.outputs.is_empty());
+    for (i, ours) in [(0, v1), (1, v1_to_2)] {
+        assert!(matches!(
+            run.aborted[&i],
+            StateError::VersionMismatch { peer: 2, theirs, ours: o }
+                if theirs == v2 && o == ours
+        ));
+    }
+
+    // and it fails on the next message or deadline, having nothing more to wait for
+    let mut late = Versioned::negotiate(PingState::new(2, 3), 3, v2);
+    late.handle_message(0, VersionedMsg::hello(v1)).unwrap();
+    assert_eq!(late.wants_to_send().len(), 1);
+    assert!(matches!(
+        late.on_timeout(),
+        Err(StateError::VersionMismatch { peer: 0, theirs, ours })
+            if theirs == v1 && ours == v2
+    ));
+    assert!(late
+        .handle_message(1, VersionedMsg::hello(v1_to_2))
+        .is_err());
+}
+
+#[test]
+pub fn test_fixed_version_mismatch() {
+    use crate::spdz::run_locally_detailed;
+
+    // party 0 is the first to send, so the others abort before sending anything
+    let run = run_locally_detailed(
+        (0..3)
+            .map(|i| {
+                (
+                    i,
+                    Versioned::fixed(PingState::new(i, 3), if i == 0 { 2 } else { 1 }),
+                )
+            })
+            .collect(),
+    );
+    assert!(run.outputs.is_empty());
+    for i in [1, 2] {
+        assert!(matches!(
+            run.aborted[&i],
+            StateError::VersionMismatch { peer: 0, theirs, ours }
+                if theirs == VersionRange::single(2) && ours == VersionRange::single(1)
+        ));
+    }
+}
+
+#[test]
+pub fn test_unversioned_message_rejected() {
+    use crate::codec::{decode, encode};
+    use crate::spdz::SignMsg;
+    use crate::utils::Zq;
+
+    let sign_msg = SignMsg::OnlineSign(crate::presign::OnlineSignMsg {
+        prev_round_digest: [0; 32],
+        sig_share: Zq::random(),
+    });
+    let versioned = VersionedMsg::payload(PROTOCOL_VERSION, sign_msg.clone());
+
+    assert_eq!(
+        decode::<VersionedMsg<SignMsg>>(&encode(&versioned)).unwrap(),
+        versioned
+    );
+    assert!(decode::<VersionedMsg<SignMsg>>(&encode(&sign_msg)).is_err());
+}
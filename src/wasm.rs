@@ -0,0 +1,457 @@
+//! wasm-bindgen exports for a browser cosigner. The host moves opaque bytes: it passes
+//! each received message to a run of the protocol, sends what the run hands out, and
+//! picks up the result once the run finishes. Keys and presignatures cross the boundary in
+//! the `codec` encoding, so the host can store them as they are.
+//!
+//! The state machines borrow the participant and its key, which wasm-bindgen types
+//! cannot do. A `Cosigner` and every `Key` loaded into it are therefore leaked for the
+//! life of the page, which suits a cosigner that sets up one party and its keys once.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use bicycl::{Mpz, PublicKey, SecretKey, QFI};
+use round_based::PartyIndex;
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::cl_setup::ClSetup;
+use crate::codec::{decode, encode, Encoded};
+use crate::participant::Participant;
+use crate::presign::PreSignature;
+use crate::spdz::{
+    DkgOutput, DkgState, PresignSession, PresignState, SignState, SigningContext, StateMachine,
+};
+use crate::utils::{CLKeyRing, Id};
+
+/// A message for the host to send: to one party, or to all of them if `recipient` is
+/// undefined.
+#[wasm_bindgen]
+pub struct OutgoingMessage {
+    recipient: Option<PartyIndex>,
+    bytes: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl OutgoingMessage {
+    #[wasm_bindgen(getter)]
+    pub fn recipient(&self) -> Option<PartyIndex> {
+        self.recipient
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+}
+
+/// A state machine with its outgoing messages queued for the host, until it finishes.
+struct Driven<S> {
+    state: Option<Encoded<S>>,
+    outbox: VecDeque<OutgoingMessage>,
+}
+
+impl<S> Driven<S>
+where
+    S: StateMachine,
+    S::Msg: Serialize + DeserializeOwned,
+{
+    fn new(state: Encoded<S>) -> Self {
+        let mut driven = Driven {
+            state: Some(state),
+            outbox: VecDeque::new(),
+        };
+        driven.collect_outgoing();
+        driven
+    }
+
+    fn state(&mut self) -> Result<&mut Encoded<S>, JsError> {
+        self.state
+            .as_mut()
+            .ok_or_else(|| JsError::new("the run has already finished"))
+    }
+
+    fn collect_outgoing(&mut self) {
+        if let Some(state) = &mut self.state {
+            self.outbox.extend(
+                state
+                    .wants_to_send()
+                    .into_iter()
+                    .map(|(recipient, bytes)| OutgoingMessage { recipient, bytes }),
+            );
+        }
+    }
+
+    fn handle_message(&mut self, from: PartyIndex, bytes: &[u8]) -> Result<(), JsError> {
+        self.state()?.handle_bytes(from, bytes)?;
+        self.collect_outgoing();
+        Ok(())
+    }
+
+    fn on_timeout(&mut self) -> Result<(), JsError> {
+        self.state()?.on_timeout()?;
+        self.collect_outgoing();
+        Ok(())
+    }
+
+    fn next_outgoing(&mut self) -> Option<OutgoingMessage> {
+        self.outbox.pop_front()
+    }
+
+    /// The output, once the state machine has one; `None` before.
+    fn finish(&mut self) -> Result<Option<S::Output>, JsError> {
+        let state = self
+            .state
+            .take()
+            .ok_or_else(|| JsError::new("the run has already finished"))?;
+        match state.try_finish() {
+            Ok(output) => Ok(Some(output)),
+            Err(state) => {
+                self.state = Some(state);
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// One party of a committee, built as `ParticipantBuilder::build` would.
+#[wasm_bindgen]
+pub struct Cosigner {
+    me: &'static Participant,
+}
+
+#[wasm_bindgen]
+impl Cosigner {
+    /// `setup` is a CL setup file as `ClSetup::save` writes it, `keyring` the encoded
+    /// map from the ids 1..=n to the class group elements of the CL public keys, and
+    /// `cl_secret` our CL secret key as a big-endian integer.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        index: PartyIndex,
+        threshold: Id,
+        parties: Id,
+        setup: &str,
+        keyring: &[u8],
+        cl_secret: &[u8],
+        session: Option<Vec<u8>>,
+    ) -> Result<Cosigner, JsError> {
+        let setup: ClSetup = serde_json::from_str(setup)?;
+        setup.verify()?;
+        let cl = setup.cl();
+        let public_keys: BTreeMap<Id, QFI> = decode(keyring)?;
+        let cl_keyring = CLKeyRing::from(
+            public_keys
+                .iter()
+                .map(|(&id, elt)| (id, PublicKey::from_qfi(&cl, elt)))
+                .collect::<BTreeMap<_, _>>(),
+        );
+        let cl_secret = SecretKey::from_mpz(&cl, &Mpz::from_bytes(cl_secret));
+
+        let mut builder = Participant::builder()
+            .index(index)
+            .threshold(threshold)
+            .parties(parties)
+            .cl_group(cl)
+            .cl_keyring(cl_keyring)
+            .cl_secret(cl_secret);
+        if let Some(session) = &session {
+            builder = builder.session(session);
+        }
+        let me = Box::leak(Box::new(builder.build()?));
+        Ok(Cosigner { me })
+    }
+
+    /// Starts the DKG, with key confirmation if the cosigner has a session.
+    pub fn dkg(&self, lazy_verification: bool) -> DkgRun {
+        let pp = self.me.pp();
+        let state = DkgState::new(
+            self.me.id(),
+            pp,
+            &pp.curve_generator,
+            self.me.cl_secret(),
+            lazy_verification,
+        );
+        let state = match self.me.session() {
+            Some(session) => state.with_key_confirmation(session),
+            None => state,
+        };
+        DkgRun {
+            me: self.me,
+            driven: Driven::new(Encoded::new(state).with_limits(&pp.limits)),
+        }
+    }
+
+    /// A key from `Key::to_bytes`, of an earlier DKG of this committee.
+    pub fn load_key(&self, bytes: &[u8]) -> Result<Key, JsError> {
+        Ok(Key::new(self.me, decode(bytes)?))
+    }
+}
+
+/// The output of a DKG, ready to presign and sign with.
+#[wasm_bindgen]
+pub struct Key {
+    me: &'static Participant,
+    output: &'static DkgOutput,
+    context: &'static SigningContext,
+}
+
+impl Key {
+    fn new(me: &'static Participant, output: DkgOutput) -> Self {
+        let output = Box::leak(Box::new(output));
+        let context = Box::leak(Box::new(SigningContext::from_dkg(me.pp(), output)));
+        Key {
+            me,
+            output,
+            context,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Key {
+    /// The encoded DKG output, for `Cosigner::load_key`. It holds our key share.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode(self.output)
+    }
+
+    /// The joint public key, compressed.
+    pub fn public_key(&self) -> Vec<u8> {
+        self.context.pk.to_bytes(true).to_vec()
+    }
+
+    /// Starts presigning the presignature numbered `counter`; see `participant::presign`.
+    pub fn presign(&self, counter: u64) -> PresignRun {
+        let pp = self.me.pp();
+        let state = PresignState::new(
+            self.me.id(),
+            pp,
+            &pp.curve_generator,
+            self.me.cl_secret(),
+            self.output,
+            PresignSession::new(self.me.session().unwrap_or_default(), counter),
+        );
+        PresignRun {
+            driven: Driven::new(Encoded::new(state).with_limits(&pp.limits)),
+        }
+    }
+
+    /// Starts signing `msg` with an encoded presignature from `PresignRun::finish`.
+    pub fn sign(&self, presignature: &[u8], msg: &[u8]) -> Result<SignRun, JsError> {
+        let pp = self.me.pp();
+        let presignature: PreSignature = decode(presignature)?;
+        let state = SignState::new(
+            self.me.id(),
+            pp,
+            &pp.curve_generator,
+            self.context,
+            presignature,
+            msg,
+        );
+        let state = match self.me.session() {
+            Some(session) => state.with_session_id(session),
+            None => state,
+        };
+        Ok(SignRun {
+            driven: Driven::new(Encoded::new(state).with_limits(&pp.limits)),
+        })
+    }
+}
+
+#[wasm_bindgen]
+pub struct DkgRun {
+    me: &'static Participant,
+    driven: Driven<DkgState<'static>>,
+}
+
+#[wasm_bindgen]
+impl DkgRun {
+    pub fn handle_message(&mut self, from: PartyIndex, bytes: &[u8]) -> Result<(), JsError> {
+        self.driven.handle_message(from, bytes)
+    }
+
+    /// See `StateMachine::on_timeout`.
+    pub fn on_timeout(&mut self) -> Result<(), JsError> {
+        self.driven.on_timeout()
+    }
+
+    pub fn next_outgoing(&mut self) -> Option<OutgoingMessage> {
+        self.driven.next_outgoing()
+    }
+
+    /// The key, once the DKG has finished; undefined before.
+    pub fn finish(&mut self) -> Result<Option<Key>, JsError> {
+        Ok(self
+            .driven
+            .finish()?
+            .map(|output| Key::new(self.me, output)))
+    }
+}
+
+#[wasm_bindgen]
+pub struct PresignRun {
+    driven: Driven<PresignState<'static>>,
+}
+
+#[wasm_bindgen]
+impl PresignRun {
+    pub fn handle_message(&mut self, from: PartyIndex, bytes: &[u8]) -> Result<(), JsError> {
+        self.driven.handle_message(from, bytes)
+    }
+
+    /// See `StateMachine::on_timeout`.
+    pub fn on_timeout(&mut self) -> Result<(), JsError> {
+        self.driven.on_timeout()
+    }
+
+    pub fn next_outgoing(&mut self) -> Option<OutgoingMessage> {
+        self.driven.next_outgoing()
+    }
+
+    /// The encoded presignature, once presigning has finished; undefined before.
+    pub fn finish(&mut self) -> Result<Option<Vec<u8>>, JsError> {
+        Ok(self
+            .driven
+            .finish()?
+            .map(|presignature| encode(&presignature)))
+    }
+}
+
+#[wasm_bindgen]
+pub struct SignRun {
+    driven: Driven<SignState<'static>>,
+}
+
+#[wasm_bindgen]
+impl SignRun {
+    pub fn handle_message(&mut self, from: PartyIndex, bytes: &[u8]) -> Result<(), JsError> {
+        self.driven.handle_message(from, bytes)
+    }
+
+    /// See `StateMachine::on_timeout`.
+    pub fn on_timeout(&mut self) -> Result<(), JsError> {
+        self.driven.on_timeout()
+    }
+
+    pub fn next_outgoing(&mut self) -> Option<OutgoingMessage> {
+        self.driven.next_outgoing()
+    }
+
+    /// The signature as the 32 bytes of r followed by the 32 bytes of s, once signing
+    /// has finished; undefined before.
+    pub fn finish(&mut self) -> Result<Option<Vec<u8>>, JsError> {
+        Ok(self.driven.finish()?.map(|outcome| {
+            [
+                &*outcome.signature.r.to_bytes(),
+                &*outcome.signature.s.to_bytes(),
+            ]
+            .concat()
+        }))
+    }
+}
+
+#[test]
+pub fn test_cosigners_in_memory() {
+    use crate::cl_setup::SecurityLevel;
+    use crate::spdz::SignatureECDSA;
+    use crate::utils::{fresh_rng, Zq, G};
+
+    // outside a browser, a JsError has nothing to show
+    fn ok<T>(result: Result<T, JsError>) -> T {
+        result.unwrap_or_else(|_| panic!("a cosigner call failed"))
+    }
+
+    /// Delivers the messages of every run until none is left, then finishes them all.
+    fn deliver<R, T>(
+        runs: &mut [R],
+        next: impl Fn(&mut R) -> Option<OutgoingMessage>,
+        handle: impl Fn(&mut R, PartyIndex, &[u8]) -> Result<(), JsError>,
+        finish: impl Fn(&mut R) -> Result<Option<T>, JsError>,
+    ) -> Vec<T> {
+        loop {
+            let mut sent = false;
+            for from in 0..runs.len() {
+                while let Some(msg) = next(&mut runs[from]) {
+                    sent = true;
+                    for to in 0..runs.len() {
+                        let to_all = msg.recipient().is_none();
+                        if to != from && (to_all || msg.recipient() == Some(to as PartyIndex)) {
+                            ok(handle(&mut runs[to], from as PartyIndex, &msg.bytes()));
+                        }
+                    }
+                }
+            }
+            if !sent {
+                break;
+            }
+        }
+        runs.iter_mut()
+            .map(|run| ok(finish(run)).expect("the run has finished"))
+            .collect()
+    }
+
+    let (n, t) = (3, 2);
+    let setup = ClSetup::generate(SecurityLevel::Toy);
+    let (keyring, secret_keys) = CLKeyRing::from_keygen(&setup.cl(), &mut fresh_rng(), n);
+    let public_keys: BTreeMap<Id, QFI> = keyring.iter().map(|(&id, pk)| (id, pk.elt())).collect();
+    let setup = serde_json::to_string(&setup).unwrap();
+    let cosigners: Vec<Cosigner> = (0..n)
+        .map(|index| {
+            let cl_secret = secret_keys[&(index + 1)].mpz().to_bytes();
+            ok(Cosigner::new(
+                index,
+                t,
+                n,
+                &setup,
+                &encode(&public_keys),
+                &cl_secret,
+                Some(b"in memory".to_vec()),
+            ))
+        })
+        .collect();
+
+    let mut dkg: Vec<DkgRun> = cosigners.iter().map(|c| c.dkg(false)).collect();
+    let keys = deliver(
+        &mut dkg,
+        DkgRun::next_outgoing,
+        DkgRun::handle_message,
+        DkgRun::finish,
+    );
+    let pk = G::from_bytes(&keys[0].public_key()).unwrap();
+    assert!(keys
+        .iter()
+        .all(|key| key.public_key() == keys[0].public_key()));
+
+    // a key survives its encoding
+    let keys: Vec<Key> = cosigners
+        .iter()
+        .zip(&keys)
+        .map(|(cosigner, key)| ok(cosigner.load_key(&key.to_bytes())))
+        .collect();
+
+    let mut presign: Vec<PresignRun> = keys.iter().map(|key| key.presign(0)).collect();
+    let presignatures = deliver(
+        &mut presign,
+        PresignRun::next_outgoing,
+        PresignRun::handle_message,
+        PresignRun::finish,
+    );
+
+    let msg = b"signed in the browser";
+    let mut sign: Vec<SignRun> = keys
+        .iter()
+        .zip(&presignatures)
+        .map(|(key, presignature)| ok(key.sign(presignature, msg)))
+        .collect();
+    let signatures = deliver(
+        &mut sign,
+        SignRun::next_outgoing,
+        SignRun::handle_message,
+        SignRun::finish,
+    );
+    for bytes in signatures {
+        let signature = SignatureECDSA {
+            r: Zq::from_bytes(&bytes[..32]).unwrap(),
+            s: Zq::from_bytes(&bytes[32..]).unwrap(),
+        };
+        assert!(signature.verify(&pk, msg));
+    }
+}
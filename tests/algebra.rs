@@ -0,0 +1,174 @@
+//! Randomized checks of the algebra behind the PVSS and MtA dealings. Each case draws a
+//! seed for the CL randomness; proptest reports it along with (n, t) when a case fails.
+
+use std::collections::BTreeMap;
+
+use bicycl::{CL_HSMqk, Mpz, RandGen, SecretKey};
+use proptest::prelude::*;
+use proptest::sample::subsequence;
+use proptest::test_runner::{Config, TestRunner};
+use robust_threshold_ecdsa::cl_setup::{ClFromLevel, SecurityLevel};
+use robust_threshold_ecdsa::utils::*;
+
+/// Class group generation dominates the running time, so every case of a test shares one.
+fn toy_cl() -> CL_HSMqk {
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    CL_HSMqk::from_level(SecurityLevel::Toy, &mut rng)
+}
+
+fn runner() -> TestRunner {
+    TestRunner::new(Config {
+        cases: 16,
+        ..Config::default()
+    })
+}
+
+/// (n, t, seed) with 1 <= t <= n <= 8
+fn params() -> impl Strategy<Value = (Id, Id, u64)> {
+    (1..=8 as Id).prop_flat_map(|n| (Just(n), 1..=n, any::<u64>()))
+}
+
+fn setup(cl: &CL_HSMqk, n: Id, t: Id, seed: u64) -> (PubParams, BTreeMap<Id, SecretKey>, RandGen) {
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(seed));
+    let (pp, secret_keys) = PubParams::with_cl(cl.clone(), n, t, &mut rng).unwrap();
+    (pp, secret_keys, rng)
+}
+
+#[test]
+pub fn test_pvss_shares_match_curve_polynomial() {
+    let cl = toy_cl();
+    let h = G::base_point2();
+
+    runner()
+        .run(&params(), |(n, t, seed)| {
+            let (pp, sk, mut rng) = setup(&cl, n, t, seed);
+            let (dealing, _, _, _) = PvssDealing::random(&pp, &mut rng, h);
+
+            for id in 1..=n {
+                let share = dealing.shares_ciphertext.decrypt(&pp.cl, id, &sk[&id]);
+                prop_assert_eq!(
+                    h * &share,
+                    dealing.curve_polynomial.eval(&party_eval_point(id)),
+                    "recipient {}",
+                    id
+                );
+            }
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[test]
+pub fn test_joint_pvss_is_sum_of_dealings() {
+    let cl = toy_cl();
+    let h = G::base_point2();
+
+    runner()
+        .run(&(params(), 1..=4usize), |((n, t, seed), k)| {
+            let (pp, sk, mut rng) = setup(&cl, n, t, seed);
+            let dealings: Vec<PvssDealing> = (0..k)
+                .map(|_| PvssDealing::random(&pp, &mut rng, h).0)
+                .collect();
+            let joint = JointPvssResult::new(&pp, dealings.clone());
+
+            for id in 1..=n {
+                let sum: Zq = dealings
+                    .iter()
+                    .map(|dealing| dealing.shares_ciphertext.decrypt(&pp.cl, id, &sk[&id]))
+                    .sum();
+                prop_assert_eq!(
+                    joint.shares_ciphertext.decrypt(&pp.cl, id, &sk[&id]),
+                    sum,
+                    "recipient {}",
+                    id
+                );
+            }
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[test]
+pub fn test_mta_dealing_unmasks_to_product() {
+    let cl = toy_cl();
+    let h = G::base_point2();
+
+    runner()
+        .run(&params(), |(n, t, seed)| {
+            let (pp, sk, mut rng) = setup(&cl, n, t, seed);
+            let pvss = JointPvssResult::new(&pp, vec![PvssDealing::random(&pp, &mut rng, h).0]);
+            let scalar = Zq::random();
+            let (mta_dealing, masks) = MtaDealing::new(&pp, &pvss, &scalar, &mut rng, h);
+
+            for id in 1..=n {
+                let share = pvss.shares_ciphertext.decrypt(&pp.cl, id, &sk[&id]);
+                let received = mta_dealing.shares_ciphertext.decrypt(&pp.cl, id, &sk[&id]);
+                prop_assert_eq!(received - &masks[&id], &scalar * &share, "recipient {}", id);
+            }
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[test]
+pub fn test_any_t_shares_reconstruct_constant_term() {
+    let cl = toy_cl();
+    let h = G::base_point2();
+
+    let strategy = params().prop_flat_map(|(n, t, seed)| {
+        let ids: Vec<Id> = (1..=n).collect();
+        (Just(n), Just(t), Just(seed), subsequence(ids, t as usize))
+    });
+    runner()
+        .run(&strategy, |(n, t, seed, parties)| {
+            let (pp, sk, mut rng) = setup(&cl, n, t, seed);
+            let (dealing, _, polynomial, _) = PvssDealing::random(&pp, &mut rng, h);
+
+            let shares: BTreeMap<Id, Zq> = parties
+                .iter()
+                .map(|&id| (id, dealing.shares_ciphertext.decrypt(&pp.cl, id, &sk[&id])))
+                .collect();
+            prop_assert_eq!(
+                pp.interpolate(&shares).unwrap(),
+                polynomial.coeffs[0].clone(),
+                "parties {:?}",
+                parties
+            );
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[test]
+pub fn test_lagrange_interpolation_recovers_polynomial() {
+    use proptest::collection::vec;
+    use robust_threshold_ecdsa::interpolation::{interpolate_at_zero, lagrange_coeffs_at};
+
+    // a polynomial of degree below t, t of the ids 1..=32, and a point to evaluate at
+    let strategy = (1..=8usize).prop_flat_map(|t| {
+        let ids: Vec<Id> = (1..=32).collect();
+        (vec(any::<u64>(), t), subsequence(ids, t), any::<u64>())
+    });
+    runner()
+        .run(&strategy, |(coeffs, parties, x)| {
+            let poly = Polynomial {
+                coeffs: coeffs.into_iter().map(Zq::from).collect(),
+            };
+            let shares: BTreeMap<Id, Zq> = parties
+                .iter()
+                .map(|&id| (id, poly.eval(&party_eval_point(id))))
+                .collect();
+            prop_assert_eq!(interpolate_at_zero(&shares), poly.coeffs[0].clone());
+
+            let x = Zq::from(x);
+            let at_x: Zq = lagrange_coeffs_at(&parties, &x)
+                .iter()
+                .map(|(id, coeff)| coeff * &shares[id])
+                .sum();
+            prop_assert_eq!(at_x, poly.eval(&x), "parties {:?}", parties);
+            Ok(())
+        })
+        .unwrap();
+}
@@ -0,0 +1,52 @@
+use assert_cmd::Command;
+use robust_threshold_ecdsa::spdz::SignatureECDSA;
+use robust_threshold_ecdsa::utils::{Zq, G};
+
+#[test]
+pub fn test_cli_json_output() {
+    let output = Command::cargo_bin("robust_threshold_ecdsa")
+        .unwrap()
+        .args([
+            "--parties",
+            "4",
+            "--threshold",
+            "2",
+            "--message-hex",
+            "deadbeef",
+            "--output",
+            "json",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let field = |name: &str| hex::decode(report[name].as_str().unwrap()).unwrap();
+
+    let signature = SignatureECDSA {
+        r: Zq::from_bytes(&field("r")).unwrap(),
+        s: Zq::from_bytes(&field("s")).unwrap(),
+    };
+    let pk = G::from_bytes(&field("public_key")).unwrap();
+    assert!(signature.verify(&pk, &[0xde, 0xad, 0xbe, 0xef]));
+
+    for phase in ["setup", "dkg", "presign", "sign"] {
+        assert!(report["timings"][phase].is_f64());
+    }
+}
+
+#[test]
+pub fn test_cli_rejects_bad_arguments() {
+    let mut cmd = Command::cargo_bin("robust_threshold_ecdsa").unwrap();
+    cmd.args(["--parties", "3", "--threshold", "3", "--message", "hi"])
+        .assert()
+        .failure();
+
+    let mut cmd = Command::cargo_bin("robust_threshold_ecdsa").unwrap();
+    cmd.args(["--message-hex", "xyz"]).assert().failure();
+
+    let mut cmd = Command::cargo_bin("robust_threshold_ecdsa").unwrap();
+    cmd.args(["--message", "hi", "--message-hex", "00"])
+        .assert()
+        .failure();
+}
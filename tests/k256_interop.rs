@@ -0,0 +1,63 @@
+//! Cross-checks `SignatureECDSA::verify` against the k256 verifier, on signatures
+//! produced by either side.
+#![cfg(not(feature = "curve-p256"))]
+
+use curv::elliptic::curves::{Point, Scalar, Secp256k1};
+use k256::ecdsa::signature::{Signer, Verifier};
+use k256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use robust_threshold_ecdsa::spdz::{message_hash, SignatureECDSA};
+
+type Zq = Scalar<Secp256k1>;
+type G = Point<Secp256k1>;
+
+/// Textbook signing with a fresh nonce.
+fn sign(x: &Zq, msg: &[u8]) -> SignatureECDSA {
+    let k = Zq::random();
+    let r = Zq::from_bigint(&(G::generator() * &k).x_coord().unwrap());
+    let s = k.invert().unwrap() * (message_hash(msg) + &r * x);
+    SignatureECDSA { r, s }
+}
+
+fn to_k256(signature: &SignatureECDSA) -> Signature {
+    let bytes = [&*signature.r.to_bytes(), &*signature.s.to_bytes()].concat();
+    let signature = Signature::from_slice(&bytes).unwrap();
+    // k256 only accepts the low-s form
+    signature.normalize_s().unwrap_or(signature)
+}
+
+#[test]
+pub fn test_verify_matches_k256() {
+    let x = Zq::random();
+    let pk = G::generator() * &x;
+    let verifying_key = VerifyingKey::from_sec1_bytes(&pk.to_bytes(true)).unwrap();
+
+    for msg in [&b""[..], b"interoperable", &[0u8; 100]] {
+        let signature = sign(&x, msg);
+        assert!(signature.verify(&pk, msg));
+        assert!(verifying_key.verify(msg, &to_k256(&signature)).is_ok());
+
+        let other_msg = [msg, b"!"].concat();
+        assert!(!signature.verify(&pk, &other_msg));
+        assert!(verifying_key
+            .verify(&other_msg, &to_k256(&signature))
+            .is_err());
+
+        let tweaked = SignatureECDSA {
+            s: &signature.s + Zq::from(1u64),
+            ..signature
+        };
+        assert!(!tweaked.verify(&pk, msg));
+        assert!(verifying_key.verify(msg, &to_k256(&tweaked)).is_err());
+    }
+
+    // and the other way round
+    let signing_key = SigningKey::from_slice(&x.to_bytes()).unwrap();
+    let msg = b"signed by k256";
+    let signature: Signature = signing_key.sign(msg);
+    let (r, s) = signature.split_bytes();
+    let signature = SignatureECDSA {
+        r: Zq::from_bytes(&r).unwrap(),
+        s: Zq::from_bytes(&s).unwrap(),
+    };
+    assert!(signature.verify(&pk, msg));
+}
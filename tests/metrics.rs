@@ -0,0 +1,120 @@
+//! Checks the per-phase counters on whole runs. The counters are process-wide, so the
+//! tests take turns: nothing else in this binary runs alongside a measured run.
+
+use std::sync::Mutex;
+
+use bicycl::{Mpz, RandGen};
+use robust_threshold_ecdsa::cl_setup::SecurityLevel;
+use robust_threshold_ecdsa::metrics::{
+    estimate_cl_ops, run_locally_metered, ClOpCounts, PhaseMetrics,
+};
+use robust_threshold_ecdsa::spdz::{
+    DkgState, PresignSession, PresignState, SignState, SigningContext,
+};
+use robust_threshold_ecdsa::utils::*;
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// DKG, presigning and signing for t-of-n, with the cost of each phase.
+fn run(n: Id, t: Id) -> Vec<PhaseMetrics> {
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let (pp, secret_keys) = PubParams::with_level(SecurityLevel::Toy, n, t, &mut rng).unwrap();
+    let h = G::base_point2();
+    let wire = |i: Id| PartyId::from_share_index(i).to_wire_index();
+
+    let mut metrics = Vec::new();
+    let keys = run_locally_metered(
+        "dkg",
+        || {
+            (1..=n)
+                .map(|i| (wire(i), DkgState::new(i, &pp, h, &secret_keys[&i], false)))
+                .collect()
+        },
+        &mut metrics,
+    );
+    let presignatures = run_locally_metered(
+        "presign",
+        || {
+            (1..=n)
+                .map(|i| {
+                    let state = PresignState::new(
+                        i,
+                        &pp,
+                        h,
+                        &secret_keys[&i],
+                        &keys[&wire(i)],
+                        PresignSession::default(),
+                    );
+                    (wire(i), state)
+                })
+                .collect()
+        },
+        &mut metrics,
+    );
+    let context = SigningContext::from_dkg(&pp, &keys[&wire(1)]);
+    let signatures = run_locally_metered(
+        "sign",
+        || {
+            presignatures
+                .into_iter()
+                .map(|(i, presignature)| {
+                    let my_id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                    let state = SignState::new(my_id, &pp, h, &context, presignature, b"metrics");
+                    (i, state)
+                })
+                .collect()
+        },
+        &mut metrics,
+    );
+    assert_eq!(signatures.len(), n as usize);
+    metrics
+}
+
+#[test]
+pub fn test_phase_metrics_grow_with_n() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let small = run(3, 2);
+    let large = run(5, 2);
+
+    let phases: Vec<_> = small.iter().map(|m| m.phase).collect();
+    assert_eq!(phases, ["dkg", "presign", "sign"]);
+    for (small, large) in small.iter().zip(&large) {
+        assert_eq!(small.phase, large.phase);
+        assert!(small.bytes_sent > 0, "{small:?}");
+        assert!(small.bytes_sent < large.bytes_sent, "{small:?} {large:?}");
+        if small.phase == "sign" {
+            // signing only combines scalars
+            continue;
+        }
+        assert!(small.qfi_exps > 0, "{small:?}");
+        assert!(small.qfi_compositions > 0, "{small:?}");
+        assert!(small.ec_mults > 0, "{small:?}");
+        assert!(small.qfi_exps < large.qfi_exps, "{small:?} {large:?}");
+        assert!(
+            small.qfi_compositions < large.qfi_compositions,
+            "{small:?} {large:?}"
+        );
+        assert!(small.ec_mults < large.ec_mults, "{small:?} {large:?}");
+    }
+}
+
+#[test]
+pub fn test_estimate_cl_ops_matches_run() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    for (n, t) in [(3, 2), (5, 2), (5, 4)] {
+        let metrics = run(n, t);
+        let counted = metrics
+            .iter()
+            .fold(ClOpCounts::default(), |acc, m| acc + m.cl_ops);
+        assert_eq!(estimate_cl_ops(t, n), counted, "t = {t}, n = {n}");
+        assert_eq!(metrics[2].cl_ops, ClOpCounts::default());
+    }
+}
+
+#[test]
+pub fn test_estimate_cl_ops_empty_committee() {
+    assert_eq!(estimate_cl_ops(0, 0), ClOpCounts::default());
+    assert_eq!(estimate_cl_ops(0, 3), ClOpCounts::default());
+    assert_eq!(estimate_cl_ops(2, 0), ClOpCounts::default());
+}
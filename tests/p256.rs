@@ -0,0 +1,73 @@
+//! The whole protocol over P-256: DKG, presigning and signing among t-of-n parties,
+//! with the signature checked by the p256 verifier as well as our own.
+
+use bicycl::{Mpz, RandGen};
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use robust_threshold_ecdsa::cl_setup::SecurityLevel;
+use robust_threshold_ecdsa::spdz::{
+    run_locally, DkgState, PresignSession, PresignState, SignState, SigningContext,
+};
+use robust_threshold_ecdsa::utils::*;
+
+#[test]
+pub fn test_sign_over_p256() {
+    let (n, t) = (4, 3);
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Zq::random()));
+    let (pp, secret_keys) = PubParams::with_level(SecurityLevel::Toy, n, t, &mut rng).unwrap();
+    let h = G::base_point2();
+    let wire = |i: Id| PartyId::from_share_index(i).to_wire_index();
+
+    let keys = run_locally(
+        (1..=n)
+            .map(|i| (wire(i), DkgState::new(i, &pp, h, &secret_keys[&i], false)))
+            .collect(),
+    );
+    assert_eq!(keys.len(), n as usize);
+    let presignatures = run_locally(
+        (1..=n)
+            .map(|i| {
+                let state = PresignState::new(
+                    i,
+                    &pp,
+                    h,
+                    &secret_keys[&i],
+                    &keys[&wire(i)],
+                    PresignSession::default(),
+                );
+                (wire(i), state)
+            })
+            .collect(),
+    );
+    assert_eq!(presignatures.len(), n as usize);
+
+    let msg = b"signed over P-256";
+    let context = SigningContext::from_dkg(&pp, &keys[&wire(1)]);
+    let outcomes = run_locally(
+        presignatures
+            .into_iter()
+            .map(|(i, presignature)| {
+                let my_id = PartyId::from_wire_index(i).unwrap().to_share_index();
+                (
+                    i,
+                    SignState::new(my_id, &pp, h, &context, presignature, msg),
+                )
+            })
+            .collect(),
+    );
+    assert_eq!(outcomes.len(), n as usize);
+
+    let pk = &keys[&wire(1)].tpk.pk;
+    let verifying_key = VerifyingKey::from_sec1_bytes(&pk.to_bytes(true)).unwrap();
+    for outcome in outcomes.values() {
+        let signature = &outcome.signature;
+        assert_eq!(&outcome.public_key, pk);
+        assert!(signature.verify(pk, msg));
+        assert!(!signature.verify(pk, b"something else"));
+
+        let bytes = [&*signature.r.to_bytes(), &*signature.s.to_bytes()].concat();
+        let p256_signature = Signature::from_slice(&bytes).unwrap();
+        assert!(verifying_key.verify(msg, &p256_signature).is_ok());
+    }
+}
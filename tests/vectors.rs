@@ -0,0 +1,459 @@
+//! Recorded runs of the whole protocol, checked in under `tests/vectors`, to catch changes
+//! that silently alter what goes over the wire: the encoding of the messages, the proofs
+//! and their challenges, the hash chain of the transcripts, or how parties are numbered.
+//!
+//! Each honest party draws its randomness from a seed of its own, so a vector records,
+//! for every honest party and phase, the messages it received and sent in order. The
+//! test builds each party afresh from its seed, feeds it the messages it received, and
+//! checks that it sends the recorded bytes and arrives at the recorded signature. On top
+//! of that, the proofs in the transcripts verify under the recorded CL keys. A vector
+//! that is missing fails the test; `cargo test --test vectors -- --ignored` records all
+//! of them afresh, the first time and after a change to the wire format that is meant.
+//! The vectors are runs over secp256k1, so a build for P-256 skips them.
+#![cfg(not(feature = "curve-p256"))]
+
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+
+use bicycl::{Mpz, RandGen, SecretKey};
+use robust_threshold_ecdsa::cl_setup::{ClSetup, SecurityLevel};
+use robust_threshold_ecdsa::codec::{decode, encode};
+use robust_threshold_ecdsa::spdz::{
+    run_locally, DkgMsg, DkgState, PresignMsg, PresignSession, PresignState, PvssMsg, SignMsg,
+    SignState, SignatureECDSA, SigningContext, StateError, StateMachine,
+};
+use robust_threshold_ecdsa::transcript::{
+    verify_transcript, Anchor, Entry, Phase, Recorded, Signed, Transcript,
+};
+use robust_threshold_ecdsa::utils::*;
+use round_based::{MessageDestination, Outgoing, PartyIndex};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+const SEED: u64 = 0x5eed;
+const SESSION: &[u8] = b"test vector";
+const MESSAGE: &[u8] = b"golden";
+
+/// A message one party received or sent, encoded, in hex.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Event {
+    Received {
+        from: PartyIndex,
+        msg: String,
+    },
+    /// `to` is empty for a broadcast.
+    Sent {
+        to: Option<PartyIndex>,
+        msg: String,
+    },
+}
+
+impl Event {
+    fn sent<M: Serialize>(out: &Outgoing<M>) -> Self {
+        Event::Sent {
+            to: match out.recipient {
+                MessageDestination::AllParties => None,
+                MessageDestination::OneParty(i) => Some(i),
+            },
+            msg: hex::encode(encode(&out.msg)),
+        }
+    }
+}
+
+/// What one party received and sent in each phase.
+#[derive(Serialize, Deserialize)]
+struct Tapes {
+    dkg: Vec<Event>,
+    presign: Vec<Event>,
+    sign: Vec<Event>,
+}
+
+/// Records what the inner state receives and sends.
+struct Taped<S> {
+    inner: S,
+    tape: Vec<Event>,
+}
+
+impl<S> Taped<S> {
+    fn new(inner: S) -> Self {
+        Taped {
+            inner,
+            tape: vec![],
+        }
+    }
+}
+
+impl<S> StateMachine for Taped<S>
+where
+    S: StateMachine,
+    S::Msg: Serialize,
+{
+    type Msg = S::Msg;
+    type Output = (S::Output, Vec<Event>);
+
+    fn handle_message(&mut self, from: PartyIndex, msg: S::Msg) -> Result<(), StateError> {
+        self.tape.push(Event::Received {
+            from,
+            msg: hex::encode(encode(&msg)),
+        });
+        self.inner.handle_message(from, msg)
+    }
+
+    fn wants_to_send(&mut self) -> Vec<Outgoing<S::Msg>> {
+        let outgoing = self.inner.wants_to_send();
+        self.tape.extend(outgoing.iter().map(Event::sent));
+        outgoing
+    }
+
+    fn try_finish(self) -> Result<Self::Output, Self> {
+        let Taped { inner, tape } = self;
+        match inner.try_finish() {
+            Ok(output) => Ok((output, tape)),
+            Err(inner) => Err(Taped { inner, tape }),
+        }
+    }
+}
+
+/// Feeds `state` the messages `tape` says it received, in order, checks that it sends
+/// exactly the messages recorded, and returns its output.
+fn replay<S>(mut state: S, tape: &[Event], what: &str) -> S::Output
+where
+    S: StateMachine,
+    S::Msg: Serialize + DeserializeOwned,
+{
+    let mut sent = VecDeque::new();
+    for event in tape {
+        sent.extend(state.wants_to_send());
+        match event {
+            Event::Received { from, msg } => {
+                let msg = decode(&hex::decode(msg).unwrap()).unwrap();
+                if let Err(err) = state.handle_message(*from, msg) {
+                    panic!("{what}: {err}");
+                }
+            }
+            Event::Sent { .. } => match sent.pop_front() {
+                Some(out) => assert_eq!(&Event::sent(&out), event, "{what}"),
+                None => panic!("{what}: sent fewer messages than recorded"),
+            },
+        }
+    }
+    sent.extend(state.wants_to_send());
+    assert!(sent.is_empty(), "{what}: sent more messages than recorded");
+    match state.try_finish() {
+        Ok(output) => output,
+        Err(_) => panic!("{what}: did not finish"),
+    }
+}
+
+/// The generator party `id` draws from in `phase`.
+fn party_rng(phase: &str, id: Id) -> ForkableRng {
+    ForkableRng::from_seed([0; 32])
+        .fork("test vector", SEED)
+        .fork(phase, id.into())
+}
+
+fn session() -> PresignSession {
+    PresignSession::new(SESSION, 0)
+}
+
+/// One full run of DKG, presigning and signing, as every party that finished recorded it.
+#[derive(Serialize, Deserialize)]
+struct Vector {
+    n: Id,
+    t: Id,
+    setup: ClSetup,
+    /// The CL secret keys of parties 1..=n, big-endian hex.
+    cl_secret_keys: BTreeMap<Id, String>,
+    /// The dealers the DKG disqualified, who take no part after it.
+    disqualified: Vec<Id>,
+    /// Compressed, hex.
+    public_key: String,
+    signature: SignatureECDSA,
+    transcripts: BTreeMap<PartyIndex, Transcript>,
+    tapes: BTreeMap<PartyIndex, Tapes>,
+}
+
+struct Case {
+    file: &'static str,
+    n: Id,
+    t: Id,
+    cheater: Option<Id>,
+}
+
+const CASES: [Case; 2] = [
+    Case {
+        file: "three_parties.json",
+        n: 3,
+        t: 2,
+        cheater: None,
+    },
+    Case {
+        file: "four_parties_disqualified_dealer.json",
+        n: 4,
+        t: 2,
+        cheater: Some(4),
+    },
+];
+
+fn path(case: &Case) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/vectors")
+        .join(case.file)
+}
+
+fn wire(id: Id) -> PartyIndex {
+    PartyId::from_share_index(id).to_wire_index()
+}
+
+fn id(i: PartyIndex) -> Id {
+    PartyId::from_wire_index(i).unwrap().to_share_index()
+}
+
+/// The CL secret keys a vector recorded.
+fn secret_keys(vector: &Vector) -> BTreeMap<Id, SecretKey> {
+    let cl = vector.setup.cl();
+    vector
+        .cl_secret_keys
+        .iter()
+        .map(|(&i, sk)| {
+            let sk = Mpz::from_bytes(&hex::decode(sk).unwrap());
+            (i, SecretKey::from_mpz(&cl, &sk))
+        })
+        .collect()
+}
+
+/// The public parameters of a vector, with the CL keys it recorded.
+fn params(vector: &Vector) -> PubParams {
+    let cl = vector.setup.cl();
+    let keyring: BTreeMap<Id, _> = secret_keys(vector)
+        .iter()
+        .map(|(&i, sk)| (i, cl.public_key_gen(sk)))
+        .collect();
+    PubParams {
+        cl,
+        t: vector.t,
+        n: vector.n,
+        cl_keyring: CLKeyRing::from(keyring),
+        curve_generator: G::base_point2().clone(),
+        limits: ProtocolLimits::default(),
+        compact_pvss: false,
+        qualification: Qualification::default(),
+    }
+}
+
+/// Runs the protocol for `case`, recording every party.
+fn record(case: &Case) -> Vector {
+    let setup = ClSetup::generate(SecurityLevel::Toy);
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(SEED));
+    let (pp, secret_keys) = PubParams::with_cl(setup.cl(), case.n, case.t, &mut rng).unwrap();
+    let h = G::base_point2();
+
+    // the cheater deals to keys other than the committee's, so its proof fails
+    let (foreign_pp, _) = PubParams::with_cl(setup.cl(), case.n, case.t, &mut rng).unwrap();
+    let keys = run_locally(
+        (1..=pp.n)
+            .map(|i| {
+                let state = if case.cheater == Some(i) {
+                    let dealing = PvssMsg::random(&foreign_pp, &mut rng, h);
+                    DkgState::with_pvss_msg(i, &pp, h, &secret_keys[&i], false, dealing)
+                } else {
+                    DkgState::seeded(i, &pp, h, &secret_keys[&i], false, party_rng("dkg", i))
+                };
+                let transcript = Transcript::new(SESSION, &pp);
+                let state = Recorded::new(wire(i), state, transcript);
+                (wire(i), Taped::new(state))
+            })
+            .collect(),
+    );
+    let ((key, _), _) = keys.values().next().expect("a party finished the DKG");
+    let honest = key.honest_parties();
+
+    let presignatures = run_locally(
+        honest
+            .iter()
+            .map(|&i| {
+                let ((key, transcript), _) = &keys[&wire(i)];
+                let rng = party_rng("presign", i);
+                let state = PresignState::seeded(i, &pp, h, &secret_keys[&i], key, session(), rng);
+                let state = Recorded::new(wire(i), state, transcript.clone());
+                (wire(i), Taped::new(state))
+            })
+            .collect(),
+    );
+    let context = SigningContext::from_dkg(&pp, key);
+    let outcomes = run_locally(
+        presignatures
+            .iter()
+            .map(|(&i, ((presignature, transcript), _))| {
+                let state = SignState::new(id(i), &pp, h, &context, presignature.clone(), MESSAGE);
+                let state = Recorded::new(i, state, transcript.clone());
+                (i, Taped::new(state))
+            })
+            .collect(),
+    );
+    assert_eq!(outcomes.len(), honest.len());
+    let ((outcome, _), _) = outcomes.values().next().unwrap();
+    let signature = outcome.signature.clone();
+    let tapes = outcomes
+        .iter()
+        .map(|(i, (_, sign))| {
+            let tapes = Tapes {
+                dkg: keys[i].1.clone(),
+                presign: presignatures[i].1.clone(),
+                sign: sign.clone(),
+            };
+            (*i, tapes)
+        })
+        .collect();
+
+    Vector {
+        n: case.n,
+        t: case.t,
+        cl_secret_keys: secret_keys
+            .iter()
+            .map(|(&i, sk)| (i, hex::encode(sk.mpz().to_bytes())))
+            .collect(),
+        setup,
+        disqualified: case.cheater.into_iter().collect(),
+        public_key: hex::encode(&*context.pk.to_bytes(true)),
+        signature,
+        transcripts: outcomes
+            .into_iter()
+            .map(|(i, ((_, transcript), _))| (i, transcript))
+            .collect(),
+        tapes,
+    }
+}
+
+fn save(case: &Case, vector: &Vector) {
+    let path = path(case);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, serde_json::to_string_pretty(vector).unwrap()).unwrap();
+}
+
+/// Decodes `bytes` as a message of `phase` and encodes it again.
+fn reencode(phase: Phase, bytes: &[u8]) -> Vec<u8> {
+    match phase {
+        Phase::Dkg => encode(&decode::<DkgMsg>(bytes).unwrap()),
+        Phase::Presign => encode(&decode::<PresignMsg>(bytes).unwrap()),
+        Phase::Sign => encode(&decode::<SignMsg>(bytes).unwrap()),
+    }
+}
+
+fn check(case: &Case, vector: &Vector) {
+    vector.setup.verify().unwrap();
+    let pp = params(vector);
+    let h = G::base_point2();
+    let pk = G::from_bytes(&hex::decode(&vector.public_key).unwrap()).unwrap();
+    assert!(vector.signature.verify(&pk, MESSAGE), "{}", case.file);
+
+    let honest: Vec<Id> = (1..=vector.n)
+        .filter(|i| !vector.disqualified.contains(i))
+        .collect();
+    assert_eq!(
+        vector
+            .transcripts
+            .keys()
+            .map(|&i| id(i))
+            .collect::<Vec<_>>(),
+        honest,
+        "{}",
+        case.file
+    );
+
+    // each broadcast, by phase, sender and round, as the first party recorded it
+    let mut broadcasts: BTreeMap<(u8, PartyIndex, u16), &[u8]> = BTreeMap::new();
+    for (i, transcript) in &vector.transcripts {
+        let json = transcript.to_json();
+        let signed = Signed {
+            pk: &pk,
+            msg: MESSAGE,
+            signature: &vector.signature,
+        };
+        let anchor = Anchor::new(SESSION, &pp);
+        let verified = verify_transcript(&json, &anchor, &pp, h, &session(), signed)
+            .unwrap_or_else(|e| panic!("{}, party {i}: {e}", case.file));
+        assert_eq!(verified.digest(), transcript.digest());
+
+        for link in &transcript.links {
+            match &link.entry {
+                Entry::Message {
+                    phase,
+                    sender,
+                    round,
+                    bytes,
+                } => {
+                    assert_eq!(&reencode(*phase, bytes), bytes, "{}", case.file);
+                    let first = *broadcasts
+                        .entry((*phase as u8, *sender, *round))
+                        .or_insert(bytes.as_slice());
+                    assert_eq!(first, bytes.as_slice(), "{}: sender {sender}", case.file);
+                    // the disqualified deal, and are not heard from again
+                    assert!(*phase == Phase::Dkg || !vector.disqualified.contains(&id(*sender)));
+                }
+                Entry::Qualified { phase, parties } if *phase != Phase::Dkg => {
+                    assert!(parties
+                        .iter()
+                        .all(|&party| !vector.disqualified.contains(&id(party))));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // each honest party, rebuilt from its seeds, sends the recorded bytes
+    assert_eq!(
+        vector.tapes.keys().collect::<Vec<_>>(),
+        vector.transcripts.keys().collect::<Vec<_>>(),
+        "{}",
+        case.file
+    );
+    let secret_keys = secret_keys(vector);
+    for (&i, tapes) in &vector.tapes {
+        let me = id(i);
+        let what = |phase: &str| format!("{}, party {i}, {phase}", case.file);
+        let state = DkgState::seeded(me, &pp, h, &secret_keys[&me], false, party_rng("dkg", me));
+        let key = replay(state, &tapes.dkg, &what("DKG"));
+        assert_eq!(key.tpk.pk, pk, "{}", case.file);
+
+        let rng = party_rng("presign", me);
+        let state = PresignState::seeded(me, &pp, h, &secret_keys[&me], &key, session(), rng);
+        let presignature = replay(state, &tapes.presign, &what("presigning"));
+
+        let context = SigningContext::from_dkg(&pp, &key);
+        let state = SignState::new(me, &pp, h, &context, presignature, MESSAGE);
+        let outcome = replay(state, &tapes.sign, &what("signing"));
+        assert_eq!(
+            encode(&outcome.signature),
+            encode(&vector.signature),
+            "{}",
+            case.file
+        );
+    }
+}
+
+#[test]
+pub fn test_vectors() {
+    for case in &CASES {
+        let path = path(case);
+        // recording one here would compare the code against itself
+        assert!(
+            path.exists(),
+            "{} is missing, record it with `regenerate_vectors` and check it in",
+            case.file
+        );
+        let vector = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        check(case, &vector);
+    }
+}
+
+#[test]
+#[ignore = "records the vectors afresh, run after an intended change to the wire format"]
+pub fn regenerate_vectors() {
+    for case in &CASES {
+        let vector = record(case);
+        check(case, &vector);
+        save(case, &vector);
+    }
+}